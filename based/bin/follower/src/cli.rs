@@ -0,0 +1,110 @@
+use std::{net::Ipv4Addr, path::PathBuf, sync::Arc};
+
+use alloy_primitives::Address;
+use bop_common::{config::LoggingConfig, schedule::SequencerSchedule};
+use bop_sequencer::follower::ExpectedSigner;
+use clap::Parser;
+use eyre::bail;
+use reqwest::Url;
+use reth_cli::chainspec::ChainSpecParser;
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_cli::chainspec::OpChainSpecParser;
+use tracing::level_filters::LevelFilter;
+
+#[derive(Parser, Debug)]
+#[command(version, about, name = "follower")]
+pub struct FollowerArgs {
+    #[arg(
+        long,
+        value_name = "CHAIN_OR_PATH",
+        long_help = OpChainSpecParser::help_message(),
+        default_value = OpChainSpecParser::SUPPORTED_CHAINS[6],
+        value_parser = OpChainSpecParser::parser(),
+    )]
+    pub chain: Arc<OpChainSpec>,
+    /// The host to run the RPC on
+    #[arg(long = "rpc.host", default_value_t = Ipv4Addr::UNSPECIFIED)]
+    pub rpc_host: Ipv4Addr,
+    /// The port to run the RPC on. Serves the based_ frag stream gossiped by the gateway and the eth_ state queries
+    #[arg(long = "rpc.port", default_value_t = 9092)]
+    pub rpc_port: u16,
+    /// Port to serve the frag stream on as HTTP bodies in a binary wire encoding, selected by the content type, see
+    /// `--gossip.encoding` on the gateway. Disabled if not set
+    #[arg(long = "rpc.wire_port")]
+    pub rpc_wire_port: Option<u16>,
+    /// Maximum number of blocks behind the head that eth_ state queries are served for
+    #[arg(long = "rpc.max_history_blocks", default_value_t = 1024)]
+    pub rpc_max_history_blocks: u64,
+    /// Url to a full node that missed blocks are synced from
+    #[arg(long = "rpc.fallback_url", default_value = "https://base-sepolia-rpc.publicnode.com")]
+    pub rpc_fallback_url: Url,
+    /// Port to serve Prometheus metrics on, on the rpc host. Disabled if not set
+    #[arg(long = "metrics.port")]
+    pub metrics_port: Option<u16>,
+    /// Address of the gateway frags must be signed by
+    #[arg(long = "sequencer.address", conflicts_with = "sequencer_schedule_path")]
+    pub sequencer_address: Option<Address>,
+    /// Path to a sequencer schedule, see `schedule.example.json`. Frags must be signed by the gateway scheduled for
+    /// their block
+    #[arg(long = "sequencer.schedule_path", conflicts_with = "sequencer_address")]
+    pub sequencer_schedule_path: Option<PathBuf>,
+    /// Url to the gateway's RPC, envs, frags and seals missed from the gossip are backfilled from its archive. Missed
+    /// messages drop the open block if not set
+    #[arg(long = "sequencer.archive_url")]
+    pub sequencer_archive_url: Option<Url>,
+    /// Directory evidence files are written to when the sequencer breaks its promises, e.g. a canonical block that
+    /// doesn't match its frags. The frag stream isn't watched if not set
+    #[arg(long = "watcher.evidence_dir")]
+    pub watcher_evidence_dir: Option<PathBuf>,
+    /// Depth in the fallback's chain at which blocks are considered canonical and checked against their frags
+    #[arg(long = "watcher.confirmations", default_value_t = 10)]
+    pub watcher_confirmations: u64,
+    /// Database location
+    #[arg(long = "db.datadir")]
+    pub db_datadir: PathBuf,
+    /// Maximum number of cached accounts
+    #[arg(long = "db.max_cached_accounts", default_value_t = 10_000)]
+    pub max_cached_accounts: u64,
+    /// Maximum number of cached storages
+    #[arg(long = "db.max_cached_storages", default_value_t = 100_000)]
+    pub max_cached_storages: u64,
+    /// Enable DEBUG logging
+    #[arg(long = "debug")]
+    pub debug: bool,
+    /// Enable TRACE logging
+    #[arg(long = "trace")]
+    pub trace: bool,
+    /// Add additional filters for logging
+    #[arg(long = "log.filters")]
+    pub log_filters: Option<String>,
+}
+
+impl FollowerArgs {
+    pub fn expected_signer(&self) -> eyre::Result<ExpectedSigner> {
+        if let Some(address) = self.sequencer_address {
+            Ok(ExpectedSigner::Address(address))
+        } else if let Some(path) = self.sequencer_schedule_path.as_ref() {
+            let schedule = SequencerSchedule::from_json(&std::fs::read_to_string(path)?)?;
+            Ok(ExpectedSigner::Schedule(schedule))
+        } else {
+            bail!("either --sequencer.address or --sequencer.schedule_path must be provided");
+        }
+    }
+}
+
+impl From<&FollowerArgs> for LoggingConfig {
+    fn from(args: &FollowerArgs) -> Self {
+        Self {
+            level: args
+                .trace
+                .then_some(LevelFilter::TRACE)
+                .or(args.debug.then_some(LevelFilter::DEBUG))
+                .unwrap_or(LevelFilter::INFO),
+            enable_file_logging: false,
+            prefix: None,
+            max_files: 100,
+            path: PathBuf::from("/tmp"),
+            filters: args.log_filters.clone(),
+        }
+    }
+}
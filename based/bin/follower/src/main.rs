@@ -0,0 +1,84 @@
+use std::net::{IpAddr, SocketAddr};
+
+use alloy_provider::ProviderBuilder;
+use bop_common::{
+    api::{EthStateApiServer, FragApiServer},
+    metrics::init_prometheus,
+    utils::{init_tracing, wait_for_signal},
+};
+use bop_db::{init_database, DatabaseRead, DatabaseWrite};
+use bop_rpc::StateRpc;
+use bop_sequencer::{follower::Follower, watcher::Watcher};
+use clap::Parser;
+use cli::FollowerArgs;
+use jsonrpsee::{http_client::HttpClientBuilder, server::ServerBuilder};
+use reth_optimism_evm::OpEvmConfig;
+use server::FollowerServer;
+use tracing::{error, info};
+
+mod cli;
+mod server;
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = FollowerArgs::parse();
+    let _guards = init_tracing((&args).into());
+
+    if let Some(port) = args.metrics_port {
+        let metrics_addr = SocketAddr::new(IpAddr::V4(args.rpc_host), port);
+        init_prometheus(metrics_addr)?;
+        info!(%metrics_addr, "serving metrics");
+    }
+
+    let signer = args.expected_signer()?;
+    let db = init_database(&args.db_datadir, args.max_cached_accounts, args.max_cached_storages, args.chain.clone())?;
+    let evm_config = OpEvmConfig::new(args.chain.clone());
+
+    let watcher = args.watcher_evidence_dir.clone().map(|dir| Watcher::new(signer.clone(), dir));
+    let follower = Follower::new(db.clone(), evm_config.clone(), signer);
+    let state = StateRpc::new(db.clone(), follower.frag_db(), evm_config, args.rpc_max_history_blocks);
+    let fallback = ProviderBuilder::new().network().on_http(args.rpc_fallback_url.clone());
+
+    let archive = args.sequencer_archive_url.as_ref().map(|url| HttpClientBuilder::default().build(url)).transpose()?;
+
+    let server = FollowerServer::new(follower, watcher, fallback, archive);
+    if let Some(dir) = args.watcher_evidence_dir.as_ref() {
+        info!(dir = %dir.display(), confirmations = args.watcher_confirmations, "watching frags");
+        tokio::spawn(server.clone().watch(args.watcher_confirmations));
+    }
+
+    if let Some(port) = args.rpc_wire_port {
+        let addr = SocketAddr::new(IpAddr::V4(args.rpc_host), port);
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        let router = server.clone().wire_router();
+        info!(%addr, "serving binary frag stream");
+        tokio::spawn(async move {
+            if let Err(err) = axum::serve(listener, router).await {
+                error!(%err, "binary frag stream server stopped");
+            }
+        });
+    }
+
+    let mut module = FragApiServer::into_rpc(server);
+    module.merge(EthStateApiServer::into_rpc(state))?;
+
+    let addr = SocketAddr::new(IpAddr::V4(args.rpc_host), args.rpc_port);
+    let handle = ServerBuilder::default().build(addr).await?.start(module);
+    info!(%addr, head = db.head_block_number()?, fallback_url = %args.rpc_fallback_url, "starting follower");
+
+    tokio::select! {
+        _ = handle.clone().stopped() => {
+            error!("server stopped");
+        }
+
+        res = wait_for_signal() => {
+            res?;
+            let _ = handle.stop();
+            handle.stopped().await;
+        }
+    }
+
+    db.flush()?;
+    info!("follower stopped");
+    Ok(())
+}
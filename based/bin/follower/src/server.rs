@@ -0,0 +1,304 @@
+use std::{
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use alloy_provider::Provider;
+use axum::{
+    body::Bytes,
+    extract::{DefaultBodyLimit, State},
+    http::{header::CONTENT_TYPE, HeaderMap, StatusCode},
+    routing::post,
+    Router,
+};
+use bop_common::{
+    api::{FragApiServer, FragArchiveApiClient},
+    communication::messages::{RpcError, RpcResult},
+    db::{DatabaseRead, DatabaseWrite},
+    p2p::{SignedMessage, VersionedMessage, WireEncoding, MAX_WIRE_MESSAGE_SIZE},
+};
+use bop_sequencer::{
+    block_sync::{fetch_blocks::fetch_block, AlloyProvider},
+    follower::{Follower, FollowerError},
+    watcher::Watcher,
+};
+use jsonrpsee::{core::async_trait, http_client::HttpClient};
+use parking_lot::Mutex;
+use tracing::{error, info, warn};
+
+/// How often the watcher checks for blocks that became canonical.
+const WATCH_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Applies the frag stream to the [`Follower`] and syncs it from the fallback when it falls behind. If a [`Watcher`] is
+/// set, every message is also recorded to be checked against the canonical chain. Envs, frags and seals missed from the
+/// gossip are backfilled from the gateway's archive, if one is set.
+#[derive(Clone)]
+pub struct FollowerServer<Db> {
+    follower: Arc<Mutex<Follower<Db>>>,
+    watcher: Option<Arc<Mutex<Watcher>>>,
+    fallback: AlloyProvider,
+    archive: Option<HttpClient>,
+    syncing: Arc<AtomicBool>,
+    backfilling: Arc<tokio::sync::Mutex<()>>,
+}
+
+impl<Db: DatabaseWrite + DatabaseRead> FollowerServer<Db> {
+    pub fn new(
+        follower: Follower<Db>,
+        watcher: Option<Watcher>,
+        fallback: AlloyProvider,
+        archive: Option<HttpClient>,
+    ) -> Self {
+        Self {
+            follower: Arc::new(Mutex::new(follower)),
+            watcher: watcher.map(|watcher| Arc::new(Mutex::new(watcher))),
+            fallback,
+            archive,
+            syncing: Default::default(),
+            backfilling: Default::default(),
+        }
+    }
+
+    /// Serves `POST /` with a message in a binary [`WireEncoding`], selected by the content type.
+    pub fn wire_router(self) -> Router {
+        Router::new()
+            .route("/", post(receive_wire::<Db>))
+            .layer(DefaultBodyLimit::max(MAX_WIRE_MESSAGE_SIZE as usize))
+            .with_state(self)
+    }
+
+    async fn handle(&self, signed: SignedMessage) -> RpcResult<()> {
+        if let Some(archive) = self.archive.as_ref() {
+            let _backfilling = self.backfilling.lock().await;
+            if let Err(err) = self.backfill_before(archive, &signed.message).await {
+                warn!(%err, "couldn't backfill from archive");
+            }
+        }
+
+        let Err(err) = self.apply(signed).await? else {
+            return Ok(());
+        };
+        match err {
+            FollowerError::Behind { head, number } => self.catch_up(head + 1, number - 1),
+            FollowerError::ParentMismatch { number, .. } => self.catch_up(number - 1, number - 1),
+            FollowerError::MissingFrags { number, to, .. } if self.archive.is_some() => {
+                return self.backfill(number, to).await;
+            }
+            _ => {}
+        }
+        warn!(%err, "rejected message");
+        Err(RpcError::InvalidMessage(err.to_string()))
+    }
+
+    /// Messages are applied one at a time on the blocking pool, as frags are executed and blocks committed.
+    async fn apply(&self, signed: SignedMessage) -> RpcResult<Result<(), FollowerError>> {
+        let follower = self.follower.clone();
+        let watcher = self.watcher.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            if let Some(watcher) = watcher {
+                watcher.lock().record(&signed);
+            }
+            follower.lock().handle(signed)
+        })
+        .await?;
+        Ok(res)
+    }
+
+    /// Applies the frags of the open block up to and including `to` from the archive. The frag that revealed the gap
+    /// is fetched again too, so it's applied in order. Gaps found while a backfill is running are filled after it,
+    /// starting from wherever it stopped.
+    async fn backfill(&self, number: u64, to: u64) -> RpcResult<()> {
+        let Some(archive) = self.archive.as_ref() else {
+            return Ok(());
+        };
+        let _backfilling = self.backfilling.lock().await;
+        self.backfill_frags(archive, number, to).await
+    }
+
+    /// Fetches what the follower missed before `message` from the archive: the rest of the open block and its seal
+    /// when a later block starts, the env of a block whose frags arrive without it, and the frags before a seal.
+    async fn backfill_before(&self, archive: &HttpClient, message: &VersionedMessage) -> RpcResult<()> {
+        let number = message.block_number();
+        let open = self.follower.lock().next_frag().map(|(open, _)| open);
+
+        match message {
+            VersionedMessage::EnvV0(_) if open.is_some_and(|open| open + 1 == number) => {
+                self.backfill_seal(archive, number - 1).await
+            }
+            VersionedMessage::FragV0(_) | VersionedMessage::FragV1(_) if open != Some(number) => {
+                if open.is_some_and(|open| open + 1 == number) {
+                    self.backfill_seal(archive, number - 1).await?;
+                }
+                let head = self.follower.lock().head_block_number().ok();
+                if head.is_some_and(|head| head + 1 == number) {
+                    self.backfill_env(archive, number).await?;
+                }
+                Ok(())
+            }
+            VersionedMessage::SealV0(seal) if seal.total_frags > 0 => {
+                self.backfill_frags(archive, number, seal.total_frags - 1).await
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Opens block `number` with its env from the archive.
+    async fn backfill_env(&self, archive: &HttpClient, number: u64) -> RpcResult<()> {
+        let Some(signed) = archive.get_env(number).await? else {
+            warn!(number, "env missing from archive");
+            return Err(RpcError::InvalidMessage(format!("env of block {number} is missing")));
+        };
+        if let Err(err) = self.apply(signed).await? {
+            warn!(%err, number, "rejected backfilled env");
+            return Err(RpcError::InvalidMessage(err.to_string()));
+        }
+        info!(number, "backfilled env");
+        Ok(())
+    }
+
+    /// Completes the open block `number` with its remaining frags and seal from the archive.
+    async fn backfill_seal(&self, archive: &HttpClient, number: u64) -> RpcResult<()> {
+        let Some(signed) = archive.get_seal(number).await? else {
+            warn!(number, "seal missing from archive");
+            return Err(RpcError::InvalidMessage(format!("seal of block {number} is missing")));
+        };
+        let VersionedMessage::SealV0(seal) = &signed.message else {
+            return Err(RpcError::InvalidMessage(format!("archive returned a {} as seal", signed.message.as_ref())));
+        };
+        if seal.total_frags > 0 {
+            self.backfill_frags(archive, number, seal.total_frags - 1).await?;
+        }
+
+        // The gossip may have sealed it in the meantime
+        if !self.follower.lock().next_frag().is_some_and(|(open, _)| open == number) {
+            return Ok(());
+        }
+        if let Err(err) = self.apply(signed).await? {
+            warn!(%err, number, "rejected backfilled seal");
+            return Err(RpcError::InvalidMessage(err.to_string()));
+        }
+        info!(number, "backfilled seal");
+        Ok(())
+    }
+
+    /// Applies the frags of the open block `number` from the next expected one up to and including `to`.
+    async fn backfill_frags(&self, archive: &HttpClient, number: u64, to: u64) -> RpcResult<()> {
+        loop {
+            let seq = match self.follower.lock().next_frag() {
+                Some((open, seq)) if open == number && seq <= to => seq,
+                _ => return Ok(()),
+            };
+
+            let Some(signed) = archive.get_frag(number, seq).await? else {
+                warn!(number, seq, "frag missing from archive");
+                return Err(RpcError::InvalidMessage(format!("frag {seq} of block {number} is missing")));
+            };
+            if let Err(err) = self.apply(signed).await? {
+                warn!(%err, number, seq, "rejected backfilled frag");
+                return Err(RpcError::InvalidMessage(err.to_string()));
+            }
+            info!(number, seq, "backfilled frag");
+        }
+    }
+
+    /// Syncs blocks `from..=to` from the fallback in the background, unless a sync is already running. Frags are
+    /// rejected until the next env after the sync.
+    fn catch_up(&self, from: u64, to: u64) {
+        if self.syncing.swap(true, Ordering::AcqRel) {
+            return;
+        }
+
+        let this = self.clone();
+        tokio::spawn(async move {
+            if let Err(err) = this.sync(from, to).await {
+                error!(%err, "couldn't sync from fallback");
+            }
+            this.syncing.store(false, Ordering::Release);
+        });
+    }
+
+    /// Fetches and commits blocks in order, following the ranges requested on reorgs.
+    async fn sync(&self, from: u64, to: u64) -> eyre::Result<()> {
+        info!(from, to, "syncing from fallback");
+
+        let mut range = Some((from, to));
+        while let Some((from, to)) = range.take() {
+            for number in from..=to {
+                let block = fetch_block(number, &self.fallback).await;
+                let follower = self.follower.clone();
+                range = tokio::task::spawn_blocking(move || follower.lock().sync_block(&block)).await??;
+                if range.is_some() {
+                    break;
+                }
+            }
+        }
+
+        info!(head = self.follower.lock().head_block_number()?, "synced from fallback");
+        Ok(())
+    }
+}
+
+async fn receive_wire<Db: DatabaseWrite + DatabaseRead>(
+    State(server): State<FollowerServer<Db>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Result<(), (StatusCode, String)> {
+    let encoding = headers
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .and_then(WireEncoding::from_content_type)
+        .ok_or((StatusCode::UNSUPPORTED_MEDIA_TYPE, "unknown wire encoding".to_string()))?;
+    let signed = SignedMessage::from_wire(&body, encoding).map_err(|err| (StatusCode::BAD_REQUEST, err.to_string()))?;
+    server.handle(signed).await.map_err(|err| (StatusCode::UNPROCESSABLE_ENTITY, err.to_string()))
+}
+
+impl<Db> FollowerServer<Db> {
+    /// Checks the messages recorded by the watcher once their block is `confirmations` deep in the fallback's chain.
+    pub async fn watch(self, confirmations: u64) {
+        let Some(watcher) = self.watcher else {
+            return;
+        };
+
+        let mut interval = tokio::time::interval(WATCH_INTERVAL);
+        loop {
+            interval.tick().await;
+            let head = match self.fallback.get_block_number().await {
+                Ok(head) => head,
+                Err(err) => {
+                    warn!(%err, "couldn't get fallback head");
+                    continue;
+                }
+            };
+
+            let pending = watcher.lock().pending_blocks(head.saturating_sub(confirmations));
+            for number in pending {
+                let block = fetch_block(number, &self.fallback).await;
+                if let Err(err) = watcher.lock().check(&block) {
+                    error!(%err, number, "couldn't check block against its frags");
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<Db: DatabaseWrite + DatabaseRead> FragApiServer for FollowerServer<Db> {
+    async fn env(&self, signed: SignedMessage) -> RpcResult<()> {
+        self.handle(signed).await
+    }
+
+    async fn new_frag(&self, signed: SignedMessage) -> RpcResult<()> {
+        self.handle(signed).await
+    }
+
+    async fn new_frag_v1(&self, signed: SignedMessage) -> RpcResult<()> {
+        self.handle(signed).await
+    }
+
+    async fn seal_frag(&self, signed: SignedMessage) -> RpcResult<()> {
+        self.handle(signed).await
+    }
+}
@@ -1,19 +1,22 @@
-use std::sync::Arc;
+use std::{net::SocketAddr, sync::Arc};
 
 use bop_common::{
-    actor::{Actor, ActorConfig},
+    actor::{Actor, ActorConfig, ActorsConfig},
     communication::Spine,
     config::GatewayArgs,
+    metrics::init_prometheus,
     shared::SharedState,
+    shutdown::{Shutdown, Stage},
     time::Duration,
     utils::{init_tracing, wait_for_signal},
 };
-use bop_db::{init_database, DatabaseRead};
-use bop_rpc::{gossiper::Gossiper, start_rpc};
+use bop_db::{init_database, DatabaseRead, DatabaseWrite};
+use bop_rpc::{archive::FragArchive, gossiper::Gossiper, start_rpc};
 use bop_sequencer::{
     block_sync::{
         block_fetcher::BlockFetcher,
         mock_fetcher::{MockFetcher, Mode},
+        providers::BlockProviders,
     },
     Sequencer, SequencerConfig, Simulator,
 };
@@ -46,7 +49,20 @@ fn main() {
 }
 
 fn run(args: GatewayArgs) -> eyre::Result<()> {
-    let spine = Spine::default();
+    let actors_config = match args.actors_config.as_ref() {
+        Some(path) => ActorsConfig::from_file(path)?,
+        None => ActorsConfig::default(),
+    };
+    let spine = Spine::new(&actors_config.channels);
+    let engine_jwt = args.engine_jwt()?;
+    let gossip_signer = args.gossip_signer()?;
+    let archive = FragArchive::new(args.gossip_archive_blocks);
+
+    if let Some(port) = args.metrics_port {
+        let metrics_addr = SocketAddr::new(args.rpc_host.into(), port);
+        init_prometheus(metrics_addr)?;
+        info!(%metrics_addr, "serving metrics");
+    }
 
     let db_bop =
         init_database(args.db_datadir.clone(), args.max_cached_accounts, args.max_cached_storages, args.chain.clone())?;
@@ -56,6 +72,9 @@ fn run(args: GatewayArgs) -> eyre::Result<()> {
 
     info!(db_block, %db_hash, "starting gateway");
 
+    let (warm_accounts, warm_slots) = db_bop.warm_caches_from_recent_blocks(args.warm_cache_blocks)?;
+    info!(warm_accounts, warm_slots, blocks = args.warm_cache_blocks, "warmed db caches");
+
     let shared_state = SharedState::new(db_bop.clone().into());
     let head_block_number = db_bop.head_block_number().expect("couldn't get head block number");
     let start_fetch = if db_bop.head_block_hash().expect("couldn't get head block hash") == B256::ZERO {
@@ -67,61 +86,121 @@ fn run(args: GatewayArgs) -> eyre::Result<()> {
     let sequencer_config: SequencerConfig = (&args).into();
     let evm_config = sequencer_config.evm_config.clone();
 
-    std::thread::scope(|s| {
-        let rt: Arc<Runtime> = tokio::runtime::Builder::new_current_thread()
-            .worker_threads(10)
-            .enable_all()
-            .build()
-            .expect("failed to create runtime")
-            .into();
+    let rt: Arc<Runtime> = actors_config.runtime.build("rpc").expect("failed to create runtime").into();
+
+    let shutdown = Shutdown::new(Duration::from_millis(args.shutdown_deadline_ms));
+    shutdown.on_stage(Stage::Flush, {
+        let db = db_bop.clone();
+        move || {
+            if let Err(err) = db.flush() {
+                error!(%err, "couldn't flush db");
+            }
+        }
+    });
 
+    std::thread::scope(|s| {
         s.spawn({
             let rt = rt.clone();
-            start_rpc(&args, &spine, &rt);
-            move || rt.block_on(wait_for_signal())
+            start_rpc(
+                &args,
+                engine_jwt,
+                &spine,
+                db_bop.clone(),
+                shared_state.clone(),
+                gossip_signer.clone(),
+                archive.clone(),
+                evm_config.clone(),
+                &shutdown,
+                &rt,
+            );
+            let shutdown = shutdown.clone();
+            move || {
+                if let Err(err) = rt.block_on(wait_for_signal()) {
+                    error!(%err, "couldn't wait for signal");
+                }
+                shutdown.shutdown();
+            }
         });
 
         let state_clone = shared_state.clone();
-        s.spawn(|| {
-            Sequencer::new(db_bop, state_clone, sequencer_config)
-                .run(spine.to_connections("Sequencer"), ActorConfig::default());
-        });
+        let config = actors_config.actor("Sequencer", ActorConfig::default());
+        let stop = shutdown.register(Stage::Sequencer);
+        config
+            .thread("Sequencer")
+            .spawn_scoped(s, || {
+                Sequencer::new(db_bop, state_clone, sequencer_config).run(
+                    spine.to_connections("Sequencer"),
+                    config,
+                    stop,
+                );
+            })
+            .expect("failed to spawn sequencer thread");
 
         let fragdb_clone = shared_state.as_ref().clone();
+        let config =
+            actors_config.actor("BlockFetch", ActorConfig::default().with_min_loop_duration(Duration::from_millis(10)));
+        let stop = shutdown.register(Stage::Intake);
         if args.test {
-            s.spawn(|| {
-                MockFetcher::new(args.rpc_fallback_url, start_fetch, start_fetch + 100, fragdb_clone, Mode::Spammer)
-                    .run(
+            config
+                .thread("BlockFetch")
+                .spawn_scoped(s, || {
+                    MockFetcher::new(
+                        args.rpc_fallback_url,
+                        start_fetch,
+                        start_fetch + 100,
+                        fragdb_clone,
+                        Mode::Spammer,
+                    )
+                    .run(spine.to_connections("BlockFetch"), config, stop);
+                })
+                .expect("failed to spawn block fetch thread");
+        } else {
+            config
+                .thread("BlockFetch")
+                .spawn_scoped(s, || {
+                    let urls =
+                        std::iter::once(args.rpc_fallback_url.clone()).chain(args.rpc_fetch_urls.iter().cloned());
+                    let providers = BlockProviders::new(urls, args.rpc_fetch_max_attempts);
+                    BlockFetcher::new(providers, args.rpc_fetch_ws_url.clone(), (db_block, db_hash)).run(
                         spine.to_connections("BlockFetch"),
-                        ActorConfig::default().with_min_loop_duration(Duration::from_millis(10)),
+                        config,
+                        stop,
                     );
-            });
-        } else {
-            s.spawn(|| {
-                BlockFetcher::new(args.rpc_fallback_url, db_block).run(
-                    spine.to_connections("BlockFetch"),
-                    ActorConfig::default().with_min_loop_duration(Duration::from_millis(10)),
-                );
-            });
+                })
+                .expect("failed to spawn block fetch thread");
         }
+
         let root_peer_url = args.gossip_root_peer_url.clone();
-        s.spawn(|| {
-            Gossiper::new(root_peer_url).run(
-                spine.to_connections("Gossiper"),
-                ActorConfig::default().with_min_loop_duration(Duration::from_millis(10)),
-            );
-        });
+        let config =
+            actors_config.actor("Gossiper", ActorConfig::default().with_min_loop_duration(Duration::from_millis(10)));
+        let stop = shutdown.register(Stage::Gossip);
+        config
+            .thread("Gossiper")
+            .spawn_scoped(s, || {
+                Gossiper::new(root_peer_url, gossip_signer, archive, args.gossip_encoding).run(
+                    spine.to_connections("Gossiper"),
+                    config,
+                    stop,
+                );
+            })
+            .expect("failed to spawn gossiper thread");
 
         for id in 0..args.sim_threads {
-            s.spawn({
-                let evm_config = evm_config.clone();
-                let connections = spine.to_connections(format!("Simulator-{id}"));
-                let db_frag = (&shared_state).into();
-                move || {
-                    let simulator = Simulator::new(db_frag, &evm_config, id);
-                    simulator.run(connections, ActorConfig::default());
-                }
-            });
+            let name = format!("Simulator-{id}");
+            let config = actors_config.actor(&name, ActorConfig::default());
+            config
+                .thread(&name)
+                .spawn_scoped(s, {
+                    let evm_config = evm_config.clone();
+                    let connections = spine.to_connections(name.as_str());
+                    let db_frag = (&shared_state).into();
+                    let stop = shutdown.register(Stage::Simulators);
+                    move || {
+                        let simulator = Simulator::new(db_frag, &evm_config, id);
+                        simulator.run(connections, config, stop);
+                    }
+                })
+                .expect("failed to spawn simulator thread");
         }
     });
 
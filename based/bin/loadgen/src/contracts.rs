@@ -0,0 +1,201 @@
+//! Hand assembled contracts, so the load generator doesn't need a compiler.
+
+use alloy_primitives::{hex, Address, Bytes, U256};
+
+/// Token with only an ERC-20 `transfer(address,uint256)`, the selector isn't checked. The deployer gets a balance of
+/// `U256::MAX`, transfers revert if the sender's balance is too low.
+///
+/// Balances are stored at the slot equal to the holder's address.
+const TOKEN_RUNTIME: [u8; 86] = hex!(
+    "6024"   // 0x00 PUSH1 0x24
+    "35"     // 0x02 CALLDATALOAD          [amount]
+    "33"     // 0x03 CALLER
+    "54"     // 0x04 SLOAD                 [balance, amount]
+    "81"     // 0x05 DUP2
+    "81"     // 0x06 DUP2
+    "10"     // 0x07 LT                    [balance < amount, balance, amount]
+    "6051"   // 0x08 PUSH1 0x51
+    "57"     // 0x0a JUMPI
+    "03"     // 0x0b SUB                   [balance - amount]
+    "33"     // 0x0c CALLER
+    "55"     // 0x0d SSTORE
+    "6024"   // 0x0e PUSH1 0x24
+    "35"     // 0x10 CALLDATALOAD          [amount]
+    "6004"   // 0x11 PUSH1 0x04
+    "35"     // 0x13 CALLDATALOAD          [to, amount]
+    "80"     // 0x14 DUP1
+    "54"     // 0x15 SLOAD                 [balance_to, to, amount]
+    "82"     // 0x16 DUP3
+    "01"     // 0x17 ADD                   [balance_to + amount, to, amount]
+    "90"     // 0x18 SWAP1
+    "55"     // 0x19 SSTORE                [amount]
+    "6000"   // 0x1a PUSH1 0x00
+    "52"     // 0x1c MSTORE                memory[0..32] = amount
+    "6004"   // 0x1d PUSH1 0x04
+    "35"     // 0x1f CALLDATALOAD          [to]
+    "33"     // 0x20 CALLER                [from, to]
+    // 0x21 PUSH32 keccak256("Transfer(address,address,uint256)")
+    "7fddf252ad1be2c89b69c2b068fc378daa952ba7f163c4a11628f55a4df523b3ef"
+    "6020"   // 0x42 PUSH1 0x20
+    "6000"   // 0x44 PUSH1 0x00
+    "a3"     // 0x46 LOG3
+    "6001"   // 0x47 PUSH1 0x01
+    "6000"   // 0x49 PUSH1 0x00
+    "52"     // 0x4b MSTORE                memory[0..32] = true
+    "6020"   // 0x4c PUSH1 0x20
+    "6000"   // 0x4e PUSH1 0x00
+    "f3"     // 0x50 RETURN
+    "5b"     // 0x51 JUMPDEST
+    "6000"   // 0x52 PUSH1 0x00
+    "80"     // 0x54 DUP1
+    "fd"     // 0x55 REVERT
+);
+
+/// Stores the balance of the deployer before the runtime code is returned.
+const TOKEN_CONSTRUCTOR: [u8; 5] = hex!(
+    "6000"   // PUSH1 0x00
+    "19"     // NOT                        [U256::MAX]
+    "33"     // CALLER
+    "55"     // SSTORE
+);
+
+/// Writes the number of new storage slots given in the calldata. Slot 0 holds the number of slots written so far, slot
+/// `i` holds `i`.
+const STORAGE_RUNTIME: [u8; 32] = hex!(
+    "6000"   // 0x00 PUSH1 0x00
+    "35"     // 0x02 CALLDATALOAD          [n]
+    "6000"   // 0x03 PUSH1 0x00
+    "54"     // 0x05 SLOAD                 [written, n]
+    "80"     // 0x06 DUP1
+    "91"     // 0x07 SWAP2
+    "01"     // 0x08 ADD
+    "90"     // 0x09 SWAP1                 [i = written, end = written + n]
+    "5b"     // 0x0a JUMPDEST
+    "81"     // 0x0b DUP2
+    "81"     // 0x0c DUP2
+    "10"     // 0x0d LT
+    "15"     // 0x0e ISZERO                [i >= end, i, end]
+    "601b"   // 0x0f PUSH1 0x1b
+    "57"     // 0x11 JUMPI
+    "6001"   // 0x12 PUSH1 0x01
+    "01"     // 0x14 ADD                   [i + 1, end]
+    "80"     // 0x15 DUP1
+    "80"     // 0x16 DUP1
+    "55"     // 0x17 SSTORE                storage[i + 1] = i + 1
+    "600a"   // 0x18 PUSH1 0x0a
+    "56"     // 0x1a JUMP
+    "5b"     // 0x1b JUMPDEST
+    "6000"   // 0x1c PUSH1 0x00
+    "55"     // 0x1e SSTORE                storage[0] = end
+    "00"     // 0x1f STOP
+);
+
+/// Init code that runs `constructor` and deploys `runtime`.
+fn init_code(constructor: &[u8], runtime: &[u8]) -> Bytes {
+    // PUSH1 len, DUP1, PUSH1 offset, PUSH1 0, CODECOPY, PUSH1 0, RETURN
+    const COPY_LEN: usize = 11;
+    let offset = constructor.len() + COPY_LEN;
+    debug_assert!(runtime.len() <= u8::MAX as usize && offset <= u8::MAX as usize);
+
+    let mut code = constructor.to_vec();
+    code.extend_from_slice(&[0x60, runtime.len() as u8, 0x80, 0x60, offset as u8, 0x60, 0x00, 0x39, 0x60, 0x00, 0xf3]);
+    code.extend_from_slice(runtime);
+    code.into()
+}
+
+pub fn token_init_code() -> Bytes {
+    init_code(&TOKEN_CONSTRUCTOR, &TOKEN_RUNTIME)
+}
+
+pub fn storage_init_code() -> Bytes {
+    init_code(&[], &STORAGE_RUNTIME)
+}
+
+/// Calldata of `transfer(to, amount)`.
+pub fn transfer_calldata(to: Address, amount: U256) -> Bytes {
+    let mut data = hex!("a9059cbb").to_vec();
+    data.extend_from_slice(to.into_word().as_slice());
+    data.extend_from_slice(&amount.to_be_bytes::<32>());
+    data.into()
+}
+
+/// Calldata for the storage contract to write `slots` new slots.
+pub fn write_slots_calldata(slots: u64) -> Bytes {
+    U256::from(slots).to_be_bytes::<32>().to_vec().into()
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::address;
+    use revm::{
+        db::{CacheDB, EmptyDB},
+        primitives::{ExecutionResult, Output, TxKind},
+        Database, DatabaseCommit, Evm,
+    };
+
+    use super::*;
+
+    fn transact(db: &mut CacheDB<EmptyDB>, caller: Address, to: TxKind, data: Bytes) -> ExecutionResult {
+        let result = Evm::builder()
+            .with_db(&mut *db)
+            .modify_tx_env(|tx| {
+                tx.caller = caller;
+                tx.transact_to = to;
+                tx.data = data;
+                tx.gas_limit = 1_000_000;
+            })
+            .build()
+            .transact()
+            .unwrap();
+        db.commit(result.state);
+        result.result
+    }
+
+    fn deploy(db: &mut CacheDB<EmptyDB>, caller: Address, code: Bytes) -> Address {
+        match transact(db, caller, TxKind::Create, code) {
+            ExecutionResult::Success { output: Output::Create(_, Some(address)), .. } => address,
+            res => panic!("deployment failed: {res:?}"),
+        }
+    }
+
+    fn slot(address: Address) -> U256 {
+        U256::from_be_slice(address.as_slice())
+    }
+
+    #[test]
+    fn token_transfers() {
+        let mut db = CacheDB::new(EmptyDB::default());
+        let alice = address!("0000000000000000000000000000000000000a11");
+        let bob = address!("0000000000000000000000000000000000000b0b");
+        let token = deploy(&mut db, alice, token_init_code());
+        assert_eq!(db.storage(token, slot(alice)).unwrap(), U256::MAX);
+
+        let res = transact(&mut db, alice, TxKind::Call(token), transfer_calldata(bob, U256::from(5)));
+        assert!(res.is_success());
+        assert_eq!(res.logs().len(), 1);
+        assert_eq!(res.logs()[0].topics()[1], alice.into_word());
+        assert_eq!(res.logs()[0].topics()[2], bob.into_word());
+        assert_eq!(db.storage(token, slot(alice)).unwrap(), U256::MAX - U256::from(5));
+        assert_eq!(db.storage(token, slot(bob)).unwrap(), U256::from(5));
+
+        let res = transact(&mut db, bob, TxKind::Call(token), transfer_calldata(alice, U256::from(6)));
+        assert!(matches!(res, ExecutionResult::Revert { .. }));
+        assert_eq!(db.storage(token, slot(bob)).unwrap(), U256::from(5));
+    }
+
+    #[test]
+    fn storage_writes_new_slots() {
+        let mut db = CacheDB::new(EmptyDB::default());
+        let alice = address!("0000000000000000000000000000000000000a11");
+        let contract = deploy(&mut db, alice, storage_init_code());
+
+        assert!(transact(&mut db, alice, TxKind::Call(contract), write_slots_calldata(3)).is_success());
+        assert!(transact(&mut db, alice, TxKind::Call(contract), write_slots_calldata(2)).is_success());
+
+        assert_eq!(db.storage(contract, U256::ZERO).unwrap(), U256::from(5));
+        for i in 1..=5u64 {
+            assert_eq!(db.storage(contract, U256::from(i)).unwrap(), U256::from(i));
+        }
+        assert_eq!(db.storage(contract, U256::from(6)).unwrap(), U256::ZERO);
+    }
+}
@@ -0,0 +1,564 @@
+use std::{
+    collections::BTreeMap,
+    fs::File,
+    io::{BufWriter, Write},
+    path::PathBuf,
+};
+
+use alloy_consensus::TxEip1559;
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::{Address, Bytes, TxKind, B256, U256};
+use alloy_provider::{network::ReceiptResponse, Provider as _, ProviderBuilder, RootProvider};
+use alloy_transport_http::Http;
+use bop_common::{
+    signing::ECDSASigner,
+    time::{Duration, Instant},
+    utils::initialize_test_tracing,
+};
+use clap::Parser;
+use mix::{Kind, Mix};
+use op_alloy_consensus::OpTxEnvelope;
+use op_alloy_network::Optimism;
+use rand::Rng;
+use reqwest::Url;
+use serde::Serialize;
+use tokio::{sync::mpsc, task::JoinSet, time::MissedTickBehavior};
+use tracing::{info, level_filters::LevelFilter, warn};
+
+mod contracts;
+mod mix;
+
+type Provider = RootProvider<Http<reqwest::Client>, Optimism>;
+
+/// Token balance every account gets, each token transfer sends one unit.
+const TOKEN_FUNDING: u64 = 1_000_000;
+
+/// Sends a mix of transactions at a target rate to a gateway or portal, and measures the time from submission until a
+/// receipt is available.
+///
+/// A fresh set of accounts is funded from `--private_key` before the load starts, together with the contracts the mix
+/// needs.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// URL transactions are sent to
+    #[arg(long)]
+    rpc_url: Url,
+
+    /// URL nonces, balances and receipts are read from, defaults to `rpc_url`
+    #[arg(long)]
+    read_url: Option<Url>,
+
+    /// Key of the account funding the load accounts
+    #[arg(
+        long,
+        env = "LOADGEN_PRIVATE_KEY",
+        default_value = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80"
+    )]
+    private_key: B256,
+
+    /// Number of accounts sending transactions
+    #[arg(long, default_value_t = 100)]
+    accounts: usize,
+
+    /// Wei sent to each account
+    #[arg(long, default_value = "100000000000000000")]
+    fund_wei: U256,
+
+    /// Target transactions per second. Nonce gaps and replacements count as one
+    #[arg(long, default_value_t = 100.0)]
+    tps: f64,
+
+    /// How long to send transactions for
+    #[arg(long, default_value_t = 60)]
+    duration_secs: u64,
+
+    /// Relative weights of the kinds of transactions, out of transfer, erc20, deploy, storage, revert, nonce_gap and
+    /// replace
+    #[arg(long, default_value = "transfer=40,erc20=20,deploy=5,storage=10,revert=5,nonce_gap=10,replace=10")]
+    mix: Mix,
+
+    /// Storage slots written by each storage transaction
+    #[arg(long, default_value_t = 10)]
+    storage_slots: u64,
+
+    /// Max fee per gas, defaults to twice `eth_gasPrice`
+    #[arg(long)]
+    max_fee_per_gas: Option<u128>,
+
+    /// Max priority fee per gas
+    #[arg(long, default_value_t = 1_000)]
+    max_priority_fee_per_gas: u128,
+
+    /// How often to poll for each receipt
+    #[arg(long, default_value_t = 10)]
+    poll_interval_ms: u64,
+
+    /// How long to wait for a receipt before counting a transaction as timed out
+    #[arg(long, default_value_t = 30)]
+    receipt_timeout_secs: u64,
+
+    /// Path to write a json line per transaction to
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+struct Account {
+    signer: ECDSASigner,
+    nonce: u64,
+    /// Set when a transaction was rejected, the nonce is refetched before the next one is sent
+    resync: bool,
+}
+
+/// Signs transactions with the fees and contracts of the run.
+struct TxBuilder {
+    chain_id: u64,
+    max_fee_per_gas: u128,
+    max_priority_fee_per_gas: u128,
+    token: Address,
+    storage: Address,
+    storage_slots: u64,
+}
+
+/// Transactions sent together in order.
+struct Planned {
+    kind: Kind,
+    account: usize,
+    raw: Vec<Bytes>,
+    /// Each group is confirmed once one of its hashes has a receipt, the transaction is done once all groups are
+    /// confirmed
+    confirm: Vec<Vec<B256>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+enum Status {
+    Included,
+    Reverted,
+    Rejected,
+    TimedOut,
+}
+
+#[derive(Debug, Serialize)]
+struct TxRecord {
+    kind: Kind,
+    #[serde(skip)]
+    account: usize,
+    hashes: Vec<B256>,
+    /// Time since the start of the load
+    sent_ms: f64,
+    /// Time from submission until the last receipt
+    latency_ms: Option<f64>,
+    status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+fn sign(signer: &ECDSASigner, tx: TxEip1559) -> (B256, Bytes) {
+    let tx = OpTxEnvelope::Eip1559(signer.sign_tx(tx).expect("signing with a valid key"));
+    (tx.tx_hash(), tx.encoded_2718().into())
+}
+
+impl TxBuilder {
+    fn tx(&self, nonce: u64, to: TxKind, value: U256, input: Bytes, gas_limit: u64) -> TxEip1559 {
+        TxEip1559 {
+            chain_id: self.chain_id,
+            nonce,
+            gas_limit,
+            max_fee_per_gas: self.max_fee_per_gas,
+            max_priority_fee_per_gas: self.max_priority_fee_per_gas,
+            to,
+            value,
+            input,
+            ..Default::default()
+        }
+    }
+
+    /// Signs the next transaction of `from`.
+    fn next(&self, from: &mut Account, to: TxKind, value: U256, input: Bytes, gas_limit: u64) -> (B256, Bytes) {
+        let tx = sign(&from.signer, self.tx(from.nonce, to, value, input, gas_limit));
+        from.nonce += 1;
+        tx
+    }
+
+    fn plan(&self, kind: Kind, account: usize, from: &mut Account, to: Address) -> Planned {
+        let transfer = TxKind::Call(to);
+        let token = TxKind::Call(self.token);
+        let txs = match kind {
+            Kind::Transfer => vec![self.next(from, transfer, U256::from(1), Bytes::new(), 21_000)],
+            Kind::Erc20 => {
+                vec![self.next(from, token, U256::ZERO, contracts::transfer_calldata(to, U256::from(1)), 100_000)]
+            }
+            Kind::Deploy => {
+                vec![self.next(from, TxKind::Create, U256::ZERO, contracts::storage_init_code(), 200_000)]
+            }
+            Kind::Storage => {
+                let input = contracts::write_slots_calldata(self.storage_slots);
+                vec![self.next(
+                    from,
+                    TxKind::Call(self.storage),
+                    U256::ZERO,
+                    input,
+                    50_000 + 25_000 * self.storage_slots,
+                )]
+            }
+            Kind::Revert => {
+                vec![self.next(from, token, U256::ZERO, contracts::transfer_calldata(to, U256::MAX), 100_000)]
+            }
+            Kind::NonceGap => {
+                let first = self.next(from, transfer, U256::from(1), Bytes::new(), 21_000);
+                let second = self.next(from, transfer, U256::from(1), Bytes::new(), 21_000);
+                vec![second, first]
+            }
+            Kind::Replace => {
+                let tx = self.tx(from.nonce, transfer, U256::from(1), Bytes::new(), 21_000);
+                let bumped = TxEip1559 {
+                    value: U256::from(2),
+                    max_fee_per_gas: tx.max_fee_per_gas * 2,
+                    max_priority_fee_per_gas: tx.max_priority_fee_per_gas * 2,
+                    ..tx.clone()
+                };
+                let (original, replacement) = (sign(&from.signer, tx), sign(&from.signer, bumped));
+                from.nonce += 1;
+                return Planned {
+                    kind,
+                    account,
+                    raw: vec![original.1, replacement.1],
+                    confirm: vec![vec![original.0, replacement.0]],
+                };
+            }
+        };
+
+        Planned {
+            kind,
+            account,
+            confirm: txs.iter().map(|(hash, _)| vec![*hash]).collect(),
+            raw: txs.into_iter().map(|(_, raw)| raw).collect(),
+        }
+    }
+}
+
+/// Sends the transactions of `planned` and polls for their receipts.
+async fn submit(
+    send: Provider,
+    read: Provider,
+    planned: Planned,
+    started: Instant,
+    poll_interval: Duration,
+    timeout: Duration,
+) -> TxRecord {
+    let mut record = TxRecord {
+        kind: planned.kind,
+        account: planned.account,
+        hashes: planned.confirm.iter().flatten().copied().collect(),
+        sent_ms: started.elapsed().as_millis(),
+        latency_ms: None,
+        status: Status::Included,
+        error: None,
+    };
+
+    let sent = Instant::now();
+    // Everything is sent even if something is rejected, so the nonces of the account stay contiguous where possible
+    for raw in &planned.raw {
+        if let Err(err) = send.send_raw_transaction(raw).await {
+            record.status = Status::Rejected;
+            record.error.get_or_insert_with(|| err.to_string());
+        }
+    }
+    if record.status == Status::Rejected {
+        return record;
+    }
+
+    let mut pending = planned.confirm;
+    while !pending.is_empty() {
+        let mut confirmed = vec![];
+        for (i, group) in pending.iter().enumerate() {
+            for hash in group {
+                match read.get_transaction_receipt(*hash).await {
+                    Ok(Some(receipt)) => {
+                        if !receipt.status() {
+                            record.status = Status::Reverted;
+                        }
+                        confirmed.push(i);
+                        break;
+                    }
+                    Ok(None) => {}
+                    Err(err) => warn!(%hash, %err, "failed to fetch receipt"),
+                }
+            }
+        }
+        for i in confirmed.into_iter().rev() {
+            pending.swap_remove(i);
+        }
+
+        if pending.is_empty() {
+            record.latency_ms = Some(sent.elapsed().as_millis());
+        } else if sent.elapsed() > timeout {
+            record.status = Status::TimedOut;
+            break;
+        } else {
+            tokio::time::sleep(poll_interval.into()).await;
+        }
+    }
+    record
+}
+
+#[derive(Default)]
+struct KindStats {
+    sent: usize,
+    included: usize,
+    reverted: usize,
+    rejected: usize,
+    timed_out: usize,
+    /// Included or reverted, but not as expected for the kind
+    unexpected: usize,
+    latencies_ms: Vec<f64>,
+}
+
+impl KindStats {
+    fn record(&mut self, record: &TxRecord) {
+        match record.status {
+            Status::Included => self.included += 1,
+            Status::Reverted => self.reverted += 1,
+            Status::Rejected => self.rejected += 1,
+            Status::TimedOut => self.timed_out += 1,
+        }
+        if matches!(record.status, Status::Included | Status::Reverted) &&
+            (record.status == Status::Included) != record.kind.succeeds()
+        {
+            self.unexpected += 1;
+        }
+        self.latencies_ms.extend(record.latency_ms);
+    }
+
+    fn done(&self) -> usize {
+        self.included + self.reverted + self.rejected + self.timed_out
+    }
+
+    fn merge(&mut self, other: &KindStats) {
+        self.sent += other.sent;
+        self.included += other.included;
+        self.reverted += other.reverted;
+        self.rejected += other.rejected;
+        self.timed_out += other.timed_out;
+        self.unexpected += other.unexpected;
+        self.latencies_ms.extend_from_slice(&other.latencies_ms);
+    }
+
+    fn summary(&self) -> String {
+        let mut latencies = self.latencies_ms.clone();
+        latencies.sort_by(f64::total_cmp);
+        format!(
+            "sent {}, included {}, reverted {}, rejected {}, timed out {}, unexpected status {}, latency ms p50 {:.1} \
+             p90 {:.1} p99 {:.1} max {:.1}",
+            self.sent,
+            self.included,
+            self.reverted,
+            self.rejected,
+            self.timed_out,
+            self.unexpected,
+            percentile(&latencies, 0.5),
+            percentile(&latencies, 0.9),
+            percentile(&latencies, 0.99),
+            latencies.last().copied().unwrap_or_default(),
+        )
+    }
+}
+
+fn percentile(sorted: &[f64], p: f64) -> f64 {
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+/// Sends `txs` from the funder and waits until all of them are included.
+async fn send_and_wait(args: &Args, send: &Provider, read: &Provider, txs: Vec<(B256, Bytes)>) -> eyre::Result<()> {
+    let started = Instant::now();
+    let mut set = JoinSet::new();
+    for (hash, raw) in txs {
+        let planned = Planned { kind: Kind::Transfer, account: 0, raw: vec![raw], confirm: vec![vec![hash]] };
+        set.spawn(submit(
+            send.clone(),
+            read.clone(),
+            planned,
+            started,
+            Duration::from_millis(args.poll_interval_ms),
+            Duration::from_secs(args.receipt_timeout_secs),
+        ));
+    }
+    while let Some(record) = set.join_next().await {
+        let record = record?;
+        eyre::ensure!(
+            record.status == Status::Included,
+            "funding transaction {} is {:?}: {}",
+            record.hashes[0],
+            record.status,
+            record.error.unwrap_or_default()
+        );
+    }
+    Ok(())
+}
+
+/// Deploys the contracts and funds the accounts.
+async fn setup(args: &Args, send: &Provider, read: &Provider) -> eyre::Result<(TxBuilder, Vec<Account>)> {
+    let chain_id = read.get_chain_id().await?;
+    let max_fee_per_gas = match args.max_fee_per_gas {
+        Some(max_fee) => max_fee,
+        None => read.get_gas_price().await? * 2,
+    };
+
+    let funder = ECDSASigner::try_from_secret(args.private_key.as_slice())?;
+    let balance = read.get_balance(funder.address).await?;
+    let needed = args.fund_wei * U256::from(args.accounts);
+    eyre::ensure!(balance > needed, "funder {} has {balance} wei, needs more than {needed}", funder.address);
+
+    let mut funder =
+        Account { nonce: read.get_transaction_count(funder.address).await?, signer: funder, resync: false };
+    let mut builder = TxBuilder {
+        chain_id,
+        max_fee_per_gas,
+        max_priority_fee_per_gas: args.max_priority_fee_per_gas,
+        token: Address::ZERO,
+        storage: Address::ZERO,
+        storage_slots: args.storage_slots,
+    };
+    info!(chain_id, max_fee_per_gas, funder = %funder.signer.address, "funding {} accounts", args.accounts);
+
+    let uses_token = args.mix.contains(Kind::Erc20) || args.mix.contains(Kind::Revert);
+    let mut txs = vec![];
+    if uses_token {
+        builder.token = funder.signer.address.create(funder.nonce);
+        txs.push(builder.next(&mut funder, TxKind::Create, U256::ZERO, contracts::token_init_code(), 200_000));
+    }
+    if args.mix.contains(Kind::Storage) {
+        builder.storage = funder.signer.address.create(funder.nonce);
+        txs.push(builder.next(&mut funder, TxKind::Create, U256::ZERO, contracts::storage_init_code(), 200_000));
+    }
+    send_and_wait(args, send, read, txs).await?;
+
+    let accounts: Vec<_> =
+        (0..args.accounts).map(|_| Account { signer: ECDSASigner::random(), nonce: 0, resync: false }).collect();
+    let mut txs = vec![];
+    for account in &accounts {
+        let to = account.signer.address;
+        txs.push(builder.next(&mut funder, TxKind::Call(to), args.fund_wei, Bytes::new(), 21_000));
+        if uses_token {
+            let input = contracts::transfer_calldata(to, U256::from(TOKEN_FUNDING));
+            txs.push(builder.next(&mut funder, TxKind::Call(builder.token), U256::ZERO, input, 100_000));
+        }
+    }
+    send_and_wait(args, send, read, txs).await?;
+
+    Ok((builder, accounts))
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    initialize_test_tracing(LevelFilter::INFO);
+    eyre::ensure!(args.accounts > 0 && args.tps > 0.0, "need at least one account and a positive tps");
+
+    let send: Provider = ProviderBuilder::new().network().on_http(args.rpc_url.clone());
+    let read: Provider =
+        ProviderBuilder::new().network().on_http(args.read_url.clone().unwrap_or(args.rpc_url.clone()));
+    let mut output = args.output.as_ref().map(File::create).transpose()?.map(BufWriter::new);
+
+    let (builder, mut accounts) = setup(&args, &send, &read).await?;
+    info!(tps = args.tps, duration_secs = args.duration_secs, mix = ?args.mix, "starting load");
+
+    let (records_tx, mut records_rx) = mpsc::unbounded_channel();
+    let mut stats: BTreeMap<Kind, KindStats> = BTreeMap::new();
+    let mut in_flight = 0usize;
+    let mut next_account = 0;
+
+    let started = Instant::now();
+    let duration = Duration::from_secs(args.duration_secs);
+    let mut ticks = tokio::time::interval(std::time::Duration::from_secs_f64(1.0 / args.tps));
+    ticks.set_missed_tick_behavior(MissedTickBehavior::Burst);
+    let mut reports = tokio::time::interval(std::time::Duration::from_secs(1));
+    reports.set_missed_tick_behavior(MissedTickBehavior::Skip);
+    let mut sending = true;
+
+    while sending || in_flight > 0 {
+        tokio::select! {
+            _ = ticks.tick(), if sending => {
+                if started.elapsed() >= duration {
+                    sending = false;
+                    continue;
+                }
+
+                let i = next_account;
+                next_account = (next_account + 1) % accounts.len();
+                let (kind, to) = {
+                    let mut rng = rand::rng();
+                    (args.mix.sample(&mut rng), accounts[rng.random_range(0..accounts.len())].signer.address)
+                };
+
+                let account = &mut accounts[i];
+                if account.resync {
+                    match read.get_transaction_count(account.signer.address).await {
+                        Ok(nonce) => {
+                            account.nonce = nonce;
+                            account.resync = false;
+                        }
+                        Err(err) => warn!(account = %account.signer.address, %err, "failed to resync nonce"),
+                    }
+                }
+
+                let planned = builder.plan(kind, i, account, to);
+                stats.entry(kind).or_default().sent += 1;
+                in_flight += 1;
+                let fut = submit(
+                    send.clone(),
+                    read.clone(),
+                    planned,
+                    started,
+                    Duration::from_millis(args.poll_interval_ms),
+                    Duration::from_secs(args.receipt_timeout_secs),
+                );
+                let records_tx = records_tx.clone();
+                tokio::spawn(async move {
+                    let _ = records_tx.send(fut.await);
+                });
+            }
+
+            Some(record) = records_rx.recv() => {
+                in_flight -= 1;
+                if record.status == Status::Rejected {
+                    accounts[record.account].resync = true;
+                }
+                stats.entry(record.kind).or_default().record(&record);
+                if let Some(output) = output.as_mut() {
+                    serde_json::to_writer(&mut *output, &record)?;
+                    writeln!(output)?;
+                }
+            }
+
+            _ = reports.tick() => {
+                let mut total = KindStats::default();
+                stats.values().for_each(|s| total.merge(s));
+                let elapsed = started.elapsed().as_secs();
+                info!(
+                    "{:.0}s: {:.1} tps, {in_flight} in flight, {}",
+                    elapsed,
+                    total.sent as f64 / elapsed.max(1.0),
+                    total.summary()
+                );
+                debug_assert_eq!(total.sent - total.done(), in_flight);
+            }
+        }
+    }
+
+    if let Some(output) = output.as_mut() {
+        output.flush()?;
+    }
+
+    let mut total = KindStats::default();
+    for (kind, kind_stats) in &stats {
+        println!("{kind}: {}", kind_stats.summary());
+        total.merge(kind_stats);
+    }
+    println!("total: {}", total.summary());
+    Ok(())
+}
@@ -0,0 +1,126 @@
+use std::{fmt, str::FromStr};
+
+use rand::Rng;
+use serde::Serialize;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Kind {
+    /// ETH transfer to another account
+    Transfer,
+    /// Token transfer to another account
+    Erc20,
+    /// Deployment of the storage contract
+    Deploy,
+    /// Call writing new slots of the storage contract
+    Storage,
+    /// Token transfer of more than the sender's balance
+    Revert,
+    /// Two transfers, the one with the higher nonce is sent first
+    NonceGap,
+    /// Transfer that is replaced right away by one with the same nonce and a higher fee
+    Replace,
+}
+
+impl Kind {
+    pub const ALL: [Kind; 7] =
+        [Kind::Transfer, Kind::Erc20, Kind::Deploy, Kind::Storage, Kind::Revert, Kind::NonceGap, Kind::Replace];
+
+    fn name(&self) -> &'static str {
+        match self {
+            Kind::Transfer => "transfer",
+            Kind::Erc20 => "erc20",
+            Kind::Deploy => "deploy",
+            Kind::Storage => "storage",
+            Kind::Revert => "revert",
+            Kind::NonceGap => "nonce_gap",
+            Kind::Replace => "replace",
+        }
+    }
+
+    /// Whether the transaction that ends up on chain succeeds.
+    pub fn succeeds(&self) -> bool {
+        *self != Kind::Revert
+    }
+}
+
+impl fmt::Display for Kind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+/// Relative weights of each kind of transaction, parsed from e.g. `transfer=60,erc20=30,revert=10`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mix {
+    weights: Vec<(Kind, u32)>,
+    total: u32,
+}
+
+impl Mix {
+    pub fn sample(&self, rng: &mut impl Rng) -> Kind {
+        let mut pick = rng.random_range(0..self.total);
+        for (kind, weight) in &self.weights {
+            if pick < *weight {
+                return *kind;
+            }
+            pick -= weight;
+        }
+        unreachable!("pick is below the total weight")
+    }
+
+    pub fn contains(&self, kind: Kind) -> bool {
+        self.weights.iter().any(|(k, _)| *k == kind)
+    }
+}
+
+impl FromStr for Mix {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut weights = Vec::new();
+        for entry in s.split(',').map(str::trim).filter(|entry| !entry.is_empty()) {
+            let (name, weight) = entry.split_once('=').ok_or_else(|| format!("expected kind=weight, got {entry}"))?;
+            let kind = Kind::ALL
+                .into_iter()
+                .find(|kind| kind.name() == name.trim())
+                .ok_or_else(|| format!("unknown transaction kind {name}"))?;
+            let weight: u32 = weight.trim().parse().map_err(|e| format!("invalid weight for {name}: {e}"))?;
+            if weights.iter().any(|(k, _)| *k == kind) {
+                return Err(format!("{name} is given twice"));
+            }
+            if weight > 0 {
+                weights.push((kind, weight));
+            }
+        }
+
+        let total = weights.iter().map(|(_, weight)| weight).sum();
+        if total == 0 {
+            return Err("mix needs at least one kind with a non-zero weight".into());
+        }
+        Ok(Self { weights, total })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_mix() {
+        let mix: Mix = "transfer=60, erc20=30,revert=10,deploy=0".parse().unwrap();
+        assert_eq!(mix.total, 100);
+        assert!(mix.contains(Kind::Erc20));
+        assert!(!mix.contains(Kind::Deploy));
+
+        let mut rng = rand::rng();
+        for _ in 0..100 {
+            assert!(matches!(mix.sample(&mut rng), Kind::Transfer | Kind::Erc20 | Kind::Revert));
+        }
+
+        assert!("transfer=1,transfer=2".parse::<Mix>().is_err());
+        assert!("swap=1".parse::<Mix>().is_err());
+        assert!("transfer=0".parse::<Mix>().is_err());
+        assert!("transfer".parse::<Mix>().is_err());
+    }
+}
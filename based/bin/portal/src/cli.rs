@@ -18,6 +18,10 @@ pub struct PortalArgs {
     #[arg(long = "portal.port", default_value_t = 8080)]
     pub portal_port: u16,
 
+    /// Port to serve Prometheus metrics on, on the portal host. Disabled if not set
+    #[arg(long = "metrics.port")]
+    pub metrics_port: Option<u16>,
+
     /// TEMP: the URL to the fallback EthAPI
     #[arg(long = "fallback.eth_url")]
     pub fallback_eth_url: Url,
@@ -63,12 +67,33 @@ pub struct PortalArgs {
     pub trace: bool,
 
     /// TEMP: Fetch 30s from this url a list of gateway urls
-    #[arg(long = "gateway.update_url")]
+    #[arg(long = "gateway.update_url", conflicts_with_all = ["gateway_schedule_path", "gateway_schedule_url"])]
     pub gateway_update_url: Option<Url>,
 
+    /// Path to a sequencer schedule, see `schedule.example.json`. Reloaded every 30s. When set, blocks are only
+    /// handed to the scheduled gateway instead of rotating on `gateway.update_interval_sec`
+    #[arg(long = "gateway.schedule_path", conflicts_with = "gateway_schedule_url")]
+    pub gateway_schedule_path: Option<PathBuf>,
+
+    /// Url to fetch the sequencer schedule from every 30s, same format as `gateway.schedule_path`
+    #[arg(long = "gateway.schedule_url", conflicts_with = "gateway_schedule_path")]
+    pub gateway_schedule_url: Option<Url>,
+
     /// The interval to update the gateway urls in seconds
     #[arg(long = "gateway.update_interval_sec", default_value_t = 60)]
     pub gateway_update_interval_sec: u64,
+
+    /// Interval between gateway health checks in milliseconds
+    #[arg(long = "gateway.health_interval_ms", default_value_t = 1_000)]
+    pub gateway_health_interval_ms: u64,
+
+    /// Maximum number of blocks a gateway can be behind the fallback and still be handed blocks
+    #[arg(long = "gateway.max_lag", default_value_t = 2)]
+    pub gateway_max_lag: u64,
+
+    /// How long an unhealthy gateway is ejected for before it can be re-admitted, in seconds
+    #[arg(long = "gateway.cooldown_sec", default_value_t = 30)]
+    pub gateway_cooldown_sec: u64,
 }
 
 impl PortalArgs {
@@ -0,0 +1,214 @@
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use alloy_primitives::B256;
+use bop_common::metrics::{PORTAL_GATEWAY_EJECTIONS, PORTAL_HEALTHY_GATEWAYS};
+use metrics::{counter, gauge};
+use parking_lot::RwLock;
+use reqwest::Url;
+use tracing::{info, warn};
+
+/// Why a gateway was ejected.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum HealthIssue {
+    /// The gateway didn't answer, or answered with an error.
+    Unreachable,
+    /// The gateway head is too far behind the fallback head.
+    Syncing { behind: u64 },
+    /// The gateway and the fallback disagree on the hash of this block.
+    HeadMismatch { number: u64 },
+    /// The last payload built by the gateway was rejected by the fallback.
+    InvalidPayload,
+}
+
+impl HealthIssue {
+    fn label(&self) -> &'static str {
+        match self {
+            HealthIssue::Unreachable => "unreachable",
+            HealthIssue::Syncing { .. } => "syncing",
+            HealthIssue::HeadMismatch { .. } => "head_mismatch",
+            HealthIssue::InvalidPayload => "invalid_payload",
+        }
+    }
+}
+
+impl fmt::Display for HealthIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            HealthIssue::Unreachable => write!(f, "unreachable"),
+            HealthIssue::Syncing { behind } => write!(f, "syncing, {behind} blocks behind"),
+            HealthIssue::HeadMismatch { number } => write!(f, "head mismatch at block {number}"),
+            HealthIssue::InvalidPayload => write!(f, "invalid payload"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct HealthConfig {
+    /// Maximum number of blocks a gateway can be behind the fallback and still be handed blocks.
+    pub max_lag: u64,
+    /// How long an ejected gateway is kept out of rotation before it can be re-admitted.
+    pub cooldown: Duration,
+    pub check_interval: Duration,
+}
+
+#[derive(Debug, Default)]
+struct GatewayHealth {
+    /// Passed a health check since it was added or re-admitted.
+    admitted: bool,
+    ejected_until: Option<Instant>,
+    last_issue: Option<HealthIssue>,
+}
+
+/// Health of the known gateways, keyed by url so it survives refreshes of the gateway list.
+///
+/// Gateways start out not admitted and only become eligible for blocks after passing a health check. Any failed check,
+/// or a bad payload, ejects the gateway for the cooldown period, after which the next passing check re-admits it.
+#[derive(Debug)]
+pub struct HealthRegistry {
+    config: HealthConfig,
+    gateways: RwLock<HashMap<Url, GatewayHealth>>,
+}
+
+impl HealthRegistry {
+    pub fn new(config: HealthConfig) -> Self {
+        Self { config, gateways: RwLock::new(HashMap::new()) }
+    }
+
+    pub fn config(&self) -> &HealthConfig {
+        &self.config
+    }
+
+    /// Whether the gateway can be picked to build blocks or serve reads.
+    pub fn is_eligible(&self, url: &Url) -> bool {
+        self.gateways.read().get(url).is_some_and(|health| health.admitted && health.ejected_until.is_none())
+    }
+
+    pub fn report_ok(&self, url: &Url, now: Instant) {
+        let mut gateways = self.gateways.write();
+        let health = gateways.entry(url.clone()).or_default();
+
+        match health.ejected_until {
+            Some(until) if now < until => {}
+            Some(_) => {
+                info!(gateway = %url, last_issue = ?health.last_issue, "re-admitting gateway");
+                health.ejected_until = None;
+                health.admitted = true;
+            }
+            None if !health.admitted => {
+                info!(gateway = %url, "admitting gateway");
+                health.admitted = true;
+            }
+            None => {}
+        }
+    }
+
+    pub fn report_issue(&self, url: &Url, issue: HealthIssue, now: Instant) {
+        let mut gateways = self.gateways.write();
+        let health = gateways.entry(url.clone()).or_default();
+
+        if health.ejected_until.is_none() {
+            warn!(gateway = %url, %issue, cooldown = ?self.config.cooldown, "ejecting gateway");
+            counter!(PORTAL_GATEWAY_EJECTIONS, "gateway" => url.to_string(), "reason" => issue.label()).increment(1);
+        }
+
+        // Keep extending the cooldown while the gateway keeps failing.
+        health.admitted = false;
+        health.ejected_until = Some(now + self.config.cooldown);
+        health.last_issue = Some(issue);
+    }
+
+    /// Drops the state of gateways that are no longer configured and updates the healthy gateways gauge.
+    pub fn retain<'a>(&self, urls: impl IntoIterator<Item = &'a Url>) {
+        let mut gateways = self.gateways.write();
+        let mut kept = HashMap::with_capacity(gateways.len());
+        for url in urls {
+            if let Some(health) = gateways.remove(url) {
+                kept.insert(url.clone(), health);
+            }
+        }
+        *gateways = kept;
+
+        let healthy = gateways.values().filter(|health| health.admitted && health.ejected_until.is_none()).count();
+        gauge!(PORTAL_HEALTHY_GATEWAYS).set(healthy as f64);
+    }
+}
+
+/// Checks a gateway head against the fallback.
+///
+/// `fallback_hash` is the fallback's hash at `min(gateway_number, fallback_number)`, `gateway_hash` the gateway's hash
+/// at that same height, since a healthy gateway can be a block ahead of the fallback with a block it just built.
+pub fn check_head(
+    gateway_number: u64,
+    fallback_number: u64,
+    gateway_hash: Option<B256>,
+    fallback_hash: Option<B256>,
+    max_lag: u64,
+) -> Result<(), HealthIssue> {
+    let behind = fallback_number.saturating_sub(gateway_number);
+    if behind > max_lag {
+        return Err(HealthIssue::Syncing { behind });
+    }
+
+    match (gateway_hash, fallback_hash) {
+        (Some(gateway_hash), Some(fallback_hash)) if gateway_hash != fallback_hash => {
+            Err(HealthIssue::HeadMismatch { number: gateway_number.min(fallback_number) })
+        }
+        (None, _) => Err(HealthIssue::Unreachable),
+        // The fallback not having the block isn't the gateway's fault
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn registry() -> HealthRegistry {
+        HealthRegistry::new(HealthConfig {
+            max_lag: 2,
+            cooldown: Duration::from_secs(10),
+            check_interval: Duration::from_secs(1),
+        })
+    }
+
+    #[test]
+    fn ejects_and_readmits_after_cooldown() {
+        let registry = registry();
+        let url: Url = "http://127.0.0.1:1".parse().unwrap();
+        let now = Instant::now();
+
+        assert!(!registry.is_eligible(&url), "unchecked gateways are not eligible");
+        registry.report_ok(&url, now);
+        assert!(registry.is_eligible(&url));
+
+        registry.report_issue(&url, HealthIssue::InvalidPayload, now);
+        assert!(!registry.is_eligible(&url));
+
+        registry.report_ok(&url, now + Duration::from_secs(5));
+        assert!(!registry.is_eligible(&url), "still cooling down");
+
+        registry.report_ok(&url, now + Duration::from_secs(10));
+        assert!(registry.is_eligible(&url));
+
+        registry.retain(std::iter::empty::<&Url>());
+        assert!(!registry.is_eligible(&url), "removed gateways start over");
+    }
+
+    #[test]
+    fn checks_head_against_fallback() {
+        let a = Some(B256::with_last_byte(1));
+        let b = Some(B256::with_last_byte(2));
+
+        assert_eq!(check_head(10, 10, a, a, 2), Ok(()));
+        assert_eq!(check_head(11, 10, a, a, 2), Ok(()));
+        assert_eq!(check_head(8, 10, a, a, 2), Ok(()));
+        assert_eq!(check_head(7, 10, a, a, 2), Err(HealthIssue::Syncing { behind: 3 }));
+        assert_eq!(check_head(11, 10, a, b, 2), Err(HealthIssue::HeadMismatch { number: 10 }));
+        assert_eq!(check_head(10, 10, None, a, 2), Err(HealthIssue::Unreachable));
+        assert_eq!(check_head(10, 10, a, None, 2), Ok(()));
+    }
+}
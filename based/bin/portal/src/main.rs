@@ -1,12 +1,13 @@
 use std::net::{IpAddr, SocketAddr};
 
-use bop_common::utils::init_tracing;
+use bop_common::{metrics::init_prometheus, utils::init_tracing};
 use clap::Parser;
 use cli::PortalArgs;
 use server::PortalServer;
 use tracing::info;
 
 mod cli;
+mod health;
 mod middleware;
 mod server;
 
@@ -15,6 +16,12 @@ async fn main() -> eyre::Result<()> {
     let args = PortalArgs::parse();
     let _guard = init_tracing((&args).into());
 
+    if let Some(port) = args.metrics_port {
+        let metrics_addr = SocketAddr::new(IpAddr::V4(args.portal_host), port);
+        init_prometheus(metrics_addr)?;
+        info!(%metrics_addr, "serving metrics");
+    }
+
     let addr = SocketAddr::new(IpAddr::V4(args.portal_host), args.portal_port);
     let server = PortalServer::new(args.clone())?;
 
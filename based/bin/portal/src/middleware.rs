@@ -1,34 +1,172 @@
-use futures::{future::BoxFuture, FutureExt};
+use std::{sync::Arc, time::Duration};
+
+use futures::{
+    future::{select_ok, BoxFuture},
+    FutureExt,
+};
 use jsonrpsee::{
-    core::{client::ClientT, traits::ToRpcParams, TEN_MB_SIZE_BYTES},
+    core::{client::ClientT, traits::ToRpcParams, ClientError, TEN_MB_SIZE_BYTES},
     server::middleware::rpc::RpcServiceT,
     types::{
         error::{INTERNAL_ERROR_CODE, INTERNAL_ERROR_MSG},
-        ErrorObject, Params, Request, ResponsePayload,
+        ErrorObject, Request, ResponsePayload,
     },
     MethodResponse,
 };
+use parking_lot::{Mutex, RwLock};
 use serde_json::value::RawValue;
 use tracing::{debug, error};
 
-use crate::server::{AuthRpcClient, RpcClient};
+use crate::server::{AuthRpcClient, Gateway, RpcClient};
+
+/// How the portal serves a method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Route {
+    /// Served by the portal's own RPC modules.
+    Local,
+    /// Sent to the current gateway and the fallback, the gateway response is preferred.
+    GatewayFirst,
+    /// Only sent to the current gateway, fails if no gateway is healthy.
+    GatewayOnly,
+    /// Only sent to the fallback.
+    FallbackOnly,
+    /// Sent to the current gateway and the fallback, the first successful response is returned.
+    Race,
+    /// Sent to all gateways and the fallback, the fallback response is returned.
+    Broadcast,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RouteRule {
+    pub method: &'static str,
+    pub route: Route,
+    /// Overrides the default timeout, for the whole request including the fallback.
+    pub timeout: Option<Duration>,
+}
+
+const fn rule(method: &'static str, route: Route) -> RouteRule {
+    RouteRule { method, route, timeout: None }
+}
+
+const fn rule_with_timeout(method: &'static str, route: Route, timeout_ms: u64) -> RouteRule {
+    RouteRule { method, route, timeout: Some(Duration::from_millis(timeout_ms)) }
+}
+
+/// Routing table of the portal. Methods not listed here are forwarded to the fallback, `engine_` methods to the
+/// fallback engine API and everything else to the fallback eth API.
+pub const ROUTES: &[RouteRule] = &[
+    // block building is driven by the portal itself
+    rule("engine_forkchoiceUpdatedV3", Route::Local),
+    rule("engine_newPayloadV3", Route::Local),
+    rule("engine_getPayloadV3", Route::Local),
+    rule("engine_newPayloadV4", Route::Local),
+    rule("engine_getPayloadV4", Route::Local),
+    rule("based_currentSequencer", Route::Local),
+    rule("based_nextSequencer", Route::Local),
+    rule("based_sequencerLookahead", Route::Local),
+    // every gateway gets transactions, so whichever sequences next has them
+    rule("eth_sendRawTransaction", Route::Broadcast),
+    // only the gateway sequencing the block can commit to including it, waits until it's in a sealed frag. The
+    // timeout is above the gateway's default `rpc.commitment_timeout_ms` of 10s, so the gateway answers first
+    rule_with_timeout("based_sendRawTransactionWithCommitment", Route::GatewayOnly, 12_000),
+    // preconfirmed state is only known to the gateway
+    rule("eth_getTransactionCount", Route::GatewayFirst),
+    rule("eth_getBalance", Route::GatewayFirst),
+    rule("eth_getStorageAt", Route::GatewayFirst),
+    rule("eth_getCode", Route::GatewayFirst),
+    rule_with_timeout("eth_call", Route::GatewayFirst, 5_000),
+    rule("eth_chainId", Route::Race),
+    rule_with_timeout("eth_estimateGas", Route::FallbackOnly, 5_000),
+    rule_with_timeout("eth_getLogs", Route::FallbackOnly, 10_000),
+];
+
+pub fn route_for(routes: &[RouteRule], method: &str) -> (Route, Option<Duration>) {
+    routes
+        .iter()
+        .find(|rule| rule.method == method)
+        .map(|rule| (rule.route, rule.timeout))
+        .unwrap_or((Route::FallbackOnly, None))
+}
+
+/// Longest timeout in the routing table, clients need to allow at least this long.
+pub fn max_route_timeout(routes: &[RouteRule]) -> Duration {
+    routes.iter().filter_map(|rule| rule.timeout).max().unwrap_or_default()
+}
+
+/// Where the proxy sends requests.
+#[derive(Clone)]
+pub struct Upstreams {
+    pub fallback_eth_client: RpcClient,
+    pub fallback_client: AuthRpcClient,
+    /// Gateway currently building blocks, `None` if no gateway is healthy.
+    pub next_gateway: Arc<Mutex<Option<Gateway>>>,
+    pub gateways: Arc<RwLock<Vec<Gateway>>>,
+    pub default_timeout: Duration,
+}
+
+impl Upstreams {
+    async fn forward(
+        &self,
+        route: Route,
+        method: &str,
+        params: Option<String>,
+    ) -> Result<serde_json::Value, ClientError> {
+        let next_gateway = self.next_gateway.lock().clone();
+
+        match (route, next_gateway) {
+            (Route::GatewayFirst, Some(gateway)) => {
+                let (gateway, fallback) =
+                    tokio::join!(gateway.request(method, params.clone()), self.fallback(method, params));
+                gateway.or(fallback)
+            }
+
+            (Route::GatewayOnly, Some(gateway)) => gateway.request(method, params).await,
+            (Route::GatewayOnly, None) => Err(ClientError::Custom("no healthy gateway".to_string())),
 
+            (Route::Race, Some(gateway)) => {
+                let requests = [gateway.request(method, params.clone()).boxed(), self.fallback(method, params).boxed()];
+                select_ok(requests).await.map(|(response, _)| response)
+            }
+
+            (Route::Broadcast, _) => {
+                let gateways = self.gateways.read().clone();
+                for gateway in gateways {
+                    let method = method.to_string();
+                    let params = params.clone();
+                    tokio::spawn(async move {
+                        if let Err(err) = gateway.request(&method, params).await {
+                            error!(%err, ?gateway, method, "failed to send to gateway");
+                        }
+                    });
+                }
+                self.fallback(method, params).await
+            }
+
+            _ => self.fallback(method, params).await,
+        }
+    }
+
+    async fn fallback(&self, method: &str, params: Option<String>) -> Result<serde_json::Value, ClientError> {
+        if method.starts_with("engine_") {
+            self.fallback_client.request(method, RawParams(params)).await
+        } else {
+            self.fallback_eth_client.request(method, RawParams(params)).await
+        }
+    }
+}
+
+/// Applies the routing table, passing [`Route::Local`] methods on to the inner service. Runs for every call, so batch
+/// requests and websocket connections are routed the same way.
 #[derive(Clone)]
 pub struct ProxyService<S> {
-    supported_methods: &'static [&'static str],
+    routes: &'static [RouteRule],
     inner: S,
-    fallback_eth_client: RpcClient,
-    fallback_client: AuthRpcClient,
+    upstreams: Upstreams,
 }
 
 impl<S> ProxyService<S> {
-    pub fn new(
-        supported_methods: &'static [&'static str],
-        inner: S,
-        fallback_eth_client: RpcClient,
-        fallback_client: AuthRpcClient,
-    ) -> Self {
-        Self { supported_methods, inner, fallback_eth_client, fallback_client }
+    pub fn new(routes: &'static [RouteRule], inner: S, upstreams: Upstreams) -> Self {
+        Self { routes, inner, upstreams }
     }
 }
 
@@ -41,40 +179,33 @@ where
     #[tracing::instrument(skip_all, name = "middleware")]
     fn call(&self, req: Request<'a>) -> Self::Future {
         let inner = self.inner.clone();
-        let fallback_client = self.fallback_client.clone();
-        let fallback_eth_client = self.fallback_eth_client.clone();
-        let supported_methods = self.supported_methods;
+        let upstreams = self.upstreams.clone();
+        let (route, timeout) = route_for(self.routes, req.method_name());
 
         async move {
-            if supported_methods.contains(&req.method_name()) {
+            if route == Route::Local {
                 debug!(method = %req.method_name(), "handling request");
+                return inner.call(req).await;
+            }
+
+            debug!(method = %req.method_name(), ?route, "forwarding request");
+            let params = req.params().as_str().map(String::from);
+            let timeout = timeout.unwrap_or(upstreams.default_timeout);
+            let response = tokio::time::timeout(timeout, upstreams.forward(route, req.method_name(), params))
+                .await
+                .unwrap_or(Err(ClientError::RequestTimeout));
 
-                inner.call(req).await
-            } else {
-                let params = WrapParams(req.params());
-
-                let r: Result<serde_json::Value, jsonrpsee::core::ClientError> =
-                    if req.method_name().contains("engine_") {
-                        debug!(method = %req.method_name(), "forwarding request to fallback");
-                        fallback_client.request(req.method_name(), params).await
-                    } else {
-                        debug!(method = %req.method_name(), "forwarding request to eth fallback");
-                        fallback_eth_client.request(req.method_name(), params).await
-                    };
-
-                match r {
-                    Ok(r) => {
-                        let payload = ResponsePayload::success(r);
-                        MethodResponse::response(req.id, payload.into(), TEN_MB_SIZE_BYTES as usize)
-                    }
-                    Err(err) => {
-                        error!(?err, "error forwarding request to fallback");
-
-                        MethodResponse::error(
-                            req.id,
-                            ErrorObject::borrowed(INTERNAL_ERROR_CODE, INTERNAL_ERROR_MSG, None),
-                        )
-                    }
+            match response {
+                Ok(r) => {
+                    let payload = ResponsePayload::success(r);
+                    MethodResponse::response(req.id, payload.into(), TEN_MB_SIZE_BYTES as usize)
+                }
+                // errors returned by the upstream, e.g. reverts, are passed on as is
+                Err(ClientError::Call(err)) => MethodResponse::error(req.id, err),
+                Err(err) => {
+                    error!(?err, method = %req.method_name(), "error forwarding request");
+
+                    MethodResponse::error(req.id, ErrorObject::borrowed(INTERNAL_ERROR_CODE, INTERNAL_ERROR_MSG, None))
                 }
             }
         }
@@ -82,12 +213,12 @@ where
     }
 }
 
-// TODO: remove this
-struct WrapParams<'a>(Params<'a>);
-impl ToRpcParams for WrapParams<'_> {
+/// Params forwarded as received, so they are only parsed by the upstream.
+pub struct RawParams(pub Option<String>);
+
+impl ToRpcParams for RawParams {
     fn to_rpc_params(self) -> Result<Option<Box<RawValue>>, serde_json::Error> {
-        // FIXME: we should not clone here
-        self.0.as_str().map(String::from).map(RawValue::from_string).transpose()
+        self.0.map(RawValue::from_string).transpose()
     }
 }
 
@@ -107,6 +238,22 @@ mod tests {
 
     use super::*;
 
+    #[test]
+    fn routes_methods() {
+        assert_eq!(route_for(ROUTES, "eth_call"), (Route::GatewayFirst, Some(Duration::from_secs(5))));
+        assert_eq!(route_for(ROUTES, "eth_sendRawTransaction"), (Route::Broadcast, None));
+        assert_eq!(
+            route_for(ROUTES, "based_sendRawTransactionWithCommitment"),
+            (Route::GatewayOnly, Some(Duration::from_secs(12)))
+        );
+        assert_eq!(route_for(ROUTES, "eth_getBlockByNumber"), (Route::FallbackOnly, None));
+        assert_eq!(max_route_timeout(ROUTES), Duration::from_secs(12));
+
+        for (i, rule) in ROUTES.iter().enumerate() {
+            assert!(ROUTES[i + 1..].iter().all(|other| other.method != rule.method), "duplicate route {}", rule.method);
+        }
+    }
+
     #[ignore = "Requires RPC calls"]
     #[tokio::test]
     async fn test_proxy() {
@@ -145,9 +292,17 @@ mod tests {
 
         let fallback_eth_client = HttpClientBuilder::default().build("http://127.0.0.1:9091").unwrap();
 
-        let rpc_middleware = RpcServiceBuilder::new().layer_fn(move |s| {
-            ProxyService::new(&["hello_mux"], s, fallback_eth_client.clone(), fallback_client.clone())
-        });
+        let upstreams = Upstreams {
+            fallback_eth_client,
+            fallback_client,
+            next_gateway: Default::default(),
+            gateways: Default::default(),
+            default_timeout: Duration::from_secs(1),
+        };
+
+        const ROUTES: &[RouteRule] = &[rule("hello_mux", Route::Local)];
+        let rpc_middleware =
+            RpcServiceBuilder::new().layer_fn(move |s| ProxyService::new(ROUTES, s, upstreams.clone()));
 
         let mux_server = ServerBuilder::default()
             .set_rpc_middleware(rpc_middleware)
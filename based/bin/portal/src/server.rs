@@ -1,44 +1,130 @@
 use std::{
     fmt,
     net::SocketAddr,
+    path::PathBuf,
     sync::{
         atomic::{AtomicU64, AtomicUsize, Ordering},
         Arc,
     },
-    time::Duration,
+    time::{Duration, Instant},
 };
 
-use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_eips::eip7685::Requests;
+use alloy_primitives::B256;
 use alloy_rpc_types::{
     engine::{ExecutionPayloadV3, ForkchoiceState, ForkchoiceUpdated, PayloadId, PayloadStatus},
-    BlockId, BlockNumberOrTag,
+    BlockNumberOrTag,
 };
 use bop_common::{
-    api::{EngineApiClient, EngineApiServer, EthApiClient, EthApiServer, OpRpcBlock, CAPABILITIES},
-    communication::messages::{RpcError, RpcResult},
+    api::{EngineApiClient, EngineApiServer, EthApiClient, EthStateApiClient, OpRpcBlock, ScheduleApiServer},
+    communication::messages::{envelope_v3, envelope_v4, EngineApiVersion, RpcError, RpcResult},
+    metrics::PORTAL_GATEWAY_REQUESTS,
+    schedule::{SequencerSchedule, SlotAssignment},
     utils::{utcnow_sec, uuid, wait_for_signal},
 };
+use futures::future::join_all;
 use jsonrpsee::{
-    core::async_trait,
+    core::{async_trait, client::ClientT, ClientError},
     http_client::{transport::HttpBackend, HttpClientBuilder},
     server::{RpcServiceBuilder, ServerBuilder},
 };
-use op_alloy_rpc_types::OpTransactionReceipt;
-use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpPayloadAttributes};
+use metrics::counter;
+use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpExecutionPayloadEnvelopeV4, OpPayloadAttributes};
 use parking_lot::{Mutex, RwLock};
 use reqwest::Url;
 use reth_rpc_layer::{AuthClientLayer, AuthClientService, JwtSecret};
-use tracing::{debug, error, info, Instrument, Level};
+use tracing::{debug, error, info, warn, Instrument, Level};
 
-use crate::{cli::PortalArgs, middleware::ProxyService};
+use crate::{
+    cli::PortalArgs,
+    health::{check_head, HealthConfig, HealthIssue, HealthRegistry},
+    middleware::{max_route_timeout, ProxyService, RawParams, Upstreams, ROUTES},
+};
 
 pub type RpcClient = jsonrpsee::http_client::HttpClient;
 pub type AuthRpcClient = jsonrpsee::http_client::HttpClient<AuthClientService<HttpBackend>>;
 
 #[derive(Clone)]
-struct Gateway {
+pub(crate) struct Gateway {
     id: Url,
     client: AuthRpcClient,
+    /// Client for requests forwarded by the proxy, which can wait longer than the engine API, see [`ROUTES`].
+    proxy_client: AuthRpcClient,
+}
+
+const FCU_METHOD: &str = "engine_forkchoiceUpdatedV3";
+
+const fn new_payload_method(version: EngineApiVersion) -> &'static str {
+    match version {
+        EngineApiVersion::V3 => "engine_newPayloadV3",
+        EngineApiVersion::V4 => "engine_newPayloadV4",
+    }
+}
+
+const fn get_payload_method(version: EngineApiVersion) -> &'static str {
+    match version {
+        EngineApiVersion::V3 => "engine_getPayloadV3",
+        EngineApiVersion::V4 => "engine_getPayloadV4",
+    }
+}
+
+/// Calls `newPayload` with the version matching the payload fork, execution requests are only passed from V4.
+async fn send_new_payload(
+    client: &AuthRpcClient,
+    payload: ExecutionPayloadV3,
+    versioned_hashes: Vec<B256>,
+    parent_beacon_block_root: B256,
+    execution_requests: Option<Requests>,
+) -> Result<PayloadStatus, ClientError> {
+    match execution_requests {
+        Some(requests) => client.new_payload_v4(payload, versioned_hashes, parent_beacon_block_root, requests).await,
+        None => client.new_payload_v3(payload, versioned_hashes, parent_beacon_block_root).await,
+    }
+}
+
+/// Calls `getPayload` with the given version, V3 envelopes are converted so both can be handled the same way.
+async fn send_get_payload(
+    client: &AuthRpcClient,
+    payload_id: PayloadId,
+    version: EngineApiVersion,
+) -> Result<OpExecutionPayloadEnvelopeV4, ClientError> {
+    match version {
+        EngineApiVersion::V3 => client.get_payload_v3(payload_id).await.map(envelope_v4),
+        EngineApiVersion::V4 => client.get_payload_v4(payload_id).await,
+    }
+}
+
+impl Gateway {
+    /// Forwards a request with its params as received.
+    pub(crate) async fn request(&self, method: &str, params: Option<String>) -> Result<serde_json::Value, ClientError> {
+        let res = self.proxy_client.request(method, RawParams(params)).await;
+        self.record(method, res)
+    }
+
+    /// Counts the outcome of a request to this gateway, passing the result through.
+    fn record<T>(&self, method: &str, res: Result<T, ClientError>) -> Result<T, ClientError> {
+        self.count(method, if res.is_ok() { "success" } else { "error" });
+        res
+    }
+
+    /// Counts an engine API response, which can be well formed but still flag the payload as invalid.
+    fn record_outcome(&self, method: &str, valid: bool) {
+        self.count(method, if valid { "success" } else { "invalid" });
+    }
+
+    fn record_error(&self, method: &str) {
+        self.count(method, "error");
+    }
+
+    fn count(&self, method: &str, outcome: &'static str) {
+        counter!(
+            PORTAL_GATEWAY_REQUESTS,
+            "gateway" => self.id.to_string(),
+            "method" => method.to_string(),
+            "outcome" => outcome
+        )
+        .increment(1);
+    }
 }
 
 impl fmt::Debug for Gateway {
@@ -52,10 +138,35 @@ pub struct PortalServer {
     fallback_eth_client: RpcClient,
     fallback_client: AuthRpcClient,
     next_gateway_index: Arc<AtomicUsize>,
-    next_gateway: Arc<Mutex<Gateway>>,
+    /// Gateway building the current block and serving reads, `None` if no gateway is healthy.
+    next_gateway: Arc<Mutex<Option<Gateway>>>,
+    /// Payload id the gateway returned for the block it's building, the fallback hands out its own id.
+    gateway_payload_id: Arc<Mutex<Option<PayloadId>>>,
     gateway_clients: Arc<RwLock<Vec<Gateway>>>,
+    health: Arc<HealthRegistry>,
+    /// When set, each block is only handed to the gateway scheduled for it.
+    schedule: Arc<RwLock<Option<SequencerSchedule>>>,
+    /// Hash and number of the last block received in `newPayload`, to resolve the number of the next block.
+    last_payload: Arc<Mutex<(B256, u64)>>,
     last_updated_sec: Arc<AtomicU64>,
     gateway_update_sec: u64,
+    fallback_timeout: Duration,
+}
+
+/// Maximum number of slots returned by `based_sequencerLookahead`.
+const MAX_LOOKAHEAD_SLOTS: u64 = 256;
+
+enum ScheduleSource {
+    Path(PathBuf),
+    Url(Url),
+}
+
+async fn load_schedule(source: &ScheduleSource) -> eyre::Result<SequencerSchedule> {
+    let json = match source {
+        ScheduleSource::Path(path) => tokio::fs::read_to_string(path).await?,
+        ScheduleSource::Url(url) => reqwest::get(url.clone()).await?.text().await?,
+    };
+    SequencerSchedule::from_json(&json)
 }
 
 async fn refresh_gateway_clients(url: Url, gateway_jwt: JwtSecret, timeout: Duration) -> eyre::Result<Vec<Gateway>> {
@@ -74,8 +185,10 @@ impl PortalServer {
         let gateway_jwt = args.gateway_jwt()?;
         let fallback_jwt = args.fallback_jwt()?;
 
+        let fallback_timeout = Duration::from_millis(args.fallback_timeout_ms);
+        // Methods can have longer timeouts than the default, which are enforced by the proxy
         let fallback_eth_client =
-            create_client(args.fallback_eth_url, Duration::from_millis(args.fallback_timeout_ms))?;
+            create_client(args.fallback_eth_url, fallback_timeout.max(max_route_timeout(ROUTES)))?;
 
         let fallback_client =
             create_auth_client(args.fallback_url, fallback_jwt, Duration::from_millis(args.fallback_timeout_ms))?;
@@ -110,32 +223,87 @@ impl PortalServer {
             });
         }
 
-        let next_gateway = Arc::new(Mutex::new(gateway_client));
+        let schedule = Arc::new(RwLock::new(None));
+        let schedule_source = match (args.gateway_schedule_path, args.gateway_schedule_url) {
+            (Some(path), _) => Some(ScheduleSource::Path(path)),
+            (_, Some(url)) => Some(ScheduleSource::Url(url)),
+            _ => None,
+        };
+
+        if let Some(source) = schedule_source {
+            let schedule_c = schedule.clone();
+            let gateway_clients_c = gateway_clients.clone();
+            let timeout = Duration::from_millis(args.gateway_timeout_ms);
+
+            tokio::spawn(async move {
+                loop {
+                    match load_schedule(&source).await {
+                        Ok(new_schedule) if schedule_c.read().as_ref() != Some(&new_schedule) => {
+                            let clients: eyre::Result<Vec<_>> = new_schedule
+                                .urls()
+                                .into_iter()
+                                .map(|url| create_gateway_client(url, gateway_jwt, timeout))
+                                .collect();
+
+                            match clients {
+                                Ok(clients) => {
+                                    info!(
+                                        epochs = new_schedule.epochs().len(),
+                                        clients = clients.len(),
+                                        "loaded sequencer schedule"
+                                    );
+                                    *gateway_clients_c.write() = clients;
+                                    *schedule_c.write() = Some(new_schedule);
+                                }
+                                Err(err) => error!(%err, "failed to create gateway clients for sequencer schedule"),
+                            }
+                        }
+                        Ok(_) => {}
+                        Err(err) => error!(%err, "failed to load sequencer schedule"),
+                    }
+
+                    tokio::time::sleep(Duration::from_secs(30)).await;
+                }
+            });
+        }
+
+        // Picked once the first health check passes
+        let next_gateway = Arc::new(Mutex::new(None));
         let next_gateway_index = Arc::new(AtomicUsize::new(0));
 
+        let health = Arc::new(HealthRegistry::new(HealthConfig {
+            max_lag: args.gateway_max_lag,
+            cooldown: Duration::from_secs(args.gateway_cooldown_sec),
+            check_interval: Duration::from_millis(args.gateway_health_interval_ms),
+        }));
+
         Ok(Self {
             fallback_eth_client,
             fallback_client,
             gateway_clients,
             next_gateway,
+            gateway_payload_id: Arc::new(Mutex::new(None)),
             next_gateway_index,
+            health,
+            schedule,
+            last_payload: Arc::new(Mutex::new((B256::ZERO, 0))),
             last_updated_sec: Arc::new(AtomicU64::new(utcnow_sec())),
             gateway_update_sec: args.gateway_update_interval_sec,
+            fallback_timeout,
         })
     }
 
     pub async fn run(self, addr: SocketAddr) -> eyre::Result<()> {
-        let fallback_client = self.fallback_client.clone();
-        let fallback_eth_client = self.fallback_eth_client.clone();
-
-        let rpc_middleware = RpcServiceBuilder::new().layer_fn(move |s| {
-            ProxyService::new(CAPABILITIES, s, fallback_eth_client.clone(), fallback_client.clone())
-        });
+        let upstreams = self.upstreams();
+        let rpc_middleware =
+            RpcServiceBuilder::new().layer_fn(move |s| ProxyService::new(ROUTES, s, upstreams.clone()));
 
         let server = ServerBuilder::default().set_rpc_middleware(rpc_middleware).build(addr).await?;
 
         let mut module = EngineApiServer::into_rpc(self.clone());
-        module.merge(EthApiServer::into_rpc(self)).expect("failed to merge modules");
+        module.merge(ScheduleApiServer::into_rpc(self.clone())).expect("failed to merge modules");
+
+        tokio::spawn(self.clone().check_health_forever());
 
         let server_handle = server.start(module);
 
@@ -152,215 +320,255 @@ impl PortalServer {
         Ok(())
     }
 
-    fn next_gateway(&self) -> Gateway {
+    fn upstreams(&self) -> Upstreams {
+        Upstreams {
+            fallback_eth_client: self.fallback_eth_client.clone(),
+            fallback_client: self.fallback_client.clone(),
+            next_gateway: self.next_gateway.clone(),
+            gateways: self.gateway_clients.clone(),
+            default_timeout: self.fallback_timeout,
+        }
+    }
+
+    fn next_gateway(&self) -> Option<Gateway> {
         self.next_gateway.lock().clone()
     }
 
-    fn refresh_next(&self) -> Gateway {
+    /// Rotates to the next healthy gateway once the update interval has passed, or straight away if the current one
+    /// is no longer healthy.
+    fn refresh_next(&self) -> Option<Gateway> {
         let now = utcnow_sec();
         let last_updated_sec = self.last_updated_sec.load(Ordering::Relaxed);
         let mut lock = self.next_gateway.lock();
 
-        if now.saturating_sub(last_updated_sec) > self.gateway_update_sec {
+        let current_healthy = lock.as_ref().is_some_and(|gateway| self.health.is_eligible(&gateway.id));
+        if !current_healthy || now.saturating_sub(last_updated_sec) > self.gateway_update_sec {
             let next_index = self.next_gateway_index.fetch_add(1, Ordering::Relaxed);
             self.last_updated_sec.store(now, Ordering::Relaxed);
             let clients = self.gateway_clients.read();
-            *lock = clients[next_index % clients.len()].clone();
+            *lock = (0..clients.len())
+                .map(|i| &clients[(next_index + i) % clients.len()])
+                .find(|gateway| self.health.is_eligible(&gateway.id))
+                .cloned();
         }
 
         lock.clone()
     }
 
-    fn gateways(&self) -> Vec<Gateway> {
-        self.gateway_clients.read().clone()
-    }
-}
-
-/// This is a temporary API to broacast transactions to both gateway and fallback. In practice this should not be
-/// receiving user facing calls so we need to find another way to do this
-#[async_trait]
-impl EthApiServer for PortalServer {
-    #[tracing::instrument(skip_all, err, ret(level = Level::DEBUG), fields(req_id = %uuid()))]
-    async fn send_raw_transaction(&self, bytes: Bytes) -> RpcResult<B256> {
-        // send to gateways and fallback
-        for gateway in self.gateways() {
-            let bytes = bytes.clone();
-            tokio::spawn(async move {
-                if let Err(err) = gateway.client.send_raw_transaction(bytes).await {
-                    error!(%err, ?gateway, "failed to send to gateway");
-                }
-            });
-        }
-
-        let response = self.fallback_eth_client.send_raw_transaction(bytes).await?;
-        Ok(response)
-    }
-
-    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
-    async fn transaction_receipt(&self, hash: B256) -> RpcResult<Option<OpTransactionReceipt>> {
-        debug!(%hash, "new request");
-
-        let fallback_fut = tokio::spawn(
-            {
-                let client = self.fallback_client.clone();
-                async move { client.transaction_receipt(hash).await }
+    /// Picks the gateway to build the block on top of `parent_hash`. With a schedule this is the gateway scheduled for
+    /// that block, without one gateways rotate on a timer.
+    async fn select_gateway(&self, parent_hash: B256) -> Option<Gateway> {
+        let Some(schedule) = self.schedule.read().clone() else {
+            return self.refresh_next();
+        };
+
+        let slot = match self.block_number_of(parent_hash).await {
+            Some(parent_number) => schedule.assignment(parent_number + 1),
+            None => {
+                warn!(%parent_hash, "unknown parent block, can't look up the scheduled gateway");
+                None
             }
-            .in_current_span(),
-        );
-        let gateway_fut = tokio::spawn(
-            {
-                let client = self.next_gateway();
-                async move { client.client.transaction_receipt(hash).await }
-            }
-            .in_current_span(),
-        );
-
-        let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
-        // ignore join errors
-        let fallback = fallback?;
-        let gateway = gateway?;
+        };
 
-        let payload = gateway.or(fallback)?;
+        let gateway = slot.and_then(|slot| {
+            debug!(?slot, "scheduled gateway");
+            self.gateways().into_iter().find(|gateway| gateway.id == slot.url && self.health.is_eligible(&gateway.id))
+        });
+        *self.next_gateway.lock() = gateway.clone();
 
-        Ok(payload)
+        gateway
     }
 
-    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
-    async fn block_by_number(&self, number: BlockNumberOrTag, full: bool) -> RpcResult<Option<OpRpcBlock>> {
-        debug!(%number, full, "new request");
-
-        let fallback_fut = tokio::spawn(
-            {
-                let client = self.fallback_client.clone();
-                async move { client.block_by_number(number, full).await }
-            }
-            .in_current_span(),
-        );
-        let gateway_fut = tokio::spawn(
-            {
-                let client = self.next_gateway();
-                async move { client.client.block_by_number(number, full).await }
-            }
-            .in_current_span(),
-        );
+    async fn block_number_of(&self, hash: B256) -> Option<u64> {
+        let (last_hash, last_number) = *self.last_payload.lock();
+        if last_hash == hash {
+            return Some(last_number);
+        }
 
-        let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
-        // ignore join errors
-        let fallback = fallback?;
-        let gateway = gateway?;
+        let block = self.fallback_client.block_by_hash(hash, false).await.ok().flatten()?;
+        Some(block.header.number)
+    }
 
-        let payload = gateway.or(fallback)?;
+    /// Number of the block currently being sequenced.
+    async fn next_block_number(&self) -> RpcResult<u64> {
+        let (_, last_number) = *self.last_payload.lock();
+        if last_number != 0 {
+            return Ok(last_number + 1);
+        }
 
-        Ok(payload)
+        let head = self.fallback_client.block_number().await?;
+        Ok(head.saturating_to::<u64>() + 1)
     }
 
-    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
-    async fn block_by_hash(&self, hash: B256, full: bool) -> RpcResult<Option<OpRpcBlock>> {
-        debug!(%hash, full, "new request");
+    async fn check_health_forever(self) {
+        loop {
+            self.check_health().await;
+            tokio::time::sleep(self.health.config().check_interval).await;
+        }
+    }
 
-        let fallback_fut = tokio::spawn(
-            {
-                let client = self.fallback_client.clone();
-                async move { client.block_by_hash(hash, full).await }
+    /// Checks every gateway against the fallback head, ejecting or re-admitting them.
+    async fn check_health(&self) {
+        let fallback_head = match self.fallback_client.block_by_number(BlockNumberOrTag::Latest, false).await {
+            Ok(Some(block)) => block,
+            Ok(None) => return,
+            Err(err) => {
+                warn!(%err, "failed to fetch fallback head, skipping gateway health checks");
+                return;
             }
-            .in_current_span(),
-        );
-        let gateway_fut = tokio::spawn(
-            {
-                let client = self.next_gateway();
-                async move { client.client.block_by_hash(hash, full).await }
+        };
+
+        let gateways = self.gateways();
+        let max_lag = self.health.config().max_lag;
+        let results = join_all(
+            gateways.iter().map(|gateway| check_gateway(gateway, &self.fallback_client, &fallback_head, max_lag)),
+        )
+        .await;
+
+        let now = Instant::now();
+        for (gateway, result) in gateways.iter().zip(results) {
+            match result {
+                Ok(()) => self.health.report_ok(&gateway.id, now),
+                Err(issue) => self.health.report_issue(&gateway.id, issue, now),
             }
-            .in_current_span(),
-        );
-
-        let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
-        // ignore join errors
-        let fallback = fallback?;
-        let gateway = gateway?;
-
-        let payload = gateway.or(fallback)?;
+        }
+        self.health.retain(gateways.iter().map(|gateway| &gateway.id));
+    }
 
-        Ok(payload)
+    fn gateways(&self) -> Vec<Gateway> {
+        self.gateway_clients.read().clone()
     }
 
-    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
-    async fn block_number(&self) -> RpcResult<U256> {
-        debug!("block number request");
+    /// Sends a new payload to all gateways and the fallback, with the version it was received with.
+    async fn new_payload(
+        &self,
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Option<Requests>,
+    ) -> RpcResult<PayloadStatus> {
+        let block_number = payload.payload_inner.payload_inner.block_number;
+        let block_hash = payload.payload_inner.payload_inner.block_hash;
+        let gas_limit = payload.payload_inner.payload_inner.gas_limit;
+        let gas_used = payload.payload_inner.payload_inner.gas_used;
+        let n_txs = payload.payload_inner.payload_inner.transactions.len();
+        let n_withdrawals = payload.payload_inner.withdrawals.len();
+        let blob_gas_used = payload.blob_gas_used;
+        let excess_blob_gas = payload.excess_blob_gas;
 
-        let fallback_fut = tokio::spawn(
-            {
-                let client = self.fallback_client.clone();
-                async move { client.block_number().await }
-            }
-            .in_current_span(),
-        );
-        let gateway_fut = tokio::spawn(
-            {
-                let client = self.next_gateway();
-                async move { client.client.block_number().await }
-            }
-            .in_current_span(),
-        );
+        debug!(block_number, %block_hash, gas_limit, gas_used, n_txs, n_withdrawals, blob_gas_used, excess_blob_gas, "new request");
+        *self.last_payload.lock() = (block_hash, block_number);
 
-        let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
-        // ignore join errors
-        let fallback = fallback?;
-        let gateway = gateway?;
+        let version = if execution_requests.is_some() { EngineApiVersion::V4 } else { EngineApiVersion::V3 };
+        let method = new_payload_method(version);
 
-        let payload = gateway.or(fallback)?;
+        // send to all gateways
+        for gateway in self.gateways() {
+            let payload = payload.clone();
+            let versioned_hashes = versioned_hashes.clone();
+            let execution_requests = execution_requests.clone();
 
-        Ok(payload)
-    }
+            tokio::spawn(
+                async move {
+                    match send_new_payload(
+                        &gateway.client,
+                        payload,
+                        versioned_hashes,
+                        parent_beacon_block_root,
+                        execution_requests,
+                    )
+                    .await
+                    {
+                        Ok(res) => {
+                            if res.is_valid() {
+                                debug!(?gateway, ?res, "gateway response");
+                            } else {
+                                error!(?gateway, ?res, "gateway response");
+                            }
+                            gateway.record_outcome(method, res.is_valid());
+                        }
+                        Err(err) => {
+                            error!(?gateway, %err, "failed gateway");
+                            gateway.record_error(method);
+                        }
+                    }
+                }
+                .in_current_span(),
+            );
+        }
 
-    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
-    async fn transaction_count(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256> {
-        debug!(%address, ?block_number, "new request");
+        let response = send_new_payload(
+            &self.fallback_client,
+            payload,
+            versioned_hashes,
+            parent_beacon_block_root,
+            execution_requests,
+        )
+        .await?;
+        Ok(response)
+    }
 
-        let fallback_fut = tokio::spawn(
-            {
-                let client = self.fallback_client.clone();
-                async move { client.transaction_count(address, block_number).await }
-            }
-            .in_current_span(),
-        );
-        let gateway_fut = tokio::spawn(
-            {
-                let client = self.next_gateway();
-                async move { client.client.transaction_count(address, block_number).await }
-            }
-            .in_current_span(),
-        );
+    /// Gets the payload from the fallback and the gateway picked for this block, returning the gateway payload if the
+    /// fallback validates it.
+    async fn get_payload(
+        &self,
+        payload_id: PayloadId,
+        version: EngineApiVersion,
+    ) -> RpcResult<OpExecutionPayloadEnvelopeV4> {
+        debug!(%payload_id, ?version, "new request");
+        let method = get_payload_method(version);
 
-        let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
-        // ignore join errors
-        let fallback = fallback?;
-        let gateway = gateway?;
+        let fallback_fut = tokio::spawn({
+            let client = self.fallback_client.clone();
 
-        let payload = gateway.or(fallback)?;
+            async move { send_get_payload(&client, payload_id, version).await }
+        });
 
-        Ok(payload)
-    }
+        let gateway_fut: tokio::task::JoinHandle<Result<OpExecutionPayloadEnvelopeV4, _>> = tokio::spawn(
+            {
+                // only get payload from previously picked gateway
+                let gateway = self.next_gateway();
+                let gateway_payload_id = self.gateway_payload_id.lock().take().unwrap_or(payload_id);
+                let fallback_client = self.fallback_client.clone();
+                let health = self.health.clone();
 
-    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
-    async fn balance(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256> {
-        debug!(%address, ?block_number, "new request");
+                async move {
+                    let gateway = gateway.ok_or_else(no_healthy_gateway)?;
+                    let gateway_payload =
+                        send_get_payload(&gateway.client, gateway_payload_id, version).await.inspect_err(|err| {
+                            error!(%err, "failed gateway");
+                            gateway.record_error(method);
+                            health.report_issue(&gateway.id, HealthIssue::Unreachable, Instant::now());
+                        })?;
+
+                    let execution_requests = (version == EngineApiVersion::V4)
+                        .then(|| Requests::new(gateway_payload.execution_requests.clone()));
+                    let payload_status = send_new_payload(
+                        &fallback_client,
+                        gateway_payload.execution_payload.clone(),
+                        vec![],
+                        gateway_payload.parent_beacon_block_root,
+                        execution_requests,
+                    )
+                    .await
+                    .inspect_err(|err| error!(%err, "failed fallback validation"))?;
 
-        let fallback_fut = tokio::spawn(
-            {
-                let client = self.fallback_client.clone();
-                async move { client.balance(address, block_number).await }
-            }
-            .in_current_span(),
-        );
-        let gateway_fut = tokio::spawn(
-            {
-                let client = self.next_gateway();
-                async move { client.client.balance(address, block_number).await }
+                    gateway.record_outcome(method, payload_status.is_valid());
+                    if payload_status.is_valid() {
+                        debug!(?gateway, ?gateway_payload, ?payload_status, "gateway response");
+                        Ok(gateway_payload)
+                    } else {
+                        error!(?gateway, ?gateway_payload, ?payload_status, "gateway response");
+                        health.report_issue(&gateway.id, HealthIssue::InvalidPayload, Instant::now());
+                        Err(RpcError::Internal)
+                    }
+                }
             }
             .in_current_span(),
         );
 
         let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
+
         // ignore join errors
         let fallback = fallback?;
         let gateway = gateway?;
@@ -390,24 +598,34 @@ impl EngineApiServer for PortalServer {
         }
 
         if payload_attributes.is_some() {
-            // pick only one gateway for this block
-            let gateway = self.refresh_next();
-            let payload_attributes = payload_attributes.clone();
-            tokio::spawn(
-                async move {
-                    match gateway.client.fork_choice_updated_v3(fork_choice_state, payload_attributes).await {
-                        Ok(res) => {
-                            if res.is_valid() {
-                                debug!(?gateway, ?res, "gateway response");
-                            } else {
-                                error!(?gateway, ?res, "gateway response");
+            // pick only one gateway for this block, only ever a healthy one
+            *self.gateway_payload_id.lock() = None;
+            if let Some(gateway) = self.select_gateway(parent_block_hash).await {
+                let payload_attributes = payload_attributes.clone();
+                let gateway_payload_id = self.gateway_payload_id.clone();
+                tokio::spawn(
+                    async move {
+                        match gateway.client.fork_choice_updated_v3(fork_choice_state, payload_attributes).await {
+                            Ok(res) => {
+                                *gateway_payload_id.lock() = res.payload_id;
+                                if res.is_valid() {
+                                    debug!(?gateway, ?res, "gateway response");
+                                } else {
+                                    error!(?gateway, ?res, "gateway response");
+                                }
+                                gateway.record_outcome(FCU_METHOD, res.is_valid());
+                            }
+                            Err(err) => {
+                                error!(?gateway, %err, "failed gateway");
+                                gateway.record_error(FCU_METHOD);
                             }
                         }
-                        Err(err) => error!(?gateway, %err, "failed gateway"),
                     }
-                }
-                .in_current_span(),
-            );
+                    .in_current_span(),
+                );
+            } else {
+                warn!(%parent_block_hash, "no healthy gateway for this block, building on the fallback only");
+            }
         } else {
             // send to all gateways
             for gateway in self.gateways() {
@@ -421,8 +639,12 @@ impl EngineApiServer for PortalServer {
                                 } else {
                                     error!(?gateway, ?res, "gateway response");
                                 }
+                                gateway.record_outcome(FCU_METHOD, res.is_valid());
+                            }
+                            Err(err) => {
+                                error!(%err, "failed gateway");
+                                gateway.record_error(FCU_METHOD);
                             }
-                            Err(err) => error!(%err, "failed gateway"),
                         }
                     }
                     .in_current_span(),
@@ -442,99 +664,95 @@ impl EngineApiServer for PortalServer {
         versioned_hashes: Vec<B256>,
         parent_beacon_block_root: B256,
     ) -> RpcResult<PayloadStatus> {
-        let block_number = payload.payload_inner.payload_inner.block_number;
-        let block_hash = payload.payload_inner.payload_inner.block_hash;
-        let gas_limit = payload.payload_inner.payload_inner.gas_limit;
-        let gas_used = payload.payload_inner.payload_inner.gas_used;
-        let n_txs = payload.payload_inner.payload_inner.transactions.len();
-        let n_withdrawals = payload.payload_inner.withdrawals.len();
-        let blob_gas_used = payload.blob_gas_used;
-        let excess_blob_gas = payload.excess_blob_gas;
-
-        debug!(block_number, %block_hash, gas_limit, gas_used, n_txs, n_withdrawals, blob_gas_used, excess_blob_gas, "new request");
-
-        // send to all gateways
-        for gateway in self.gateways() {
-            let payload = payload.clone();
-            let versioned_hashes = versioned_hashes.clone();
-
-            tokio::spawn(
-                async move {
-                    match gateway.client.new_payload_v3(payload, versioned_hashes, parent_beacon_block_root).await {
-                        Ok(res) => {
-                            if res.is_valid() {
-                                debug!(?gateway, ?res, "gateway response");
-                            } else {
-                                error!(?gateway, ?res, "gateway response");
-                            }
-                        }
-                        Err(err) => error!(?gateway, %err, "failed gateway"),
-                    }
-                }
-                .in_current_span(),
-            );
-        }
-
-        let response = self.fallback_client.new_payload_v3(payload, versioned_hashes, parent_beacon_block_root).await?;
-        Ok(response)
+        self.new_payload(payload, versioned_hashes, parent_beacon_block_root, None).await
     }
 
     #[tracing::instrument(skip_all, err, ret(level = Level::DEBUG), fields(req_id = %uuid()))]
     async fn get_payload_v3(&self, payload_id: PayloadId) -> RpcResult<OpExecutionPayloadEnvelopeV3> {
-        debug!(%payload_id, "new request");
-
-        let fallback_fut = tokio::spawn({
-            let client = self.fallback_client.clone();
+        self.get_payload(payload_id, EngineApiVersion::V3).await.map(envelope_v3)
+    }
 
-            async move { client.get_payload_v3(payload_id).await }
-        });
+    #[tracing::instrument(skip_all, err, ret(level = Level::DEBUG), fields(req_id = %uuid()))]
+    async fn new_payload_v4(
+        &self,
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Requests,
+    ) -> RpcResult<PayloadStatus> {
+        self.new_payload(payload, versioned_hashes, parent_beacon_block_root, Some(execution_requests)).await
+    }
 
-        let gateway_fut: tokio::task::JoinHandle<Result<OpExecutionPayloadEnvelopeV3, _>> = tokio::spawn(
-            {
-                // only get payload from previously picked gateway
-                let gateway = self.next_gateway();
-                let fallback_client = self.fallback_client.clone();
+    #[tracing::instrument(skip_all, err, ret(level = Level::DEBUG), fields(req_id = %uuid()))]
+    async fn get_payload_v4(&self, payload_id: PayloadId) -> RpcResult<OpExecutionPayloadEnvelopeV4> {
+        self.get_payload(payload_id, EngineApiVersion::V4).await
+    }
+}
 
-                async move {
-                    let gateway_payload = gateway
-                        .client
-                        .get_payload_v3(payload_id)
-                        .await
-                        .inspect_err(|err| error!(%err, "failed gateway"))?;
-
-                    let payload_status = fallback_client
-                        .new_payload_v3(
-                            gateway_payload.execution_payload.clone(),
-                            vec![],
-                            gateway_payload.parent_beacon_block_root,
-                        )
-                        .await
-                        .inspect_err(|err| error!(%err, "failed fallback validation"))?;
+#[async_trait]
+impl ScheduleApiServer for PortalServer {
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn current_sequencer(&self) -> RpcResult<Option<SlotAssignment>> {
+        let Some(schedule) = self.schedule.read().clone() else {
+            return Ok(None);
+        };
 
-                    if payload_status.is_valid() {
-                        debug!(?gateway, ?gateway_payload, ?payload_status, "gateway response");
-                        Ok(gateway_payload)
-                    } else {
-                        error!(?gateway, ?gateway_payload, ?payload_status, "gateway response");
-                        Err(RpcError::Internal)
-                    }
-                }
-            }
-            .in_current_span(),
-        );
+        Ok(schedule.assignment(self.next_block_number().await?))
+    }
 
-        let (fallback, gateway) = tokio::join!(fallback_fut, gateway_fut);
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn next_sequencer(&self) -> RpcResult<Option<SlotAssignment>> {
+        let Some(schedule) = self.schedule.read().clone() else {
+            return Ok(None);
+        };
 
-        // ignore join errors
-        let fallback = fallback?;
-        let gateway = gateway?;
+        Ok(schedule.lookahead(self.next_block_number().await?, 2).into_iter().nth(1))
+    }
 
-        let payload = gateway.or(fallback)?;
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn sequencer_lookahead(&self, slots: u64) -> RpcResult<Vec<SlotAssignment>> {
+        let Some(schedule) = self.schedule.read().clone() else {
+            return Ok(vec![]);
+        };
 
-        Ok(payload)
+        Ok(schedule.lookahead(self.next_block_number().await?, slots.min(MAX_LOOKAHEAD_SLOTS) as usize))
     }
 }
 
+/// Checks a gateway head against the fallback head. Gateways don't serve blocks, only headers.
+async fn check_gateway(
+    gateway: &Gateway,
+    fallback_client: &AuthRpcClient,
+    fallback_head: &OpRpcBlock,
+    max_lag: u64,
+) -> Result<(), HealthIssue> {
+    let head = gateway
+        .client
+        .header_by_number(BlockNumberOrTag::Latest)
+        .await
+        .ok()
+        .flatten()
+        .ok_or(HealthIssue::Unreachable)?;
+    let (number, fallback_number) = (head.number, fallback_head.header.number);
+
+    // compare hashes at the lower of the two heads, the gateway can be ahead with a block it just built
+    let (gateway_hash, fallback_hash) = if number > fallback_number {
+        let header = gateway.client.header_by_number(fallback_number.into()).await.ok().flatten();
+        (header.map(|header| header.hash), Some(fallback_head.header.hash))
+    } else if number < fallback_number {
+        let block = fallback_client.block_by_number(number.into(), false).await.ok().flatten();
+        (Some(head.hash), block.map(|block| block.header.hash))
+    } else {
+        (Some(head.hash), Some(fallback_head.header.hash))
+    };
+
+    check_head(number, fallback_number, gateway_hash, fallback_hash, max_lag)
+}
+
+fn no_healthy_gateway() -> ClientError {
+    ClientError::Custom("no healthy gateway".to_string())
+}
+
 fn create_client(url: Url, timeout: Duration) -> eyre::Result<RpcClient> {
     let client = HttpClientBuilder::default().request_timeout(timeout).build(url)?;
     Ok(client)
@@ -551,6 +769,94 @@ fn create_auth_client(url: Url, jwt: JwtSecret, timeout: Duration) -> eyre::Resu
 
 fn create_gateway_client(url: Url, jwt: JwtSecret, timeout: Duration) -> eyre::Result<Gateway> {
     let client = create_auth_client(url.clone(), jwt, timeout)?;
-    let gateway_client = Gateway { client, id: url };
+    let proxy_client = create_auth_client(url.clone(), jwt, timeout.max(max_route_timeout(ROUTES)))?;
+    let gateway_client = Gateway { client, proxy_client, id: url };
     Ok(gateway_client)
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy_rpc_types::Header;
+    use jsonrpsee::{
+        server::ServerHandle,
+        types::{ErrorObjectOwned, Params},
+        RpcModule,
+    };
+    use reth_rpc_layer::{AuthLayer, JwtAuthValidator};
+
+    use super::*;
+
+    fn block_hash(number: u64) -> B256 {
+        B256::with_last_byte(number as u8)
+    }
+
+    fn rpc_block(number: u64, hash: B256) -> OpRpcBlock {
+        let mut header = Header::default();
+        header.inner.number = number;
+        header.hash = hash;
+        OpRpcBlock { header, ..Default::default() }
+    }
+
+    /// Block number requested by `params`, if it is part of a chain with head `head`.
+    fn requested_number(params: Params, head: u64) -> Result<Option<u64>, ErrorObjectOwned> {
+        let number = match params.sequence().next::<BlockNumberOrTag>()? {
+            BlockNumberOrTag::Latest => head,
+            number => number.as_number().unwrap_or(u64::MAX),
+        };
+        Ok((number <= head).then_some(number))
+    }
+
+    /// Serves the headers and blocks of a chain with head `head` behind the engine API's JWT auth, like a gateway.
+    async fn start_node_stub(jwt: JwtSecret, head: u64) -> (Url, ServerHandle) {
+        let mut module = RpcModule::new(());
+        module
+            .register_method("eth_getHeaderByNumber", move |params, _, _| {
+                let number = requested_number(params, head)?;
+                Ok::<_, ErrorObjectOwned>(number.map(|number| rpc_block(number, block_hash(number)).header))
+            })
+            .unwrap();
+        module
+            .register_method("eth_getBlockByNumber", move |params, _, _| {
+                let number = requested_number(params, head)?;
+                Ok::<_, ErrorObjectOwned>(number.map(|number| rpc_block(number, block_hash(number))))
+            })
+            .unwrap();
+
+        let auth = tower::ServiceBuilder::new().layer(AuthLayer::new(JwtAuthValidator::new(jwt)));
+        let server = ServerBuilder::default()
+            .set_http_middleware(auth)
+            .build("127.0.0.1:0".parse::<SocketAddr>().unwrap())
+            .await
+            .unwrap();
+        let url = format!("http://{}", server.local_addr().unwrap()).parse().unwrap();
+        (url, server.start(module))
+    }
+
+    #[tokio::test]
+    async fn checks_gateway_rpc() {
+        let jwt = JwtSecret::random();
+        let (url, _handle) = start_node_stub(jwt, 3).await;
+
+        let timeout = Duration::from_secs(1);
+        let gateway = create_gateway_client(url.clone(), jwt, timeout).unwrap();
+        let fallback_client = create_auth_client(url.clone(), jwt, timeout).unwrap();
+        let check = |fallback_number: u64, fallback_hash: B256| {
+            let fallback_head = rpc_block(fallback_number, fallback_hash);
+            let (gateway, fallback_client) = (&gateway, &fallback_client);
+            async move { check_gateway(gateway, fallback_client, &fallback_head, 2).await }
+        };
+
+        assert_eq!(check(3, block_hash(3)).await, Ok(()));
+        assert_eq!(check(3, B256::repeat_byte(1)).await, Err(HealthIssue::HeadMismatch { number: 3 }));
+        // The gateway is a block ahead, its hash at the fallback's head is compared
+        assert_eq!(check(2, block_hash(2)).await, Ok(()));
+        assert_eq!(check(2, B256::repeat_byte(1)).await, Err(HealthIssue::HeadMismatch { number: 2 }));
+        // The gateway is behind, the fallback's hash at the gateway's head is compared
+        assert_eq!(check(5, block_hash(5)).await, Ok(()));
+        assert_eq!(check(8, block_hash(8)).await, Err(HealthIssue::Syncing { behind: 5 }));
+
+        let wrong_jwt = create_gateway_client(url, JwtSecret::random(), timeout).unwrap();
+        let fallback_head = rpc_block(3, block_hash(3));
+        assert_eq!(check_gateway(&wrong_jwt, &fallback_client, &fallback_head, 2).await, Err(HealthIssue::Unreachable));
+    }
+}
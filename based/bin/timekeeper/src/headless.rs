@@ -0,0 +1,461 @@
+//! Modes without the TUI, to keep timing history from boxes where nobody is watching a terminal.
+//!
+//! [`Exporter`] periodically writes the percentiles and rates of every timer, [`dump`] writes all samples in a window
+//! around a block.
+
+use std::{fmt, io::Write};
+
+use bop_common::{
+    communication::Consumer,
+    metrics::{TIMER_DURATION, TIMER_RATE},
+    time::{Duration, Instant, Nanos, Repeater, TimingMessage},
+};
+use clap::ValueEnum;
+use metrics::gauge;
+use serde::Serialize;
+use tracing::warn;
+
+use crate::timekeeper::{clock_overhead, open_timer_queues, timer_names};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SampleKind {
+    /// From the ingestion of a message until a timer started processing it
+    Latency,
+    /// Time spent processing a message
+    Business,
+}
+
+impl SampleKind {
+    fn as_str(&self) -> &'static str {
+        match self {
+            SampleKind::Latency => "latency",
+            SampleKind::Business => "business",
+        }
+    }
+}
+
+impl fmt::Display for SampleKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Writes rows as csv with a header, or as json lines.
+struct RowWriter<W> {
+    out: W,
+    format: Format,
+    wrote_header: bool,
+}
+
+impl<W: Write> RowWriter<W> {
+    /// `header` is false when `out` continues csv that already has one, e.g. a file that is appended to.
+    fn new(out: W, format: Format, header: bool) -> Self {
+        Self { out, format, wrote_header: !header }
+    }
+
+    fn write<R: CsvRow + Serialize>(&mut self, row: &R) -> std::io::Result<()> {
+        match self.format {
+            Format::Csv => {
+                if !self.wrote_header {
+                    writeln!(self.out, "{}", R::HEADER)?;
+                    self.wrote_header = true;
+                }
+                row.write_csv(&mut self.out)
+            }
+            Format::Json => {
+                serde_json::to_writer(&mut self.out, row)?;
+                writeln!(self.out)
+            }
+        }
+    }
+}
+
+trait CsvRow {
+    const HEADER: &'static str;
+
+    fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()>;
+}
+
+/// Statistics of one kind of samples of a timer over an export interval. Durations are in nanoseconds.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct IntervalStats {
+    /// Unix timestamp of the end of the interval
+    pub timestamp_ms: u64,
+    pub timer: String,
+    pub kind: SampleKind,
+    pub samples: usize,
+    /// Messages published to the queue per second, counts messages that were overwritten before they were read
+    pub per_sec: f64,
+    pub p50_ns: u64,
+    pub p90_ns: u64,
+    pub p99_ns: u64,
+    pub max_ns: u64,
+}
+
+impl CsvRow for IntervalStats {
+    const HEADER: &'static str = "timestamp_ms,timer,kind,samples,per_sec,p50_ns,p90_ns,p99_ns,max_ns";
+
+    fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(
+            out,
+            "{},{},{},{},{:.3},{},{},{},{}",
+            self.timestamp_ms,
+            self.timer,
+            self.kind,
+            self.samples,
+            self.per_sec,
+            self.p50_ns,
+            self.p90_ns,
+            self.p99_ns,
+            self.max_ns
+        )
+    }
+}
+
+fn percentile(sorted: &[u64], p: f64) -> u64 {
+    if sorted.is_empty() {
+        return 0;
+    }
+    sorted[((sorted.len() - 1) as f64 * p).round() as usize]
+}
+
+/// Samples of one queue of a timer since the last export.
+struct Series {
+    kind: SampleKind,
+    consumer: Consumer<TimingMessage>,
+    samples: Vec<u64>,
+    /// Messages published to the queue at the start of the interval
+    published: usize,
+}
+
+impl Series {
+    fn new(kind: SampleKind, consumer: Consumer<TimingMessage>) -> Self {
+        Self { kind, published: consumer.tot_published(), consumer, samples: vec![] }
+    }
+
+    fn drain(&mut self, offset: Duration) {
+        while let Some(msg) = self.consumer.try_consume() {
+            self.samples.push(Nanos::from(Duration(msg.elapsed().0.saturating_sub(offset.0))).0);
+        }
+    }
+
+    fn take_stats(&mut self, timer: &str, elapsed: Duration, timestamp_ms: u64) -> IntervalStats {
+        let published = self.consumer.tot_published();
+        let per_sec = published.saturating_sub(self.published) as f64 / elapsed.as_secs();
+        self.published = published;
+
+        self.samples.sort_unstable();
+        let stats = IntervalStats {
+            timestamp_ms,
+            timer: timer.to_string(),
+            kind: self.kind,
+            samples: self.samples.len(),
+            per_sec,
+            p50_ns: percentile(&self.samples, 0.5),
+            p90_ns: percentile(&self.samples, 0.9),
+            p99_ns: percentile(&self.samples, 0.99),
+            max_ns: self.samples.last().copied().unwrap_or_default(),
+        };
+        self.samples.clear();
+        stats
+    }
+}
+
+struct ExportedTimer {
+    name: String,
+    series: [Series; 2],
+}
+
+/// Periodically writes [`IntervalStats`] for every timer with samples, and optionally sets them as gauges.
+pub struct Exporter<W> {
+    queues_dir: String,
+    timers: Vec<ExportedTimer>,
+    queue_checker: Repeater,
+    interval: Duration,
+    interval_start: Instant,
+    clock_overhead: Duration,
+    writer: RowWriter<W>,
+    /// Whether to set the stats as gauges, for a Prometheus endpoint
+    metrics: bool,
+}
+
+impl<W: Write> Exporter<W> {
+    /// Csv rows are written without a header if `header` is false, see [`RowWriter::new`].
+    pub fn new(queues_dir: String, interval: Duration, format: Format, out: W, header: bool, metrics: bool) -> Self {
+        Self {
+            queues_dir,
+            timers: vec![],
+            queue_checker: Repeater::every(Duration::from_secs(10)),
+            interval,
+            interval_start: Instant::now(),
+            clock_overhead: clock_overhead(),
+            writer: RowWriter::new(out, format, header),
+            metrics,
+        }
+    }
+
+    fn check_new_queues(&mut self) {
+        for name in timer_names(&self.queues_dir) {
+            if !self.timers.iter().any(|t| t.name == name) {
+                let (latency, business) = open_timer_queues(&self.queues_dir, &name);
+                let series = [Series::new(SampleKind::Latency, latency), Series::new(SampleKind::Business, business)];
+                self.timers.push(ExportedTimer { name, series });
+            }
+        }
+        self.timers.sort_unstable_by(|t1, t2| t1.name.cmp(&t2.name));
+    }
+
+    /// Reads new samples, and exports them once the interval is over.
+    pub fn update(&mut self) -> std::io::Result<()> {
+        if self.queue_checker.fired() {
+            self.check_new_queues();
+        }
+        for series in self.timers.iter_mut().flat_map(|t| &mut t.series) {
+            series.drain(self.clock_overhead);
+        }
+
+        let elapsed = self.interval_start.elapsed();
+        if elapsed < self.interval {
+            return Ok(());
+        }
+        self.interval_start = Instant::now();
+
+        let timestamp_ms = Nanos::now().as_millis_u64();
+        for timer in &mut self.timers {
+            for series in &mut timer.series {
+                let stats = series.take_stats(&timer.name, elapsed, timestamp_ms);
+                if stats.samples == 0 {
+                    continue;
+                }
+                if self.metrics {
+                    set_gauges(&stats);
+                }
+                self.writer.write(&stats)?;
+            }
+        }
+        self.writer.out.flush()
+    }
+
+    /// Exports until writing fails.
+    pub fn run(&mut self) -> std::io::Result<()> {
+        loop {
+            self.update()?;
+            std::thread::sleep(std::time::Duration::from_millis(1));
+        }
+    }
+}
+
+fn set_gauges(stats: &IntervalStats) {
+    let kind = stats.kind.as_str();
+    for (quantile, ns) in [("0.5", stats.p50_ns), ("0.9", stats.p90_ns), ("0.99", stats.p99_ns), ("1", stats.max_ns)] {
+        gauge!(TIMER_DURATION, "timer" => stats.timer.clone(), "kind" => kind, "quantile" => quantile)
+            .set(Nanos(ns).as_secs());
+    }
+    gauge!(TIMER_RATE, "timer" => stats.timer.clone(), "kind" => kind).set(stats.per_sec);
+}
+
+/// A single timing message, with unix timestamps.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct RawSample {
+    pub timer: String,
+    pub kind: SampleKind,
+    pub start_ns: u64,
+    pub stop_ns: u64,
+    pub duration_ns: u64,
+}
+
+impl CsvRow for RawSample {
+    const HEADER: &'static str = "timer,kind,start_ns,stop_ns,duration_ns";
+
+    fn write_csv(&self, out: &mut impl Write) -> std::io::Result<()> {
+        writeln!(out, "{},{},{},{},{}", self.timer, self.kind, self.start_ns, self.stop_ns, self.duration_ns)
+    }
+}
+
+/// Converts [`Instant`]s to unix time, relative to a pair of both taken at the same time.
+#[derive(Clone, Copy, Debug)]
+struct WallClock {
+    instant: Instant,
+    nanos: Nanos,
+}
+
+impl WallClock {
+    fn now() -> Self {
+        Self { instant: Instant::now(), nanos: Nanos::now() }
+    }
+
+    fn to_unix(self, t: Instant) -> Nanos {
+        if t >= self.instant {
+            self.nanos + Nanos::from(t - self.instant)
+        } else {
+            self.nanos.saturating_sub(Nanos::from(self.instant - t))
+        }
+    }
+}
+
+/// Window of samples to dump, as unix time.
+#[derive(Clone, Copy, Debug)]
+pub struct DumpWindow {
+    pub from: Nanos,
+    pub to: Nanos,
+}
+
+impl DumpWindow {
+    /// Window from `before` the start of the block with `timestamp` until `after` it.
+    pub fn around_block(timestamp: u64, before: Nanos, after: Nanos) -> Self {
+        let start = Nanos::from_secs(timestamp);
+        Self { from: start.saturating_sub(before), to: start + after }
+    }
+
+    fn contains(&self, sample: &RawSample) -> bool {
+        Nanos(sample.stop_ns) >= self.from && Nanos(sample.start_ns) <= self.to
+    }
+}
+
+/// Reads samples of all timers until the end of `window`, and writes the ones overlapping with it sorted by start.
+/// Returns the number of samples written.
+///
+/// Queues only hold recent messages, so this has to be started before the window begins to capture all of it.
+pub fn dump(queues_dir: &str, window: DumpWindow, format: Format, out: impl Write) -> std::io::Result<usize> {
+    if Nanos::now() > window.from {
+        warn!(from = %window.from, "dump window already started, earlier samples are missing");
+    }
+
+    let clock = WallClock::now();
+    let mut timers: Vec<(String, [Series; 2])> = vec![];
+    let mut queue_checker = Repeater::every(Duration::from_secs(1));
+    let mut samples = vec![];
+
+    // Give timers that publish right at the end of the window a moment to do so
+    while Nanos::now() < window.to + Nanos::from_millis(100) {
+        if queue_checker.fired() {
+            for name in timer_names(queues_dir) {
+                if !timers.iter().any(|(n, _)| *n == name) {
+                    let (latency, business) = open_timer_queues(queues_dir, &name);
+                    timers.push((
+                        name,
+                        [Series::new(SampleKind::Latency, latency), Series::new(SampleKind::Business, business)],
+                    ));
+                }
+            }
+        }
+
+        for (name, series) in &mut timers {
+            for series in series {
+                while let Some(msg) = series.consumer.try_consume() {
+                    let sample = RawSample {
+                        timer: name.clone(),
+                        kind: series.kind,
+                        start_ns: clock.to_unix(msg.start_t).0,
+                        stop_ns: clock.to_unix(msg.stop_t).0,
+                        duration_ns: Nanos::from(msg.elapsed()).0,
+                    };
+                    if window.contains(&sample) {
+                        samples.push(sample);
+                    }
+                }
+            }
+        }
+        std::thread::sleep(std::time::Duration::from_millis(1));
+    }
+
+    samples.sort_by_key(|sample| sample.start_ns);
+    let mut writer = RowWriter::new(out, format, true);
+    for sample in &samples {
+        writer.write(sample)?;
+    }
+    writer.out.flush()?;
+    Ok(samples.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stats(timer: &str) -> IntervalStats {
+        IntervalStats {
+            timestamp_ms: 1_700_000_000_000,
+            timer: timer.into(),
+            kind: SampleKind::Latency,
+            samples: 100,
+            per_sec: 10.0,
+            p50_ns: 50,
+            p90_ns: 90,
+            p99_ns: 99,
+            max_ns: 100,
+        }
+    }
+
+    #[test]
+    fn percentiles() {
+        let sorted: Vec<u64> = (1..=100).collect();
+        assert_eq!(percentile(&sorted, 0.5), 51);
+        assert_eq!(percentile(&sorted, 0.9), 90);
+        assert_eq!(percentile(&sorted, 0.99), 99);
+        assert_eq!(percentile(&sorted, 1.0), 100);
+        assert_eq!(percentile(&[], 0.5), 0);
+    }
+
+    #[test]
+    fn writes_rows() {
+        let mut csv = RowWriter::new(vec![], Format::Csv, true);
+        csv.write(&stats("sequencer")).unwrap();
+        csv.write(&stats("simulator")).unwrap();
+        assert_eq!(
+            String::from_utf8(csv.out).unwrap(),
+            "timestamp_ms,timer,kind,samples,per_sec,p50_ns,p90_ns,p99_ns,max_ns\n\
+             1700000000000,sequencer,latency,100,10.000,50,90,99,100\n\
+             1700000000000,simulator,latency,100,10.000,50,90,99,100\n"
+        );
+
+        let mut appended = RowWriter::new(vec![], Format::Csv, false);
+        appended.write(&stats("sequencer")).unwrap();
+        assert_eq!(
+            String::from_utf8(appended.out).unwrap(),
+            "1700000000000,sequencer,latency,100,10.000,50,90,99,100\n"
+        );
+
+        let mut json = RowWriter::new(vec![], Format::Json, true);
+        json.write(&stats("sequencer")).unwrap();
+        assert_eq!(json.out.last(), Some(&b'\n'));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&json.out).unwrap(),
+            serde_json::json!({
+                "timestamp_ms": 1_700_000_000_000u64,
+                "timer": "sequencer",
+                "kind": "latency",
+                "samples": 100,
+                "per_sec": 10.0,
+                "p50_ns": 50,
+                "p90_ns": 90,
+                "p99_ns": 99,
+                "max_ns": 100,
+            })
+        );
+    }
+
+    #[test]
+    fn dump_window() {
+        let window = DumpWindow::around_block(100, Nanos::from_millis(500), Nanos::from_secs(2));
+        let sample = |start_ms: u64, stop_ms: u64| RawSample {
+            timer: "sequencer".into(),
+            kind: SampleKind::Business,
+            start_ns: Nanos::from_millis(start_ms).0,
+            stop_ns: Nanos::from_millis(stop_ms).0,
+            duration_ns: Nanos::from_millis(stop_ms - start_ms).0,
+        };
+
+        assert!(window.contains(&sample(99_400, 99_600)));
+        assert!(window.contains(&sample(101_000, 101_001)));
+        assert!(window.contains(&sample(101_999, 103_000)));
+        assert!(!window.contains(&sample(99_000, 99_499)));
+        assert!(!window.contains(&sample(102_001, 102_002)));
+    }
+}
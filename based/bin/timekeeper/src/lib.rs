@@ -1,4 +1,5 @@
 pub mod circular_buffer;
+pub mod headless;
 pub mod statistics;
 pub mod timekeeper;
 pub mod tui;
@@ -1,10 +1,20 @@
-use std::io::stdout;
+use std::{
+    fs::File,
+    io::{stdout, BufWriter, Write},
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+};
 
 use bop_common::{
     communication::queues_dir_string,
-    time::{utils::renderloop_60_fps, Nanos},
+    metrics::init_prometheus,
+    time::{utils::renderloop_60_fps, Duration, Nanos},
+};
+use bop_timekeeper::{
+    headless::{dump, DumpWindow, Exporter, Format},
+    TimeKeeper,
 };
-use bop_timekeeper::TimeKeeper;
+use clap::{Parser, Subcommand};
 use crossterm::{
     event::{self, KeyCode, KeyEvent, KeyEventKind},
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
@@ -16,11 +26,101 @@ use ratatui::{
     text::Line,
     Terminal,
 };
-use tracing::warn;
+use tracing::{info, warn};
 
 const BLOCK_TSTAMP: Nanos = Nanos::from_secs(1729699211);
 
-fn main() {
+/// Shows the timers of a running gateway. Without a subcommand the timers are rendered in a TUI.
+#[derive(Parser)]
+#[command(version, about, name = "bop-timekeeper")]
+struct Args {
+    #[command(subcommand)]
+    mode: Option<Mode>,
+}
+
+#[derive(Subcommand)]
+enum Mode {
+    /// Periodically writes the percentiles and message rates of each timer
+    Export {
+        /// Interval the statistics are calculated over
+        #[arg(long, default_value_t = 10)]
+        interval_secs: u64,
+
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+
+        /// File to append to, stdout if not set. Csv is only written with a header to an empty file
+        #[arg(long)]
+        output: Option<PathBuf>,
+
+        /// Port to serve the statistics as Prometheus gauges on. Disabled if not set
+        #[arg(long = "metrics.port")]
+        metrics_port: Option<u16>,
+    },
+    /// Writes all samples in a window around a block and exits. Has to be started before the window begins
+    Dump {
+        /// Timestamp of the block
+        #[arg(long)]
+        block_timestamp: u64,
+
+        /// Start of the window before the block timestamp
+        #[arg(long, default_value_t = 500)]
+        before_ms: u64,
+
+        /// End of the window after the block timestamp
+        #[arg(long, default_value_t = 2500)]
+        after_ms: u64,
+
+        #[arg(long, value_enum, default_value_t = Format::Csv)]
+        format: Format,
+
+        /// File to write to, stdout if not set
+        #[arg(long)]
+        output: Option<PathBuf>,
+    },
+}
+
+/// Opens where rows are written, and whether it's empty so csv output needs a header.
+fn output(path: Option<&PathBuf>, append: bool) -> std::io::Result<(Box<dyn Write>, bool)> {
+    Ok(match path {
+        Some(path) => {
+            let file = File::options().create(true).write(true).append(append).truncate(!append).open(path)?;
+            let empty = file.metadata()?.len() == 0;
+            (Box::new(BufWriter::new(file)), empty)
+        }
+        None => (Box::new(stdout()), true),
+    })
+}
+
+fn main() -> eyre::Result<()> {
+    let mode = Args::parse().mode;
+    if mode.is_some() {
+        // Rows may be written to stdout
+        tracing_subscriber::fmt().with_writer(std::io::stderr).init();
+    }
+
+    match mode {
+        None => tui(),
+        Some(Mode::Export { interval_secs, format, output: path, metrics_port }) => {
+            if let Some(port) = metrics_port {
+                init_prometheus(SocketAddr::new(IpAddr::V4(Ipv4Addr::UNSPECIFIED), port))?;
+            }
+            let (out, empty) = output(path.as_ref(), true)?;
+            let interval = Duration::from_secs(interval_secs);
+            Exporter::new(queues_dir_string(), interval, format, out, empty, metrics_port.is_some()).run()?;
+        }
+        Some(Mode::Dump { block_timestamp, before_ms, after_ms, format, output: path }) => {
+            let window =
+                DumpWindow::around_block(block_timestamp, Nanos::from_millis(before_ms), Nanos::from_millis(after_ms));
+            let (out, _) = output(path.as_ref(), false)?;
+            let n_samples = dump(&queues_dir_string(), window, format, out)?;
+            info!(n_samples, "dumped samples");
+        }
+    }
+    Ok(())
+}
+
+fn tui() {
     stdout().execute(EnterAlternateScreen).unwrap();
     enable_raw_mode().unwrap();
     let mut terminal = Terminal::new(CrosstermBackend::new(stdout())).unwrap();
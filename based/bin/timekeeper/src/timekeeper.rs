@@ -121,6 +121,26 @@ pub fn clock_overhead() -> Duration {
     Duration((end.0 - start.0) / 1_000_000)
 }
 
+/// Names of the timers that have queues in `queues_dir`.
+pub(crate) fn timer_names(queues_dir: &str) -> Vec<String> {
+    let Ok(entries) = std::fs::read_dir(queues_dir) else {
+        return vec![];
+    };
+    entries
+        .filter_map(|e| e.ok())
+        .filter_map(|entry| entry.file_name().to_str()?.strip_prefix("latency-").map(str::to_string))
+        .collect()
+}
+
+/// Opens the latency and business time queues of a timer.
+pub(crate) fn open_timer_queues(queues_dir: &str, name: &str) -> (Consumer<TimingMessage>, Consumer<TimingMessage>) {
+    let latency_q = Queue::<TimingMessage>::open_shared(format!("{queues_dir}/latency-{name}"))
+        .expect("couldn't open latency queue");
+    let processing_q =
+        Queue::<TimingMessage>::open_shared(format!("{queues_dir}/timing-{name}")).expect("couldn't open timing queue");
+    (latency_q.into(), processing_q.into())
+}
+
 #[derive(Clone, Debug, Default)]
 struct TimeDatas {
     data: Vec<TimerData>,
@@ -187,30 +207,18 @@ impl TimeKeeper {
     }
 
     fn check_new_queues(&mut self) {
-        let queues_dir = &self.queues_dir;
-        let Ok(entries) = std::fs::read_dir(queues_dir) else {
-            return;
-        };
-        for entry in entries.filter_map(|e| e.ok()) {
-            let name = entry.path().as_os_str().to_str().unwrap().to_string();
-            if name.contains("latency") {
-                let (_dir, real_name) = name.split_once("latency-").unwrap();
-
-                if !self.time_datas.contains(real_name) {
-                    let latency_q = Queue::open_shared(format!("{queues_dir}/latency-{real_name}"))
-                        .expect("couldn't open latency queue");
-                    let processing_q = Queue::open_shared(format!("{queues_dir}/timing-{real_name}"))
-                        .expect("couldn't open timing queue");
-                    let d = TimerData::new(
-                        real_name.to_string().clone(),
-                        self.samples_per_median,
-                        self.n_datapoints,
-                        self.clock_overhead,
-                        latency_q.into(),
-                        processing_q.into(),
-                    );
-                    self.time_datas.push(d);
-                }
+        for name in timer_names(&self.queues_dir) {
+            if !self.time_datas.contains(&name) {
+                let (latency_consumer, processing_consumer) = open_timer_queues(&self.queues_dir, &name);
+                let d = TimerData::new(
+                    name,
+                    self.samples_per_median,
+                    self.n_datapoints,
+                    self.clock_overhead,
+                    latency_consumer,
+                    processing_consumer,
+                );
+                self.time_datas.push(d);
             }
         }
     }
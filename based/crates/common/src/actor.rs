@@ -1,21 +1,50 @@
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    Arc,
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::atomic::{AtomicUsize, Ordering},
 };
 
-use tracing::{debug, span, Level};
+use serde::{Deserialize, Deserializer};
+use tracing::{debug, info, span, warn, Level};
 
 use crate::{
-    communication::SpineConnections,
-    time::{vsync, Duration, Timer},
+    communication::{ChannelsConfig, SpineConnections},
+    shutdown::StopToken,
+    time::{vsync, vsync_busy, Duration, Timer},
     utils::last_part_of_typename,
 };
 
-#[derive(Copy, Clone, Default)]
+/// How an actor waits out the remainder of its `min_loop_duration`.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpinStrategy {
+    /// Yield the core to the OS scheduler.
+    #[default]
+    Sleep,
+    /// Busy poll, for actors pinned to an isolated core where wake up latency matters more than cpu usage.
+    Busy,
+}
+
+/// Thread and loop configuration of an actor. Fields left unset fall back to defaults, see [`ActorConfig::or`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
 pub struct ActorConfig {
     /// If Some: every round through the Actor::loop_body will at least take this long.
-    /// If work finished before this minimum time, the thread will sleep for the remainder.
+    /// If work finished before this minimum time, the thread will sleep or spin for the remainder.
+    #[serde(rename = "min_loop_duration_us", deserialize_with = "deserialize_micros")]
     min_loop_duration: Option<Duration>,
+    /// Wait strategy for the remainder of `min_loop_duration`.
+    spin: Option<SpinStrategy>,
+    /// Core the actor thread is pinned to.
+    core: Option<usize>,
+    /// Name of the actor thread, truncated to 15 characters by the OS.
+    name: Option<String>,
+    /// `SCHED_FIFO` priority of the actor thread, between 1 and 99. Requires `CAP_SYS_NICE`.
+    realtime_priority: Option<i32>,
+}
+
+fn deserialize_micros<'de, D: Deserializer<'de>>(deserializer: D) -> Result<Option<Duration>, D::Error> {
+    Ok(Option::<u64>::deserialize(deserializer)?.map(Duration::from_micros))
 }
 
 impl ActorConfig {
@@ -23,6 +52,161 @@ impl ActorConfig {
         self.min_loop_duration = Some(min_loop_duration);
         self
     }
+
+    pub fn with_spin(mut self, spin: SpinStrategy) -> Self {
+        self.spin = Some(spin);
+        self
+    }
+
+    pub fn with_core(mut self, core: usize) -> Self {
+        self.core = Some(core);
+        self
+    }
+
+    pub fn with_name(mut self, name: impl Into<String>) -> Self {
+        self.name = Some(name.into());
+        self
+    }
+
+    pub fn with_realtime_priority(mut self, priority: i32) -> Self {
+        self.realtime_priority = Some(priority);
+        self
+    }
+
+    /// Fills the fields that are not set with the ones from `defaults`.
+    pub fn or(self, defaults: ActorConfig) -> Self {
+        Self {
+            min_loop_duration: self.min_loop_duration.or(defaults.min_loop_duration),
+            spin: self.spin.or(defaults.spin),
+            core: self.core.or(defaults.core),
+            name: self.name.or(defaults.name),
+            realtime_priority: self.realtime_priority.or(defaults.realtime_priority),
+        }
+    }
+
+    /// Builder for the actor thread, named after the configured name or `default_name`.
+    pub fn thread(&self, default_name: &str) -> std::thread::Builder {
+        std::thread::Builder::new().name(self.name.clone().unwrap_or_else(|| default_name.to_string()))
+    }
+
+    /// Pins the calling thread and sets its priority. Failures are logged, the actor still runs without them.
+    fn apply_to_current_thread(&self) {
+        if let Some(core) = self.core {
+            if core_affinity::set_for_current(core_affinity::CoreId { id: core }) {
+                info!(core, "pinned thread");
+            } else {
+                warn!(core, "couldn't pin thread");
+            }
+        }
+
+        if let Some(priority) = self.realtime_priority {
+            match set_realtime_priority(priority) {
+                Ok(()) => info!(priority, "set real-time priority"),
+                Err(err) => warn!(priority, %err, "couldn't set real-time priority"),
+            }
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn set_realtime_priority(priority: i32) -> std::io::Result<()> {
+    let param = libc::sched_param { sched_priority: priority };
+    // SAFETY: `param` outlives the call and pid 0 targets the calling thread
+    let res = unsafe { libc::sched_setscheduler(0, libc::SCHED_FIFO, &param) };
+    if res == 0 {
+        Ok(())
+    } else {
+        Err(std::io::Error::last_os_error())
+    }
+}
+
+#[cfg(not(target_os = "linux"))]
+fn set_realtime_priority(_priority: i32) -> std::io::Result<()> {
+    Err(std::io::Error::new(std::io::ErrorKind::Unsupported, "real-time priority is only supported on linux"))
+}
+
+/// Tokio runtime serving the RPC, kept off the cores of the busy polling actors.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RuntimeConfig {
+    pub worker_threads: usize,
+    /// Cores the worker threads are pinned to, round robin. Not pinned if empty.
+    pub cores: Vec<usize>,
+}
+
+impl Default for RuntimeConfig {
+    fn default() -> Self {
+        Self { worker_threads: 4, cores: vec![] }
+    }
+}
+
+impl RuntimeConfig {
+    pub fn build(&self, name: &str) -> std::io::Result<tokio::runtime::Runtime> {
+        let cores = self.cores.clone();
+        let next = AtomicUsize::new(0);
+
+        tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(self.worker_threads)
+            .thread_name(name)
+            .on_thread_start(move || {
+                if cores.is_empty() {
+                    return;
+                }
+                let core = cores[next.fetch_add(1, Ordering::Relaxed) % cores.len()];
+                if !core_affinity::set_for_current(core_affinity::CoreId { id: core }) {
+                    warn!(core, "couldn't pin runtime thread");
+                }
+            })
+            .enable_all()
+            .build()
+    }
+}
+
+/// Thread configuration of all actors and the RPC runtime, and the channels between them, loaded from a TOML file:
+///
+/// ```toml
+/// [runtime]
+/// worker_threads = 4
+/// cores = [0, 1]
+///
+/// [actors.Sequencer]
+/// core = 2
+/// realtime_priority = 80
+/// spin = "busy"
+///
+/// [actors.Simulator]
+/// min_loop_duration_us = 100
+///
+/// [channels.sequencer_to_simulator]
+/// capacity = 8192
+/// overflow = "drop_oldest"
+/// ```
+///
+/// Actors are looked up by the name of their connections. Numbered actors, e.g. `Simulator-1`, fall back to the
+/// entry of their kind.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ActorsConfig {
+    pub runtime: RuntimeConfig,
+    pub actors: HashMap<String, ActorConfig>,
+    pub channels: ChannelsConfig,
+}
+
+impl ActorsConfig {
+    pub fn from_toml(toml: &str) -> eyre::Result<Self> {
+        Ok(toml::from_str(toml)?)
+    }
+
+    pub fn from_file(path: impl AsRef<Path>) -> eyre::Result<Self> {
+        Self::from_toml(&std::fs::read_to_string(path)?)
+    }
+
+    /// Config of the actor called `name`, with unset fields taken from `defaults`.
+    pub fn actor(&self, name: &str, defaults: ActorConfig) -> ActorConfig {
+        let kind = name.split_once('-').map_or(name, |(kind, _)| kind);
+        let config = self.actors.get(name).or_else(|| self.actors.get(kind)).cloned().unwrap_or_default();
+        config.or(defaults.with_name(name))
+    }
 }
 
 pub trait Actor<Db>: Sized {
@@ -39,6 +223,12 @@ pub trait Actor<Db>: Sized {
         debug!("initialized...");
     }
 
+    /// Called after each loop once the actor's shutdown stage is signalled, the actor keeps looping until this returns
+    /// true. Used to finish in-flight work, `stop` tells how much time is left.
+    fn ready_to_stop(&mut self, _connections: &mut SpineConnections<Db>, _stop: &StopToken) -> bool {
+        true
+    }
+
     fn on_exit(self, _connections: &mut SpineConnections<Db>) {}
     fn _on_exit(self, connections: &mut SpineConnections<Db>) {
         debug!("running final tasks before stopping...");
@@ -46,25 +236,30 @@ pub trait Actor<Db>: Sized {
         debug!("finalized");
     }
 
-    fn run(mut self, mut connections: SpineConnections<Db>, actor_config: ActorConfig) {
+    /// Runs the actor until `stop` is signalled and the actor is ready to stop. `stop` is only dropped after
+    /// [`Actor::on_exit`], which lets the next shutdown stage start.
+    fn run(mut self, mut connections: SpineConnections<Db>, actor_config: ActorConfig, stop: StopToken) {
         let name = self.name();
         let _s = span!(Level::INFO, "", id = name).entered();
 
+        actor_config.apply_to_current_thread();
+
         let mut loop_timer = Timer::new(format!("{}-loop", name));
 
         self._on_init(&mut connections);
 
-        let term = Arc::new(AtomicBool::new(false));
-        signal_hook::flag::register(signal_hook::consts::SIGINT, Arc::clone(&term))
-            .expect("couldn't register signal hook for some reason");
-
         loop {
-            loop_timer.start();
-            if vsync(actor_config.min_loop_duration, || {
+            let body = || {
+                loop_timer.start();
                 self.loop_body(&mut connections);
                 loop_timer.stop();
-                term.load(Ordering::Relaxed)
-            }) {
+                stop.should_stop()
+            };
+            let stopping = match actor_config.spin.unwrap_or_default() {
+                SpinStrategy::Sleep => vsync(actor_config.min_loop_duration, body),
+                SpinStrategy::Busy => vsync_busy(actor_config.min_loop_duration, body),
+            };
+            if stopping && self.ready_to_stop(&mut connections, &stop) {
                 break;
             }
         }
@@ -72,3 +267,73 @@ pub trait Actor<Db>: Sized {
         self._on_exit(&mut connections)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::communication::{ChannelConfig, OverflowPolicy};
+
+    #[test]
+    fn parses_config_file() {
+        let config = ActorsConfig::from_toml(
+            r#"
+            [runtime]
+            worker_threads = 2
+            cores = [0, 1]
+
+            [actors.Sequencer]
+            core = 2
+            realtime_priority = 80
+            spin = "busy"
+
+            [actors.Simulator]
+            min_loop_duration_us = 100
+
+            [channels.sequencer_to_simulator]
+            capacity = 8192
+            overflow = "drop_oldest"
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(config.runtime, RuntimeConfig { worker_threads: 2, cores: vec![0, 1] });
+        assert_eq!(
+            config.actor("Sequencer", ActorConfig::default()),
+            ActorConfig::default()
+                .with_core(2)
+                .with_realtime_priority(80)
+                .with_spin(SpinStrategy::Busy)
+                .with_name("Sequencer")
+        );
+        assert_eq!(
+            config.channels.sequencer_to_simulator,
+            ChannelConfig { capacity: 8192, overflow: OverflowPolicy::DropOldest, ..Default::default() }
+        );
+        assert_eq!(config.channels.simulator_to_sequencer, ChannelConfig::default());
+        assert!(ActorsConfig::from_toml("[actors.Sequencer]\ncpu = 2").is_err());
+    }
+
+    #[test]
+    fn falls_back_to_kind_and_defaults() {
+        let config = ActorsConfig::from_toml(
+            r#"
+            [actors.Simulator]
+            core = 3
+
+            [actors.Simulator-1]
+            core = 4
+            "#,
+        )
+        .unwrap();
+        let defaults = ActorConfig::default().with_min_loop_duration(Duration::from_millis(10)).with_core(7);
+
+        let sim_0 = config.actor("Simulator-0", defaults.clone());
+        assert_eq!(sim_0, defaults.clone().with_core(3).with_name("Simulator-0"));
+
+        let sim_1 = config.actor("Simulator-1", defaults.clone());
+        assert_eq!(sim_1.core, Some(4));
+
+        let fetcher = config.actor("BlockFetch", defaults.clone());
+        assert_eq!(fetcher, defaults.with_name("BlockFetch"));
+    }
+}
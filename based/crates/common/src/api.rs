@@ -1,27 +1,17 @@
+use alloy_eips::eip7685::Requests;
 use alloy_primitives::{Address, Bytes, B256, U256};
 use alloy_rpc_types::{
     engine::{ExecutionPayloadV3, ForkchoiceState, ForkchoiceUpdated, PayloadId, PayloadStatus},
-    BlockId, BlockNumberOrTag,
+    BlockId, BlockNumberOrTag, Header, TransactionRequest,
 };
 use jsonrpsee::proc_macros::rpc;
 use op_alloy_consensus::OpTxEnvelope;
 use op_alloy_rpc_types::OpTransactionReceipt;
-use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpPayloadAttributes};
-
-use crate::communication::messages::RpcResult;
-
-pub const CAPABILITIES: &[&str] = &[
-    "engine_forkchoiceUpdatedV3",
-    "engine_getPayloadV3",
-    "engine_newPayloadV3",
-    "eth_sendRawTransaction",
-    // "eth_getTransactionReceipt",
-    // "eth_getBlockByNumber",
-    // "eth_getBlockByHash",
-    // "eth_blockNumber",
-    // "eth_getTransactionCount",
-    // "eth_getBalance",
-];
+use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpExecutionPayloadEnvelopeV4, OpPayloadAttributes};
+
+use crate::{
+    communication::messages::RpcResult, p2p::SignedMessage, schedule::SlotAssignment, signing::SignedPreconfirmation,
+};
 
 pub type OpRpcBlock = alloy_rpc_types::Block<OpTxEnvelope>;
 
@@ -31,7 +21,7 @@ pub type OpRpcBlock = alloy_rpc_types::Block<OpTxEnvelope>;
 /// ref: https://github.com/ethereum/execution-apis/tree/main/src/engine
 /// ref: https://specs.optimism.io/protocol/exec-engine.html#engine-api
 ///
-/// NOTE: currently only v3 endpoints are supported
+/// Payloads from Isthmus on are passed with the v4 endpoints, earlier ones with v3. Fork choice updates use v3 for both.
 #[rpc(client, server, namespace = "engine")]
 pub trait EngineApi {
     /// Used by the op-node to set which blocks are considered canonical.
@@ -57,12 +47,28 @@ pub trait EngineApi {
     /// Used to fetch an execution payload from a previous `payload_id` set in `forkchoiceUpdatedV3`
     #[method(name = "getPayloadV3")]
     async fn get_payload_v3(&self, payload_id: PayloadId) -> RpcResult<OpExecutionPayloadEnvelopeV3>;
+
+    /// Used to validate an Isthmus execution payload. OP Stack chains have no execution layer requests, so
+    /// `execution_requests` must be empty.
+    #[method(name = "newPayloadV4")]
+    async fn new_payload_v4(
+        &self,
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Requests,
+    ) -> RpcResult<PayloadStatus>;
+
+    /// Used to fetch an Isthmus execution payload from a previous `payload_id` set in `forkchoiceUpdatedV3`
+    #[method(name = "getPayloadV4")]
+    async fn get_payload_v4(&self, payload_id: PayloadId) -> RpcResult<OpExecutionPayloadEnvelopeV4>;
 }
 
 /// The Eth API is used to interact with the EL directly.
 ///
 /// This is a temporary API that the gateway implements to serve the latest preconf state, before a
-/// gossip protocol is implemented in op-node. Historical state will not be served from this API
+/// gossip protocol is implemented in op-node. State queries at past blocks are served by the gateway within its
+/// history window, see [`EthStateApi`].
 #[rpc(client, server, namespace = "eth")]
 pub trait EthApi {
     /// Sends signed transaction, returning its hash
@@ -98,9 +104,115 @@ pub trait EthApi {
     async fn balance(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256>;
 }
 
+/// State queries served by the gateway.
+///
+/// `pending` is the latest preconfirmed state, `latest` the last committed block, and explicit block numbers or
+/// hashes are served from the changesets for as long as they are within the gateway's history window.
+#[rpc(client, server, namespace = "eth")]
+pub trait EthStateApi {
+    /// Returns the number of the last committed block.
+    #[method(name = "blockNumber")]
+    async fn block_number(&self) -> RpcResult<U256>;
+
+    /// Returns the header of a committed block, `pending` is not supported.
+    #[method(name = "getHeaderByNumber")]
+    async fn header_by_number(&self, number: BlockNumberOrTag) -> RpcResult<Option<Header>>;
+
+    /// Returns the nonce of a given address at a given block number.
+    #[method(name = "getTransactionCount")]
+    async fn transaction_count(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256>;
+
+    /// Returns the balance of the account of given address.
+    #[method(name = "getBalance")]
+    async fn balance(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256>;
+
+    /// Returns the value of a storage slot of the given address.
+    #[method(name = "getStorageAt")]
+    async fn storage_at(&self, address: Address, slot: U256, block_number: Option<BlockId>) -> RpcResult<B256>;
+
+    /// Returns the code of the given address.
+    #[method(name = "getCode")]
+    async fn code(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<Bytes>;
+
+    /// Executes a call without creating a transaction, returning its output.
+    #[method(name = "call")]
+    async fn call(&self, request: TransactionRequest, block_number: Option<BlockId>) -> RpcResult<Bytes>;
+}
+
 #[rpc(client, server, namespace = "eth")]
 pub trait MinimalEthApi {
     /// Sends signed transaction, returning its hash
     #[method(name = "sendRawTransaction")]
     async fn send_raw_transaction(&self, bytes: Bytes) -> RpcResult<B256>;
 }
+
+/// Transaction submission with a commitment from the sequencer, for users that need more than a tx hash.
+#[rpc(client, server, namespace = "based")]
+pub trait PreconfApi {
+    /// Sends a signed transaction and waits until it is included in a sealed frag. Returns the inclusion signed by the
+    /// sequencer, which can be checked with [`crate::signing::verify_preconfirmation`].
+    #[method(name = "sendRawTransactionWithCommitment")]
+    async fn send_raw_transaction_with_commitment(&self, bytes: Bytes) -> RpcResult<SignedPreconfirmation>;
+}
+
+/// Sequencer rotation served by the portal, so followers and users know whose frags and commitments to trust.
+///
+/// Slots are relative to the next block to be sequenced. All methods return nothing if no schedule is configured.
+#[rpc(client, server, namespace = "based")]
+pub trait ScheduleApi {
+    /// Returns the slot of the block currently being sequenced.
+    #[method(name = "currentSequencer")]
+    async fn current_sequencer(&self) -> RpcResult<Option<SlotAssignment>>;
+
+    /// Returns the slot following the current one.
+    #[method(name = "nextSequencer")]
+    async fn next_sequencer(&self) -> RpcResult<Option<SlotAssignment>>;
+
+    /// Returns up to `slots` consecutive slots, starting with the current one.
+    #[method(name = "sequencerLookahead")]
+    async fn sequencer_lookahead(&self, slots: u64) -> RpcResult<Vec<SlotAssignment>>;
+}
+
+/// Frag stream gossiped by the gateway, served by followers.
+///
+/// Each message is signed by the gateway over its tree hash root, see [`SignedMessage`].
+#[rpc(client, server, namespace = "based")]
+pub trait FragApi {
+    /// Opens a new block with the environment of the block.
+    #[method(name = "env")]
+    async fn env(&self, signed: SignedMessage) -> RpcResult<()>;
+
+    /// Applies the next frag of the open block.
+    #[method(name = "newFrag")]
+    async fn new_frag(&self, signed: SignedMessage) -> RpcResult<()>;
+
+    /// Applies the next frag of the open block and checks its commitments to the state after it.
+    #[method(name = "newFragV1")]
+    async fn new_frag_v1(&self, signed: SignedMessage) -> RpcResult<()>;
+
+    /// Seals the open block.
+    #[method(name = "sealFrag")]
+    async fn seal_frag(&self, signed: SignedMessage) -> RpcResult<()>;
+}
+
+/// Messages the gateway gossiped for its most recent blocks, for followers to backfill what they missed.
+///
+/// All methods return nothing for blocks that are not, or no longer, kept by the gateway.
+#[rpc(client, server, namespace = "based")]
+pub trait FragArchiveApi {
+    /// Returns the env of a block.
+    #[method(name = "getEnv")]
+    async fn get_env(&self, block_number: u64) -> RpcResult<Option<SignedMessage>>;
+
+    /// Returns a frag of a block.
+    #[method(name = "getFrag")]
+    async fn get_frag(&self, block_number: u64, seq: u64) -> RpcResult<Option<SignedMessage>>;
+
+    /// Returns all frags of a block, ordered by seq.
+    #[method(name = "getFrags")]
+    async fn get_frags(&self, block_number: u64) -> RpcResult<Vec<SignedMessage>>;
+
+    /// Returns the seal of a block.
+    #[method(name = "getSeal")]
+    async fn get_seal(&self, block_number: u64) -> RpcResult<Option<SignedMessage>>;
+}
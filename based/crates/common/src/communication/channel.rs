@@ -0,0 +1,380 @@
+//! Bounded spine channels with a configurable capacity and overflow policy.
+//!
+//! Every drop, timed out send and new high-water mark is counted per channel and exported through metrics, so a
+//! saturated channel shows up on the dashboards instead of silently losing messages.
+
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+use crossbeam_channel::{SendTimeoutError, TrySendError};
+use metrics::{counter, gauge};
+use serde::Deserialize;
+use tracing::{error, info, warn};
+
+use super::NonBlockingSender;
+use crate::{
+    metrics::{CHANNEL_DROPS, CHANNEL_HIGH_WATER_MARK, CHANNEL_SATURATED, CHANNEL_SEND_TIMEOUTS},
+    time::Duration,
+};
+
+/// What happens to a message sent to a full channel.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OverflowPolicy {
+    /// The oldest queued message is dropped to make room, sends never wait.
+    DropOldest,
+    /// The message being sent is dropped, sends never wait.
+    DropNewest,
+    /// Sends wait for room, up to the timeout of the send. Messages that time out are dropped.
+    #[default]
+    Block,
+}
+
+impl OverflowPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OverflowPolicy::DropOldest => "drop_oldest",
+            OverflowPolicy::DropNewest => "drop_newest",
+            OverflowPolicy::Block => "block",
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelConfig {
+    pub capacity: usize,
+    pub overflow: OverflowPolicy,
+    /// Fill level, in percent of `capacity`, at which the channel is reported as saturated.
+    pub saturation_percent: u8,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self { capacity: 4096, overflow: OverflowPolicy::Block, saturation_percent: 90 }
+    }
+}
+
+/// Configuration of each of the channels of the [`Spine`](super::Spine), by name.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChannelsConfig {
+    pub simulator_to_sequencer: ChannelConfig,
+    pub sequencer_to_simulator: ChannelConfig,
+    pub sequencer_to_rpc: ChannelConfig,
+    pub engine_rpc_to_sequencer: ChannelConfig,
+    pub eth_rpc_to_sequencer: ChannelConfig,
+    pub blockfetch_to_sequencer: ChannelConfig,
+    pub sequencer_to_blockfetch: ChannelConfig,
+    pub sequencer_frag_broadcast: ChannelConfig,
+}
+
+/// Counters of a channel since it was created.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq)]
+pub struct ChannelStats {
+    pub drops: u64,
+    pub timeouts: u64,
+    pub high_water_mark: usize,
+}
+
+struct State {
+    name: &'static str,
+    config: ChannelConfig,
+    drops: AtomicU64,
+    timeouts: AtomicU64,
+    high_water_mark: AtomicUsize,
+    saturated: AtomicBool,
+}
+
+impl State {
+    fn record_drop(&self) {
+        self.drops.fetch_add(1, Ordering::Relaxed);
+        counter!(CHANNEL_DROPS, "channel" => self.name, "policy" => self.config.overflow.as_str()).increment(1);
+    }
+
+    fn record_timeout(&self, timeout: Duration) {
+        error!(channel = self.name, %timeout, "send timed out, dropping message");
+        self.timeouts.fetch_add(1, Ordering::Relaxed);
+        counter!(CHANNEL_SEND_TIMEOUTS, "channel" => self.name).increment(1);
+        self.record_drop();
+    }
+
+    fn record_len(&self, len: usize) {
+        if len > self.high_water_mark.fetch_max(len, Ordering::Relaxed) {
+            gauge!(CHANNEL_HIGH_WATER_MARK, "channel" => self.name).set(len as f64);
+        }
+
+        let capacity = self.config.capacity;
+        let saturated = len * 100 >= capacity * self.config.saturation_percent as usize;
+        if saturated != self.saturated.swap(saturated, Ordering::Relaxed) {
+            if saturated {
+                warn!(channel = self.name, len, capacity, "channel saturated");
+            } else {
+                info!(channel = self.name, len, capacity, "channel no longer saturated");
+            }
+            gauge!(CHANNEL_SATURATED, "channel" => self.name).set(if saturated { 1.0 } else { 0.0 });
+        }
+    }
+}
+
+/// Sending side of a bounded channel that applies the [`OverflowPolicy`] of the channel and keeps [`ChannelStats`].
+pub struct ChannelSender<T> {
+    sender: crossbeam_channel::Sender<T>,
+    /// Used to evict the oldest message, only kept with [`OverflowPolicy::DropOldest`] as it keeps the channel
+    /// connected after the receivers are gone.
+    evict: Option<crossbeam_channel::Receiver<T>>,
+    state: Arc<State>,
+}
+
+/// Creates a channel called `name`, the name is used as metrics label.
+pub fn bounded<T>(name: &'static str, config: ChannelConfig) -> (ChannelSender<T>, crossbeam_channel::Receiver<T>) {
+    let (sender, receiver) = crossbeam_channel::bounded(config.capacity);
+    let state = Arc::new(State {
+        name,
+        config,
+        drops: AtomicU64::new(0),
+        timeouts: AtomicU64::new(0),
+        high_water_mark: AtomicUsize::new(0),
+        saturated: AtomicBool::new(false),
+    });
+    let evict = (config.overflow == OverflowPolicy::DropOldest).then(|| receiver.clone());
+    (ChannelSender { sender, evict, state }, receiver)
+}
+
+impl<T> ChannelSender<T> {
+    pub fn name(&self) -> &'static str {
+        self.state.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.sender.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.sender.is_empty()
+    }
+
+    pub fn stats(&self) -> ChannelStats {
+        ChannelStats {
+            drops: self.state.drops.load(Ordering::Relaxed),
+            timeouts: self.state.timeouts.load(Ordering::Relaxed),
+            high_water_mark: self.state.high_water_mark.load(Ordering::Relaxed),
+        }
+    }
+
+    fn record_sent(&self) {
+        self.state.record_len(self.sender.len());
+    }
+
+    /// Same as [`NonBlockingSender::send_timeout`], but waits for room without blocking the thread, for senders
+    /// running on an async runtime.
+    pub async fn send_timeout_async(&self, mut data: T, timeout: Duration) -> Result<(), T> {
+        if self.state.config.overflow != OverflowPolicy::Block {
+            return self.try_send(data);
+        }
+
+        let deadline = tokio::time::Instant::now() + std::time::Duration::from(timeout);
+        loop {
+            match self.sender.try_send(data) {
+                Ok(()) => {
+                    self.record_sent();
+                    return Ok(());
+                }
+                Err(TrySendError::Full(d)) if tokio::time::Instant::now() < deadline => {
+                    data = d;
+                    tokio::time::sleep(ASYNC_SEND_RETRY_INTERVAL).await;
+                }
+                Err(TrySendError::Full(d)) => {
+                    self.state.record_timeout(timeout);
+                    return Err(d);
+                }
+                Err(TrySendError::Disconnected(d)) => return Err(d),
+            }
+        }
+    }
+}
+
+/// How long [`ChannelSender::send_timeout_async`] waits before retrying a send to a full channel.
+const ASYNC_SEND_RETRY_INTERVAL: std::time::Duration = std::time::Duration::from_micros(100);
+
+impl<T> NonBlockingSender<T> for ChannelSender<T> {
+    /// Sends without waiting. A full channel drops the oldest message with [`OverflowPolicy::DropOldest`] and the
+    /// sent one with [`OverflowPolicy::DropNewest`]. With [`OverflowPolicy::Block`] the message is handed back without
+    /// counting a drop, as the caller may retry.
+    fn try_send(&self, mut data: T) -> Result<(), T> {
+        loop {
+            match self.sender.try_send(data) {
+                Ok(()) => {
+                    self.record_sent();
+                    return Ok(());
+                }
+                Err(TrySendError::Full(d)) => match &self.evict {
+                    Some(evict) => {
+                        if evict.try_recv().is_ok() {
+                            self.state.record_drop();
+                        }
+                        data = d;
+                    }
+                    None => {
+                        self.state.record_len(self.sender.len());
+                        if self.state.config.overflow == OverflowPolicy::DropNewest {
+                            self.state.record_drop();
+                        }
+                        return Err(d);
+                    }
+                },
+                Err(TrySendError::Disconnected(d)) => return Err(d),
+            }
+        }
+    }
+
+    fn send_timeout(&self, data: T, timeout: Duration) -> Result<(), T> {
+        if self.state.config.overflow != OverflowPolicy::Block {
+            return self.try_send(data);
+        }
+
+        match self.sender.send_timeout(data, timeout.into()) {
+            Ok(()) => {
+                self.record_sent();
+                Ok(())
+            }
+            Err(SendTimeoutError::Timeout(d)) => {
+                self.state.record_timeout(timeout);
+                Err(d)
+            }
+            Err(SendTimeoutError::Disconnected(d)) => Err(d),
+        }
+    }
+
+    fn send_forever(&self, data: T) -> Result<(), T> {
+        if self.state.config.overflow != OverflowPolicy::Block {
+            return self.try_send(data);
+        }
+
+        match self.sender.try_send(data) {
+            Ok(()) => {
+                self.record_sent();
+                Ok(())
+            }
+            Err(TrySendError::Full(d)) => {
+                warn!(channel = self.state.name, "channel full, waiting for room");
+                self.sender.send(d).map_err(|e| e.into_inner())?;
+                self.record_sent();
+                Ok(())
+            }
+            Err(TrySendError::Disconnected(d)) => Err(d),
+        }
+    }
+}
+
+impl<T> Clone for ChannelSender<T> {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone(), evict: self.evict.clone(), state: self.state.clone() }
+    }
+}
+
+impl<T> fmt::Debug for ChannelSender<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ChannelSender")
+            .field("name", &self.state.name)
+            .field("config", &self.state.config)
+            .field("len", &self.len())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(overflow: OverflowPolicy) -> (ChannelSender<u32>, crossbeam_channel::Receiver<u32>) {
+        bounded("test", ChannelConfig { capacity: 2, overflow, saturation_percent: 100 })
+    }
+
+    #[test]
+    fn drop_oldest_keeps_newest() {
+        let (sender, receiver) = channel(OverflowPolicy::DropOldest);
+        for i in 0..5 {
+            assert!(sender.try_send(i).is_ok());
+        }
+
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![3, 4]);
+        assert_eq!(sender.stats(), ChannelStats { drops: 3, timeouts: 0, high_water_mark: 2 });
+    }
+
+    #[test]
+    fn drop_newest_rejects_send() {
+        let (sender, receiver) = channel(OverflowPolicy::DropNewest);
+        for i in 0..4 {
+            let _ = sender.send_timeout(i, Duration::from_millis(10));
+        }
+
+        assert_eq!(sender.send_forever(4), Err(4));
+        assert_eq!(receiver.try_iter().collect::<Vec<_>>(), vec![0, 1]);
+        assert_eq!(sender.stats(), ChannelStats { drops: 3, timeouts: 0, high_water_mark: 2 });
+    }
+
+    #[test]
+    fn block_times_out() {
+        let (sender, receiver) = channel(OverflowPolicy::Block);
+        for i in 0..2 {
+            assert!(sender.send_timeout(i, Duration::from_millis(1)).is_ok());
+        }
+
+        assert_eq!(sender.send_timeout(2, Duration::from_millis(1)), Err(2));
+        assert_eq!(sender.stats(), ChannelStats { drops: 1, timeouts: 1, high_water_mark: 2 });
+
+        let consumer = std::thread::spawn(move || {
+            std::thread::sleep(std::time::Duration::from_millis(5));
+            receiver.recv().unwrap()
+        });
+        assert!(sender.send_forever(3).is_ok());
+        assert_eq!(consumer.join().unwrap(), 0);
+    }
+
+    #[test]
+    fn block_try_send_is_not_a_drop() {
+        let (sender, _receiver) = channel(OverflowPolicy::Block);
+        for i in 0..2 {
+            assert!(sender.try_send(i).is_ok());
+        }
+
+        assert_eq!(sender.try_send(2), Err(2));
+        assert_eq!(sender.stats(), ChannelStats { drops: 0, timeouts: 0, high_water_mark: 2 });
+    }
+
+    #[test]
+    fn disconnects_without_receivers() {
+        for overflow in [OverflowPolicy::Block, OverflowPolicy::DropNewest] {
+            let (sender, receiver) = channel(overflow);
+            drop(receiver);
+
+            assert_eq!(sender.try_send(0), Err(0));
+            assert_eq!(sender.send_forever(1), Err(1));
+            assert_eq!(sender.stats().drops, 0);
+        }
+    }
+
+    #[tokio::test]
+    async fn block_async_send_waits_for_room() {
+        let (sender, receiver) = channel(OverflowPolicy::Block);
+        for i in 0..2 {
+            assert!(sender.try_send(i).is_ok());
+        }
+
+        assert_eq!(sender.send_timeout_async(2, Duration::from_millis(1)).await, Err(2));
+        assert_eq!(sender.stats(), ChannelStats { drops: 1, timeouts: 1, high_water_mark: 2 });
+
+        let consumer = tokio::spawn(async move {
+            tokio::time::sleep(std::time::Duration::from_millis(5)).await;
+            receiver.recv().unwrap()
+        });
+        assert!(sender.send_timeout_async(3, Duration::from_millis(1_000)).await.is_ok());
+        assert_eq!(consumer.await.unwrap(), 0);
+    }
+}
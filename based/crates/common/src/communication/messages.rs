@@ -4,19 +4,23 @@ use std::{
 };
 
 use alloy_consensus::BlockHeader;
-use alloy_eips::eip2718::Encodable2718;
-use alloy_primitives::B256;
+use alloy_eips::{eip2718::Encodable2718, eip7685::Requests};
+use alloy_primitives::{keccak256, Bytes, B256};
+use alloy_rlp::Encodable;
 use alloy_rpc_types::engine::{
-    ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3, ForkchoiceState, PayloadAttributes, PayloadError,
-    PayloadId,
+    ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3, ForkchoiceState, ForkchoiceUpdated, PayloadAttributes,
+    PayloadError, PayloadId, PayloadStatus,
 };
 use jsonrpsee::types::{ErrorCode, ErrorObject as RpcErrorObject};
-use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpPayloadAttributes};
+use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpExecutionPayloadEnvelopeV4, OpPayloadAttributes};
 use reth_evm::{execute::BlockExecutionError, NextBlockEnvAttributes};
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_forks::OpHardforks;
 use reth_optimism_primitives::{transaction::TransactionSenderInfo, OpBlock};
 use reth_primitives::BlockWithSenders;
 use revm_primitives::{Address, Env, SpecId, U256};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use strum_macros::AsRefStr;
 use thiserror::Error;
 use tokio::sync::oneshot::{self};
@@ -162,11 +166,79 @@ impl<T> From<InternalMessage<T>> for Nanos {
 /// Supported Engine API RPC methods
 #[derive(Debug, AsRefStr)]
 pub enum EngineApi {
-    ForkChoiceUpdatedV3 { fork_choice_state: ForkchoiceState, payload_attributes: Option<Box<OpPayloadAttributes>> },
-    NewPayloadV3 { payload: ExecutionPayloadV3, versioned_hashes: Vec<B256>, parent_beacon_block_root: B256 },
-    GetPayloadV3 { payload_id: PayloadId, res: oneshot::Sender<OpExecutionPayloadEnvelopeV3> },
+    ForkChoiceUpdatedV3 {
+        fork_choice_state: ForkchoiceState,
+        payload_attributes: Option<Box<OpPayloadAttributes>>,
+        /// `None` for internally generated messages that nobody waits on
+        res: Option<oneshot::Sender<ForkchoiceUpdated>>,
+    },
+    NewPayloadV3 {
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        res: Option<oneshot::Sender<PayloadStatus>>,
+    },
+    GetPayloadV3 { payload_id: PayloadId, res: oneshot::Sender<RpcResult<OpExecutionPayloadEnvelopeV3>> },
+    NewPayloadV4 {
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Requests,
+        res: Option<oneshot::Sender<PayloadStatus>>,
+    },
+    GetPayloadV4 { payload_id: PayloadId, res: oneshot::Sender<RpcResult<OpExecutionPayloadEnvelopeV4>> },
+}
+
+/// Version of the `newPayload` and `getPayload` engine API methods a payload has to be passed with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EngineApiVersion {
+    V3,
+    /// From Isthmus
+    V4,
+}
+
+impl EngineApiVersion {
+    pub fn for_timestamp(chain_spec: &OpChainSpec, timestamp: u64) -> Self {
+        if chain_spec.is_isthmus_active_at_timestamp(timestamp) {
+            EngineApiVersion::V4
+        } else {
+            EngineApiVersion::V3
+        }
+    }
+}
+
+/// The v4 envelope only adds the execution requests, which are always empty on OP Stack chains.
+pub fn envelope_v4(envelope: OpExecutionPayloadEnvelopeV3) -> OpExecutionPayloadEnvelopeV4 {
+    OpExecutionPayloadEnvelopeV4 {
+        execution_payload: envelope.execution_payload,
+        block_value: envelope.block_value,
+        blobs_bundle: envelope.blobs_bundle,
+        should_override_builder: envelope.should_override_builder,
+        parent_beacon_block_root: envelope.parent_beacon_block_root,
+        execution_requests: vec![],
+    }
+}
+
+pub fn envelope_v3(envelope: OpExecutionPayloadEnvelopeV4) -> OpExecutionPayloadEnvelopeV3 {
+    OpExecutionPayloadEnvelopeV3 {
+        execution_payload: envelope.execution_payload,
+        block_value: envelope.block_value,
+        blobs_bundle: envelope.blobs_bundle,
+        should_override_builder: envelope.should_override_builder,
+        parent_beacon_block_root: envelope.parent_beacon_block_root,
+    }
 }
 impl EngineApi {
+    /// Payload id the sequencer will assign when receiving this message, `None` if it doesn't start a new payload.
+    pub fn payload_id(&self) -> Option<PayloadId> {
+        match self {
+            EngineApi::ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes: Some(attributes), .. } => {
+                Some(payload_id_v3(fork_choice_state.head_block_hash, attributes))
+            }
+            _ => None,
+        }
+    }
+
     pub fn messages_from_block(
         block: &BlockSyncMessage,
         txs_in_attributes: bool,
@@ -219,12 +291,24 @@ impl EngineApi {
             blob_gas_used: Default::default(),
             excess_blob_gas: Default::default(),
         };
-        let new_payload = EngineApi::NewPayloadV3 {
-            payload: v3,
-            versioned_hashes: Default::default(),
-            parent_beacon_block_root: block
-                .parent_beacon_block_root()
-                .expect("parent beacon root should always be set"),
+        let parent_beacon_block_root =
+            block.parent_beacon_block_root().expect("parent beacon root should always be set");
+        // only Isthmus blocks commit to (empty) execution requests
+        let new_payload = if block.requests_hash.is_some() {
+            EngineApi::NewPayloadV4 {
+                payload: v3,
+                versioned_hashes: Default::default(),
+                parent_beacon_block_root,
+                execution_requests: Default::default(),
+                res: None,
+            }
+        } else {
+            EngineApi::NewPayloadV3 {
+                payload: v3,
+                versioned_hashes: Default::default(),
+                parent_beacon_block_root,
+                res: None,
+            }
         };
 
         let fcu_1 = EngineApi::ForkChoiceUpdatedV3 {
@@ -234,6 +318,7 @@ impl EngineApi {
                 finalized_block_hash: Default::default(),
             },
             payload_attributes: None,
+            res: None,
         };
         let fcu = EngineApi::ForkChoiceUpdatedV3 {
             fork_choice_state: ForkchoiceState {
@@ -242,11 +327,57 @@ impl EngineApi {
                 finalized_block_hash: Default::default(),
             },
             payload_attributes: op_payload_attributes,
+            res: None,
         };
         (new_payload, fcu_1, fcu)
     }
 }
 
+/// Derives the id of the payload built on top of `parent_hash` with `attributes`.
+///
+/// Same attributes on the same parent always give the same id, so a repeated fork choice update resolves to the
+/// payload that is already being built. Follows op-reth and op-geth, so the gateway hands out the same ids as the
+/// fallback execution client.
+pub fn payload_id_v3(parent_hash: B256, attributes: &OpPayloadAttributes) -> PayloadId {
+    let inner = &attributes.payload_attributes;
+
+    let mut hasher = Sha256::new();
+    hasher.update(parent_hash.as_slice());
+    hasher.update(inner.timestamp.to_be_bytes());
+    hasher.update(inner.prev_randao.as_slice());
+    hasher.update(inner.suggested_fee_recipient.as_slice());
+    if let Some(withdrawals) = &inner.withdrawals {
+        let mut buf = Vec::new();
+        withdrawals.encode(&mut buf);
+        hasher.update(buf);
+    }
+    if let Some(parent_beacon_block_root) = inner.parent_beacon_block_root {
+        hasher.update(parent_beacon_block_root.as_slice());
+    }
+
+    let no_tx_pool = attributes.no_tx_pool.unwrap_or_default();
+    if no_tx_pool || attributes.transactions.as_ref().is_some_and(|txs| !txs.is_empty()) {
+        hasher.update([no_tx_pool as u8]);
+        let txs_len = attributes.transactions.as_ref().map(|txs| txs.len()).unwrap_or_default();
+        hasher.update(txs_len.to_be_bytes());
+        for tx in attributes.transactions.iter().flatten() {
+            hasher.update(keccak256(tx).as_slice());
+        }
+    }
+
+    if let Some(gas_limit) = attributes.gas_limit {
+        hasher.update(gas_limit.to_be_bytes());
+    }
+    if let Some(eip_1559_params) = attributes.eip_1559_params {
+        hasher.update(eip_1559_params.as_slice());
+    }
+
+    let mut out = hasher.finalize();
+    // first byte is the engine API version the payload was requested with
+    out[0] = 3;
+    PayloadId::new(out[..8].try_into().expect("sha256 is 32 bytes"))
+}
+
 pub type RpcResult<T> = Result<T, RpcError>;
 
 #[derive(Debug, thiserror::Error)]
@@ -272,8 +403,29 @@ pub enum RpcError {
     #[error("db error: {0}")]
     Db(#[from] crate::db::Error),
 
-    #[error("no return")]
-    NoReturn,
+    #[error("unknown payload")]
+    UnknownPayload,
+
+    #[error("unsupported fork")]
+    UnsupportedFork,
+
+    #[error("invalid block: {0}")]
+    InvalidBlock(String),
+
+    #[error("execution reverted")]
+    Reverted(Bytes),
+
+    #[error("execution failed: {0}")]
+    ExecutionFailed(String),
+
+    #[error("shutting down")]
+    ShuttingDown,
+
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+
+    #[error("sequencer overloaded")]
+    Overloaded,
 }
 
 impl From<RpcError> for RpcErrorObject<'static> {
@@ -284,13 +436,26 @@ impl From<RpcError> for RpcErrorObject<'static> {
             RpcError::ChannelClosed(_) |
             RpcError::Jsonrpsee(_) |
             RpcError::TokioJoin(_) |
-            RpcError::Db(_) |
-            RpcError::NoReturn => internal_error(),
+            RpcError::Db(_) => internal_error(),
+            // Engine API spec error code
+            RpcError::UnknownPayload => RpcErrorObject::owned(-38001, "Unknown payload", None::<()>),
+            RpcError::UnsupportedFork => RpcErrorObject::owned(-38005, "Unsupported fork", None::<()>),
             RpcError::InvalidTransaction(error) => RpcErrorObject::owned(
                 ErrorCode::InvalidParams.code(),
                 ErrorCode::InvalidParams.message(),
                 Some(error.to_string()),
             ),
+            RpcError::InvalidBlock(error) | RpcError::InvalidMessage(error) => RpcErrorObject::owned(
+                ErrorCode::InvalidParams.code(),
+                ErrorCode::InvalidParams.message(),
+                Some(error),
+            ),
+            // Same code and shape as geth, so tooling can decode the revert reason.
+            RpcError::Reverted(output) => RpcErrorObject::owned(3, "execution reverted", Some(output)),
+            RpcError::ExecutionFailed(error) => RpcErrorObject::owned(-32015, error, None::<()>),
+            RpcError::ShuttingDown => RpcErrorObject::owned(-32000, "shutting down", None::<()>),
+            // EIP-1474 limit exceeded
+            RpcError::Overloaded => RpcErrorObject::owned(-32005, "sequencer overloaded", None::<()>),
         }
     }
 }
@@ -307,14 +472,8 @@ pub enum SequencerToSimulator<Db> {
     /// Simulate Tx Top of frag
     //TODO: Db could be set on frag commit once we broadcast msgs to sims
     SimulateTxTof(Arc<Transaction>, DBFrag<Db>),
-}
-impl<Db> SequencerToSimulator<Db> {
-    pub fn sim_info(&self) -> (Address, u64, u64) {
-        match self {
-            SequencerToSimulator::SimulateTx(t, db) => (t.sender(), t.nonce(), db.state_id()),
-            SequencerToSimulator::SimulateTxTof(t, db) => (t.sender(), t.nonce(), db.state_id()),
-        }
-    }
+    /// Read the accounts into the db caches, so they aren't read cold while sequencing. No result is sent back.
+    WarmAccounts(Vec<Address>, DBFrag<Db>),
 }
 
 #[derive(Debug)]
@@ -351,7 +510,7 @@ pub enum SimulatorToSequencerMsg {
     TxPoolTopOfFrag(SimulationResult<SimulatedTx>),
 }
 
-#[derive(Clone, Debug, Error, AsRefStr)]
+#[derive(Clone, Debug, Error, AsRefStr, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum SimulationError {
     #[error("Evm error: {0}")]
@@ -419,3 +578,33 @@ impl std::fmt::Debug for NextBlockAttributes {
             .finish()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{address, b256, bytes};
+
+    use super::*;
+
+    #[test]
+    fn payload_id_matches_op_geth() {
+        let attributes = OpPayloadAttributes {
+            payload_attributes: PayloadAttributes {
+                timestamp: 1728933301,
+                prev_randao: b256!("9158595abbdab2c90635087619aa7042bbebe47642dfab3c9bfb934f6b082765"),
+                suggested_fee_recipient: address!("4200000000000000000000000000000000000011"),
+                withdrawals: Some(vec![]),
+                parent_beacon_block_root: Some(b256!(
+                    "8fe0193b9bf83cb7e5a08538e494fecc23046aab9a497af3704f4afdae3250ff"
+                )),
+            },
+            transactions: Some(vec![bytes!("7ef8f8a0dc19cfa777d90980e4875d0a548a881baaa3f83f14d1bc0d3038bc329350e54194deaddeaddeaddeaddeaddeaddeaddeaddead00019442000000000000000000000000000000000000158080830f424080b8a4440a5e20000f424000000000000000000000000300000000670d6d890000000000000125000000000000000000000000000000000000000000000000000000000000000700000000000000000000000000000000000000000000000000000000000000014bf9181db6e381d4384bbf69c48b0ee0eed23c6ca26143c6d2544f9d39997a590000000000000000000000007f83d659683caf2767fd3c720981d51f5bc365bc")]),
+            no_tx_pool: None,
+            gas_limit: Some(30000000),
+            eip_1559_params: None,
+        };
+        let parent_hash = b256!("3533bf30edaf9505d0810bf475cbe4e5f4b9889904b9845e83efdeab4e92eb1e");
+
+        // id op-geth returned for these attributes
+        assert_eq!(payload_id_v3(parent_hash, &attributes), PayloadId::new(0x03d2dae446d2a86a_u64.to_be_bytes()));
+    }
+}
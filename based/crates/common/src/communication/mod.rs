@@ -7,8 +7,10 @@ use messages::{
 use shared_memory::ShmemError;
 use thiserror::Error;
 
+pub mod channel;
 pub mod queue;
 pub mod seqlock;
+pub use channel::{ChannelConfig, ChannelSender, ChannelStats, ChannelsConfig, OverflowPolicy};
 pub use queue::{Consumer, Producer, Queue};
 pub use seqlock::Seqlock;
 pub mod messages;
@@ -23,10 +25,30 @@ use crate::{
 };
 
 pub type CrossBeamReceiver<T> = crossbeam_channel::Receiver<InternalMessage<T>>;
-pub type Sender<T> = crossbeam_channel::Sender<InternalMessage<T>>;
+pub type Sender<T> = ChannelSender<InternalMessage<T>>;
 
 pub trait NonBlockingSender<T> {
     fn try_send(&self, data: T) -> Result<(), T>;
+
+    /// Retries until `timeout` passed.
+    fn send_timeout(&self, mut data: T, timeout: Duration) -> Result<(), T> {
+        let curt = Instant::now();
+        while let Err(d) = self.try_send(data) {
+            if timeout < curt.elapsed() {
+                return Err(d);
+            }
+            data = d;
+        }
+        Ok(())
+    }
+
+    /// Retries until the data is sent, only fails if the receiving side is gone.
+    fn send_forever(&self, mut data: T) -> Result<(), T> {
+        while let Err(d) = self.try_send(data) {
+            data = d;
+        }
+        Ok(())
+    }
 }
 
 pub trait HasSender<T> {
@@ -50,42 +72,24 @@ pub trait TrackedSenders {
         self.get_sender().try_send(msg)
     }
 
+    /// Waits for room as long as the overflow policy of the channel allows.
     fn send_forever<T>(&self, data: T)
     where
         Self: HasSender<T>,
     {
-        if let Err(e) = self.send(data) {
-            error!("Couldn't send {}: retrying forever...", last_part_of_typename::<T>());
-            let mut msg = e.into_data();
-            while let Err(e) = self.send(msg) {
-                msg = e.into_data();
-            }
+        let msg = self.ingestion_t().to_msg(data);
+        if self.get_sender().send_forever(msg).is_err() {
+            error!("Couldn't send {}: receiver disconnected", last_part_of_typename::<T>());
         }
     }
 
+    /// Waits for room for at most `timeout`, if the overflow policy of the channel allows waiting.
     fn send_timeout<T>(&self, data: T, timeout: Duration) -> Result<(), InternalMessage<T>>
     where
         Self: HasSender<T>,
     {
-        if let Err(e) = self.send(data) {
-            error!("Couldn't send {}: retrying for {timeout}...", last_part_of_typename::<T>());
-            let curt = Instant::now();
-            let mut msg = e.into_data();
-            while let Err(e) = self.send(msg) {
-                if timeout < curt.elapsed() {
-                    error!("Couldn't send {}: retried for {timeout}, breaking off", last_part_of_typename::<T>());
-                    return Err(e);
-                }
-                msg = e.into_data();
-            }
-        }
-        Ok(())
-    }
-}
-
-impl<T> NonBlockingSender<T> for crossbeam_channel::Sender<T> {
-    fn try_send(&self, data: T) -> Result<(), T> {
-        self.send(data).map_err(|e| e.into_inner())
+        let msg = self.ingestion_t().to_msg(data);
+        self.get_sender().send_timeout(msg, timeout)
     }
 }
 
@@ -261,14 +265,28 @@ pub struct Spine<Db: 'static> {
 
 impl<Db> Default for Spine<Db> {
     fn default() -> Self {
-        let (sender_simulator_to_sequencer, receiver_simulator_to_sequencer) = crossbeam_channel::bounded(4096);
-        let (sender_sequencer_to_simulator, receiver_sequencer_to_simulator) = crossbeam_channel::bounded(4096);
-        let (sender_sequencer_to_rpc, receiver_sequencer_to_rpc) = crossbeam_channel::bounded(4096);
-        let (sender_engine_rpc_to_sequencer, receiver_engine_rpc_to_sequencer) = crossbeam_channel::bounded(4096);
-        let (sender_eth_rpc_to_sequencer, receiver_eth_rpc_to_sequencer) = crossbeam_channel::bounded(4096);
-        let (sender_blockfetch_to_sequencer, receiver_blockfetch_to_sequencer) = crossbeam_channel::bounded(4096);
-        let (sender_sequencer_frag_broadcast, receiver_sequencer_frag_broadcast) = crossbeam_channel::bounded(4096);
-        let (sender_sequencer_to_blockfetch, receiver_sequencer_to_blockfetch) = crossbeam_channel::bounded(4096);
+        Self::new(&ChannelsConfig::default())
+    }
+}
+
+impl<Db> Spine<Db> {
+    pub fn new(config: &ChannelsConfig) -> Self {
+        let (sender_simulator_to_sequencer, receiver_simulator_to_sequencer) =
+            channel::bounded("simulator_to_sequencer", config.simulator_to_sequencer);
+        let (sender_sequencer_to_simulator, receiver_sequencer_to_simulator) =
+            channel::bounded("sequencer_to_simulator", config.sequencer_to_simulator);
+        let (sender_sequencer_to_rpc, receiver_sequencer_to_rpc) =
+            channel::bounded("sequencer_to_rpc", config.sequencer_to_rpc);
+        let (sender_engine_rpc_to_sequencer, receiver_engine_rpc_to_sequencer) =
+            channel::bounded("engine_rpc_to_sequencer", config.engine_rpc_to_sequencer);
+        let (sender_eth_rpc_to_sequencer, receiver_eth_rpc_to_sequencer) =
+            channel::bounded("eth_rpc_to_sequencer", config.eth_rpc_to_sequencer);
+        let (sender_blockfetch_to_sequencer, receiver_blockfetch_to_sequencer) =
+            channel::bounded("blockfetch_to_sequencer", config.blockfetch_to_sequencer);
+        let (sender_sequencer_frag_broadcast, receiver_sequencer_frag_broadcast) =
+            channel::bounded("sequencer_frag_broadcast", config.sequencer_frag_broadcast);
+        let (sender_sequencer_to_blockfetch, receiver_sequencer_to_blockfetch) =
+            channel::bounded("sequencer_to_blockfetch", config.sequencer_to_blockfetch);
 
         // MPMC to be safe, should only be produced to by the sequencer but
         let evm_block_params = Queue::new(4096, queue::QueueType::MPMC).expect("couldn't initialize queue");
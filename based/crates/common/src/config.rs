@@ -1,11 +1,15 @@
 use std::{net::Ipv4Addr, path::PathBuf, sync::Arc};
 
 use clap::Parser;
+use eyre::bail;
 use reqwest::Url;
 use reth_cli::chainspec::ChainSpecParser;
 use reth_optimism_chainspec::OpChainSpec;
 use reth_optimism_cli::chainspec::OpChainSpecParser;
-use tracing::level_filters::LevelFilter;
+use reth_rpc_layer::JwtSecret;
+use tracing::{level_filters::LevelFilter, warn};
+
+use crate::{p2p::WireEncoding, signing::ECDSASigner};
 
 #[derive(Parser, Debug)]
 #[command(version, about, name = "gateway")]
@@ -21,21 +25,70 @@ pub struct GatewayArgs {
     /// The host to run the engine_ and eth_ RPC
     #[arg(long = "rpc.host", default_value_t = Ipv4Addr::UNSPECIFIED)]
     pub rpc_host: Ipv4Addr,
-    /// The port to run the engine_ and eth_ RPC
+    /// The port to run the public eth_ RPC
     #[arg(long = "rpc.port", default_value_t = 9090)]
     pub rpc_port: u16,
+    /// The port to run the JWT authenticated engine_ RPC, which also serves eth_ for the portal
+    #[arg(long = "engine.port", default_value_t = 9091)]
+    pub engine_port: u16,
+    /// The JWT secret for the engine RPC
+    #[arg(long = "engine.jwt", conflicts_with = "engine_jwt_path")]
+    pub engine_jwt: Option<JwtSecret>,
+    /// Path to the JWT secret file for the engine RPC
+    #[arg(long = "engine.jwt_path", conflicts_with = "engine_jwt")]
+    pub engine_jwt_path: Option<PathBuf>,
+    /// Maximum number of blocks behind the head that eth_ state queries are served for
+    #[arg(long = "rpc.max_history_blocks", default_value_t = 1024)]
+    pub rpc_max_history_blocks: u64,
+    /// How long `based_sendRawTransactionWithCommitment` waits for the transaction to be included in a sealed frag
+    #[arg(long = "rpc.commitment_timeout_ms", default_value_t = 10_000)]
+    pub rpc_commitment_timeout_ms: u64,
     /// Url to a full node for syncing and eth_ fallback requests
     #[arg(long = "rpc.fallback_url", default_value = "https://base-sepolia-rpc.publicnode.com")]
     pub rpc_fallback_url: Url,
+    /// Additional full node urls that blocks are fetched from, round robin with the fallback url
+    #[arg(long = "rpc.fetch_urls", value_delimiter = ',')]
+    pub rpc_fetch_urls: Vec<Url>,
+    /// Websocket url of a full node to follow new heads on. If not set, the fetch urls are polled for the head
+    #[arg(long = "rpc.fetch_ws_url")]
+    pub rpc_fetch_ws_url: Option<Url>,
+    /// Maximum attempts to fetch a block, across all fetch urls, before the batch is retried
+    #[arg(long = "rpc.fetch_max_attempts", default_value_t = 10)]
+    pub rpc_fetch_max_attempts: usize,
+    /// Port to serve Prometheus metrics on, on the rpc host. Disabled if not set
+    #[arg(long = "metrics.port")]
+    pub metrics_port: Option<u16>,
     /// Url to the root peer gossip node
     #[arg(long = "gossip.root_peer_url")]
     pub gossip_root_peer_url: Option<Url>,
+    /// Hex encoded private key frags are signed with, followers verify frags against its address. A random key is
+    /// used if not set
+    #[arg(long = "gossip.signer_key")]
+    pub gossip_signer_key: Option<String>,
+    /// Gossip messages as an HTTP body in this binary encoding instead of a JSON-RPC request. The root peer must
+    /// serve the binary endpoint, e.g. a follower with `--rpc.wire_port`
+    #[arg(long = "gossip.encoding")]
+    pub gossip_encoding: Option<WireEncoding>,
+    /// Number of most recent blocks whose gossiped messages are kept, for followers to backfill with `based_getFrag`
+    #[arg(long = "gossip.archive_blocks", default_value_t = 128)]
+    pub gossip_archive_blocks: usize,
+    /// Gossip frags as `FragV1`, which commit to the gas used, state and receipts after each frag, instead of `FragV0`.
+    /// Only for followers that understand `based_newFragV1`
+    #[arg(long = "gossip.frag_v1")]
+    pub gossip_frag_v1: bool,
     /// Duration of a frag in ms
     #[arg(long = "sequencer.frag_duration_ms", default_value_t = 200)]
     pub frag_duration_ms: u64,
     /// Number of sims per loop
     #[arg(long = "sequencer.sim_threads", default_value_t = 5)]
     pub sim_threads: usize,
+    /// Path to a TOML file with the thread configuration of the actors and the RPC runtime, e.g. core affinity and
+    /// real-time priority, and the capacity and overflow policy of the channels between them. See `ActorsConfig`
+    #[arg(long = "actors.config")]
+    pub actors_config: Option<PathBuf>,
+    /// Time the gateway has to stop after SIGINT/SIGTERM, after which the remaining shutdown stages are not waited for
+    #[arg(long = "shutdown.deadline_ms", default_value_t = 5000)]
+    pub shutdown_deadline_ms: u64,
     /// Database location
     #[arg(long = "db.datadir")]
     pub db_datadir: PathBuf,
@@ -45,6 +98,9 @@ pub struct GatewayArgs {
     /// Maximum number of cached storages
     #[arg(long = "db.max_cached_storages", default_value_t = 100_000)]
     pub max_cached_storages: u64,
+    /// Number of most recent blocks whose touched accounts and storage are loaded into the caches on startup
+    #[arg(long = "db.warm_cache_blocks", default_value_t = 32)]
+    pub warm_cache_blocks: u64,
     /// Test mode
     #[arg(long = "test")]
     pub test: bool,
@@ -72,6 +128,33 @@ pub struct GatewayArgs {
     /// If true will commit locally sequenced blocks to the db before getting payload from the engine api.
     #[arg(long = "sequencer.commit_sealed_frags_to_db", default_value_t = false)]
     pub commit_sealed_frags_to_db: bool,
+    /// Records all inputs of the sequencer to this file, to replay them with the `replay` binary. Adds some work to
+    /// every loop of the sequencer, only for debugging
+    #[arg(long = "sequencer.record_path")]
+    pub sequencer_record_path: Option<PathBuf>,
+}
+
+impl GatewayArgs {
+    pub fn engine_jwt(&self) -> eyre::Result<JwtSecret> {
+        if let Some(jwt) = self.engine_jwt {
+            Ok(jwt)
+        } else if let Some(path) = self.engine_jwt_path.as_ref() {
+            let jwt = JwtSecret::from_file(path)?;
+            Ok(jwt)
+        } else {
+            bail!("either --engine.jwt or --engine.jwt_path must be provided");
+        }
+    }
+
+    pub fn gossip_signer(&self) -> eyre::Result<ECDSASigner> {
+        match self.gossip_signer_key.as_deref() {
+            Some(key) => Ok(ECDSASigner::try_from_hex(key)?),
+            None => {
+                warn!("no --gossip.signer_key set, signing frags with a random key");
+                Ok(ECDSASigner::random())
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,74 @@
+use std::fmt::{Display, Formatter};
+
+use metrics::counter;
+
+use crate::metrics::{DB_CACHE_EVICTIONS, DB_CACHE_HITS, DB_CACHE_MISSES};
+
+/// Hit, miss and eviction counts of a single read cache since startup.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheCounters {
+    pub hits: u64,
+    pub misses: u64,
+    pub evictions: u64,
+}
+
+impl CacheCounters {
+    /// Fraction of lookups served from the cache, 0 if there were no lookups.
+    pub fn hit_rate(&self) -> f64 {
+        let lookups = self.hits + self.misses;
+        if lookups == 0 {
+            return 0.0;
+        }
+        self.hits as f64 / lookups as f64
+    }
+}
+
+impl Display for CacheCounters {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "hits={} misses={} evictions={} hit_rate={:.2}%",
+            self.hits,
+            self.misses,
+            self.evictions,
+            self.hit_rate() * 100.0
+        )
+    }
+}
+
+/// Counters of all database read caches.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct CacheStats {
+    pub accounts: CacheCounters,
+    pub storage: CacheCounters,
+    pub bytecodes: CacheCounters,
+}
+
+impl CacheStats {
+    /// Exports the counters as metrics, labeled by cache.
+    pub fn record_metrics(&self) {
+        for (cache, counters) in [("accounts", self.accounts), ("storage", self.storage), ("bytecodes", self.bytecodes)]
+        {
+            counter!(DB_CACHE_HITS, "cache" => cache).absolute(counters.hits);
+            counter!(DB_CACHE_MISSES, "cache" => cache).absolute(counters.misses);
+            counter!(DB_CACHE_EVICTIONS, "cache" => cache).absolute(counters.evictions);
+        }
+    }
+}
+
+impl Display for CacheStats {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "accounts: [{}], storage: [{}], bytecodes: [{}]", self.accounts, self.storage, self.bytecodes)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hit_rate() {
+        assert_eq!(CacheCounters::default().hit_rate(), 0.0);
+        assert_eq!(CacheCounters { hits: 3, misses: 1, evictions: 0 }.hit_rate(), 0.75);
+    }
+}
@@ -1,8 +1,7 @@
 use std::sync::Arc;
 
-use alloy_primitives::{map::HashMap, B256};
+use alloy_primitives::{map::HashMap, Keccak256, B256};
 use parking_lot::RwLock;
-use rand::RngCore;
 use reth_trie_common::updates::TrieUpdates;
 use revm::db::{states::bundle_state::BundleRetention, BundleState};
 use revm_primitives::{
@@ -10,7 +9,7 @@ use revm_primitives::{
     Account, AccountInfo, Address, Bytecode, U256,
 };
 
-use super::{DatabaseRead, Error, State};
+use super::{next_state_id, CacheStats, DatabaseRead, Error, State};
 use crate::transaction::SimulatedTx;
 
 /// This is a wrapper around db to tag frags onto before
@@ -33,7 +32,7 @@ impl<Db> DBFrag<Db> {
     /// Used on block commit to clear State, ready for next round of Sequenced Frags
     pub fn reset(&mut self) {
         self.db.write().reset();
-        self.state_id = rand::rng().next_u64();
+        self.state_id = next_state_id();
     }
 
     pub fn state_id(&self) -> u64 {
@@ -43,6 +42,43 @@ impl<Db> DBFrag<Db> {
     pub fn is_valid(&self, sim_res_state: u64) -> bool {
         sim_res_state == self.state_id
     }
+
+    /// Cheap commitment to the state changes of the block so far, used instead of a state root per frag. Hashes the
+    /// current value of every account and storage slot touched since the last reset, in address and slot order, so two
+    /// dbs that executed the same txs on the same parent state produce the same commitment.
+    pub fn state_commitment(&self) -> B256 {
+        let guard = self.db.read();
+        let Some(transition_state) = guard.transition_state.as_ref() else {
+            return B256::ZERO;
+        };
+
+        let mut transitions: Vec<_> = transition_state.transitions.iter().collect();
+        transitions.sort_unstable_by_key(|(address, _)| **address);
+
+        let mut hasher = Keccak256::new();
+        for (address, transition) in transitions {
+            hasher.update(address);
+            match transition.info.as_ref() {
+                Some(info) => {
+                    hasher.update([1]);
+                    hasher.update(info.nonce.to_be_bytes());
+                    hasher.update(info.balance.to_be_bytes::<32>());
+                    hasher.update(info.code_hash);
+                }
+                // destroyed
+                None => hasher.update([0]),
+            }
+            hasher.update([transition.storage_was_destroyed as u8]);
+
+            let mut storage: Vec<_> = transition.storage.iter().collect();
+            storage.sort_unstable_by_key(|(slot, _)| **slot);
+            for (slot, value) in storage {
+                hasher.update(slot.to_be_bytes::<32>());
+                hasher.update(value.present_value.to_be_bytes::<32>());
+            }
+        }
+        hasher.finalize()
+    }
 }
 
 impl<Db: DatabaseRef> DBFrag<Db> {
@@ -60,7 +96,7 @@ impl<Db: DatabaseRef> DBFrag<Db> {
             }
         }
 
-        self.state_id = rand::random()
+        self.state_id = next_state_id()
     }
 
     pub fn get_nonce(&self, address: Address) -> Result<u64, Error> {
@@ -133,6 +169,10 @@ impl<Db: DatabaseRead> DatabaseRead for DBFrag<Db> {
         self.db.read().database.calculate_state_root(bundle_state)
     }
 
+    fn calculate_storage_root(&self, address: Address, bundle_state: &BundleState) -> Result<B256, Error> {
+        self.db.read().database.calculate_storage_root(address, bundle_state)
+    }
+
     fn head_block_number(&self) -> Result<u64, Error> {
         self.db.read().database.head_block_number()
     }
@@ -140,11 +180,19 @@ impl<Db: DatabaseRead> DatabaseRead for DBFrag<Db> {
     fn head_block_hash(&self) -> Result<B256, Error> {
         self.db.read().database.head_block_hash()
     }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.db.read().database.cache_stats()
+    }
+
+    fn warm_accounts(&self, addresses: &[Address]) {
+        self.db.read().database.warm_accounts(addresses)
+    }
 }
 
 impl<Db: DatabaseRead + Database> From<Db> for DBFrag<Db> {
     fn from(value: Db) -> Self {
         let state = State::builder().with_database(value).with_bundle_update().without_state_clear().build();
-        Self { db: Arc::new(RwLock::new(state)), state_id: rand::random() }
+        Self { db: Arc::new(RwLock::new(state)), state_id: next_state_id() }
     }
 }
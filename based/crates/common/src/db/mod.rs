@@ -1,8 +1,10 @@
 use std::{
+    cell::Cell,
     collections::hash_map::Entry,
     fmt::{Debug, Display},
 };
 
+use alloy_consensus::Header;
 use alloy_primitives::{map::HashMap, B256};
 use auto_impl::auto_impl;
 use reth_optimism_primitives::{OpBlock, OpReceipt};
@@ -16,6 +18,8 @@ use revm_primitives::{
     Account, Address,
 };
 
+pub mod cache_stats;
+pub use cache_stats::{CacheCounters, CacheStats};
 pub mod error;
 pub use error::Error;
 pub mod frag;
@@ -27,6 +31,30 @@ pub use state::State;
 
 use crate::time::BlockSyncTimers;
 
+thread_local! {
+    static NEXT_STATE_ID: Cell<u64> = Cell::new(rand::random());
+}
+
+/// Returns a new id for a [`DBFrag`] or [`DBSorting`] state, so sims on stale states can be told apart. Ids are
+/// sequential per thread from a random start, a replay that starts from the same id gets the same ids as the
+/// recorded run.
+pub fn next_state_id() -> u64 {
+    NEXT_STATE_ID.with(|id| {
+        let next = id.get();
+        id.set(next.wrapping_add(1));
+        next
+    })
+}
+
+/// The id [`next_state_id`] returns next on this thread.
+pub fn peek_state_id() -> u64 {
+    NEXT_STATE_ID.with(Cell::get)
+}
+
+pub fn set_next_state_id(next: u64) {
+    NEXT_STATE_ID.with(|id| id.set(next));
+}
+
 /// Database trait for all DB operations.
 #[auto_impl(&, Arc)]
 pub trait DatabaseWrite:
@@ -41,6 +69,11 @@ pub trait DatabaseWrite:
     ) -> Result<(), Error>;
 
     fn roll_back_head(&self) -> Result<(), Error>;
+
+    /// Forces committed blocks to disk, called once on shutdown.
+    fn flush(&self) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 /// Database read functions
@@ -51,11 +84,39 @@ pub trait DatabaseRead:
     /// Calculate the state root with the provided `BundleState` overlaid on the latest DB state.
     fn calculate_state_root(&self, bundle_state: &BundleState) -> Result<(B256, TrieUpdates), Error>;
 
+    /// Calculate the storage root of `address` with the provided `BundleState` overlaid on the latest DB state.
+    fn calculate_storage_root(&self, address: Address, bundle_state: &BundleState) -> Result<B256, Error>;
+
     /// Returns the head block number, ie. the highest block number on the chain
     fn head_block_number(&self) -> Result<u64, Error>;
 
     /// Returns the head block hash, ie. the hash of the highest block on the chain
     fn head_block_hash(&self) -> Result<B256, Error>;
+
+    /// Returns the hit/miss/eviction counters of the read caches, if the implementation has any.
+    fn cache_stats(&self) -> Option<CacheStats> {
+        None
+    }
+
+    /// Loads the given accounts and their bytecode into the read caches ahead of use, if the implementation has any.
+    fn warm_accounts(&self, _addresses: &[Address]) {}
+}
+
+/// Read access to past blocks, for as far back as the implementation keeps history.
+#[auto_impl(&, Arc)]
+pub trait DatabaseHistory: DatabaseRead {
+    /// View of the state at the end of a past block.
+    type Historical: DatabaseRef<Error = Error> + Debug + Send + Sync;
+
+    /// Returns a view of the state at the end of block `number`.
+    fn state_at_block(&self, number: u64) -> Result<Self::Historical, Error>;
+
+    /// Returns the number of the canonical block with the given hash, searching at most `max_depth` blocks back
+    /// from the head.
+    fn block_number_by_hash(&self, hash: B256, max_depth: u64) -> Result<Option<u64>, Error>;
+
+    /// Returns the header of a canonical block, if it is stored.
+    fn header_by_number(&self, number: u64) -> Result<Option<Header>, Error>;
 }
 
 impl<DbRead: DatabaseRead> DatabaseRead for CacheDB<DbRead> {
@@ -63,6 +124,10 @@ impl<DbRead: DatabaseRead> DatabaseRead for CacheDB<DbRead> {
         self.db.calculate_state_root(bundle_state)
     }
 
+    fn calculate_storage_root(&self, address: Address, bundle_state: &BundleState) -> Result<B256, Error> {
+        self.db.calculate_storage_root(address, bundle_state)
+    }
+
     fn head_block_number(&self) -> Result<u64, Error> {
         self.db.head_block_number()
     }
@@ -70,6 +135,14 @@ impl<DbRead: DatabaseRead> DatabaseRead for CacheDB<DbRead> {
     fn head_block_hash(&self) -> Result<B256, Error> {
         self.db.head_block_hash()
     }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        self.db.cache_stats()
+    }
+
+    fn warm_accounts(&self, addresses: &[Address]) {
+        self.db.warm_accounts(addresses)
+    }
 }
 
 /// Converts cached state in a `CachedDB` into `BundleState`
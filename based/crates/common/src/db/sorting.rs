@@ -7,7 +7,7 @@ use revm_primitives::{
     AccountInfo, Address, Bytecode, EvmState, U256,
 };
 
-use super::{DBFrag, State};
+use super::{next_state_id, DBFrag, State};
 
 /// DB That is used when sorting a new frag
 /// Thread safe
@@ -19,7 +19,7 @@ pub struct DBSorting<Db> {
 
 impl<Db> DBSorting<Db> {
     pub fn new(frag_db: DBFrag<Db>) -> Self {
-        Self { db: Arc::new(RwLock::new(State::new(frag_db))), state_id: rand::random() }
+        Self { db: Arc::new(RwLock::new(State::new(frag_db))), state_id: next_state_id() }
     }
 
     pub fn state_id(&self) -> u64 {
@@ -29,7 +29,7 @@ impl<Db> DBSorting<Db> {
 impl<Db: DatabaseRef> DBSorting<Db> {
     pub fn commit_ref(&mut self, state: &EvmState) {
         self.db.write().commit_ref(state);
-        self.state_id = rand::random()
+        self.state_id = next_state_id()
     }
 }
 
@@ -3,8 +3,11 @@ pub mod api;
 pub mod communication;
 pub mod config;
 pub mod db;
+pub mod metrics;
 pub mod p2p;
+pub mod schedule;
 pub mod shared;
+pub mod shutdown;
 pub mod signing;
 pub mod time;
 pub mod transaction;
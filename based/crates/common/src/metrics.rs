@@ -0,0 +1,131 @@
+//! Prometheus metrics, served on `/metrics` by the gateway, the portal, the follower and the headless timekeeper.
+//!
+//! Metrics are recorded through the `metrics` macros using the names below, so the hot paths only pay for an atomic
+//! update once a metric has been registered.
+
+use std::net::SocketAddr;
+
+use metrics::{describe_counter, describe_gauge, describe_histogram, Unit};
+use metrics_exporter_prometheus::{BuildError, Matcher, PrometheusBuilder};
+
+// Sequencer
+/// 1 for the current state of the sequencer state machine, 0 for the others. Label: `state`.
+pub const SEQUENCER_STATE: &str = "bop_sequencer_state";
+pub const FRAGS_PER_BLOCK: &str = "bop_sequencer_frags_per_block";
+pub const TXS_PER_FRAG: &str = "bop_sequencer_txs_per_frag";
+/// Label: `result`, either `success` or `error`.
+pub const SIMS: &str = "bop_sequencer_sims_total";
+pub const SEAL_FRAG_DURATION: &str = "bop_sequencer_seal_frag_duration_seconds";
+pub const SEAL_BLOCK_DURATION: &str = "bop_sequencer_seal_block_duration_seconds";
+/// Label: `stage`, `seal` when sealing a sequenced block, `sync` when validating a synced block.
+pub const STATE_ROOT_DURATION: &str = "bop_state_root_duration_seconds";
+pub const TX_POOL_ACTIVE_TXS: &str = "bop_tx_pool_active_txs";
+pub const TX_POOL_SENDERS: &str = "bop_tx_pool_senders";
+
+// Simulator
+pub const SIM_DURATION: &str = "bop_simulator_sim_duration_seconds";
+
+// Block sync
+pub const DB_HEAD_BLOCK: &str = "bop_db_head_block";
+pub const UPSTREAM_HEAD_BLOCK: &str = "bop_upstream_head_block";
+
+// Database read caches
+/// Label: `cache`, one of `accounts`, `storage` or `bytecodes`.
+pub const DB_CACHE_HITS: &str = "bop_db_cache_hits_total";
+/// Label: `cache`.
+pub const DB_CACHE_MISSES: &str = "bop_db_cache_misses_total";
+/// Label: `cache`.
+pub const DB_CACHE_EVICTIONS: &str = "bop_db_cache_evictions_total";
+
+// Spine channels
+/// Labels: `channel` and `policy`, the overflow policy of the channel.
+pub const CHANNEL_DROPS: &str = "bop_channel_drops_total";
+/// Label: `channel`.
+pub const CHANNEL_SEND_TIMEOUTS: &str = "bop_channel_send_timeouts_total";
+/// Label: `channel`.
+pub const CHANNEL_HIGH_WATER_MARK: &str = "bop_channel_high_water_mark";
+/// 1 while the channel is filled above its saturation threshold. Label: `channel`.
+pub const CHANNEL_SATURATED: &str = "bop_channel_saturated";
+
+// Gossip
+/// Labels: `kind`, the message type, and `result`, either `success` or `error`.
+pub const GOSSIP_MESSAGES: &str = "bop_gossip_messages_total";
+
+// Follower
+/// Labels: `kind`, the message type, and `result`, either `success` or the reason it was rejected.
+pub const FOLLOWER_MESSAGES: &str = "bop_follower_messages_total";
+pub const FOLLOWER_HEAD_BLOCK: &str = "bop_follower_head_block";
+/// Label: `kind`, the kind of violation.
+pub const WATCHER_VIOLATIONS: &str = "bop_watcher_violations_total";
+
+// Portal
+/// Labels: `gateway`, `method` and `outcome`, one of `success`, `error` or `invalid`.
+pub const PORTAL_GATEWAY_REQUESTS: &str = "bop_portal_gateway_requests_total";
+/// Labels: `gateway` and `reason`, one of `unreachable`, `syncing`, `head_mismatch` or `invalid_payload`.
+pub const PORTAL_GATEWAY_EJECTIONS: &str = "bop_portal_gateway_ejections_total";
+pub const PORTAL_HEALTHY_GATEWAYS: &str = "bop_portal_healthy_gateways";
+
+// Timekeeper
+/// Labels: `timer`, `kind`, either `latency` or `business`, and `quantile`.
+pub const TIMER_DURATION: &str = "bop_timer_duration_seconds";
+/// Labels: `timer` and `kind`.
+pub const TIMER_RATE: &str = "bop_timer_messages_per_second";
+
+const LATENCY_BUCKETS: &[f64] = &[0.000_01, 0.000_05, 0.000_1, 0.000_5, 0.001, 0.005, 0.01, 0.05, 0.1, 0.5, 1.0, 5.0];
+const COUNT_BUCKETS: &[f64] = &[0.0, 1.0, 2.0, 5.0, 10.0, 20.0, 50.0, 100.0, 200.0, 500.0, 1000.0];
+
+/// Installs the global metrics recorder and serves it on `http://{addr}/metrics`. The listener runs on the ambient
+/// tokio runtime if there is one, otherwise on a dedicated thread.
+pub fn init_prometheus(addr: SocketAddr) -> Result<(), BuildError> {
+    PrometheusBuilder::new()
+        .with_http_listener(addr)
+        .set_buckets_for_metric(Matcher::Suffix("_seconds".to_string()), LATENCY_BUCKETS)?
+        .set_buckets_for_metric(Matcher::Full(FRAGS_PER_BLOCK.to_string()), COUNT_BUCKETS)?
+        .set_buckets_for_metric(Matcher::Full(TXS_PER_FRAG.to_string()), COUNT_BUCKETS)?
+        .install()?;
+
+    describe();
+    Ok(())
+}
+
+fn describe() {
+    describe_gauge!(SEQUENCER_STATE, "Current state of the sequencer state machine");
+    describe_histogram!(FRAGS_PER_BLOCK, Unit::Count, "Frags sealed per sequenced block");
+    describe_histogram!(TXS_PER_FRAG, Unit::Count, "Transactions per sealed frag");
+    describe_counter!(SIMS, Unit::Count, "Transaction simulations handled while sorting");
+    describe_histogram!(SEAL_FRAG_DURATION, Unit::Seconds, "Time to seal a frag");
+    describe_histogram!(SEAL_BLOCK_DURATION, Unit::Seconds, "Time to seal a block and return the payload");
+    describe_histogram!(STATE_ROOT_DURATION, Unit::Seconds, "Time to calculate a state root");
+    describe_gauge!(TX_POOL_ACTIVE_TXS, Unit::Count, "Simulated transactions ready to be included");
+    describe_gauge!(TX_POOL_SENDERS, Unit::Count, "Senders with pending transactions");
+
+    describe_histogram!(SIM_DURATION, Unit::Seconds, "Time to simulate a transaction");
+
+    describe_gauge!(DB_HEAD_BLOCK, "Last block committed to the database");
+    describe_gauge!(
+        UPSTREAM_HEAD_BLOCK,
+        "Head block reported by the upstream providers, block sync lag is this minus bop_db_head_block"
+    );
+
+    describe_counter!(DB_CACHE_HITS, Unit::Count, "Reads served from a database read cache");
+    describe_counter!(DB_CACHE_MISSES, Unit::Count, "Reads that missed a database read cache");
+    describe_counter!(DB_CACHE_EVICTIONS, Unit::Count, "Entries evicted from a database read cache");
+
+    describe_counter!(CHANNEL_DROPS, Unit::Count, "Messages dropped because a spine channel was full");
+    describe_counter!(CHANNEL_SEND_TIMEOUTS, Unit::Count, "Sends to a full spine channel that timed out");
+    describe_gauge!(CHANNEL_HIGH_WATER_MARK, Unit::Count, "Highest number of messages queued in a spine channel");
+    describe_gauge!(CHANNEL_SATURATED, "Whether a spine channel is filled above its saturation threshold");
+
+    describe_counter!(GOSSIP_MESSAGES, Unit::Count, "Messages gossiped to the root peer");
+
+    describe_counter!(FOLLOWER_MESSAGES, Unit::Count, "Gossiped messages received by the follower");
+    describe_gauge!(FOLLOWER_HEAD_BLOCK, "Last block verified and committed by the follower");
+    describe_counter!(WATCHER_VIOLATIONS, Unit::Count, "Broken promises of the sequencer found by the watcher");
+
+    describe_counter!(PORTAL_GATEWAY_REQUESTS, Unit::Count, "Requests forwarded by the portal to gateways");
+    describe_counter!(PORTAL_GATEWAY_EJECTIONS, Unit::Count, "Gateways ejected from rotation by the portal");
+    describe_gauge!(PORTAL_HEALTHY_GATEWAYS, Unit::Count, "Gateways currently eligible to build blocks");
+
+    describe_gauge!(TIMER_DURATION, Unit::Seconds, "Quantiles of a timer over the last export interval");
+    describe_gauge!(TIMER_RATE, "Messages per second published by a timer over the last export interval");
+}
@@ -1,21 +1,32 @@
-use alloy_primitives::{Address, Bytes, B256, U256};
+use std::io::{Read, Write};
+
+use alloy_primitives::{Address, Bytes, PrimitiveSignature, B256, U256};
+use clap::ValueEnum;
 use revm_primitives::BlockEnv;
 use serde::{Deserialize, Serialize};
-use ssz_types::{typenum, VariableList};
+use ssz::{Decode, Encode};
+use ssz_derive::{Decode, Encode};
+use ssz_types::{typenum, FixedVector, VariableList};
 use strum_macros::AsRefStr;
 use tree_hash::TreeHash;
 use tree_hash_derive::TreeHash;
 
-use crate::{signing::ECDSASigner, transaction::Transaction as BuilderTransaction};
+use crate::{
+    signing::{ECDSASigner, SignerError},
+    transaction::Transaction as BuilderTransaction,
+};
 
-#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Serialize, Deserialize, AsRefStr)]
+/// The variant order is the union selector of the tree hash and SSZ encodings, new versions are only appended.
+#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Encode, Decode, Serialize, Deserialize, AsRefStr)]
 #[tree_hash(enum_behaviour = "union")]
+#[ssz(enum_behaviour = "union")]
 #[serde(untagged)]
 #[non_exhaustive]
 pub enum VersionedMessage {
     FragV0(FragV0),
     SealV0(SealV0),
     EnvV0(EnvV0),
+    FragV1(FragV1),
 }
 
 impl From<FragV0> for VersionedMessage {
@@ -36,24 +47,30 @@ impl From<EnvV0> for VersionedMessage {
     }
 }
 
+impl From<FragV1> for VersionedMessage {
+    fn from(value: FragV1) -> Self {
+        Self::FragV1(value)
+    }
+}
+
 pub type MaxExtraDataSize = typenum::U256;
 pub type ExtraData = VariableList<u8, MaxExtraDataSize>;
 
 /// Initial message to set the block environment for the current block
-#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Encode, Decode, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct EnvV0 {
-    number: u64,
-    parent_hash: B256,
-    beneficiary: Address,
-    timestamp: u64,
-    gas_limit: u64,
-    basefee: u64,
-    difficulty: U256,
-    prevrandao: B256,
+    pub number: u64,
+    pub parent_hash: B256,
+    pub beneficiary: Address,
+    pub timestamp: u64,
+    pub gas_limit: u64,
+    pub basefee: u64,
+    pub difficulty: U256,
+    pub prevrandao: B256,
     #[serde(with = "ssz_types::serde_utils::hex_var_list")]
-    extra_data: ExtraData,
-    parent_beacon_block_root: B256,
+    pub extra_data: ExtraData,
+    pub parent_beacon_block_root: B256,
 }
 
 impl EnvV0 {
@@ -80,18 +97,18 @@ pub type Transactions = VariableList<Transaction, MaxTransactionsPerPayload>;
 
 /// A _fragment_ of a block, containing a sequenced set of transactions that will be eventually included in the next
 /// block in this order
-#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Serialize, Deserialize)]
-#[serde(rename_all = "camelCase")]
+#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Encode, Decode, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
 pub struct FragV0 {
     /// Block in which this frag will be included
-    block_number: u64,
+    pub block_number: u64,
     /// Index of this frag. Frags need to be applied sequentially by index, up to [`SealV0::total_frags`]
-    seq: u64,
+    pub seq: u64,
     /// Whether this is the last frag in the sequence
     pub is_last: bool,
     /// Ordered list of EIP-2718 encoded transactions
     #[serde(with = "ssz_types::serde_utils::list_of_hex_var_list")]
-    txs: Transactions,
+    pub txs: Transactions,
 }
 
 impl FragV0 {
@@ -106,8 +123,38 @@ impl FragV0 {
     }
 }
 
+/// A [`FragV0`] that also commits to the block state after its transactions, so followers can reject a diverging frag
+/// as soon as it is applied instead of at the [`SealV0`]
+#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Encode, Decode, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct FragV1 {
+    /// Block in which this frag will be included
+    pub block_number: u64,
+    /// Index of this frag. Frags need to be applied sequentially by index, up to [`SealV0::total_frags`]
+    pub seq: u64,
+    /// Whether this is the last frag in the sequence
+    pub is_last: bool,
+    /// Ordered list of EIP-2718 encoded transactions
+    #[serde(with = "ssz_types::serde_utils::list_of_hex_var_list")]
+    pub txs: Transactions,
+    /// Gas used by all transactions of the block up to and including this frag
+    pub cumulative_gas_used: u64,
+    /// Commitment to the accounts and storage changed by the block so far, see `DBFrag::state_commitment`
+    pub state_commitment: B256,
+    /// Receipts root of all transactions of the block up to and including this frag
+    pub receipts_root: B256,
+    /// Tree hash root of the previous frag of the block, zero for the first frag
+    pub prev_frag_hash: B256,
+}
+
+impl From<FragV1> for FragV0 {
+    fn from(value: FragV1) -> Self {
+        Self { block_number: value.block_number, seq: value.seq, is_last: value.is_last, txs: value.txs }
+    }
+}
+
 /// A message sealing a sequence of frags, with fields from the block header
-#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, TreeHash, Encode, Decode, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct SealV0 {
     /// How many frags for this block were in this sequence
@@ -125,35 +172,157 @@ pub struct SealV0 {
 }
 
 impl VersionedMessage {
-    pub fn to_json(&self, signer: &ECDSASigner) -> serde_json::Value {
+    /// Signs the tree hash root of the message.
+    pub fn sign(self, signer: &ECDSASigner) -> SignedMessage {
         let signature = signer.sign_message(self.tree_hash_root()).expect("couldn't sign message");
-        let signature = Bytes::from(signature.as_bytes());
+        SignedMessage { signature: Bytes::from(signature.as_bytes()), message: self }
+    }
 
-        let method = match &self {
+    /// Name of the JSON-RPC method the message is gossiped with.
+    pub fn method(&self) -> &'static str {
+        match self {
             VersionedMessage::FragV0(_) => "based_newFrag",
             VersionedMessage::SealV0(_) => "based_sealFrag",
             VersionedMessage::EnvV0(_) => "based_env",
-        };
+            VersionedMessage::FragV1(_) => "based_newFragV1",
+        }
+    }
 
+    /// Block the message belongs to.
+    pub fn block_number(&self) -> u64 {
+        match self {
+            VersionedMessage::FragV0(frag) => frag.block_number,
+            VersionedMessage::SealV0(seal) => seal.block_number,
+            VersionedMessage::EnvV0(env) => env.number,
+            VersionedMessage::FragV1(frag) => frag.block_number,
+        }
+    }
+
+    pub fn to_json(&self, signer: &ECDSASigner) -> serde_json::Value {
+        self.clone().sign(signer).to_json()
+    }
+}
+
+/// A [`VersionedMessage`] with the signature of the gateway over its tree hash root, as it is gossiped.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SignedMessage {
+    pub signature: Bytes,
+    pub message: VersionedMessage,
+}
+
+/// Binary encodings a [`SignedMessage`] can be gossiped with, as an alternative to the JSON-RPC request which hex
+/// encodes every transaction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum WireEncoding {
+    /// SSZ container of the 65 byte signature and the [`VersionedMessage`] union.
+    Ssz,
+    /// [`WireEncoding::Ssz`] compressed with the snappy framing format.
+    SszSnappy,
+}
+
+impl WireEncoding {
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            WireEncoding::Ssz => "application/octet-stream",
+            WireEncoding::SszSnappy => "application/x-snappy-framed",
+        }
+    }
+
+    pub fn from_content_type(content_type: &str) -> Option<Self> {
+        [WireEncoding::Ssz, WireEncoding::SszSnappy]
+            .into_iter()
+            .find(|encoding| encoding.content_type() == content_type)
+    }
+}
+
+/// Upper bound for a decompressed message, so a small snappy payload can't exhaust memory.
+pub const MAX_WIRE_MESSAGE_SIZE: u64 = 64 * 1024 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum WireError {
+    #[error("signature must be 65 bytes, got {0}")]
+    SignatureLength(usize),
+    #[error("invalid ssz: {0}")]
+    Ssz(String),
+    #[error("invalid snappy frame: {0}")]
+    Snappy(#[from] std::io::Error),
+    #[error("message exceeds {} bytes", MAX_WIRE_MESSAGE_SIZE)]
+    TooLarge,
+}
+
+type SignatureBytes = FixedVector<u8, typenum::U65>;
+
+#[derive(Encode, Decode)]
+struct SszSignedMessage {
+    signature: SignatureBytes,
+    message: VersionedMessage,
+}
+
+impl SignedMessage {
+    /// JSON-RPC request the message is gossiped with.
+    pub fn to_json(&self) -> serde_json::Value {
         serde_json::json!({
             "jsonrpc": "2.0",
-            "method": method,
-            "params": [{"signature": signature, "message": self}],
+            "method": self.message.method(),
+            "params": [self],
             "id": 1
         })
     }
+
+    pub fn to_wire(&self, encoding: WireEncoding) -> Result<Vec<u8>, WireError> {
+        let signature = SignatureBytes::new(self.signature.to_vec())
+            .map_err(|_| WireError::SignatureLength(self.signature.len()))?;
+        let ssz = SszSignedMessage { signature, message: self.message.clone() }.as_ssz_bytes();
+
+        match encoding {
+            WireEncoding::Ssz => Ok(ssz),
+            WireEncoding::SszSnappy => {
+                let mut encoder = snap::write::FrameEncoder::new(Vec::with_capacity(ssz.len()));
+                encoder.write_all(&ssz)?;
+                Ok(encoder.into_inner().expect("flushing to a vec can't fail"))
+            }
+        }
+    }
+
+    pub fn from_wire(bytes: &[u8], encoding: WireEncoding) -> Result<Self, WireError> {
+        let decompressed;
+        let ssz = match encoding {
+            WireEncoding::Ssz => bytes,
+            WireEncoding::SszSnappy => {
+                let mut buf = Vec::new();
+                snap::read::FrameDecoder::new(bytes).take(MAX_WIRE_MESSAGE_SIZE + 1).read_to_end(&mut buf)?;
+                if buf.len() as u64 > MAX_WIRE_MESSAGE_SIZE {
+                    return Err(WireError::TooLarge);
+                }
+                decompressed = buf;
+                decompressed.as_slice()
+            }
+        };
+
+        let SszSignedMessage { signature, message } =
+            SszSignedMessage::from_ssz_bytes(ssz).map_err(|err| WireError::Ssz(format!("{err:?}")))?;
+        Ok(Self { signature: Bytes::from(signature.to_vec()), message })
+    }
+
+    /// Recovers the address that signed the message.
+    pub fn recover_signer(&self) -> Result<Address, SignerError> {
+        let signature = PrimitiveSignature::try_from(self.signature.as_ref())
+            .map_err(|err| SignerError::SignerError(err.to_string()))?;
+        signature
+            .recover_address_from_prehash(&self.message.tree_hash_root())
+            .map_err(|err| SignerError::SignerError(err.to_string()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
-    use alloy_primitives::{address, b256};
+    use alloy_primitives::{address, b256, hex};
     use tree_hash::TreeHash;
 
     use super::*;
 
-    #[test]
-    fn test_env_v0() {
-        let env = EnvV0 {
+    fn env() -> EnvV0 {
+        EnvV0 {
             number: 1,
             beneficiary: address!("1234567890123456789012345678901234567890"),
             timestamp: 2,
@@ -164,9 +333,12 @@ mod tests {
             parent_hash: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
             extra_data: ExtraData::from(vec![1, 2, 3]),
             parent_beacon_block_root: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
-        };
+        }
+    }
 
-        let message = VersionedMessage::from(env);
+    #[test]
+    fn test_env_v0() {
+        let message = VersionedMessage::from(env());
         let hash = message.tree_hash_root();
         assert_eq!(hash, b256!("fa09df7670737568ba783dfd934e19b06e6681e367a866a5647449bd4e5ca324"));
     }
@@ -184,8 +356,32 @@ mod tests {
     }
 
     #[test]
-    fn test_seal_v0() {
-        let sealed = SealV0 {
+    fn test_frag_v1() {
+        let frag = FragV1 {
+            block_number: 1,
+            seq: 1,
+            is_last: false,
+            txs: Transactions::from(vec![Transaction::from(vec![1, 2, 3])]),
+            cumulative_gas_used: 21_000,
+            state_commitment: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+            receipts_root: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+            prev_frag_hash: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+        };
+
+        // untagged, so a V1 frag must not deserialize as the V0 frag it extends
+        let message = VersionedMessage::from(frag.clone());
+        let json = serde_json::to_value(&message).unwrap();
+        assert_eq!(serde_json::from_value::<VersionedMessage>(json).unwrap(), message);
+        assert_eq!(message.method(), "based_newFragV1");
+
+        let v0 = VersionedMessage::from(FragV0::from(frag));
+        let json = serde_json::to_value(&v0).unwrap();
+        assert_eq!(serde_json::from_value::<VersionedMessage>(json).unwrap(), v0);
+        assert_ne!(v0.tree_hash_root(), message.tree_hash_root());
+    }
+
+    fn seal() -> SealV0 {
+        SealV0 {
             total_frags: 8,
             block_number: 123,
             gas_used: 25_000,
@@ -195,10 +391,162 @@ mod tests {
             receipts_root: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
             state_root: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
             block_hash: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
-        };
+        }
+    }
 
-        let message = VersionedMessage::from(sealed);
+    #[test]
+    fn test_seal_v0() {
+        let message = VersionedMessage::from(seal());
         let hash = message.tree_hash_root();
         assert_eq!(hash, b256!("e86afda21ddc7338c7e84561681fde45e2ab55cce8cde3163e0ae5f1c378439e"));
     }
+
+    #[test]
+    fn recovers_signer() {
+        let signer = ECDSASigner::random();
+        let frag = FragV0 { block_number: 1, seq: 0, is_last: true, txs: Transactions::from(vec![]) };
+        let signed = VersionedMessage::from(frag).sign(&signer);
+
+        assert_eq!(signed.recover_signer().unwrap(), signer.address);
+
+        let json = serde_json::to_value(&signed).unwrap();
+        assert_eq!(serde_json::from_value::<SignedMessage>(json).unwrap(), signed);
+
+        let tampered = SignedMessage { message: SealV0 { total_frags: 1, ..seal() }.into(), ..signed };
+        assert_ne!(tampered.recover_signer().unwrap(), signer.address);
+    }
+
+    // The vectors below were produced by an encoder written independently of this crate and of `ethereum_ssz`/`snap`,
+    // from the consensus specs' SSZ serialization and snappy's framing_format.txt, so they catch encodings that
+    // round trip here but that other implementations can't read.
+
+    #[test]
+    fn ssz_vectors() {
+        let frag = FragV0 {
+            block_number: 1,
+            seq: 0,
+            is_last: true,
+            txs: Transactions::from(vec![Transaction::from(vec![1, 2, 3])]),
+        };
+        let frag_v1 = FragV1 {
+            block_number: 1,
+            seq: 1,
+            is_last: false,
+            txs: Transactions::from(vec![Transaction::from(vec![1, 2, 3])]),
+            cumulative_gas_used: 21_000,
+            state_commitment: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+            receipts_root: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+            prev_frag_hash: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+        };
+
+        // union selector, then the container with offsets for the variable size fields
+        let vectors = [
+            (VersionedMessage::from(frag), hex!("0001000000000000000000000000000000011500000004000000010203").to_vec()),
+            (
+                VersionedMessage::from(seal()),
+                hex!(
+                    "0108000000000000007b00000000000000a86100000000000040420f0000000000"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                )
+                .to_vec(),
+            ),
+            (
+                VersionedMessage::from(env()),
+                hex!(
+                    "020100000000000000e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "1234567890123456789012345678901234567890"
+                    "020000000000000003000000000000000400000000000000"
+                    "0500000000000000000000000000000000000000000000000000000000000000"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758b8000000"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758010203"
+                )
+                .to_vec(),
+            ),
+            (
+                VersionedMessage::from(frag_v1),
+                hex!(
+                    "0301000000000000000100000000000000007d0000000852000000000000"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"
+                    "04000000010203"
+                )
+                .to_vec(),
+            ),
+        ];
+
+        for (message, ssz) in vectors {
+            assert_eq!(message.as_ssz_bytes(), ssz, "{}", message.as_ref());
+            assert_eq!(VersionedMessage::from_ssz_bytes(&ssz).unwrap(), message);
+        }
+        assert!(VersionedMessage::from_ssz_bytes(&hex!("07")).is_err());
+    }
+
+    #[test]
+    fn wire_vectors() {
+        let frag = FragV0 {
+            block_number: 1,
+            seq: 0,
+            is_last: true,
+            txs: Transactions::from(vec![Transaction::from(vec![0xaa; 100]), Transaction::from(vec![1, 2, 3])]),
+        };
+        let signed = SignedMessage { signature: Bytes::from_iter(0..65u8), message: frag.into() };
+
+        // 65 byte signature, offset of the message, then the message union
+        let ssz = hex!(
+            "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+            "202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f"
+            "4045000000"
+            "00010000000000000000000000000000000115000000080000006c000000"
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+            "aaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaaa"
+            "010203"
+        );
+        assert_eq!(signed.to_wire(WireEncoding::Ssz).unwrap(), ssz);
+        assert_eq!(SignedMessage::from_wire(&ssz, WireEncoding::Ssz).unwrap(), signed);
+
+        // stream identifier, then a single chunk with the masked crc32c of the ssz bytes
+        let compressed_chunk = hex!(
+            "ff060000734e61507059"
+            "006e000093c0944d"
+            "ca01f046000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e1f"
+            "202122232425262728292a2b2c2d2e2f303132333435363738393a3b3c3d3e3f404500000000"
+            "010e05002a0400340115000000080000006c000000aafe01008a010008010203"
+        );
+        let uncompressed_chunk = [&hex!("ff060000734e6150705901ce000093c0944d")[..], &ssz].concat();
+        for snappy in [&compressed_chunk[..], &uncompressed_chunk] {
+            assert_eq!(SignedMessage::from_wire(snappy, WireEncoding::SszSnappy).unwrap(), signed);
+        }
+
+        // frames written by this crate have the same stream identifier and checksum
+        let snappy = signed.to_wire(WireEncoding::SszSnappy).unwrap();
+        assert_eq!(snappy[..10], hex!("ff060000734e61507059"));
+        assert_eq!(snappy[14..18], hex!("93c0944d"), "checksum of the uncompressed ssz");
+    }
+
+    #[test]
+    fn wire_round_trip() {
+        let signer = ECDSASigner::random();
+        let txs = (0..100).map(|i| Transaction::from(vec![i; 200])).collect::<Vec<_>>();
+        let frag = FragV0 { block_number: 1, seq: 0, is_last: true, txs: Transactions::from(txs) };
+        let signed = VersionedMessage::from(frag).sign(&signer);
+
+        let ssz = signed.to_wire(WireEncoding::Ssz).unwrap();
+        let snappy = signed.to_wire(WireEncoding::SszSnappy).unwrap();
+        assert!(snappy.len() < ssz.len());
+        assert!(ssz.len() < serde_json::to_vec(&signed).unwrap().len());
+
+        for (bytes, encoding) in [(ssz, WireEncoding::Ssz), (snappy, WireEncoding::SszSnappy)] {
+            let decoded = SignedMessage::from_wire(&bytes, encoding).unwrap();
+            assert_eq!(decoded, signed);
+            assert_eq!(decoded.recover_signer().unwrap(), signer.address);
+        }
+
+        let unsigned = SignedMessage { signature: Bytes::new(), ..signed };
+        assert!(matches!(unsigned.to_wire(WireEncoding::Ssz), Err(WireError::SignatureLength(0))));
+    }
 }
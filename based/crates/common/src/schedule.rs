@@ -0,0 +1,225 @@
+//! Lookahead schedule of which gateway sequences which block.
+//!
+//! A schedule is a list of epochs, each starting at a block number and splitting the following blocks into fixed size
+//! slots. Slots are assigned to the epoch's gateways round robin, with a gateway of weight `n` sequencing `n`
+//! consecutive slots. An epoch lasts until the next one starts, so a new rotation can be published ahead of time and
+//! takes over exactly at its start block.
+
+use alloy_primitives::Address;
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum ScheduleError {
+    #[error("schedule has no epochs")]
+    Empty,
+    #[error("epoch starting at block {0} has no gateways with a non zero weight")]
+    NoGateways(u64),
+    #[error("epoch starting at block {0} has zero blocks per slot")]
+    ZeroSlot(u64),
+    #[error("epochs must have strictly increasing start blocks, got {0} after {1}")]
+    Unordered(u64, u64),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledGateway {
+    pub url: Url,
+    /// Key the gateway signs frags and commitments with.
+    pub address: Address,
+    #[serde(default = "default_weight")]
+    pub weight: u64,
+}
+
+fn default_weight() -> u64 {
+    1
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleEpoch {
+    pub start_block: u64,
+    pub blocks_per_slot: u64,
+    pub gateways: Vec<ScheduledGateway>,
+}
+
+impl ScheduleEpoch {
+    fn total_weight(&self) -> u64 {
+        self.gateways.iter().map(|gateway| gateway.weight).sum()
+    }
+
+    fn gateway_for_slot(&self, slot: u64) -> &ScheduledGateway {
+        let mut index = slot % self.total_weight();
+        self.gateways
+            .iter()
+            .find(|gateway| {
+                if index < gateway.weight {
+                    true
+                } else {
+                    index -= gateway.weight;
+                    false
+                }
+            })
+            .expect("index is below the total weight")
+    }
+}
+
+/// A range of blocks sequenced by a single gateway.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SlotAssignment {
+    pub start_block: u64,
+    /// Last block of the slot, inclusive.
+    pub end_block: u64,
+    pub url: Url,
+    pub address: Address,
+}
+
+impl SlotAssignment {
+    pub fn contains(&self, block_number: u64) -> bool {
+        (self.start_block..=self.end_block).contains(&block_number)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SequencerSchedule {
+    epochs: Vec<ScheduleEpoch>,
+}
+
+impl SequencerSchedule {
+    pub fn new(epochs: Vec<ScheduleEpoch>) -> Result<Self, ScheduleError> {
+        if epochs.is_empty() {
+            return Err(ScheduleError::Empty);
+        }
+        for epoch in &epochs {
+            if epoch.total_weight() == 0 {
+                return Err(ScheduleError::NoGateways(epoch.start_block));
+            }
+            if epoch.blocks_per_slot == 0 {
+                return Err(ScheduleError::ZeroSlot(epoch.start_block));
+            }
+        }
+        for pair in epochs.windows(2) {
+            if pair[1].start_block <= pair[0].start_block {
+                return Err(ScheduleError::Unordered(pair[1].start_block, pair[0].start_block));
+            }
+        }
+
+        Ok(Self { epochs })
+    }
+
+    pub fn from_json(json: &str) -> eyre::Result<Self> {
+        let epochs: Vec<ScheduleEpoch> = serde_json::from_str(json)?;
+        Ok(Self::new(epochs)?)
+    }
+
+    pub fn epochs(&self) -> &[ScheduleEpoch] {
+        &self.epochs
+    }
+
+    /// Urls of all gateways in the schedule, without duplicates.
+    pub fn urls(&self) -> Vec<Url> {
+        let mut urls: Vec<Url> = Vec::new();
+        for gateway in self.epochs.iter().flat_map(|epoch| &epoch.gateways) {
+            if !urls.contains(&gateway.url) {
+                urls.push(gateway.url.clone());
+            }
+        }
+        urls
+    }
+
+    /// Returns the slot containing `block_number`, `None` if it's before the first epoch.
+    pub fn assignment(&self, block_number: u64) -> Option<SlotAssignment> {
+        let index = self.epochs.iter().rposition(|epoch| epoch.start_block <= block_number)?;
+        let epoch = &self.epochs[index];
+
+        let slot = (block_number - epoch.start_block) / epoch.blocks_per_slot;
+        let start_block = epoch.start_block + slot * epoch.blocks_per_slot;
+        let mut end_block = start_block + epoch.blocks_per_slot - 1;
+        // the last slot of an epoch is cut short by the next one
+        if let Some(next) = self.epochs.get(index + 1) {
+            end_block = end_block.min(next.start_block - 1);
+        }
+
+        let gateway = epoch.gateway_for_slot(slot);
+        Some(SlotAssignment { start_block, end_block, url: gateway.url.clone(), address: gateway.address })
+    }
+
+    /// Returns `slots` consecutive slots starting with the one containing `block_number`.
+    pub fn lookahead(&self, block_number: u64, slots: usize) -> Vec<SlotAssignment> {
+        let mut assignments: Vec<SlotAssignment> = Vec::with_capacity(slots);
+        let mut block_number = block_number;
+        while assignments.len() < slots {
+            let Some(assignment) = self.assignment(block_number) else {
+                break;
+            };
+            block_number = assignment.end_block + 1;
+            assignments.push(assignment);
+        }
+        assignments
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn gateway(port: u16, weight: u64) -> ScheduledGateway {
+        ScheduledGateway {
+            url: format!("http://127.0.0.1:{port}").parse().unwrap(),
+            address: Address::with_last_byte(port as u8),
+            weight,
+        }
+    }
+
+    #[test]
+    fn assigns_weighted_slots() {
+        let schedule = SequencerSchedule::new(vec![ScheduleEpoch {
+            start_block: 100,
+            blocks_per_slot: 10,
+            gateways: vec![gateway(1, 2), gateway(2, 1)],
+        }])
+        .unwrap();
+
+        assert_eq!(schedule.assignment(99), None);
+
+        let ports: Vec<_> = schedule.lookahead(100, 4).into_iter().map(|slot| slot.url.port().unwrap()).collect();
+        assert_eq!(ports, vec![1, 1, 2, 1]);
+
+        let slot = schedule.assignment(125).unwrap();
+        assert_eq!((slot.start_block, slot.end_block, slot.address), (120, 129, Address::with_last_byte(2)));
+    }
+
+    #[test]
+    fn hands_over_at_epoch_start() {
+        let schedule = SequencerSchedule::new(vec![
+            ScheduleEpoch { start_block: 0, blocks_per_slot: 10, gateways: vec![gateway(1, 1)] },
+            ScheduleEpoch { start_block: 25, blocks_per_slot: 5, gateways: vec![gateway(2, 1), gateway(3, 1)] },
+        ])
+        .unwrap();
+
+        let slots: Vec<_> = schedule
+            .lookahead(12, 4)
+            .into_iter()
+            .map(|slot| (slot.start_block, slot.end_block, slot.url.port().unwrap()))
+            .collect();
+        assert_eq!(slots, vec![(10, 19, 1), (20, 24, 1), (25, 29, 2), (30, 34, 3)]);
+        assert_eq!(schedule.urls().len(), 3);
+    }
+
+    #[test]
+    fn rejects_invalid_schedules() {
+        assert_eq!(SequencerSchedule::new(vec![]), Err(ScheduleError::Empty));
+        assert_eq!(
+            SequencerSchedule::new(vec![ScheduleEpoch { start_block: 0, blocks_per_slot: 1, gateways: vec![] }]),
+            Err(ScheduleError::NoGateways(0))
+        );
+        assert_eq!(
+            SequencerSchedule::new(vec![
+                ScheduleEpoch { start_block: 5, blocks_per_slot: 1, gateways: vec![gateway(1, 1)] },
+                ScheduleEpoch { start_block: 5, blocks_per_slot: 1, gateways: vec![gateway(1, 1)] },
+            ]),
+            Err(ScheduleError::Unordered(5, 5))
+        );
+    }
+}
@@ -2,10 +2,11 @@ use std::sync::Arc;
 
 use op_alloy_consensus::OpTxEnvelope;
 use op_alloy_rpc_types::OpTransactionReceipt;
-use parking_lot::RwLock;
+use parking_lot::{Mutex, RwLock};
 use revm_primitives::{HashMap, B256};
+use tokio::sync::oneshot;
 
-use crate::db::DBFrag;
+use crate::{db::DBFrag, signing::Preconfirmation};
 
 /// Shared state between Sequencer and RPC
 /// Allows for access to the State and Receipts
@@ -14,11 +15,17 @@ use crate::db::DBFrag;
 pub struct SharedState<Db> {
     db: DBFrag<Db>,
     receipts: Arc<RwLock<HashMap<B256, OpTransactionReceipt>>>,
+    /// RPC requests waiting for a transaction to be included in a sealed frag
+    inclusion_waiters: Arc<Mutex<HashMap<B256, Vec<oneshot::Sender<Preconfirmation>>>>>,
 }
 
 impl<Db> SharedState<Db> {
     pub fn new(db: DBFrag<Db>) -> Self {
-        Self { db, receipts: Arc::new(RwLock::new(Default::default())) }
+        Self {
+            db,
+            receipts: Arc::new(RwLock::new(Default::default())),
+            inclusion_waiters: Arc::new(Mutex::new(Default::default())),
+        }
     }
 
     /// Resets the pending state its holding for live blocks that are being built.
@@ -35,6 +42,36 @@ impl<Db> SharedState<Db> {
     pub fn get_receipt(&self, tx_hash: &B256) -> Option<OpTransactionReceipt> {
         self.receipts.read().get(tx_hash).cloned()
     }
+
+    /// Returns a receiver that gets the inclusion of the transaction once it's in a sealed frag. Has to be called
+    /// before the transaction is sent to the sequencer, see [`Self::cancel_inclusion_waiters`] for giving up.
+    pub fn wait_for_inclusion(&self, tx_hash: B256) -> oneshot::Receiver<Preconfirmation> {
+        let (tx, rx) = oneshot::channel();
+        self.inclusion_waiters.lock().entry(tx_hash).or_default().push(tx);
+        rx
+    }
+
+    /// Drops the waiters of a transaction whose receivers were dropped, e.g. after a timeout.
+    pub fn cancel_inclusion_waiters(&self, tx_hash: &B256) {
+        let mut waiters = self.inclusion_waiters.lock();
+        if let Some(senders) = waiters.get_mut(tx_hash) {
+            senders.retain(|sender| !sender.is_closed());
+            if senders.is_empty() {
+                waiters.remove(tx_hash);
+            }
+        }
+    }
+
+    /// Sends the inclusion of a transaction to its waiters. `inclusion` is only built if anyone is waiting.
+    pub fn notify_inclusion(&self, tx_hash: &B256, inclusion: impl FnOnce() -> Preconfirmation) {
+        let Some(senders) = self.inclusion_waiters.lock().remove(tx_hash) else {
+            return;
+        };
+        let inclusion = inclusion();
+        for sender in senders {
+            let _ = sender.send(inclusion);
+        }
+    }
 }
 
 impl<Db: Clone> From<&SharedState<Db>> for DBFrag<Db> {
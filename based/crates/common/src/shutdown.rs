@@ -0,0 +1,214 @@
+//! Ordered shutdown of the gateway.
+//!
+//! Actors and servers register a [`StopToken`] for the [`Stage`] they stop in. On shutdown the stages are signalled in
+//! order, each one after all tokens of the previous stage were dropped, so e.g. the gossiper only stops once the
+//! sequencer has handed over its last messages. Stages that take longer than the remaining deadline are not waited
+//! for, the remaining stages are signalled straight away.
+
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use parking_lot::Mutex;
+use strum_macros::AsRefStr;
+use tokio::sync::watch;
+use tracing::{info, warn};
+
+use crate::time::{Duration, Instant};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, AsRefStr)]
+#[repr(u8)]
+pub enum Stage {
+    /// New transactions are rejected and the public RPC stops.
+    Intake = 1,
+    /// The sequencer finishes the block it's building, or aborts it once the deadline is close.
+    Sequencer = 2,
+    /// The engine RPC stops and the gossiper sends what is left in its queue.
+    Gossip = 3,
+    /// Writes are flushed to disk.
+    Flush = 4,
+    /// Simulators stop.
+    Simulators = 5,
+}
+
+impl Stage {
+    pub const ALL: [Stage; 5] = [Stage::Intake, Stage::Sequencer, Stage::Gossip, Stage::Flush, Stage::Simulators];
+
+    fn index(self) -> usize {
+        self as usize - 1
+    }
+}
+
+type Hook = Box<dyn FnOnce() + Send>;
+
+struct Inner {
+    /// Last stage that was signalled, 0 while running.
+    stage: watch::Sender<u8>,
+    running: [AtomicUsize; Stage::ALL.len()],
+    hooks: Mutex<Vec<(Stage, Hook)>>,
+    deadline: Duration,
+    /// Set when shutdown starts.
+    started: Mutex<Option<Instant>>,
+}
+
+/// Shared shutdown controller, cheap to clone.
+#[derive(Clone)]
+pub struct Shutdown {
+    inner: Arc<Inner>,
+}
+
+impl Shutdown {
+    /// `deadline` bounds the whole shutdown, from the first stage being signalled to the last one.
+    pub fn new(deadline: Duration) -> Self {
+        Self {
+            inner: Arc::new(Inner {
+                stage: watch::Sender::new(0),
+                running: Default::default(),
+                hooks: Mutex::new(vec![]),
+                deadline,
+                started: Mutex::new(None),
+            }),
+        }
+    }
+
+    /// Registers something that stops in `stage`. The stage is done once all of its tokens are dropped.
+    pub fn register(&self, stage: Stage) -> StopToken {
+        self.inner.running[stage.index()].fetch_add(1, Ordering::AcqRel);
+        StopToken { shutdown: self.clone(), stage, receiver: self.inner.stage.subscribe() }
+    }
+
+    /// Runs `hook` when `stage` is signalled, before waiting for the tokens of the stage.
+    pub fn on_stage(&self, stage: Stage, hook: impl FnOnce() + Send + 'static) {
+        self.inner.hooks.lock().push((stage, Box::new(hook)));
+    }
+
+    /// Whether `stage` has been signalled.
+    pub fn is_stopping(&self, stage: Stage) -> bool {
+        *self.inner.stage.borrow() >= stage as u8
+    }
+
+    /// Time left until the deadline, `None` if shutdown hasn't started.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.inner.started.lock().map(|started| self.inner.deadline.saturating_sub(started.elapsed()))
+    }
+
+    /// Signals all stages in order, blocking until each one is done or the deadline passed.
+    pub fn shutdown(&self) {
+        let start = Instant::now();
+        *self.inner.started.lock() = Some(start);
+        info!(deadline = %self.inner.deadline, "shutting down");
+
+        for stage in Stage::ALL {
+            self.inner.stage.send_replace(stage as u8);
+
+            let hooks = {
+                let mut hooks = self.inner.hooks.lock();
+                let (now, later): (Vec<_>, Vec<_>) =
+                    std::mem::take(&mut *hooks).into_iter().partition(|(s, _)| *s == stage);
+                *hooks = later;
+                now
+            };
+            for (_, hook) in hooks {
+                hook();
+            }
+
+            while self.inner.running[stage.index()].load(Ordering::Acquire) > 0 {
+                if start.elapsed() >= self.inner.deadline {
+                    warn!(stage = stage.as_ref(), "shutdown deadline passed, not waiting for stage");
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+            info!(stage = stage.as_ref(), elapsed = %start.elapsed(), "stage stopped");
+        }
+    }
+}
+
+/// Held by whatever stops in a [`Stage`], dropping it marks it as stopped.
+pub struct StopToken {
+    shutdown: Shutdown,
+    stage: Stage,
+    receiver: watch::Receiver<u8>,
+}
+
+impl StopToken {
+    pub fn should_stop(&self) -> bool {
+        *self.receiver.borrow() >= self.stage as u8
+    }
+
+    /// Resolves once the stage of this token is signalled.
+    pub async fn stopped(&mut self) {
+        let stage = self.stage as u8;
+        let _ = self.receiver.wait_for(|s| *s >= stage).await;
+    }
+
+    /// Time left until the shutdown deadline, `None` if shutdown hasn't started.
+    pub fn remaining(&self) -> Option<Duration> {
+        self.shutdown.remaining()
+    }
+
+    pub fn stage(&self) -> Stage {
+        self.stage
+    }
+}
+
+impl Drop for StopToken {
+    fn drop(&mut self) {
+        self.shutdown.inner.running[self.stage.index()].fetch_sub(1, Ordering::AcqRel);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc;
+
+    use super::*;
+
+    #[test]
+    fn stops_stages_in_order() {
+        let shutdown = Shutdown::new(Duration::from_secs(5));
+        let (tx, rx) = mpsc::channel();
+
+        let handles: Vec<_> = [Stage::Simulators, Stage::Gossip, Stage::Sequencer]
+            .into_iter()
+            .map(|stage| {
+                let token = shutdown.register(stage);
+                let tx = tx.clone();
+                std::thread::spawn(move || {
+                    while !token.should_stop() {
+                        std::thread::sleep(std::time::Duration::from_millis(1));
+                    }
+                    tx.send(stage).unwrap();
+                })
+            })
+            .collect();
+
+        let flush_tx = tx.clone();
+        shutdown.on_stage(Stage::Flush, move || flush_tx.send(Stage::Flush).unwrap());
+        assert!(!shutdown.is_stopping(Stage::Intake));
+
+        shutdown.shutdown();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+
+        let order: Vec<_> = rx.try_iter().collect();
+        assert_eq!(order, vec![Stage::Sequencer, Stage::Gossip, Stage::Flush, Stage::Simulators]);
+        assert!(shutdown.is_stopping(Stage::Simulators));
+    }
+
+    #[test]
+    fn deadline_bounds_stuck_stage() {
+        let shutdown = Shutdown::new(Duration::from_millis(20));
+        let stuck = shutdown.register(Stage::Sequencer);
+        let simulators = shutdown.register(Stage::Simulators);
+
+        let start = Instant::now();
+        shutdown.shutdown();
+
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(stuck.should_stop() && simulators.should_stop());
+        assert_eq!(shutdown.remaining(), Some(Duration::ZERO));
+    }
+}
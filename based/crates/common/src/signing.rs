@@ -2,10 +2,11 @@ use std::fmt;
 
 use alloy_consensus::{SignableTransaction, Signed};
 use alloy_network::TxSignerSync;
-use alloy_primitives::{hex, hex::FromHexError, Address, PrimitiveSignature, B256};
+use alloy_primitives::{hex, hex::FromHexError, keccak256, Address, Bytes, PrimitiveSignature, B256};
 use alloy_signer::SignerSync;
 use alloy_signer_local::PrivateKeySigner;
 use rand::RngCore;
+use serde::{Deserialize, Serialize};
 
 #[derive(Debug, thiserror::Error)]
 pub enum SignerError {
@@ -15,6 +16,8 @@ pub enum SignerError {
     AlloySignerError(#[from] alloy_signer::Error),
     #[error("Signer error: {0}")]
     SignerError(String),
+    #[error("signed by {got}, expected {expected}")]
+    UnexpectedSigner { got: Address, expected: Address },
 }
 
 #[derive(Clone)]
@@ -69,3 +72,99 @@ impl Default for ECDSASigner {
         Self::random()
     }
 }
+
+/// Commitment by the sequencer that a transaction was included in a sealed frag.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Preconfirmation {
+    pub tx_hash: B256,
+    pub block_number: u64,
+    /// Index of the frag in the block
+    pub seq: u64,
+    /// Index of the transaction in the block
+    pub index: u64,
+    /// Keccak256 of the EIP-2718 encoded receipt, i.e. its leaf in the receipts trie
+    pub receipt_hash: B256,
+}
+
+impl Preconfirmation {
+    /// Hash the sequencer signs, `keccak256(abi.encodePacked(txHash, uint64(blockNumber), uint64(seq),
+    /// uint64(index), receiptHash))`, so contracts can check the signature with `ecrecover`.
+    pub fn signing_hash(&self) -> B256 {
+        let mut buf = [0u8; 32 + 3 * 8 + 32];
+        buf[..32].copy_from_slice(self.tx_hash.as_slice());
+        buf[32..40].copy_from_slice(&self.block_number.to_be_bytes());
+        buf[40..48].copy_from_slice(&self.seq.to_be_bytes());
+        buf[48..56].copy_from_slice(&self.index.to_be_bytes());
+        buf[56..].copy_from_slice(self.receipt_hash.as_slice());
+        keccak256(buf)
+    }
+
+    pub fn sign(self, signer: &ECDSASigner) -> Result<SignedPreconfirmation, SignerError> {
+        let signature = signer.sign_message(self.signing_hash())?;
+        Ok(SignedPreconfirmation { preconfirmation: self, signature: Bytes::from(signature.as_bytes()) })
+    }
+}
+
+/// A [`Preconfirmation`] with the signature of the sequencer over its [`Preconfirmation::signing_hash`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct SignedPreconfirmation {
+    #[serde(flatten)]
+    pub preconfirmation: Preconfirmation,
+    pub signature: Bytes,
+}
+
+impl SignedPreconfirmation {
+    /// Recovers the address that signed the preconfirmation.
+    pub fn recover_signer(&self) -> Result<Address, SignerError> {
+        let signature = PrimitiveSignature::try_from(self.signature.as_ref())
+            .map_err(|err| SignerError::SignerError(err.to_string()))?;
+        signature
+            .recover_address_from_prehash(&self.preconfirmation.signing_hash())
+            .map_err(|err| SignerError::SignerError(err.to_string()))
+    }
+}
+
+/// Checks that `signed` was signed by the `sequencer`, e.g. the gateway scheduled for its block.
+pub fn verify_preconfirmation(signed: &SignedPreconfirmation, sequencer: Address) -> Result<(), SignerError> {
+    let got = signed.recover_signer()?;
+    if got != sequencer {
+        return Err(SignerError::UnexpectedSigner { got, expected: sequencer });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::b256;
+
+    use super::*;
+
+    #[test]
+    fn verifies_preconfirmation() {
+        let signer = ECDSASigner::random();
+        let preconf = Preconfirmation {
+            tx_hash: b256!("e75fae0065403d4091f3d6549c4219db69c96d9de761cfc75fe9792b6166c758"),
+            block_number: 10,
+            seq: 2,
+            index: 7,
+            receipt_hash: b256!("e86afda21ddc7338c7e84561681fde45e2ab55cce8cde3163e0ae5f1c378439e"),
+        };
+        let signed = preconf.sign(&signer).unwrap();
+        verify_preconfirmation(&signed, signer.address).unwrap();
+
+        let json = serde_json::to_value(&signed).unwrap();
+        assert_eq!(json["blockNumber"], 10);
+        assert_eq!(serde_json::from_value::<SignedPreconfirmation>(json).unwrap(), signed);
+
+        let other = ECDSASigner::random();
+        assert!(matches!(
+            verify_preconfirmation(&signed, other.address),
+            Err(SignerError::UnexpectedSigner { got, .. }) if got == signer.address
+        ));
+
+        let tampered = SignedPreconfirmation { preconfirmation: Preconfirmation { index: 8, ..preconf }, ..signed };
+        assert!(verify_preconfirmation(&tampered, signer.address).is_err());
+    }
+}
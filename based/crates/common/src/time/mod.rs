@@ -23,6 +23,44 @@ fn nanos_for_100() -> u64 {
     *GLOBAL_NANOS_FOR_100.get_or_init(|| global_clock().delta_as_nanos(0, 100))
 }
 
+#[cfg(feature = "virtual-clock")]
+pub use virtual_clock::set_virtual_now;
+#[cfg(feature = "virtual-clock")]
+use virtual_clock::virtual_now;
+
+/// Per thread override of [`Instant::now`], to replay recorded inputs with the timing they were recorded with. Only
+/// compiled with the `virtual-clock` feature, so the real clock doesn't check it on every call.
+#[cfg(feature = "virtual-clock")]
+mod virtual_clock {
+    use std::{
+        cell::Cell,
+        sync::atomic::{AtomicBool, Ordering},
+    };
+
+    use super::Instant;
+
+    /// Whether any thread set a virtual clock, so [`Instant::now`] only checks the thread local once one is in use.
+    static VIRTUAL_CLOCK_IN_USE: AtomicBool = AtomicBool::new(false);
+
+    thread_local! {
+        static VIRTUAL_NOW: Cell<Option<u64>> = const { Cell::new(None) };
+    }
+
+    /// Makes [`Instant::now`] return `now` on this thread until it's set again, or the real time after `None`.
+    pub fn set_virtual_now(now: Option<Instant>) {
+        VIRTUAL_CLOCK_IN_USE.store(true, Ordering::Relaxed);
+        VIRTUAL_NOW.with(|virtual_now| virtual_now.set(now.map(|now| now.0)));
+    }
+
+    #[inline]
+    pub(super) fn virtual_now() -> Option<u64> {
+        if !VIRTUAL_CLOCK_IN_USE.load(Ordering::Relaxed) {
+            return None;
+        }
+        VIRTUAL_NOW.with(Cell::get)
+    }
+}
+
 /// Returns a high-precision timestamp counter:
 /// - On x86: uses rdtscp (synchronized cycle counter)
 /// - On ARM64: uses CNTVCT_EL0 (virtual timer counter)
@@ -55,7 +93,7 @@ fn rdtscp() -> u64 {
 pub mod utils;
 
 pub use types::{duration::Duration, instant::Instant, nanos::Nanos};
-pub use utils::{vsync, vsync_with_cancel};
+pub use utils::{vsync, vsync_busy, vsync_with_cancel};
 
 use crate::communication::messages::InternalMessage;
 
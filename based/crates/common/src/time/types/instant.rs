@@ -3,6 +3,8 @@ use std::ops::{Add, AddAssign, Sub};
 use serde::{Deserialize, Serialize};
 
 use super::{Duration, Nanos};
+#[cfg(feature = "virtual-clock")]
+use crate::time::virtual_now;
 use crate::time::{global_clock, nanos_for_100, rdtscp};
 // Socket is in the top 2 bits, rdtscp counter in lower 62
 #[derive(Copy, Clone, Debug, Default, Serialize, Deserialize)]
@@ -14,6 +16,10 @@ impl Instant {
 
     #[inline]
     pub fn now() -> Self {
+        #[cfg(feature = "virtual-clock")]
+        if let Some(now) = virtual_now() {
+            return Instant(now);
+        }
         Instant(rdtscp())
     }
 
@@ -0,0 +1,210 @@
+use std::{
+    fs::File,
+    io::{BufReader, BufWriter},
+    path::PathBuf,
+    sync::Arc,
+};
+
+use alloy_consensus::BlockHeader;
+use alloy_primitives::Address;
+use alloy_provider::{
+    network::{primitives::HeaderResponse, BlockResponse},
+    Provider, ProviderBuilder,
+};
+use bop_db::{open_database, DatabaseHistory, DatabaseRead, SequencerDB};
+use clap::{Parser, Subcommand};
+use op_alloy_network::Optimism;
+use reqwest::Url;
+use reth_cli::chainspec::ChainSpecParser;
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_cli::chainspec::OpChainSpecParser;
+use revm::DatabaseRef;
+
+#[derive(Parser)]
+#[command(author, version, about = "Inspect, export and repair a sequencer datadir", long_about = None)]
+struct Args {
+    /// Path to the database directory
+    #[arg(short, long)]
+    db_path: PathBuf,
+
+    #[arg(
+        long,
+        value_name = "CHAIN_OR_PATH",
+        long_help = OpChainSpecParser::help_message(),
+        default_value = OpChainSpecParser::SUPPORTED_CHAINS[6],
+        value_parser = OpChainSpecParser::parser(),
+    )]
+    chain: Arc<OpChainSpec>,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Print the head block number and hash, the last committed block and the last block with state changes
+    Head,
+    /// Print the canonical hash of a block
+    BlockHash {
+        /// Block number
+        number: u64,
+    },
+    /// Recompute the state root from the trie tables and verify it against the stored head header, and optionally
+    /// against the head block on an RPC
+    VerifyStateRoot {
+        /// RPC URL to also check the head block against
+        #[arg(short, long)]
+        rpc_url: Option<Url>,
+    },
+    /// Dump an account's info, code and storage as json
+    DumpAccount {
+        /// Account address
+        address: Address,
+    },
+    /// Export the plain state to a json lines file
+    ExportState {
+        /// Output file
+        #[arg(short, long)]
+        output: PathBuf,
+    },
+    /// Replace the state at the head with a json lines file produced by `export-state`, rebuild the trie and drop
+    /// the history of older blocks
+    ImportState {
+        /// Input file
+        #[arg(short, long)]
+        input: PathBuf,
+    },
+    /// Remove canonical headers and committed block markers that are ahead of the committed state
+    Repair {
+        /// Only print what would be removed
+        #[arg(long)]
+        dry_run: bool,
+    },
+}
+
+impl Command {
+    fn writes(&self) -> bool {
+        match self {
+            Command::ImportState { .. } => true,
+            Command::Repair { dry_run } => !dry_run,
+            Command::Head |
+            Command::BlockHash { .. } |
+            Command::VerifyStateRoot { .. } |
+            Command::DumpAccount { .. } |
+            Command::ExportState { .. } => false,
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+
+    // Only the commands that are meant to change the datadir open it writable. None of them run the recovery of
+    // interrupted commits the sequencer does on startup, so the datadir is inspected as it was left.
+    let read_only = !args.command.writes();
+    let db = open_database(&args.db_path, args.chain, read_only)?;
+
+    match args.command {
+        Command::Head => {
+            println!("Head block number: {}", db.head_block_number()?);
+            println!("Head block hash: {}", db.head_block_hash()?);
+            match db.last_committed_block()? {
+                Some(number) => println!("Last committed block: {number}"),
+                None => println!("Last committed block: none"),
+            }
+            match db.last_state_block()? {
+                Some(number) => println!("Last block with state: {number}"),
+                None => println!("Last block with state: none (no changesets)"),
+            }
+        }
+        Command::BlockHash { number } => {
+            let hash = db.block_hash_ref(number)?;
+            println!("{hash}");
+        }
+        Command::VerifyStateRoot { rpc_url } => verify_state_root(&db, rpc_url).await?,
+        Command::DumpAccount { address } => {
+            let dump = db.dump_account(address)?.ok_or_else(|| eyre::eyre!("Account {address} not found"))?;
+            println!("{}", serde_json::to_string_pretty(&dump)?);
+        }
+        Command::ExportState { output } => {
+            let writer = BufWriter::new(File::create(&output)?);
+            let count = db.export_state(writer)?;
+            println!("Exported {count} accounts to {}", output.display());
+        }
+        Command::ImportState { input } => {
+            let reader = BufReader::new(File::open(&input)?);
+            let (count, root) = db.import_state(reader)?;
+            println!("Imported {count} accounts from {}. State root: {root}", input.display());
+        }
+        Command::Repair { dry_run } => repair(&db, dry_run)?,
+    }
+
+    Ok(())
+}
+
+async fn verify_state_root(db: &SequencerDB, rpc_url: Option<Url>) -> eyre::Result<()> {
+    let head_number = db.head_block_number()?;
+    let head_hash = db.head_block_hash()?;
+    let state_root = db.state_root()?;
+    println!("Head block: {head_number} ({head_hash})");
+    println!("Computed state root: {state_root}");
+
+    let header =
+        db.header_by_number(head_number)?.ok_or_else(|| eyre::eyre!("Header of block {head_number} not found"))?;
+    let header_hash = header.hash_slow();
+    if header_hash != head_hash {
+        return Err(eyre::eyre!("Stored header does not match head hash. Got: {header_hash}, Expected: {head_hash}"));
+    }
+    if header.state_root != state_root {
+        return Err(eyre::eyre!("State root mismatch. Got: {state_root}, Expected: {}", header.state_root));
+    }
+    println!("State root matches stored header of block {head_number}");
+
+    let Some(rpc_url) = rpc_url else {
+        return Ok(());
+    };
+
+    let provider = ProviderBuilder::new().network::<Optimism>().on_http(rpc_url);
+    let block = provider
+        .get_block_by_number(head_number.into(), false.into())
+        .await?
+        .ok_or_else(|| eyre::eyre!("Block {head_number} not found on RPC"))?;
+    let header = block.header();
+
+    if header.hash() != head_hash {
+        return Err(eyre::eyre!("Stored head hash does not match RPC. Got: {head_hash}, Expected: {}", header.hash()));
+    }
+    if header.state_root() != state_root {
+        return Err(eyre::eyre!("State root does not match RPC. Got: {state_root}, Expected: {}", header.state_root()));
+    }
+
+    println!("Head block matches RPC");
+    Ok(())
+}
+
+fn repair(db: &SequencerDB, dry_run: bool) -> eyre::Result<()> {
+    let head_number = db.head_block_number()?;
+    let last_committed = db.last_committed_block()?;
+    let Some(target) = db.last_consistent_block()? else {
+        return Err(eyre::eyre!("No committed block marker or changesets, unable to tell which block the state is at"));
+    };
+
+    if head_number <= target && last_committed.is_none_or(|committed| committed <= target) {
+        println!("Nothing to repair. Head: {head_number}, last committed block: {target}");
+        return Ok(());
+    }
+
+    if dry_run {
+        println!(
+            "Would remove canonical headers {}..={head_number} and set the last committed block to {target} (was \
+             {last_committed:?})",
+            target + 1
+        );
+        return Ok(());
+    }
+
+    let removed = db.truncate_above(target)?;
+    println!("Removed {} canonical headers. New head: {}", removed.len(), db.head_block_number()?);
+    Ok(())
+}
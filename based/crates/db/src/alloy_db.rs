@@ -167,6 +167,18 @@ impl DatabaseRead for AlloyDB {
         Ok((root, TrieUpdates::default()))
     }
 
+    /// Fetches the storage root at the next block
+    fn calculate_storage_root(&self, address: Address, _: &BundleState) -> Result<B256, Error> {
+        let next_block = self.block_number() + 1;
+
+        let proof = self
+            .rt
+            .block_on(self.provider.get_proof(address, vec![]).number(next_block).into_future())
+            .map_err(|e| Error::Other(e.to_string()))?;
+
+        Ok(proof.storage_hash)
+    }
+
     /// Returns the current block head number.
     fn head_block_number(&self) -> Result<u64, Error> {
         Ok(self.block_number())
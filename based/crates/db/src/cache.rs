@@ -1,60 +1,160 @@
-use std::sync::Arc;
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
 
 use alloy_primitives::{Address, B256, U256};
+use bop_common::{
+    db::{CacheCounters, CacheStats},
+    time::Timer,
+};
 use moka::sync::{Cache, CacheBuilder};
 use reth_db::{
     mdbx::{tx::Tx, RO},
-    PlainAccountState, PlainStorageState,
+    Bytecodes, PlainAccountState, PlainStorageState,
 };
 use reth_db_api::{cursor::DbDupCursorRO, transaction::DbTx};
 use revm::db::BundleState;
-use revm_primitives::AccountInfo;
+use revm_primitives::{AccountInfo, Bytecode, KECCAK_EMPTY};
 
 use crate::Error;
 
+/// Maximum total size in bytes of the cached contract bytecode.
+const MAX_CACHED_BYTECODE_BYTES: u64 = 256 * 1024 * 1024;
+
 /// Caches used to accelerate database reads. Cache entries are retained according to LRU policy.
 /// On database commits, corresponding entries in the caches are invalidated.
 #[derive(Clone)]
 pub(super) struct ReadCaches {
     account_info: Cache<Address, Option<AccountInfo>>,
     storage: Cache<(Address, U256), U256>,
+    /// Bytecode is immutable for a given hash, so entries are never invalidated.
+    bytecodes: Cache<B256, Bytecode>,
+    metrics: Arc<CacheMetrics>,
+    /// Time each database read on a cache miss, giving miss rates and latencies in the timekeeper.
+    account_miss_timer: Timer,
+    storage_miss_timer: Timer,
+    bytecode_miss_timer: Timer,
 }
 
 impl ReadCaches {
     pub(super) fn new(max_cached_accounts: u64, max_cached_storage: u64) -> Self {
-        let account_info = CacheBuilder::new(max_cached_accounts).build();
-        let storage = CacheBuilder::new(max_cached_storage).build();
-        Self { account_info, storage }
+        let metrics = Arc::new(CacheMetrics::default());
+
+        let account_info = CacheBuilder::new(max_cached_accounts)
+            .eviction_listener({
+                let metrics = metrics.clone();
+                move |_, _, cause| {
+                    if cause.was_evicted() {
+                        metrics.accounts.evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .build();
+        let storage = CacheBuilder::new(max_cached_storage)
+            .eviction_listener({
+                let metrics = metrics.clone();
+                move |_, _, cause| {
+                    if cause.was_evicted() {
+                        metrics.storage.evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .build();
+        let bytecodes = CacheBuilder::new(MAX_CACHED_BYTECODE_BYTES)
+            .weigher(|_, code: &Bytecode| code.len().try_into().unwrap_or(u32::MAX))
+            .eviction_listener({
+                let metrics = metrics.clone();
+                move |_, _, cause| {
+                    if cause.was_evicted() {
+                        metrics.bytecodes.evictions.fetch_add(1, Ordering::Relaxed);
+                    }
+                }
+            })
+            .build();
+
+        Self {
+            account_info,
+            storage,
+            bytecodes,
+            metrics,
+            account_miss_timer: Timer::new("DB-account_miss"),
+            storage_miss_timer: Timer::new("DB-storage_miss"),
+            bytecode_miss_timer: Timer::new("DB-bytecode_miss"),
+        }
     }
 
     pub(super) fn account_info(&self, address: &Address, tx: &Tx<RO>) -> Result<Option<AccountInfo>, Error> {
+        let mut timer = self.account_miss_timer;
         self.account_info
             .entry_by_ref(address)
-            .or_try_insert_with(|| {
-                tx.get::<PlainAccountState>(*address)
-                    .map(|opt| opt.map(|account| account.into()))
-                    .map_err(Error::ReadTransactionError)
+            .or_try_insert_with(|| timer.time(|| read_account_info(address, tx)))
+            .map(|entry| {
+                self.metrics.accounts.record(entry.is_fresh());
+                entry.into_value()
             })
-            .map(|entry| entry.into_value())
             .map_err(|e| Arc::into_inner(e).unwrap_or_else(|| Error::Other("Couldn't unwrap Arced error".to_string())))
     }
 
     pub(super) fn storage(&self, key: &(Address, U256), tx: &Tx<RO>) -> Result<U256, Error> {
+        let mut timer = self.storage_miss_timer;
         self.storage
             .entry_by_ref(key)
-            .or_try_insert_with(|| {
-                let (address, index) = key;
-                let mut cursor = tx.cursor_dup_read::<PlainStorageState>().map_err(Error::ReadTransactionError)?;
-                let storage_key = B256::from(index.to_be_bytes());
-                match cursor.seek_by_key_subkey(*address, storage_key).map_err(Error::ReadTransactionError)? {
-                    Some(entry) if entry.key == storage_key => Ok(entry.value),
-                    _ => Ok(U256::default()),
-                }
+            .or_try_insert_with(|| timer.time(|| read_storage(key, tx)))
+            .map(|entry| {
+                self.metrics.storage.record(entry.is_fresh());
+                entry.into_value()
             })
-            .map(|entry| entry.into_value())
             .map_err(|e| Arc::into_inner(e).unwrap())
     }
 
+    pub(super) fn bytecode(&self, code_hash: &B256, tx: &Tx<RO>) -> Result<Bytecode, Error> {
+        if let Some(code) = self.bytecodes.get(code_hash) {
+            self.metrics.bytecodes.record(false);
+            return Ok(code);
+        }
+        self.metrics.bytecodes.record(true);
+
+        let mut timer = self.bytecode_miss_timer;
+        // Missing code is not cached, it may still be deployed by a later block.
+        match timer.time(|| tx.get::<Bytecodes>(*code_hash)).map_err(Error::ReadTransactionError)? {
+            Some(code) => {
+                self.bytecodes.insert(*code_hash, code.0.clone());
+                Ok(code.0)
+            }
+            None => Ok(Bytecode::default()),
+        }
+    }
+
+    /// Loads an account and its bytecode into the caches without counting towards hits and misses.
+    pub(super) fn warm_account(&self, address: &Address, tx: &Tx<RO>) -> Result<(), Error> {
+        let info = match self.account_info.get(address) {
+            Some(info) => info,
+            None => {
+                let info = read_account_info(address, tx)?;
+                self.account_info.insert(*address, info.clone());
+                info
+            }
+        };
+
+        if let Some(code_hash) = info.map(|info| info.code_hash).filter(|hash| *hash != KECCAK_EMPTY) {
+            if !self.bytecodes.contains_key(&code_hash) {
+                if let Some(code) = tx.get::<Bytecodes>(code_hash)? {
+                    self.bytecodes.insert(code_hash, code.0);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Loads a storage slot into the cache without counting towards hits and misses.
+    pub(super) fn warm_storage(&self, key: &(Address, U256), tx: &Tx<RO>) -> Result<(), Error> {
+        if !self.storage.contains_key(key) {
+            self.storage.insert(*key, read_storage(key, tx)?);
+        }
+        Ok(())
+    }
+
     pub(super) fn update(&self, changes: &BundleState) {
         for (address, account) in changes.state() {
             if account.was_destroyed() {
@@ -66,7 +166,83 @@ impl ReadCaches {
                 }
             }
         }
+        for (code_hash, code) in &changes.contracts {
+            self.bytecodes.insert(*code_hash, code.clone());
+        }
+        self.account_info.run_pending_tasks();
+        self.storage.run_pending_tasks();
+        self.bytecodes.run_pending_tasks();
+    }
+
+    /// Removes the given accounts and storage slots, e.g. after the block that wrote them was rolled back.
+    pub(super) fn invalidate<'a>(
+        &self,
+        addresses: impl IntoIterator<Item = &'a Address>,
+        slots: impl IntoIterator<Item = &'a (Address, U256)>,
+    ) {
+        for address in addresses {
+            self.account_info.invalidate(address);
+        }
+        for slot in slots {
+            self.storage.invalidate(slot);
+        }
         self.account_info.run_pending_tasks();
         self.storage.run_pending_tasks();
     }
+
+    pub(super) fn stats(&self) -> CacheStats {
+        CacheStats {
+            accounts: self.metrics.accounts.snapshot(),
+            storage: self.metrics.storage.snapshot(),
+            bytecodes: self.metrics.bytecodes.snapshot(),
+        }
+    }
+}
+
+fn read_account_info(address: &Address, tx: &Tx<RO>) -> Result<Option<AccountInfo>, Error> {
+    tx.get::<PlainAccountState>(*address)
+        .map(|opt| opt.map(|account| account.into()))
+        .map_err(Error::ReadTransactionError)
+}
+
+fn read_storage((address, index): &(Address, U256), tx: &Tx<RO>) -> Result<U256, Error> {
+    let mut cursor = tx.cursor_dup_read::<PlainStorageState>().map_err(Error::ReadTransactionError)?;
+    let storage_key = B256::from(index.to_be_bytes());
+    match cursor.seek_by_key_subkey(*address, storage_key).map_err(Error::ReadTransactionError)? {
+        Some(entry) if entry.key == storage_key => Ok(entry.value),
+        _ => Ok(U256::default()),
+    }
+}
+
+#[derive(Default)]
+struct CacheMetrics {
+    accounts: Counters,
+    storage: Counters,
+    bytecodes: Counters,
+}
+
+#[derive(Default)]
+struct Counters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    evictions: AtomicU64,
+}
+
+impl Counters {
+    #[inline]
+    fn record(&self, miss: bool) {
+        if miss {
+            self.misses.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.hits.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn snapshot(&self) -> CacheCounters {
+        CacheCounters {
+            hits: self.hits.load(Ordering::Relaxed),
+            misses: self.misses.load(Ordering::Relaxed),
+            evictions: self.evictions.load(Ordering::Relaxed),
+        }
+    }
 }
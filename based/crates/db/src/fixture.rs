@@ -0,0 +1,552 @@
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path, sync::Arc};
+
+use alloy_primitives::{keccak256, Address, Bytes, B256, U256};
+use alloy_rlp::{Decodable, Encodable};
+use bop_common::time::{BlockSyncTimers, Duration, Instant};
+use parking_lot::RwLock;
+use reth_optimism_primitives::{OpBlock, OpReceipt};
+use reth_primitives::BlockWithSenders;
+use reth_provider::{BlockExecutionOutput, ProviderError};
+use reth_trie_common::{updates::TrieUpdates, Nibbles, TrieAccount, TrieNode, EMPTY_ROOT_HASH};
+use reth_trie_sparse::RevealedSparseTrie;
+use revm::{
+    db::{BundleAccount, BundleState},
+    DatabaseRef,
+};
+use revm_primitives::{db::Database, AccountInfo, Bytecode, HashMap, KECCAK_EMPTY};
+use serde::{Deserialize, Serialize};
+
+use crate::{AccountDump, DatabaseRead, DatabaseWrite, Error};
+
+/// A range of blocks with all state they access, exported once from an RPC so benchmarks can run offline.
+///
+/// `accounts` is the state before the first block. It only holds the accounts and slots the blocks touch, `proofs`
+/// reveal the paths to them in the state trie so the roots can be computed after each block.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BlockFixture {
+    /// RLP encoded blocks with their senders, in order.
+    pub blocks: Vec<FixtureBlock>,
+    pub accounts: Vec<AccountDump>,
+    /// Hashes of the blocks before the first one, for `BLOCKHASH`.
+    pub block_hashes: BTreeMap<u64, B256>,
+    #[serde(default)]
+    pub proofs: Vec<AccountProof>,
+}
+
+/// Merkle proofs of an account and some of its slots in the state before the first block, as returned by
+/// `eth_getProof`. Each proof lists the nodes from the root to the key.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AccountProof {
+    pub address: Address,
+    pub proof: Vec<Bytes>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage_proofs: BTreeMap<B256, Vec<Bytes>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FixtureBlock {
+    pub block: Bytes,
+    pub senders: Vec<Address>,
+}
+
+impl FixtureBlock {
+    pub fn new(block: &BlockWithSenders<OpBlock>) -> Self {
+        Self { block: alloy_rlp::encode(&block.block).into(), senders: block.senders.clone() }
+    }
+
+    pub fn decode(&self) -> Result<BlockWithSenders<OpBlock>, Error> {
+        let block = OpBlock::decode(&mut self.block.as_ref()).map_err(|e| Error::Other(e.to_string()))?;
+        Ok(BlockWithSenders::new_unchecked(block, self.senders.clone()))
+    }
+}
+
+impl BlockFixture {
+    /// Fixtures are stored as snappy framed json.
+    pub fn read(path: &Path) -> Result<Self, Error> {
+        let file = File::open(path).map_err(|e| Error::Other(format!("{}: {e}", path.display())))?;
+        serde_json::from_reader(BufReader::new(snap::read::FrameDecoder::new(file)))
+            .map_err(|e| Error::Other(e.to_string()))
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), Error> {
+        let file = File::create(path).map_err(|e| Error::Other(format!("{}: {e}", path.display())))?;
+        let mut writer = snap::write::FrameEncoder::new(file);
+        serde_json::to_writer(&mut writer, self).map_err(|e| Error::Other(e.to_string()))?;
+        writer.into_inner().map_err(|e| Error::Other(e.to_string()))?;
+        Ok(())
+    }
+
+    pub fn blocks(&self) -> Result<Vec<BlockWithSenders<OpBlock>>, Error> {
+        self.blocks.iter().map(FixtureBlock::decode).collect()
+    }
+}
+
+#[derive(Debug, Default)]
+struct FixtureState {
+    head: u64,
+    head_hash: B256,
+    accounts: HashMap<Address, AccountInfo>,
+    storage: HashMap<Address, HashMap<U256, U256>>,
+    codes: HashMap<B256, Bytecode>,
+    block_hashes: HashMap<u64, B256>,
+    tries: FixtureTries,
+    /// Time spent calculating state and storage roots since the last [`FixtureDB::take_root_time`].
+    root_time: Duration,
+}
+
+/// Sparse state and storage tries at the head, with the paths the fixture proofs reveal. That covers every account and
+/// slot the blocks touch, which is all a root calculation needs.
+#[derive(Debug, Clone, Default)]
+struct FixtureTries {
+    accounts: Option<RevealedSparseTrie>,
+    storages: HashMap<Address, Option<RevealedSparseTrie>>,
+}
+
+impl FixtureTries {
+    fn new(proofs: &[AccountProof]) -> Result<Self, Error> {
+        let mut tries = Self::default();
+        for proof in proofs {
+            reveal(&mut tries.accounts, keccak256(proof.address), &proof.proof)?;
+            for (slot, slot_proof) in &proof.storage_proofs {
+                reveal(tries.storages.entry(proof.address).or_default(), keccak256(slot), slot_proof)?;
+            }
+        }
+        Ok(tries)
+    }
+
+    fn state_root(&mut self) -> B256 {
+        self.accounts.as_mut().map_or(EMPTY_ROOT_HASH, RevealedSparseTrie::root)
+    }
+
+    /// Applies the changes of `bundle` to the storage trie of `address` and returns its root.
+    fn storage_root(&mut self, address: Address, bundle: &BundleState) -> Result<B256, Error> {
+        match bundle.account(&address) {
+            Some(account) => self.apply_storage(address, account),
+            None => self.stored_storage_root(address),
+        }
+    }
+
+    fn apply(&mut self, bundle: &BundleState) -> Result<(), Error> {
+        for (address, account) in &bundle.state {
+            let storage_root = self.apply_storage(*address, account)?;
+            let accounts = self.accounts.get_or_insert_with(RevealedSparseTrie::default);
+            let key = Nibbles::unpack(keccak256(address));
+            match &account.info {
+                Some(info) if !(info.is_empty() && storage_root == EMPTY_ROOT_HASH) => {
+                    let account = TrieAccount {
+                        nonce: info.nonce,
+                        balance: info.balance,
+                        storage_root,
+                        code_hash: info.code_hash,
+                    };
+                    let mut value = Vec::with_capacity(account.length());
+                    account.encode(&mut value);
+                    accounts.update_leaf(key, value).map_err(trie_error)?;
+                }
+                _ => accounts.remove_leaf(&key).map_err(trie_error)?,
+            }
+        }
+        Ok(())
+    }
+
+    fn apply_storage(&mut self, address: Address, account: &BundleAccount) -> Result<B256, Error> {
+        if !account.was_destroyed() && account.storage.is_empty() {
+            return self.stored_storage_root(address);
+        }
+
+        let storage = self.storages.entry(address).or_default();
+        if account.was_destroyed() {
+            *storage = None;
+        }
+        let storage = storage.get_or_insert_with(RevealedSparseTrie::default);
+        for (slot, value) in &account.storage {
+            let key = Nibbles::unpack(keccak256(B256::from(slot.to_be_bytes())));
+            if value.present_value.is_zero() {
+                storage.remove_leaf(&key).map_err(trie_error)?;
+            } else {
+                storage
+                    .update_leaf(key, alloy_rlp::encode_fixed_size(&value.present_value).to_vec())
+                    .map_err(trie_error)?;
+            }
+        }
+        Ok(storage.root())
+    }
+
+    /// Storage root of `address` in its account leaf, for accounts whose storage didn't change.
+    fn stored_storage_root(&self, address: Address) -> Result<B256, Error> {
+        let key = Nibbles::unpack(keccak256(address));
+        match self.accounts.as_ref().and_then(|accounts| accounts.get_leaf_value(&key)) {
+            Some(value) => Ok(TrieAccount::decode(&mut value.as_slice()).map_err(trie_error)?.storage_root),
+            None => Ok(EMPTY_ROOT_HASH),
+        }
+    }
+}
+
+/// Reveals the nodes of an `eth_getProof` proof for `key`. The first node is the root, the path of each following
+/// one is found by walking the key through the nodes before it.
+fn reveal(trie: &mut Option<RevealedSparseTrie>, key: B256, proof: &[Bytes]) -> Result<(), Error> {
+    let key = Nibbles::unpack(key);
+    let mut path = Nibbles::default();
+    for bytes in proof {
+        let node = TrieNode::decode(&mut bytes.as_ref()).map_err(trie_error)?;
+        let mut next = path.clone();
+        match &node {
+            TrieNode::Branch(_) if path.len() < key.len() => next.push_unchecked(key[path.len()]),
+            TrieNode::Extension(extension) => next.extend_from_slice_unchecked(&extension.key),
+            _ => {}
+        }
+
+        match trie {
+            Some(trie) => trie.reveal_node(path, node, None).map_err(trie_error)?,
+            None => *trie = Some(RevealedSparseTrie::from_root(node, None, false).map_err(trie_error)?),
+        }
+        path = next;
+    }
+    Ok(())
+}
+
+fn trie_error(err: impl std::fmt::Display) -> Error {
+    Error::Other(format!("fixture trie: {err}"))
+}
+
+/// In memory database over the state of a [`BlockFixture`], at the parent of its first block. Blocks the sequencer
+/// commits are applied on top, accounts and slots that aren't in the fixture read as empty.
+#[derive(Debug, Clone)]
+pub struct FixtureDB {
+    state: Arc<RwLock<FixtureState>>,
+}
+
+impl FixtureDB {
+    pub fn new(fixture: &BlockFixture) -> Result<Self, Error> {
+        let first =
+            fixture.blocks.first().ok_or_else(|| Error::Other("fixture has no blocks".to_string()))?.decode()?;
+
+        let mut state = FixtureState {
+            head: first.header.number.saturating_sub(1),
+            head_hash: first.header.parent_hash,
+            block_hashes: fixture.block_hashes.iter().map(|(number, hash)| (*number, *hash)).collect(),
+            tries: FixtureTries::new(&fixture.proofs)?,
+            ..Default::default()
+        };
+        state.block_hashes.insert(state.head, state.head_hash);
+
+        for dump in &fixture.accounts {
+            let (code_hash, code) = match &dump.code {
+                Some(code) if !code.is_empty() => (keccak256(code), Some(Bytecode::new_raw(code.clone()))),
+                _ => (KECCAK_EMPTY, None),
+            };
+            if let Some(code) = &code {
+                state.codes.insert(code_hash, code.clone());
+            }
+            state
+                .accounts
+                .insert(dump.address, AccountInfo::new(dump.balance, dump.nonce, code_hash, code.unwrap_or_default()));
+            state.storage.insert(
+                dump.address,
+                dump.storage.iter().map(|(slot, value)| (U256::from_be_bytes(slot.0), *value)).collect(),
+            );
+        }
+
+        Ok(Self { state: Arc::new(RwLock::new(state)) })
+    }
+
+    /// Returns the time spent calculating state and storage roots since the last call.
+    pub fn take_root_time(&self) -> Duration {
+        std::mem::take(&mut self.state.write().root_time)
+    }
+
+    /// Runs `f` on a copy of the tries at the head, the copy is dropped after.
+    fn with_tries<R>(&self, f: impl FnOnce(&mut FixtureTries) -> Result<R, Error>) -> Result<R, Error> {
+        let start = Instant::now();
+        let mut tries = self.state.read().tries.clone();
+        let res = f(&mut tries);
+        self.state.write().root_time += start.elapsed();
+        res
+    }
+}
+
+impl DatabaseRef for FixtureDB {
+    type Error = ProviderError;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        Ok(self.state.read().accounts.get(&address).cloned())
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        Ok(self.state.read().codes.get(&code_hash).cloned().unwrap_or_default())
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        Ok(self.state.read().storage.get(&address).and_then(|storage| storage.get(&index)).copied().unwrap_or_default())
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        self.state.read().block_hashes.get(&number).copied().ok_or_else(|| Error::BlockNotFound(number).into())
+    }
+}
+
+impl Database for FixtureDB {
+    type Error = ProviderError;
+
+    #[inline]
+    fn basic(&mut self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        <Self as DatabaseRef>::basic_ref(self, address)
+    }
+
+    #[inline]
+    fn code_by_hash(&mut self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        <Self as DatabaseRef>::code_by_hash_ref(self, code_hash)
+    }
+
+    #[inline]
+    fn storage(&mut self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        <Self as DatabaseRef>::storage_ref(self, address, index)
+    }
+
+    #[inline]
+    fn block_hash(&mut self, number: u64) -> Result<B256, Self::Error> {
+        <Self as DatabaseRef>::block_hash_ref(self, number)
+    }
+}
+
+impl DatabaseRead for FixtureDB {
+    /// Trie updates aren't tracked, the fixture tries are updated in place when a block is committed.
+    fn calculate_state_root(&self, bundle: &BundleState) -> Result<(B256, TrieUpdates), Error> {
+        self.with_tries(|tries| {
+            tries.apply(bundle)?;
+            Ok((tries.state_root(), TrieUpdates::default()))
+        })
+    }
+
+    fn calculate_storage_root(&self, address: Address, bundle: &BundleState) -> Result<B256, Error> {
+        self.with_tries(|tries| tries.storage_root(address, bundle))
+    }
+
+    fn head_block_number(&self) -> Result<u64, Error> {
+        Ok(self.state.read().head)
+    }
+
+    fn head_block_hash(&self) -> Result<B256, Error> {
+        Ok(self.state.read().head_hash)
+    }
+}
+
+impl DatabaseWrite for FixtureDB {
+    fn commit_block_unchecked(
+        &self,
+        block: &BlockWithSenders<OpBlock>,
+        block_execution_output: BlockExecutionOutput<OpReceipt>,
+        _trie_updates: TrieUpdates,
+        _timers: &mut BlockSyncTimers,
+    ) -> Result<(), Error> {
+        let mut state = self.state.write();
+        let bundle = block_execution_output.state;
+        let mut tries = state.tries.clone();
+        tries.apply(&bundle)?;
+        state.tries = tries;
+
+        for (hash, code) in bundle.contracts {
+            state.codes.insert(hash, code);
+        }
+        for (address, account) in bundle.state {
+            let storage = state.storage.entry(address).or_default();
+            if account.was_destroyed() {
+                storage.clear();
+            }
+            for (slot, value) in account.storage {
+                storage.insert(slot, value.present_value);
+            }
+            match account.info {
+                Some(info) => state.accounts.insert(address, info),
+                None => state.accounts.remove(&address),
+            };
+        }
+
+        state.head = block.header.number;
+        state.head_hash = block.block.header.hash_slow();
+        let (head, head_hash) = (state.head, state.head_hash);
+        state.block_hashes.insert(head, head_hash);
+        Ok(())
+    }
+
+    fn roll_back_head(&self) -> Result<(), Error> {
+        Err(Error::Other("can't roll back a fixture db".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_consensus::Header;
+    use reth_trie_common::{proof::ProofRetainer, HashBuilder};
+    use revm_primitives::address;
+
+    use super::*;
+    use crate::test_utils::TestDatadir;
+
+    const ACCOUNT: Address = address!("00000000000000000000000000000000000000aa");
+    const OTHER: Address = address!("00000000000000000000000000000000000000bb");
+    const CODE: Bytes = Bytes::from_static(&[0x60, 0x00]);
+
+    /// Root of a trie with `leaves` and the proofs of `targets`, in the order `eth_getProof` returns them.
+    fn trie(leaves: &[(B256, Vec<u8>)], targets: &[B256]) -> (B256, Vec<Vec<Bytes>>) {
+        let retainer = ProofRetainer::new(targets.iter().map(Nibbles::unpack).collect());
+        let mut builder = HashBuilder::default().with_proof_retainer(retainer);
+        let mut leaves = leaves.to_vec();
+        leaves.sort();
+        for (key, value) in &leaves {
+            builder.add_leaf(Nibbles::unpack(key), value);
+        }
+        let root = builder.root();
+
+        let nodes = builder.take_proof_nodes();
+        let proofs = targets
+            .iter()
+            .map(|target| {
+                nodes.matching_nodes_sorted(&Nibbles::unpack(target)).into_iter().map(|(_, node)| node).collect()
+            })
+            .collect();
+        (root, proofs)
+    }
+
+    fn slot(slot: u64, value: u64) -> (B256, Vec<u8>) {
+        (keccak256(B256::with_last_byte(slot as u8)), alloy_rlp::encode_fixed_size(&U256::from(value)).to_vec())
+    }
+
+    fn account(address: Address, nonce: u64, balance: u64, storage_root: B256, code_hash: B256) -> (B256, Vec<u8>) {
+        let account = TrieAccount { nonce, balance: U256::from(balance), storage_root, code_hash };
+        (keccak256(address), alloy_rlp::encode(account))
+    }
+
+    /// `ACCOUNT` with code and a slot, and `OTHER` with only a balance. The proofs cover both accounts, and slot 5
+    /// of `ACCOUNT` that's written by the test block.
+    fn fixture() -> BlockFixture {
+        let block = OpBlock {
+            header: Header { number: 10, parent_hash: B256::repeat_byte(9), ..Default::default() },
+            body: Default::default(),
+        };
+
+        let slots = [B256::with_last_byte(3), B256::with_last_byte(5)];
+        let (storage_root, storage_proofs) = trie(&[slot(3, 4)], &slots.map(keccak256));
+        let (_, proofs) = trie(
+            &[
+                account(ACCOUNT, 1, 2, storage_root, keccak256(&CODE)),
+                account(OTHER, 0, 5, EMPTY_ROOT_HASH, KECCAK_EMPTY),
+            ],
+            &[keccak256(ACCOUNT), keccak256(OTHER)],
+        );
+
+        BlockFixture {
+            blocks: vec![FixtureBlock::new(&BlockWithSenders::new_unchecked(block, vec![]))],
+            accounts: vec![
+                AccountDump {
+                    address: ACCOUNT,
+                    nonce: 1,
+                    balance: U256::from(2),
+                    code_hash: None,
+                    code: Some(CODE),
+                    storage: BTreeMap::from([(B256::with_last_byte(3), U256::from(4))]),
+                },
+                AccountDump {
+                    address: OTHER,
+                    nonce: 0,
+                    balance: U256::from(5),
+                    code_hash: None,
+                    code: None,
+                    storage: BTreeMap::new(),
+                },
+            ],
+            block_hashes: BTreeMap::from([(8, B256::repeat_byte(8))]),
+            proofs: vec![
+                AccountProof {
+                    address: ACCOUNT,
+                    proof: proofs[0].clone(),
+                    storage_proofs: slots.into_iter().zip(storage_proofs).collect(),
+                },
+                AccountProof { address: OTHER, proof: proofs[1].clone(), storage_proofs: BTreeMap::new() },
+            ],
+        }
+    }
+
+    /// Sets the balance of `ACCOUNT` to 7, slot 3 to 6 and slot 5 to 8, and removes `OTHER`.
+    fn test_block_state(db: &FixtureDB) -> BundleState {
+        let previous = db.basic_ref(ACCOUNT).unwrap();
+        let info = AccountInfo { balance: U256::from(7), ..previous.clone().unwrap() };
+        let storage = HashMap::from_iter([
+            (U256::from(3), (U256::from(4), U256::from(6))),
+            (U256::from(5), (U256::ZERO, U256::from(8))),
+        ]);
+        BundleState::new(
+            [(ACCOUNT, previous, Some(info), storage), (OTHER, db.basic_ref(OTHER).unwrap(), None, HashMap::default())],
+            Vec::<Vec<(Address, Option<Option<AccountInfo>>, Vec<(U256, U256)>)>>::new(),
+            [],
+        )
+    }
+
+    #[test]
+    fn reads_fixture_state() {
+        let dir = TestDatadir::new();
+        let path = dir.path().join("fixture.sz");
+        fixture().write(&path).unwrap();
+        let fixture = BlockFixture::read(&path).unwrap();
+
+        let db = FixtureDB::new(&fixture).unwrap();
+        assert_eq!(db.head_block_number().unwrap(), 9);
+        assert_eq!(db.head_block_hash().unwrap(), B256::repeat_byte(9));
+        assert_eq!(db.block_hash_ref(8).unwrap(), B256::repeat_byte(8));
+
+        let account = db.basic_ref(ACCOUNT).unwrap().unwrap();
+        assert_eq!((account.nonce, account.balance), (1, U256::from(2)));
+        assert_eq!(db.code_by_hash_ref(account.code_hash).unwrap().original_bytes(), CODE);
+        assert_eq!(db.storage_ref(ACCOUNT, U256::from(3)).unwrap(), U256::from(4));
+        assert_eq!(db.storage_ref(ACCOUNT, U256::from(5)).unwrap(), U256::ZERO);
+        assert_eq!(db.basic_ref(Address::ZERO).unwrap(), None);
+    }
+
+    #[test]
+    fn calculates_roots_from_proofs() {
+        let fixture = fixture();
+        let db = FixtureDB::new(&fixture).unwrap();
+
+        let (storage_root, _) = trie(&[slot(3, 4)], &[]);
+        let (parent_root, _) = trie(
+            &[
+                account(ACCOUNT, 1, 2, storage_root, keccak256(&CODE)),
+                account(OTHER, 0, 5, EMPTY_ROOT_HASH, KECCAK_EMPTY),
+            ],
+            &[],
+        );
+        assert_eq!(db.calculate_state_root(&BundleState::default()).unwrap().0, parent_root);
+        assert_eq!(db.calculate_storage_root(ACCOUNT, &BundleState::default()).unwrap(), storage_root);
+
+        let state = test_block_state(&db);
+        let (storage_root, _) = trie(&[slot(3, 6), slot(5, 8)], &[]);
+        let (state_root, _) = trie(&[account(ACCOUNT, 1, 7, storage_root, keccak256(&CODE))], &[]);
+        assert_eq!(db.calculate_storage_root(ACCOUNT, &state).unwrap(), storage_root);
+        assert_eq!(db.calculate_state_root(&state).unwrap().0, state_root);
+
+        // Calculating a root doesn't change the tries at the head.
+        assert_eq!(db.calculate_state_root(&BundleState::default()).unwrap().0, parent_root);
+        assert!(db.take_root_time().0 > 0);
+        assert_eq!(db.take_root_time().0, 0);
+    }
+
+    #[test]
+    fn commits_blocks() {
+        let fixture = fixture();
+        let db = FixtureDB::new(&fixture).unwrap();
+        let block = fixture.blocks[0].decode().unwrap();
+
+        let state = test_block_state(&db);
+        let (state_root, _) = db.calculate_state_root(&state).unwrap();
+        let output = BlockExecutionOutput { state, receipts: vec![], requests: Default::default(), gas_used: 0 };
+        db.commit_block_unchecked(&block, output, TrieUpdates::default(), &mut BlockSyncTimers::default()).unwrap();
+
+        assert_eq!(db.head_block_number().unwrap(), 10);
+        assert_eq!(db.head_block_hash().unwrap(), block.block.header.hash_slow());
+        assert_eq!(db.block_hash_ref(10).unwrap(), block.block.header.hash_slow());
+        assert_eq!(db.basic_ref(ACCOUNT).unwrap().unwrap().balance, U256::from(7));
+        assert_eq!(db.storage_ref(ACCOUNT, U256::from(3)).unwrap(), U256::from(6));
+        assert_eq!(db.basic_ref(OTHER).unwrap(), None);
+        assert_eq!(db.calculate_state_root(&BundleState::default()).unwrap().0, state_root);
+    }
+}
@@ -0,0 +1,164 @@
+use std::{
+    fmt::{Debug, Formatter},
+    sync::Arc,
+};
+
+use alloy_consensus::Header;
+use bop_common::db::DatabaseHistory;
+use reth_db::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    models::{storage_sharded_key::StorageShardedKey, BlockNumberAddress, ShardedKey},
+    table::Table,
+    tables,
+    transaction::DbTx,
+    BlockNumberList,
+};
+use reth_primitives::Account;
+use reth_storage_api::{DBProvider, HeaderProvider};
+use revm::DatabaseRef;
+use revm_primitives::{AccountInfo, Address, Bytecode, B256, U256};
+
+use crate::{Error, ProviderReadOnly, SequencerDB};
+
+/// View of the state at the end of a past block.
+///
+/// Values changed by later blocks are reconstructed from the account and storage changesets, everything else is
+/// read from the plain state. All reads go through the same read transaction, so the view stays consistent while
+/// new blocks are committed. The `AccountsHistory` and `StoragesHistory` indices point each lookup to the changeset
+/// of the first later block that touched the key, so the cost doesn't depend on the distance to the head.
+#[derive(Clone)]
+pub struct HistoricalStateDB {
+    provider: Arc<ProviderReadOnly>,
+    block_number: u64,
+}
+
+impl HistoricalStateDB {
+    pub fn block_number(&self) -> u64 {
+        self.block_number
+    }
+
+    fn account(&self, address: Address) -> Result<Option<Account>, Error> {
+        let tx = self.provider.tx_ref();
+
+        // The first change after `block_number` holds the value the account had at `block_number`.
+        let key = ShardedKey::new(address, self.block_number + 1);
+        if let Some(block) = self.first_change_after::<tables::AccountsHistory>(key, |k| k.key == address)? {
+            let mut changesets = tx.cursor_dup_read::<tables::AccountChangeSets>()?;
+            if let Some(entry) = changesets.seek_by_key_subkey(block, address)?.filter(|e| e.address == address) {
+                return Ok(entry.info);
+            }
+            return Err(Error::Other(format!("Missing account changeset of {address} at block {block}")));
+        }
+
+        Ok(tx.get::<tables::PlainAccountState>(address)?)
+    }
+
+    fn storage(&self, address: Address, key: B256) -> Result<U256, Error> {
+        let tx = self.provider.tx_ref();
+
+        let sharded_key = StorageShardedKey::new(address, key, self.block_number + 1);
+        let key_filter = |k: &StorageShardedKey| k.address == address && k.sharded_key.key == key;
+        if let Some(block) = self.first_change_after::<tables::StoragesHistory>(sharded_key, key_filter)? {
+            let mut changesets = tx.cursor_dup_read::<tables::StorageChangeSets>()?;
+            let block_address = BlockNumberAddress((block, address));
+            if let Some(entry) = changesets.seek_by_key_subkey(block_address, key)?.filter(|e| e.key == key) {
+                return Ok(entry.value);
+            }
+            return Err(Error::Other(format!("Missing storage changeset of {address} {key} at block {block}")));
+        }
+
+        let mut plain_storage = tx.cursor_dup_read::<tables::PlainStorageState>()?;
+        match plain_storage.seek_by_key_subkey(address, key)? {
+            Some(entry) if entry.key == key => Ok(entry.value),
+            _ => Ok(U256::ZERO),
+        }
+    }
+
+    /// Returns the first block after `block_number` that changed the key, `None` if it's unchanged since.
+    ///
+    /// Shards are keyed by the highest block they contain, so seeking to `block_number + 1` lands on the only shard
+    /// that can hold the next change.
+    fn first_change_after<T>(&self, key: T::Key, key_filter: impl Fn(&T::Key) -> bool) -> Result<Option<u64>, Error>
+    where
+        T: Table<Value = BlockNumberList>,
+    {
+        let mut cursor = self.provider.tx_ref().cursor_read::<T>()?;
+        let Some((_, chunk)) = cursor.seek(key)?.filter(|(key, _)| key_filter(key)) else {
+            return Ok(None);
+        };
+        let rank = chunk.0.rank(self.block_number);
+        Ok(chunk.0.select(rank))
+    }
+}
+
+impl Debug for HistoricalStateDB {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "HistoricalStateDB({})", self.block_number)
+    }
+}
+
+impl DatabaseRef for HistoricalStateDB {
+    type Error = Error;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        Ok(self.account(address)?.map(Into::into))
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        // Bytecode is keyed by hash and never removed, so the latest table is valid for any block.
+        let code = self.provider.tx_ref().get::<tables::Bytecodes>(code_hash)?;
+        Ok(code.unwrap_or_default().0)
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        self.storage(address, B256::from(index.to_be_bytes()))
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        if number > self.block_number {
+            return Err(Error::BlockNotFound(number));
+        }
+        let hash = self.provider.tx_ref().get::<tables::CanonicalHeaders>(number)?;
+        hash.ok_or(Error::BlockNotFound(number))
+    }
+}
+
+impl DatabaseHistory for SequencerDB {
+    type Historical = HistoricalStateDB;
+
+    fn state_at_block(&self, number: u64) -> Result<HistoricalStateDB, Error> {
+        let provider = self.provider()?;
+        let head = provider.tx_ref().cursor_read::<tables::CanonicalHeaders>()?.last()?.map_or(0, |(num, _)| num);
+        if number > head {
+            return Err(Error::BlockNotFound(number));
+        }
+
+        // Every block touches at least the fee vaults, so a missing changeset means the state of the blocks after
+        // `number` was never executed here, e.g. headers were bulk inserted or the state was imported.
+        if number < head {
+            let first = provider.tx_ref().cursor_read::<tables::AccountChangeSets>()?.first()?.map(|(num, _)| num);
+            if first.is_none_or(|first| first > number + 1) {
+                return Err(Error::Other(format!("State at block {number} is not available, missing changesets")));
+            }
+        }
+
+        Ok(HistoricalStateDB { provider, block_number: number })
+    }
+
+    fn block_number_by_hash(&self, hash: B256, max_depth: u64) -> Result<Option<u64>, Error> {
+        let provider = self.provider()?;
+        let mut cursor = provider.tx_ref().cursor_read::<tables::CanonicalHeaders>()?;
+        for entry in cursor.walk_back(None)?.take(max_depth.saturating_add(1) as usize) {
+            let (number, block_hash) = entry?;
+            if block_hash == hash {
+                return Ok(Some(number));
+            }
+        }
+        Ok(None)
+    }
+
+    fn header_by_number(&self, number: u64) -> Result<Option<Header>, Error> {
+        // the genesis header is only written to the static files
+        Ok(self.provider()?.header_by_number(number)?)
+    }
+}
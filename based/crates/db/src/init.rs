@@ -1,13 +1,26 @@
 use std::{fs, path::Path, sync::Arc};
 
+use alloy_primitives::B256;
 use eyre::bail;
-use reth_db::{init_db, mdbx::MaxReadTransactionDuration, ClientVersion};
+use reth_db::{
+    cursor::DbCursorRO,
+    init_db,
+    mdbx::{DatabaseArguments, MaxReadTransactionDuration},
+    open_db, open_db_read_only, tables,
+    transaction::{DbTx, DbTxMut},
+    ClientVersion,
+};
 use reth_db_common::init::init_genesis;
 use reth_optimism_chainspec::OpChainSpec;
-use reth_provider::{providers::StaticFileProvider, ProviderFactory};
+use reth_provider::{
+    providers::StaticFileProvider, HistoryWriter, ProviderFactory, StageCheckpointReader, StageCheckpointWriter,
+};
+use reth_stages_types::StageCheckpoint;
+use reth_storage_api::DBProvider;
 use reth_storage_errors::db::LogLevel;
+use tracing::{info, warn};
 
-use super::{Error, SequencerDB};
+use super::{remove_canonical_headers_above, Error, SequencerDB, HISTORY_INDEXED_BLOCK, LAST_COMMITTED_BLOCK};
 
 /// Initialise the database.
 /// # Params
@@ -16,6 +29,10 @@ use super::{Error, SequencerDB};
 /// * `max_cached_accounts` - maximum number of `AccountInfo` structs to cache in database read caches.
 /// * `max_cached_storages` - maximum number of individual storage slots to cache in database read caches.
 ///
+/// Any canonical headers above the [`LAST_COMMITTED_BLOCK`] marker are left over from an interrupted commit and are
+/// removed before the database is returned. Changesets committed before the history indices were written are indexed
+/// at the same point.
+///
 /// Returns the initialised [`SequencerDB`] implementation, or [`Error`] if there is a problem.
 pub fn init_database<P: AsRef<Path>>(
     db_location: P,
@@ -31,27 +48,114 @@ pub fn init_database<P: AsRef<Path>>(
     create_or_check_dir(&static_files_dir)?;
     create_or_check_dir(&revert_files_dir)?;
 
-    let default_client_version =
-        ClientVersion { version: "V1".into(), git_sha: "GITSHA1".into(), build_timestamp: "now".to_string() };
-    let db_args = reth_db::mdbx::DatabaseArguments::new(default_client_version)
-        .with_log_level(Some(LogLevel::Error))
-        .with_max_read_transaction_duration(Some(MaxReadTransactionDuration::Unbounded))
-        .with_exclusive(Some(false));
+    let db_args = database_arguments();
     let db = Arc::new(init_db(db_dir, db_args).map_err(|e| Error::DatabaseInitialisationError(e.to_string()))?);
     let factory = ProviderFactory::new(db, chain_spec.clone(), StaticFileProvider::read_write(static_files_dir)?);
 
     init_genesis(&factory)?;
 
     let db = SequencerDB::new(factory, max_cached_accounts, max_cached_storages);
+    recover_last_committed_block(&db, chain_spec.genesis_hash())?;
+    index_history(&db)?;
+
+    // check_init_genesis(&db, &chain_spec)?;
+
+    Ok(db)
+}
+
+/// Opens an existing database as is, without initialising the genesis or recovering an interrupted commit, for tools
+/// that inspect a datadir. With `read_only` the environment and the static files are opened read-only, so nothing can
+/// be written.
+pub fn open_database<P: AsRef<Path>>(
+    db_location: P,
+    chain_spec: Arc<OpChainSpec>,
+    read_only: bool,
+) -> eyre::Result<SequencerDB> {
+    let db_dir = db_location.as_ref().join("db");
+    let static_files_dir = db_location.as_ref().join("static_files");
+    if !db_dir.is_dir() || !static_files_dir.is_dir() {
+        bail!("No database found at {}", db_location.as_ref().display());
+    }
+
+    let (db, static_files) = if read_only {
+        (open_db_read_only(&db_dir, database_arguments())?, StaticFileProvider::read_only(static_files_dir, false)?)
+    } else {
+        (open_db(&db_dir, database_arguments())?, StaticFileProvider::read_write(static_files_dir)?)
+    };
+    let factory = ProviderFactory::new(Arc::new(db), chain_spec, static_files);
+
+    Ok(SequencerDB::new(factory, 0, 0))
+}
+
+fn database_arguments() -> DatabaseArguments {
+    let default_client_version =
+        ClientVersion { version: "V1".into(), git_sha: "GITSHA1".into(), build_timestamp: "now".to_string() };
+    DatabaseArguments::new(default_client_version)
+        .with_log_level(Some(LogLevel::Error))
+        .with_max_read_transaction_duration(Some(MaxReadTransactionDuration::Unbounded))
+        .with_exclusive(Some(false))
+}
+
+/// Makes sure the canonical headers end at the last fully committed block, and that the marker is present.
+fn recover_last_committed_block(db: &SequencerDB, genesis_hash: B256) -> Result<(), Error> {
+    let rw_provider = db.factory.provider_rw().map_err(Error::ProviderError)?;
+    let tx = rw_provider.tx_ref();
 
     // Write the genesis header to the database explicitly.
     // This is because the `init_genesis` function does not write the genesis header to the database.
-    let hash = chain_spec.genesis_hash();
-    db.write_canonical_header(0, hash)?;
+    if tx.get::<tables::CanonicalHeaders>(0)?.is_none() {
+        tx.put::<tables::CanonicalHeaders>(0, genesis_hash)?;
+    }
 
-    // check_init_genesis(&db, &chain_spec)?;
+    let head = tx.cursor_read::<tables::CanonicalHeaders>()?.last()?.map_or(0, |(num, _)| num);
+    let last_committed = match rw_provider.get_stage_checkpoint(LAST_COMMITTED_BLOCK)? {
+        Some(checkpoint) => checkpoint.block_number,
+        None => {
+            // Fresh datadir, or one written before the marker existed. Trust the current head.
+            info!(head, "no last committed block marker found, initialising at head");
+            head
+        }
+    };
 
-    Ok(db)
+    if tx.get::<tables::CanonicalHeaders>(last_committed)?.is_none() {
+        return Err(Error::Other(format!("missing canonical header for last committed block {last_committed}")));
+    }
+
+    if head > last_committed {
+        let removed = remove_canonical_headers_above(tx, last_committed)?;
+        warn!(head, last_committed, removed = removed.len(), "removed canonical headers from an interrupted commit");
+    }
+
+    rw_provider.save_stage_checkpoint(LAST_COMMITTED_BLOCK, StageCheckpoint::new(last_committed))?;
+    rw_provider.commit()?;
+    db.reset_provider();
+
+    Ok(())
+}
+
+/// Adds the changesets of blocks committed after [`HISTORY_INDEXED_BLOCK`] to the history indices. The genesis
+/// indices are written by `init_genesis`.
+fn index_history(db: &SequencerDB) -> Result<(), Error> {
+    let rw_provider = db.factory.provider_rw().map_err(Error::ProviderError)?;
+    let last_committed = rw_provider.get_stage_checkpoint(LAST_COMMITTED_BLOCK)?.map_or(0, |c| c.block_number);
+    let start = match rw_provider.get_stage_checkpoint(HISTORY_INDEXED_BLOCK)? {
+        Some(checkpoint) => checkpoint.block_number + 1,
+        None => {
+            let first = rw_provider.tx_ref().cursor_read::<tables::AccountChangeSets>()?.first()?;
+            first.map_or(last_committed + 1, |(num, _)| num.max(1))
+        }
+    };
+
+    if start <= last_committed {
+        info!(start, last_committed, "indexing account and storage history");
+        rw_provider.update_history_indices(start..=last_committed)?;
+    }
+
+    rw_provider.save_stage_checkpoint(HISTORY_INDEXED_BLOCK, StageCheckpoint::new(last_committed))?;
+    rw_provider.commit()?;
+    db.reset_provider();
+
+    Ok(())
 }
 
 fn create_or_check_dir<P: AsRef<Path>>(dir: &P) -> eyre::Result<()> {
@@ -1,26 +1,28 @@
 use std::{
+    collections::HashSet,
     fmt::{Debug, Formatter},
     sync::Arc,
 };
 
-use bop_common::time::BlockSyncTimers;
+use bop_common::{db::CacheStats, time::BlockSyncTimers};
 use parking_lot::RwLock;
 use reth_db::{
     cursor::{DbCursorRO, DbCursorRW, DbDupCursorRO},
     models::BlockNumberAddress,
     tables,
     transaction::{DbTx, DbTxMut},
-    Bytecodes, CanonicalHeaders, DatabaseEnv,
+    CanonicalHeaders, DatabaseEnv,
 };
 use reth_node_types::NodeTypesWithDBAdapter;
 use reth_optimism_node::OpNode;
 use reth_optimism_primitives::{OpBlock, OpReceipt};
 use reth_primitives::{BlockWithSenders, StorageEntry};
 use reth_provider::{
-    providers::ConsistentDbView, BlockExecutionOutput, DatabaseProviderRO, LatestStateProviderRef, ProviderFactory,
-    StateWriter, TrieWriter,
+    providers::ConsistentDbView, BlockExecutionOutput, DatabaseProviderRO, DatabaseProviderRW, HistoryWriter,
+    LatestStateProviderRef, ProviderFactory, StageCheckpointReader, StageCheckpointWriter, StateWriter, TrieWriter,
 };
-use reth_storage_api::{DBProvider, HashedPostStateProvider};
+use reth_stages_types::{StageCheckpoint, StageId};
+use reth_storage_api::{DBProvider, HashedPostStateProvider, StorageRootProvider};
 use reth_trie::{StateRoot, TrieInput};
 use reth_trie_common::updates::TrieUpdates;
 use reth_trie_db::{DatabaseHashedCursorFactory, DatabaseTrieCursorFactory};
@@ -29,18 +31,35 @@ use revm::{
     db::{BundleState, OriginalValuesKnown},
     Database, DatabaseRef,
 };
-use revm_primitives::{AccountInfo, Address, Bytecode, B256, U256};
+use revm_primitives::{keccak256, AccountInfo, Address, Bytecode, B256, U256};
+use tracing::warn;
 
 mod alloy_db;
 mod cache;
+mod fixture;
+mod history;
 mod init;
+mod state_dump;
+#[cfg(any(test, feature = "test-utils"))]
+pub mod test_utils;
 pub use alloy_db::AlloyDB;
-pub use bop_common::db::{DatabaseRead, DatabaseWrite, Error};
-pub use init::init_database;
+pub use bop_common::db::{DatabaseHistory, DatabaseRead, DatabaseWrite, Error};
+pub use fixture::{AccountProof, BlockFixture, FixtureBlock, FixtureDB};
+pub use history::HistoricalStateDB;
+pub use init::{init_database, open_database};
+pub use state_dump::AccountDump;
 
 use crate::cache::ReadCaches;
 
+/// Stage checkpoint recording the last block whose state, trie and canonical header were committed together.
+pub const LAST_COMMITTED_BLOCK: StageId = StageId::Other("LastCommittedBlock");
+
+/// Stage checkpoint recording the last block whose changesets are in the `AccountsHistory` and `StoragesHistory`
+/// indices.
+pub const HISTORY_INDEXED_BLOCK: StageId = StageId::Other("HistoryIndexedBlock");
+
 pub type ProviderReadOnly = DatabaseProviderRO<Arc<DatabaseEnv>, NodeTypesWithDBAdapter<OpNode, Arc<DatabaseEnv>>>;
+type ProviderReadWrite = DatabaseProviderRW<Arc<DatabaseEnv>, NodeTypesWithDBAdapter<OpNode, Arc<DatabaseEnv>>>;
 
 #[derive(Clone)]
 pub struct SequencerDB {
@@ -90,6 +109,136 @@ impl SequencerDB {
         rw_provider.commit()?;
         Ok(())
     }
+
+    /// Returns the last block that was fully committed, i.e. whose state, trie and canonical header were written in
+    /// a single transaction. `None` if the datadir predates the marker.
+    pub fn last_committed_block(&self) -> Result<Option<u64>, Error> {
+        let provider = self.provider()?;
+        Ok(provider.get_stage_checkpoint(LAST_COMMITTED_BLOCK)?.map(|checkpoint| checkpoint.block_number))
+    }
+
+    /// Returns the highest block for which state changes have been committed, based on the account changesets.
+    /// Every block touches at least the L1 block info contract, so this tracks the block the state is at.
+    pub fn last_state_block(&self) -> Result<Option<u64>, Error> {
+        let provider = self.provider()?;
+        Ok(provider.tx_ref().cursor_read::<tables::AccountChangeSets>()?.last()?.map(|(num, _)| num))
+    }
+
+    /// Pre-loads the read caches with all accounts and storage slots touched by the last `blocks` committed blocks,
+    /// so that simulations right after a restart don't all hit the database cold.
+    /// Returns the number of accounts and storage slots loaded.
+    pub fn warm_caches_from_recent_blocks(&self, blocks: u64) -> Result<(usize, usize), Error> {
+        if blocks == 0 {
+            return Ok((0, 0));
+        }
+        let provider = self.provider()?;
+        let tx = provider.tx_ref();
+
+        let head = self.head_block_number()?;
+        let range = head.saturating_sub(blocks - 1)..=head;
+
+        let mut addresses = HashSet::new();
+        for entry in tx.cursor_read::<tables::AccountChangeSets>()?.walk_range(range.clone())? {
+            addresses.insert(entry?.1.address);
+        }
+        let mut slots = HashSet::new();
+        for entry in tx.cursor_read::<tables::StorageChangeSets>()?.walk_range(BlockNumberAddress::range(range))? {
+            let (block_address, storage) = entry?;
+            slots.insert((block_address.address(), U256::from_be_bytes(storage.key.0)));
+        }
+
+        for address in &addresses {
+            self.caches.warm_account(address, tx)?;
+        }
+        for slot in &slots {
+            self.caches.warm_storage(slot, tx)?;
+        }
+
+        Ok((addresses.len(), slots.len()))
+    }
+
+    /// Returns the highest block that both the [`LAST_COMMITTED_BLOCK`] marker and the changesets agree has been
+    /// committed. Tools like `bulk-insert-headers` move the marker without writing state, and an imported state dump
+    /// has no changesets, so either one alone can be ahead of the state. `None` if neither is known.
+    pub fn last_consistent_block(&self) -> Result<Option<u64>, Error> {
+        Ok(match (self.last_committed_block()?, self.last_state_block()?) {
+            (Some(committed), Some(state)) => Some(committed.min(state)),
+            (committed, state) => committed.or(state),
+        })
+    }
+
+    /// Deletes all canonical headers above `number` and lowers the [`LAST_COMMITTED_BLOCK`] and
+    /// [`HISTORY_INDEXED_BLOCK`] markers to `number`, in a single transaction. Returns the removed block numbers.
+    pub fn truncate_above(&self, number: u64) -> Result<Vec<u64>, Error> {
+        let rw_provider = self.factory.provider_rw().map_err(Error::ProviderError)?;
+        let removed = remove_canonical_headers_above(rw_provider.tx_ref(), number)?;
+        rw_provider.save_stage_checkpoint(LAST_COMMITTED_BLOCK, StageCheckpoint::new(number))?;
+        let history_indexed = rw_provider.get_stage_checkpoint(HISTORY_INDEXED_BLOCK)?;
+        if history_indexed.is_some_and(|checkpoint| checkpoint.block_number > number) {
+            rw_provider.save_stage_checkpoint(HISTORY_INDEXED_BLOCK, StageCheckpoint::new(number))?;
+        }
+        rw_provider.commit()?;
+        self.reset_provider();
+        Ok(removed)
+    }
+
+    /// Writes the state, trie and canonical header of `block` and marks it as committed, without committing the
+    /// transaction.
+    fn write_block(
+        &self,
+        block: &BlockWithSenders<OpBlock>,
+        state: &BundleState,
+        trie_updates: &TrieUpdates,
+        timers: &mut BlockSyncTimers,
+    ) -> Result<ProviderReadWrite, Error> {
+        let rw_provider = self.factory.provider_rw().map_err(Error::ProviderError)?;
+        let block_number = block.block.header.number;
+
+        timers.state_changes.time(|| {
+            let (plain_state, reverts) = state.to_plain_state_and_reverts(OriginalValuesKnown::Yes);
+            // Write state reverts
+            rw_provider.write_state_reverts(reverts, block_number)?;
+            // Index the changesets for historical lookups
+            rw_provider.update_history_indices(block_number..=block_number)?;
+            // Write plain state
+            rw_provider.write_state_changes(plain_state)
+        })?;
+
+        // Write state trie updates
+        timers.trie_updates.time(|| {
+            let latest_state = LatestStateProviderRef::new(&rw_provider);
+            let hashed_state = latest_state.hashed_post_state(state);
+            rw_provider.write_hashed_state(&hashed_state.into_sorted()).map_err(Error::ProviderError)?;
+            rw_provider.write_trie_updates(trie_updates).map_err(Error::ProviderError)
+        })?;
+        timers.header_write.time(|| {
+            // Write to header tables and mark the block as committed
+            rw_provider.tx_ref().put::<tables::CanonicalHeaders>(block_number, block.block.header.hash_slow())?;
+            rw_provider.tx_ref().put::<tables::Headers>(block_number, block.block.header.clone())?;
+            rw_provider.save_stage_checkpoint(LAST_COMMITTED_BLOCK, StageCheckpoint::new(block_number))?;
+            rw_provider.save_stage_checkpoint(HISTORY_INDEXED_BLOCK, StageCheckpoint::new(block_number))?;
+            Ok::<_, Error>(())
+        })?;
+
+        Ok(rw_provider)
+    }
+}
+
+/// Deletes all canonical headers above `number` within the given transaction. Returns the removed block numbers.
+pub(crate) fn remove_canonical_headers_above<TX: DbTxMut + DbTx>(tx: &TX, number: u64) -> Result<Vec<u64>, Error> {
+    let mut cursor = tx.cursor_write::<tables::CanonicalHeaders>()?;
+
+    let mut removed = Vec::new();
+    while let Some((num, _)) = cursor.last()? {
+        if num <= number {
+            break;
+        }
+        cursor.delete_current()?;
+        tx.delete::<tables::Headers>(num, None)?;
+        removed.push(num);
+    }
+
+    Ok(removed)
 }
 
 impl Debug for SequencerDB {
@@ -108,6 +257,14 @@ impl DatabaseRead for SequencerDB {
         parallel_state_root.incremental_root_with_updates().map_err(Error::ParallelStateRootError)
     }
 
+    fn calculate_storage_root(&self, address: Address, bundle_state: &BundleState) -> Result<B256, Error> {
+        let provider = self.provider()?;
+        let latest_state = LatestStateProviderRef::new(provider.as_ref());
+        let hashed_storage =
+            latest_state.hashed_post_state(bundle_state).storages.remove(&keccak256(address)).unwrap_or_default();
+        Ok(latest_state.storage_root(address, hashed_storage)?)
+    }
+
     fn head_block_number(&self) -> Result<u64, Error> {
         let provider = self.provider()?;
         provider.tx_ref().cursor_read::<CanonicalHeaders>()?.last()?.map_or(Ok(0), |(num, _)| Ok(num))
@@ -117,11 +274,29 @@ impl DatabaseRead for SequencerDB {
         let provider = self.provider()?;
         provider.tx_ref().cursor_read::<CanonicalHeaders>()?.last()?.map_or(Ok(B256::ZERO), |(_, hash)| Ok(hash))
     }
+
+    fn cache_stats(&self) -> Option<CacheStats> {
+        Some(self.caches.stats())
+    }
+
+    fn warm_accounts(&self, addresses: &[Address]) {
+        let Ok(provider) = self.provider() else {
+            return;
+        };
+        for address in addresses {
+            if let Err(e) = self.caches.warm_account(address, provider.tx_ref()) {
+                warn!(%address, %e, "failed to warm account");
+            }
+        }
+    }
 }
 
 impl DatabaseWrite for SequencerDB {
     /// Commit a new block to the database without performing state root check. This should only be
     /// used if the state root calculation has already been performed upstream.
+    ///
+    /// State, trie, canonical header and the [`LAST_COMMITTED_BLOCK`] marker are all written in a single
+    /// transaction, so a crash leaves the database either fully at the previous block or fully at this one.
     fn commit_block_unchecked(
         &self,
         block: &BlockWithSenders<OpBlock>,
@@ -129,41 +304,15 @@ impl DatabaseWrite for SequencerDB {
         trie_updates: TrieUpdates,
         timers: &mut BlockSyncTimers,
     ) -> Result<(), Error> {
-        let provider = self.factory.provider().map_err(Error::ProviderError)?;
-        let rw_provider = self.factory.provider_rw().map_err(Error::ProviderError)?;
-
-        timers.caches.time(|| self.caches.update(&block_execution_output.state));
-
-        // Update the read caches.
-
-        timers.state_changes.time(|| {
-            let (plain_state, reverts) =
-                block_execution_output.state.to_plain_state_and_reverts(OriginalValuesKnown::Yes);
-            // Write state reverts
-            rw_provider.write_state_reverts(reverts, block.block.header.number)?;
-            // Write plain state
-            rw_provider.write_state_changes(plain_state)
-        })?;
-
-        // Write state trie updates
-        timers.trie_updates.time(|| {
-            let latest_state = LatestStateProviderRef::new(&provider);
-            let hashed_state = latest_state.hashed_post_state(&block_execution_output.state);
-            rw_provider.write_hashed_state(&hashed_state.into_sorted()).map_err(Error::ProviderError)?;
-            rw_provider.write_trie_updates(&trie_updates).map_err(Error::ProviderError)
-        })?;
-        timers.header_write.time(|| {
-            // Write to header table
-            rw_provider
-                .tx_ref()
-                .put::<tables::CanonicalHeaders>(block.block.header.number, block.block.header.hash_slow())
-                .unwrap();
-        });
+        let rw_provider = self.write_block(block, &block_execution_output.state, &trie_updates, timers)?;
 
         timers.db_commit.time(|| rw_provider.commit())?;
 
         self.reset_provider();
 
+        // Only update the read caches once the block is durable.
+        timers.caches.time(|| self.caches.update(&block_execution_output.state));
+
         Ok(())
     }
 
@@ -178,6 +327,10 @@ impl DatabaseWrite for SequencerDB {
         // Trie
         rw_provider.unwind_trie_state_range(range.clone())?;
 
+        // History indices, read from the changesets so they have to go first
+        rw_provider.unwind_account_history_indices_range(range.clone())?;
+        rw_provider.unwind_storage_history_indices_range(storage_range.clone())?;
+
         // Flat state
         let storage_changeset = rw_provider.take::<tables::StorageChangeSets>(storage_range.clone())?;
         let account_changeset = rw_provider.take::<tables::AccountChangeSets>(range)?;
@@ -192,6 +345,13 @@ impl DatabaseWrite for SequencerDB {
             &mut plain_storage_cursor,
         )?;
 
+        // Entries touched by the reverted block are stale in the read caches.
+        let reverted_addresses = state.keys().copied().collect::<Vec<_>>();
+        let reverted_slots = state
+            .iter()
+            .flat_map(|(address, (_, _, storage))| storage.keys().map(|key| (*address, U256::from_be_bytes(key.0))))
+            .collect::<Vec<_>>();
+
         // iterate over local plain state remove all account and all storages.
         for (address, (old_account, new_account, storage)) in &state {
             // revert account if needed.
@@ -224,12 +384,29 @@ impl DatabaseWrite for SequencerDB {
         }
 
         rw_provider.tx_ref().delete::<tables::CanonicalHeaders>(head_block_number, None).unwrap();
+        rw_provider.tx_ref().delete::<tables::Headers>(head_block_number, None)?;
+        let checkpoint = StageCheckpoint::new(head_block_number.saturating_sub(1));
+        rw_provider.save_stage_checkpoint(LAST_COMMITTED_BLOCK, checkpoint)?;
+        rw_provider.save_stage_checkpoint(HISTORY_INDEXED_BLOCK, checkpoint)?;
 
         rw_provider.commit()?;
         self.reset_provider();
 
+        self.caches.invalidate(&reverted_addresses, &reverted_slots);
+        let provider = self.provider()?;
+        for address in &reverted_addresses {
+            self.caches.warm_account(address, provider.tx_ref())?;
+        }
+        for slot in &reverted_slots {
+            self.caches.warm_storage(slot, provider.tx_ref())?;
+        }
+
         Ok(())
     }
+
+    fn flush(&self) -> Result<(), Error> {
+        self.factory.db_ref().sync(true).map(|_| ()).map_err(|e| Error::Other(e.to_string()))
+    }
 }
 
 impl DatabaseRef for SequencerDB {
@@ -240,8 +417,7 @@ impl DatabaseRef for SequencerDB {
     }
 
     fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
-        let code = self.provider()?.tx_ref().get::<Bytecodes>(code_hash).map_err(Error::ReadTransactionError)?;
-        Ok(code.unwrap_or_default().0)
+        self.caches.bytecode(&code_hash, self.provider()?.tx_ref())
     }
 
     fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
@@ -278,3 +454,212 @@ impl Database for SequencerDB {
         self.block_hash_ref(number)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::process::Command;
+
+    use alloy_consensus::Header;
+    use reth_optimism_chainspec::BASE_SEPOLIA;
+    use revm_primitives::HashMap;
+
+    use super::*;
+    use crate::{init_database, open_database, test_utils::TestDatadir};
+
+    /// Datadir the child process commits blocks into. Only set in the child.
+    const CRASH_DATADIR_ENV: &str = "BOP_DB_CRASH_DATADIR";
+
+    const TEST_ACCOUNT: Address = Address::with_last_byte(0xbb);
+
+    /// Empty block at `number` that sets the balance of [`TEST_ACCOUNT`] to `number`, with its trie updates.
+    fn test_block(
+        db: &SequencerDB,
+        number: u64,
+    ) -> (BlockWithSenders<OpBlock>, BlockExecutionOutput<OpReceipt>, TrieUpdates) {
+        let previous = db.basic_ref(TEST_ACCOUNT).unwrap();
+        let info = AccountInfo { balance: U256::from(number), ..Default::default() };
+        let state = BundleState::new(
+            [(TEST_ACCOUNT, previous.clone(), Some(info), HashMap::default())],
+            [[(TEST_ACCOUNT, Some(previous), Vec::<(U256, U256)>::new())]],
+            [],
+        );
+        let (_, trie_updates) = db.calculate_state_root(&state).unwrap();
+
+        let block = OpBlock { header: Header { number, ..Default::default() }, body: Default::default() };
+        let block = BlockWithSenders::new_unchecked(block, vec![]);
+        let output = BlockExecutionOutput { state, receipts: vec![], requests: Default::default(), gas_used: 0 };
+        (block, output, trie_updates)
+    }
+
+    /// Commits the [`test_block`] at `number`.
+    fn commit_test_block(db: &SequencerDB, number: u64) {
+        let (block, output, trie_updates) = test_block(db, number);
+        db.commit_block_unchecked(&block, output, trie_updates, &mut BlockSyncTimers::default()).unwrap();
+    }
+
+    #[test]
+    fn recovers_from_crash_mid_commit() {
+        // Child process: commit two blocks, then abort with the third written but not committed.
+        if let Ok(datadir) = std::env::var(CRASH_DATADIR_ENV) {
+            let db = init_database(&datadir, 0, 0, BASE_SEPOLIA.clone()).unwrap();
+            for number in 1..=2 {
+                commit_test_block(&db, number);
+            }
+            let (block, output, trie_updates) = test_block(&db, 3);
+            let _rw_provider =
+                db.write_block(&block, &output.state, &trie_updates, &mut BlockSyncTimers::default()).unwrap();
+            std::process::abort();
+        }
+
+        let datadir = TestDatadir::new();
+
+        let status = Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", "tests::recovers_from_crash_mid_commit", "--nocapture"])
+            .env(CRASH_DATADIR_ENV, datadir.path())
+            .status()
+            .unwrap();
+        assert!(!status.success(), "child process should have been aborted mid-commit");
+
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        assert_eq!(db.head_block_number().unwrap(), 2);
+        assert_eq!(db.last_committed_block().unwrap(), Some(2));
+        assert_eq!(db.last_state_block().unwrap(), Some(2));
+        assert_eq!(db.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(2));
+
+        // A header written outside of a block commit is dropped on the next start.
+        db.write_canonical_header(3, B256::with_last_byte(3)).unwrap();
+        drop(db);
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        assert_eq!(db.head_block_number().unwrap(), 2);
+
+        // The recovered database keeps committing from where it left off.
+        commit_test_block(&db, 3);
+        assert_eq!(db.head_block_number().unwrap(), 3);
+        assert_eq!(db.last_committed_block().unwrap(), Some(3));
+    }
+
+    #[test]
+    fn repairs_marker_ahead_of_state() {
+        let datadir = TestDatadir::new();
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        for number in 1..=3 {
+            commit_test_block(&db, number);
+        }
+
+        // Headers and marker moved ahead without state, with the header at the marker missing.
+        let rw_provider = db.factory.provider_rw().unwrap();
+        let header = Header { number: 4, ..Default::default() };
+        rw_provider.tx_ref().put::<tables::CanonicalHeaders>(4, header.hash_slow()).unwrap();
+        rw_provider.tx_ref().put::<tables::Headers>(4, header).unwrap();
+        rw_provider.save_stage_checkpoint(LAST_COMMITTED_BLOCK, StageCheckpoint::new(5)).unwrap();
+        rw_provider.save_stage_checkpoint(HISTORY_INDEXED_BLOCK, StageCheckpoint::new(5)).unwrap();
+        rw_provider.commit().unwrap();
+        drop(db);
+        assert!(init_database(datadir.path(), 100, 100, BASE_SEPOLIA.clone()).is_err());
+
+        let db = open_database(datadir.path(), BASE_SEPOLIA.clone(), false).unwrap();
+        assert_eq!(db.last_consistent_block().unwrap(), Some(3));
+        assert_eq!(db.truncate_above(3).unwrap(), vec![4]);
+        drop(db);
+
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        assert_eq!(db.head_block_number().unwrap(), 3);
+        assert_eq!(db.last_committed_block().unwrap(), Some(3));
+        assert_eq!(db.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(3));
+
+        commit_test_block(&db, 4);
+        assert_eq!(db.state_at_block(4).unwrap().basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(4));
+    }
+
+    #[test]
+    fn imports_state_at_head() {
+        let datadir = TestDatadir::new();
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        for number in 1..=3 {
+            commit_test_block(&db, number);
+        }
+        let mut dump = Vec::new();
+        db.export_state(&mut dump).unwrap();
+        let state_root = db.state_root().unwrap();
+
+        assert_eq!(db.import_state(dump.as_slice()).unwrap().1, state_root);
+        assert_eq!(db.last_state_block().unwrap(), None);
+        assert_eq!(db.last_committed_block().unwrap(), Some(3));
+        assert!(db.state_at_block(2).is_err());
+        drop(db);
+
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        assert_eq!(db.head_block_number().unwrap(), 3);
+        commit_test_block(&db, 4);
+        assert_eq!(db.state_at_block(3).unwrap().basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(3));
+        assert_eq!(db.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(4));
+    }
+
+    #[test]
+    fn reads_historical_state() {
+        let datadir = TestDatadir::new();
+
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        for number in 1..=3 {
+            commit_test_block(&db, number);
+        }
+
+        assert!(db.state_at_block(0).unwrap().basic_ref(TEST_ACCOUNT).unwrap().is_none());
+        for number in 1..=3 {
+            let state = db.state_at_block(number).unwrap();
+            assert_eq!(state.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(number));
+        }
+        assert!(matches!(db.state_at_block(4), Err(Error::BlockNotFound(4))));
+
+        let hash = db.block_hash_ref(2).unwrap();
+        assert_eq!(db.block_number_by_hash(hash, 10).unwrap(), Some(2));
+        assert_eq!(db.block_number_by_hash(hash, 0).unwrap(), None);
+        assert_eq!(db.header_by_number(2).unwrap().unwrap().hash_slow(), hash);
+    }
+
+    #[test]
+    fn history_indices_follow_rollbacks() {
+        let datadir = TestDatadir::new();
+
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        for number in 1..=3 {
+            commit_test_block(&db, number);
+        }
+        db.roll_back_head().unwrap();
+        db.roll_back_head().unwrap();
+        commit_test_block(&db, 2);
+        commit_test_block(&db, 3);
+
+        for number in 1..=3 {
+            let state = db.state_at_block(number).unwrap();
+            assert_eq!(state.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(number));
+        }
+
+        // Reopening doesn't index the committed blocks a second time.
+        drop(db);
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        let state = db.state_at_block(1).unwrap();
+        assert_eq!(state.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(1));
+    }
+
+    #[test]
+    fn warms_caches_from_recent_blocks() {
+        let datadir = TestDatadir::new();
+
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        commit_test_block(&db, 1);
+        commit_test_block(&db, 2);
+
+        // Fresh caches over the same database, as after a restart.
+        let db = SequencerDB::new(db.factory.clone(), 100, 100);
+        assert_eq!(db.warm_caches_from_recent_blocks(2).unwrap(), (1, 0));
+
+        assert_eq!(db.basic_ref(TEST_ACCOUNT).unwrap().unwrap().balance, U256::from(2));
+        let stats = db.cache_stats().unwrap();
+        assert_eq!(stats.accounts.hits, 1);
+        assert_eq!(stats.accounts.misses, 0);
+
+        db.basic_ref(Address::with_last_byte(0xcc)).unwrap();
+        assert_eq!(db.cache_stats().unwrap().accounts.misses, 1);
+    }
+}
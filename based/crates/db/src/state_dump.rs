@@ -0,0 +1,196 @@
+use std::{
+    collections::BTreeMap,
+    io::{BufRead, Write},
+};
+
+use alloy_primitives::{keccak256, Bytes};
+use reth_db::{
+    cursor::{DbCursorRO, DbDupCursorRO},
+    tables,
+    transaction::{DbTx, DbTxMut},
+};
+use reth_primitives::{Account, Bytecode, StorageEntry};
+use reth_provider::{StageCheckpointWriter, TrieWriter};
+use reth_stages_types::StageCheckpoint;
+use reth_storage_api::DBProvider;
+use reth_trie::StateRoot;
+use reth_trie_db::{DatabaseHashedCursorFactory, DatabaseTrieCursorFactory};
+use revm_primitives::{Address, B256, U256};
+use serde::{Deserialize, Serialize};
+
+use crate::{Error, SequencerDB, HISTORY_INDEXED_BLOCK, LAST_COMMITTED_BLOCK};
+
+/// Portable representation of a single account in the plain state, written as one json line per account.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct AccountDump {
+    pub address: Address,
+    pub nonce: u64,
+    pub balance: U256,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code_hash: Option<B256>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub code: Option<Bytes>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub storage: BTreeMap<B256, U256>,
+}
+
+impl SequencerDB {
+    /// Returns the plain state of a single account, including its code and all non-zero storage slots.
+    pub fn dump_account(&self, address: Address) -> Result<Option<AccountDump>, Error> {
+        let provider = self.provider()?;
+        let tx = provider.tx_ref();
+
+        let Some(account) = tx.get::<tables::PlainAccountState>(address)? else {
+            return Ok(None);
+        };
+        let mut storage_cursor = tx.cursor_dup_read::<tables::PlainStorageState>()?;
+        account_dump(tx, address, account, &mut storage_cursor).map(Some)
+    }
+
+    /// Writes the full plain state as json lines, one [`AccountDump`] per line. Returns the number of accounts
+    /// written.
+    pub fn export_state<W: Write>(&self, mut writer: W) -> Result<usize, Error> {
+        let provider = self.provider()?;
+        let tx = provider.tx_ref();
+
+        let mut accounts_cursor = tx.cursor_read::<tables::PlainAccountState>()?;
+        let mut storage_cursor = tx.cursor_dup_read::<tables::PlainStorageState>()?;
+
+        let mut count = 0;
+        for entry in accounts_cursor.walk(None)? {
+            let (address, account) = entry?;
+            let dump = account_dump(tx, address, account, &mut storage_cursor)?;
+            serde_json::to_writer(&mut writer, &dump).map_err(|e| Error::Other(e.to_string()))?;
+            writer.write_all(b"\n").map_err(|e| Error::Other(e.to_string()))?;
+            count += 1;
+        }
+        writer.flush().map_err(|e| Error::Other(e.to_string()))?;
+
+        Ok(count)
+    }
+
+    /// Replaces the plain and hashed state with the json lines produced by [`Self::export_state`] and rebuilds
+    /// the state trie from scratch. The imported state is taken to be at the canonical head: the changesets and
+    /// history indices, which don't match it, are cleared and the committed block markers are set to the head.
+    /// Returns the number of accounts imported and the resulting state root.
+    pub fn import_state<R: BufRead>(&self, reader: R) -> Result<(usize, B256), Error> {
+        let rw_provider = self.factory.provider_rw().map_err(Error::ProviderError)?;
+        let tx = rw_provider.tx_ref();
+
+        tx.clear::<tables::PlainAccountState>()?;
+        tx.clear::<tables::PlainStorageState>()?;
+        tx.clear::<tables::HashedAccounts>()?;
+        tx.clear::<tables::HashedStorages>()?;
+        tx.clear::<tables::AccountsTrie>()?;
+        tx.clear::<tables::StoragesTrie>()?;
+        tx.clear::<tables::AccountChangeSets>()?;
+        tx.clear::<tables::StorageChangeSets>()?;
+        tx.clear::<tables::AccountsHistory>()?;
+        tx.clear::<tables::StoragesHistory>()?;
+
+        let mut count = 0;
+        for line in reader.lines() {
+            let line = line.map_err(|e| Error::Other(e.to_string()))?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let dump: AccountDump = serde_json::from_str(&line).map_err(|e| Error::Other(e.to_string()))?;
+
+            let bytecode_hash = match dump.code {
+                Some(code) if !code.is_empty() => {
+                    let hash = keccak256(&code);
+                    if dump.code_hash.is_some_and(|expected| expected != hash) {
+                        return Err(Error::Other(format!("code hash mismatch for account {}", dump.address)));
+                    }
+                    tx.put::<tables::Bytecodes>(hash, Bytecode::new_raw(code))?;
+                    Some(hash)
+                }
+                _ => dump.code_hash,
+            };
+
+            let account = Account { nonce: dump.nonce, balance: dump.balance, bytecode_hash };
+            let hashed_address = keccak256(dump.address);
+            tx.put::<tables::PlainAccountState>(dump.address, account)?;
+            tx.put::<tables::HashedAccounts>(hashed_address, account)?;
+
+            for (key, value) in dump.storage.into_iter().filter(|(_, value)| !value.is_zero()) {
+                tx.put::<tables::PlainStorageState>(dump.address, StorageEntry { key, value })?;
+                tx.put::<tables::HashedStorages>(hashed_address, StorageEntry { key: keccak256(key), value })?;
+            }
+            count += 1;
+        }
+
+        let (root, trie_updates) =
+            StateRoot::new(DatabaseTrieCursorFactory::new(tx), DatabaseHashedCursorFactory::new(tx))
+                .root_with_updates()
+                .map_err(Error::RethStateRootError)?;
+        rw_provider.write_trie_updates(&trie_updates).map_err(Error::ProviderError)?;
+
+        let head = tx.cursor_read::<tables::CanonicalHeaders>()?.last()?.map_or(0, |(num, _)| num);
+        rw_provider.save_stage_checkpoint(LAST_COMMITTED_BLOCK, StageCheckpoint::new(head))?;
+        rw_provider.save_stage_checkpoint(HISTORY_INDEXED_BLOCK, StageCheckpoint::new(head))?;
+
+        rw_provider.commit()?;
+        self.reset_provider();
+
+        Ok((count, root))
+    }
+}
+
+fn account_dump<TX: DbTx, C: DbCursorRO<tables::PlainStorageState> + DbDupCursorRO<tables::PlainStorageState>>(
+    tx: &TX,
+    address: Address,
+    account: Account,
+    storage_cursor: &mut C,
+) -> Result<AccountDump, Error> {
+    let code = match account.bytecode_hash {
+        Some(hash) => tx.get::<tables::Bytecodes>(hash)?.map(|code| code.0.original_bytes()),
+        None => None,
+    };
+
+    let mut storage = BTreeMap::new();
+    for entry in storage_cursor.walk_dup(Some(address), None)? {
+        let (_, StorageEntry { key, value }) = entry?;
+        storage.insert(key, value);
+    }
+
+    Ok(AccountDump {
+        address,
+        nonce: account.nonce,
+        balance: account.balance,
+        code_hash: account.bytecode_hash,
+        code,
+        storage,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::{address, b256, bytes};
+
+    use super::*;
+
+    #[test]
+    fn account_dump_roundtrip() {
+        let dump = AccountDump {
+            address: address!("4200000000000000000000000000000000000015"),
+            nonce: 1,
+            balance: U256::from(10),
+            code_hash: Some(keccak256(bytes!("6080"))),
+            code: Some(bytes!("6080")),
+            storage: BTreeMap::from([(
+                b256!("0000000000000000000000000000000000000000000000000000000000000001"),
+                U256::from(42),
+            )]),
+        };
+
+        let json = serde_json::to_string(&dump).unwrap();
+        assert_eq!(serde_json::from_str::<AccountDump>(&json).unwrap(), dump);
+
+        // Empty optional fields are omitted and default on the way back in.
+        let eoa = AccountDump { code_hash: None, code: None, storage: BTreeMap::new(), ..dump };
+        let json = serde_json::to_string(&eoa).unwrap();
+        assert!(!json.contains("storage"));
+        assert_eq!(serde_json::from_str::<AccountDump>(&json).unwrap(), eoa);
+    }
+}
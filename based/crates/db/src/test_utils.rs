@@ -0,0 +1,32 @@
+//! Helpers for tests that need a database on disk.
+
+use std::{path::Path, sync::Arc};
+
+use reth_optimism_chainspec::OpChainSpec;
+use tempfile::TempDir;
+
+use crate::{init_database, SequencerDB};
+
+/// Temporary datadir, removed with everything in it when dropped, also when the test panics.
+pub struct TestDatadir(TempDir);
+
+impl TestDatadir {
+    pub fn new() -> Self {
+        Self(tempfile::Builder::new().prefix("bop-").tempdir().expect("couldn't create temporary datadir"))
+    }
+
+    pub fn path(&self) -> &Path {
+        self.0.path()
+    }
+
+    /// Initialises the database in this datadir, or opens it again, see [`init_database`].
+    pub fn init(&self, chain_spec: Arc<OpChainSpec>) -> SequencerDB {
+        init_database(self.path(), 100, 100, chain_spec).expect("couldn't initialise database")
+    }
+}
+
+impl Default for TestDatadir {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -175,6 +175,22 @@ impl TxPool {
         }
     }
 
+    /// Returns at most `max` senders and recipients of pending txs, used to pre-warm database caches.
+    pub fn touched_addresses(&self, max: usize) -> Vec<Address> {
+        let mut addresses = Vec::with_capacity((self.pool_data.len() * 2).min(max));
+        for (sender, tx_list) in &self.pool_data {
+            addresses.push(*sender);
+            addresses.extend(tx_list.iter().filter_map(|tx| tx.to()));
+            if addresses.len() >= max {
+                addresses.truncate(max);
+                break;
+            }
+        }
+        addresses.sort_unstable();
+        addresses.dedup();
+        addresses
+    }
+
     #[inline]
     pub fn clone_active(&self) -> Vec<SimulatedTxList> {
         self.active_txs.clone_txs()
@@ -190,6 +206,12 @@ impl TxPool {
         self.active_txs.num_txs()
     }
 
+    /// Number of senders with pending transactions.
+    #[inline]
+    pub fn num_senders(&self) -> usize {
+        self.pool_data.len()
+    }
+
     #[inline]
     pub fn active_empty(&self) -> bool {
         self.active_txs.is_empty()
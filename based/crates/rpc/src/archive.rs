@@ -0,0 +1,121 @@
+//! Signed messages of the most recent blocks, as they were gossiped. Followers that missed part of the gossip, e.g. a
+//! frag on a flaky link, backfill it from here instead of dropping the whole block.
+
+use std::{collections::BTreeMap, sync::Arc};
+
+use bop_common::{
+    api::FragArchiveApiServer,
+    communication::messages::RpcResult,
+    p2p::{SignedMessage, VersionedMessage},
+};
+use jsonrpsee::core::async_trait;
+use parking_lot::RwLock;
+
+#[derive(Debug, Default)]
+struct BlockMessages {
+    env: Option<SignedMessage>,
+    frags: BTreeMap<u64, SignedMessage>,
+    seal: Option<SignedMessage>,
+}
+
+#[derive(Debug, Clone)]
+pub struct FragArchive {
+    blocks: Arc<RwLock<BTreeMap<u64, BlockMessages>>>,
+    max_blocks: usize,
+}
+
+impl FragArchive {
+    pub fn new(max_blocks: usize) -> Self {
+        Self { blocks: Default::default(), max_blocks }
+    }
+
+    /// Stores a message, dropping the oldest block once more than `max_blocks` are kept. A new env for a block
+    /// replaces all earlier messages of that block, e.g. when it's sequenced again after being aborted.
+    pub fn insert(&self, signed: SignedMessage) {
+        let mut blocks = self.blocks.write();
+        let block = blocks.entry(signed.message.block_number()).or_default();
+        match &signed.message {
+            VersionedMessage::EnvV0(_) => *block = BlockMessages { env: Some(signed), ..Default::default() },
+            VersionedMessage::FragV0(frag) => {
+                block.frags.insert(frag.seq, signed);
+            }
+            VersionedMessage::FragV1(frag) => {
+                block.frags.insert(frag.seq, signed);
+            }
+            VersionedMessage::SealV0(_) => block.seal = Some(signed),
+            _ => {}
+        }
+
+        while blocks.len() > self.max_blocks {
+            blocks.pop_first();
+        }
+    }
+
+    pub fn env(&self, block_number: u64) -> Option<SignedMessage> {
+        self.blocks.read().get(&block_number).and_then(|block| block.env.clone())
+    }
+
+    pub fn frag(&self, block_number: u64, seq: u64) -> Option<SignedMessage> {
+        self.blocks.read().get(&block_number).and_then(|block| block.frags.get(&seq).cloned())
+    }
+
+    /// All frags of a block, ordered by seq.
+    pub fn frags(&self, block_number: u64) -> Vec<SignedMessage> {
+        self.blocks.read().get(&block_number).map(|block| block.frags.values().cloned().collect()).unwrap_or_default()
+    }
+
+    pub fn seal(&self, block_number: u64) -> Option<SignedMessage> {
+        self.blocks.read().get(&block_number).and_then(|block| block.seal.clone())
+    }
+}
+
+#[async_trait]
+impl FragArchiveApiServer for FragArchive {
+    async fn get_env(&self, block_number: u64) -> RpcResult<Option<SignedMessage>> {
+        Ok(self.env(block_number))
+    }
+
+    async fn get_frag(&self, block_number: u64, seq: u64) -> RpcResult<Option<SignedMessage>> {
+        Ok(self.frag(block_number, seq))
+    }
+
+    async fn get_frags(&self, block_number: u64) -> RpcResult<Vec<SignedMessage>> {
+        Ok(self.frags(block_number))
+    }
+
+    async fn get_seal(&self, block_number: u64) -> RpcResult<Option<SignedMessage>> {
+        Ok(self.seal(block_number))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bop_common::{
+        p2p::{FragV0, Transactions},
+        signing::ECDSASigner,
+    };
+
+    use super::*;
+
+    fn frag(block_number: u64, seq: u64, signer: &ECDSASigner) -> SignedMessage {
+        let frag = FragV0 { block_number, seq, is_last: false, txs: Transactions::from(vec![]) };
+        VersionedMessage::from(frag).sign(signer)
+    }
+
+    #[test]
+    fn keeps_recent_blocks() {
+        let signer = ECDSASigner::random();
+        let archive = FragArchive::new(2);
+
+        archive.insert(frag(1, 0, &signer));
+        archive.insert(frag(2, 1, &signer));
+        archive.insert(frag(2, 0, &signer));
+        assert_eq!(archive.frag(1, 0), Some(frag(1, 0, &signer)));
+        assert_eq!(archive.frags(2), vec![frag(2, 0, &signer), frag(2, 1, &signer)]);
+
+        archive.insert(frag(3, 0, &signer));
+        assert_eq!(archive.frag(1, 0), None);
+        assert_eq!(archive.frags(3).len(), 1);
+        assert_eq!(archive.env(3), None);
+    }
+}
@@ -1,24 +1,62 @@
+use alloy_eips::eip7685::Requests;
 use alloy_primitives::B256;
 use alloy_rpc_types::engine::{ExecutionPayloadV3, ForkchoiceState, ForkchoiceUpdated, PayloadId, PayloadStatus};
 use bop_common::{
     api::EngineApiServer,
-    communication::messages::{self, RpcError, RpcResult},
+    communication::messages::{self, EngineApiVersion, RpcError, RpcResult},
+    db::DatabaseHistory,
 };
 use jsonrpsee::core::async_trait;
-use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpPayloadAttributes};
+use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpExecutionPayloadEnvelopeV4, OpPayloadAttributes};
 use tokio::sync::oneshot;
 use tracing::{trace, Level};
 
 use crate::RpcServer;
 
-impl RpcServer {
-    fn send(&self, msg: messages::EngineApi) {
-        let _ = self.engine_rpc_tx.send(msg.into());
+impl<Db: DatabaseHistory> RpcServer<Db> {
+    /// Waits for room in the channel to the sequencer for at most the engine timeout, without blocking the runtime.
+    async fn send(&self, msg: messages::EngineApi) -> RpcResult<()> {
+        self.engine_rpc_tx.send_timeout_async(msg.into(), self.engine_timeout).await.map_err(|_| RpcError::Overloaded)
+    }
+
+    /// Payloads have to be passed with the endpoint version of the fork active at their timestamp.
+    fn check_version(&self, payload: &ExecutionPayloadV3, version: EngineApiVersion) -> RpcResult<()> {
+        let timestamp = payload.payload_inner.payload_inner.timestamp;
+        if EngineApiVersion::for_timestamp(self.evm_config.chain_spec(), timestamp) != version {
+            return Err(RpcError::UnsupportedFork);
+        }
+        Ok(())
+    }
+
+    async fn new_payload(
+        &self,
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Option<Requests>,
+    ) -> RpcResult<PayloadStatus> {
+        let (tx, rx) = oneshot::channel();
+        let res = Some(tx);
+        self.send(match execution_requests {
+            Some(execution_requests) => messages::EngineApi::NewPayloadV4 {
+                payload,
+                versioned_hashes,
+                parent_beacon_block_root,
+                execution_requests,
+                res,
+            },
+            None => messages::EngineApi::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, res },
+        })
+        .await?;
+
+        let res = tokio::time::timeout(self.engine_timeout.into(), rx).await??;
+
+        Ok(res)
     }
 }
 
 #[async_trait]
-impl EngineApiServer for RpcServer {
+impl<Db: DatabaseHistory> EngineApiServer for RpcServer<Db> {
     #[tracing::instrument(skip_all, ret(level = Level::TRACE))]
     async fn fork_choice_updated_v3(
         &self,
@@ -27,11 +65,17 @@ impl EngineApiServer for RpcServer {
     ) -> RpcResult<ForkchoiceUpdated> {
         trace!(?fork_choice_state, ?payload_attributes, "new request");
 
+        let (tx, rx) = oneshot::channel();
         self.send(messages::EngineApi::ForkChoiceUpdatedV3 {
             fork_choice_state,
             payload_attributes: payload_attributes.map(Box::new),
-        });
-        Err(RpcError::NoReturn)
+            res: Some(tx),
+        })
+        .await?;
+
+        let res = tokio::time::timeout(self.engine_timeout.into(), rx).await??;
+
+        Ok(res)
     }
 
     #[tracing::instrument(skip_all,  ret(level = Level::TRACE))]
@@ -43,8 +87,26 @@ impl EngineApiServer for RpcServer {
     ) -> RpcResult<PayloadStatus> {
         trace!(?payload, ?versioned_hashes, %parent_beacon_block_root, "new request");
 
-        self.send(messages::EngineApi::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root });
-        Err(RpcError::NoReturn)
+        self.check_version(&payload, EngineApiVersion::V3)?;
+        self.new_payload(payload, versioned_hashes, parent_beacon_block_root, None).await
+    }
+
+    #[tracing::instrument(skip_all, ret(level = Level::TRACE))]
+    async fn new_payload_v4(
+        &self,
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Requests,
+    ) -> RpcResult<PayloadStatus> {
+        trace!(?payload, ?versioned_hashes, %parent_beacon_block_root, ?execution_requests, "new request");
+
+        self.check_version(&payload, EngineApiVersion::V4)?;
+        // OP Stack chains don't support any EIP-7685 request types
+        if execution_requests.iter().len() != 0 {
+            return Err(RpcError::InvalidBlock("execution requests must be empty".to_string()));
+        }
+        self.new_payload(payload, versioned_hashes, parent_beacon_block_root, Some(execution_requests)).await
     }
 
     #[tracing::instrument(skip_all, ret(level = Level::TRACE))]
@@ -52,11 +114,20 @@ impl EngineApiServer for RpcServer {
         trace!(%payload_id, "new request");
 
         let (tx, rx) = oneshot::channel();
-        self.send(messages::EngineApi::GetPayloadV3 { payload_id, res: tx });
+        self.send(messages::EngineApi::GetPayloadV3 { payload_id, res: tx }).await?;
 
         // wait with timeout
-        let res = tokio::time::timeout(self.engine_timeout.into(), rx).await??;
+        tokio::time::timeout(self.engine_timeout.into(), rx).await??
+    }
 
-        Ok(res)
+    #[tracing::instrument(skip_all, ret(level = Level::TRACE))]
+    async fn get_payload_v4(&self, payload_id: PayloadId) -> RpcResult<OpExecutionPayloadEnvelopeV4> {
+        trace!(%payload_id, "new request");
+
+        let (tx, rx) = oneshot::channel();
+        self.send(messages::EngineApi::GetPayloadV4 { payload_id, res: tx }).await?;
+
+        // wait with timeout
+        tokio::time::timeout(self.engine_timeout.into(), rx).await??
     }
 }
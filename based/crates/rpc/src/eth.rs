@@ -0,0 +1,288 @@
+use alloy_consensus::Header;
+use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_rpc_types::{BlockId, BlockNumberOrTag, Header as RpcHeader, TransactionRequest};
+use bop_common::{
+    api::EthStateApiServer,
+    communication::messages::{RpcError, RpcResult},
+    db::{DBFrag, DatabaseHistory, Error},
+};
+use jsonrpsee::core::async_trait;
+use reth_evm::{env::EvmEnv, execute::ProviderError, ConfigureEvm, ConfigureEvmEnv};
+use reth_optimism_evm::OpEvmConfig;
+use revm::{db::CacheDB, DatabaseRef};
+use revm_primitives::{AccountInfo, Bytecode, EnvWithHandlerCfg, ExecutionResult, OptimismFields, TxEnv, TxKind};
+use tracing::{trace, Level};
+
+/// Serves `eth_` state queries, see [`EthStateApi`](bop_common::api::EthStateApi).
+#[derive(Debug, Clone)]
+pub struct StateRpc<Db> {
+    /// Committed state, used for `latest` and past blocks.
+    db: Db,
+    /// Preconfirmed state of the block being built, used for `pending`.
+    frag_db: DBFrag<Db>,
+    evm_config: OpEvmConfig,
+    max_history_blocks: u64,
+}
+
+impl<Db> StateRpc<Db> {
+    pub fn new(db: Db, frag_db: DBFrag<Db>, evm_config: OpEvmConfig, max_history_blocks: u64) -> Self {
+        Self { db, frag_db, evm_config, max_history_blocks }
+    }
+}
+
+/// Which state a request is served from.
+#[derive(Debug, Clone, Copy)]
+enum StateAt {
+    /// Preconfirmed state of the block being built on top of `head`.
+    Pending { head: u64 },
+    /// State at the end of a committed block.
+    Block { number: u64, head: u64 },
+}
+
+impl StateAt {
+    /// Block whose header is used as environment when executing calls.
+    fn env_block(&self) -> u64 {
+        match *self {
+            StateAt::Pending { head } => head,
+            StateAt::Block { number, .. } => number,
+        }
+    }
+}
+
+/// State a single request reads from, unifying the error types of the underlying databases.
+enum StateView<'a, Db: DatabaseHistory> {
+    Pending(&'a DBFrag<Db>),
+    Latest(&'a Db),
+    Historical(Db::Historical),
+}
+
+impl<Db: DatabaseHistory> DatabaseRef for StateView<'_, Db> {
+    type Error = Error;
+
+    fn basic_ref(&self, address: Address) -> Result<Option<AccountInfo>, Self::Error> {
+        match self {
+            StateView::Pending(db) => db.basic_ref(address).map_err(db_error),
+            StateView::Latest(db) => db.basic_ref(address).map_err(db_error),
+            StateView::Historical(db) => db.basic_ref(address),
+        }
+    }
+
+    fn code_by_hash_ref(&self, code_hash: B256) -> Result<Bytecode, Self::Error> {
+        match self {
+            StateView::Pending(db) => db.code_by_hash_ref(code_hash).map_err(db_error),
+            StateView::Latest(db) => db.code_by_hash_ref(code_hash).map_err(db_error),
+            StateView::Historical(db) => db.code_by_hash_ref(code_hash),
+        }
+    }
+
+    fn storage_ref(&self, address: Address, index: U256) -> Result<U256, Self::Error> {
+        match self {
+            StateView::Pending(db) => db.storage_ref(address, index).map_err(db_error),
+            StateView::Latest(db) => db.storage_ref(address, index).map_err(db_error),
+            StateView::Historical(db) => db.storage_ref(address, index),
+        }
+    }
+
+    fn block_hash_ref(&self, number: u64) -> Result<B256, Self::Error> {
+        match self {
+            StateView::Pending(db) => db.block_hash_ref(number).map_err(db_error),
+            StateView::Latest(db) => db.block_hash_ref(number).map_err(db_error),
+            StateView::Historical(db) => db.block_hash_ref(number),
+        }
+    }
+}
+
+fn db_error(error: impl Into<ProviderError>) -> Error {
+    Error::ProviderError(error.into())
+}
+
+impl<Db: DatabaseHistory> StateRpc<Db> {
+    /// Resolves the requested block to the state it should be served from. Defaults to `latest`.
+    fn resolve_block(&self, block_number: Option<BlockId>) -> RpcResult<StateAt> {
+        let head = self.db.head_block_number()?;
+        let number = match block_number.unwrap_or(BlockId::latest()) {
+            BlockId::Number(BlockNumberOrTag::Pending) => return Ok(StateAt::Pending { head }),
+            BlockId::Number(BlockNumberOrTag::Latest) => head,
+            BlockId::Number(BlockNumberOrTag::Earliest) => 0,
+            BlockId::Number(BlockNumberOrTag::Number(number)) => number,
+            BlockId::Number(tag) => return Err(RpcError::InvalidBlock(format!("unsupported block tag {tag}"))),
+            BlockId::Hash(hash) => self
+                .db
+                .block_number_by_hash(hash.block_hash, self.max_history_blocks)?
+                .ok_or_else(|| RpcError::InvalidBlock(format!("unknown block hash {}", hash.block_hash)))?,
+        };
+
+        if number > head {
+            return Err(RpcError::InvalidBlock(format!("block {number} is ahead of the head {head}")));
+        }
+        if head - number > self.max_history_blocks {
+            return Err(RpcError::InvalidBlock(format!(
+                "block {number} is outside the history window of {} blocks",
+                self.max_history_blocks
+            )));
+        }
+
+        Ok(StateAt::Block { number, head })
+    }
+
+    fn state_view(&self, at: StateAt) -> RpcResult<StateView<'_, Db>> {
+        Ok(match at {
+            StateAt::Pending { .. } => StateView::Pending(&self.frag_db),
+            StateAt::Block { number, head } if number == head => StateView::Latest(&self.db),
+            StateAt::Block { number, .. } => StateView::Historical(self.db.state_at_block(number)?),
+        })
+    }
+
+    /// Runs `f` on the blocking thread pool, as database reads may hit disk.
+    async fn blocking<R, F>(&self, f: F) -> RpcResult<R>
+    where
+        R: Send + 'static,
+        F: FnOnce(Self) -> RpcResult<R> + Send + 'static,
+    {
+        let this = self.clone();
+        tokio::task::spawn_blocking(move || f(this)).await?
+    }
+
+    fn account(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<Option<AccountInfo>> {
+        let state = self.state_view(self.resolve_block(block_number)?)?;
+        Ok(state.basic_ref(address)?)
+    }
+
+    /// Header of a committed block, `None` if it is ahead of the head or not stored.
+    fn header(&self, number: BlockNumberOrTag) -> RpcResult<Option<RpcHeader>> {
+        let head = self.db.head_block_number()?;
+        let number = match number {
+            BlockNumberOrTag::Latest => head,
+            BlockNumberOrTag::Earliest => 0,
+            BlockNumberOrTag::Number(number) => number,
+            tag => return Err(RpcError::InvalidBlock(format!("unsupported block tag {tag}"))),
+        };
+        if number > head {
+            return Ok(None);
+        }
+
+        let header = self.db.header_by_number(number)?;
+        Ok(header.map(|inner| RpcHeader { hash: inner.hash_slow(), inner, total_difficulty: None, size: None }))
+    }
+
+    /// Executes `request` on top of the requested state, with the environment of the corresponding header.
+    fn call_at(&self, request: TransactionRequest, block_number: Option<BlockId>) -> RpcResult<Bytes> {
+        let at = self.resolve_block(block_number)?;
+        let header = self
+            .db
+            .header_by_number(at.env_block())?
+            .ok_or_else(|| RpcError::InvalidBlock(format!("header of block {} is not available", at.env_block())))?;
+        let state = self.state_view(at)?;
+
+        let env = self.call_env(&header, &request);
+        let mut evm = self.evm_config.evm_with_env(CacheDB::new(state), env);
+        let result = evm.transact().map_err(|e| RpcError::ExecutionFailed(e.to_string()))?.result;
+
+        match result {
+            ExecutionResult::Success { output, .. } => Ok(output.into_data()),
+            ExecutionResult::Revert { output, .. } => Err(RpcError::Reverted(output)),
+            ExecutionResult::Halt { reason, .. } => Err(RpcError::ExecutionFailed(format!("{reason:?}"))),
+        }
+    }
+
+    fn call_env(&self, header: &Header, request: &TransactionRequest) -> EnvWithHandlerCfg {
+        let EvmEnv { mut cfg_env_with_handler_cfg, mut block_env } = self.evm_config.cfg_and_block_env(header);
+
+        // Calls don't need funds to cover gas, and are free unless the request sets a price.
+        cfg_env_with_handler_cfg.disable_balance_check = true;
+        if request.gas_price.is_none() && request.max_fee_per_gas.is_none() {
+            block_env.basefee = U256::ZERO;
+        }
+
+        let tx_env = TxEnv {
+            caller: request.from.unwrap_or_default(),
+            gas_limit: request.gas.unwrap_or(block_env.gas_limit.saturating_to()),
+            gas_price: U256::from(request.gas_price.or(request.max_fee_per_gas).unwrap_or_default()),
+            gas_priority_fee: request.max_priority_fee_per_gas.map(U256::from),
+            transact_to: request.to.unwrap_or(TxKind::Create),
+            value: request.value.unwrap_or_default(),
+            data: request.input.input().cloned().unwrap_or_default(),
+            access_list: request.access_list.clone().map(|list| list.0).unwrap_or_default(),
+            // Not a deposit, an empty envelope means no L1 data fee is charged.
+            optimism: OptimismFields { enveloped_tx: Some(Bytes::new()), ..Default::default() },
+            ..Default::default()
+        };
+
+        EnvWithHandlerCfg::new_with_cfg_env(cfg_env_with_handler_cfg, block_env, tx_env)
+    }
+}
+
+#[async_trait]
+impl<Db: DatabaseHistory> EthStateApiServer for StateRpc<Db> {
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn block_number(&self) -> RpcResult<U256> {
+        trace!("new request");
+
+        Ok(U256::from(self.db.head_block_number()?))
+    }
+
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn header_by_number(&self, number: BlockNumberOrTag) -> RpcResult<Option<RpcHeader>> {
+        trace!(%number, "new request");
+
+        self.blocking(move |this| this.header(number)).await
+    }
+
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn transaction_count(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256> {
+        trace!(%address, ?block_number, "new request");
+
+        self.blocking(move |this| {
+            let account = this.account(address, block_number)?;
+            Ok(U256::from(account.map(|account| account.nonce).unwrap_or_default()))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn balance(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<U256> {
+        trace!(%address, ?block_number, "new request");
+
+        self.blocking(move |this| {
+            let account = this.account(address, block_number)?;
+            Ok(account.map(|account| account.balance).unwrap_or_default())
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn storage_at(&self, address: Address, slot: U256, block_number: Option<BlockId>) -> RpcResult<B256> {
+        trace!(%address, %slot, ?block_number, "new request");
+
+        self.blocking(move |this| {
+            let state = this.state_view(this.resolve_block(block_number)?)?;
+            Ok(B256::from(state.storage_ref(address, slot)?.to_be_bytes()))
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn code(&self, address: Address, block_number: Option<BlockId>) -> RpcResult<Bytes> {
+        trace!(%address, ?block_number, "new request");
+
+        self.blocking(move |this| {
+            let state = this.state_view(this.resolve_block(block_number)?)?;
+            let Some(account) = state.basic_ref(address)? else {
+                return Ok(Bytes::new());
+            };
+            let code = match account.code {
+                Some(code) => code,
+                None => state.code_by_hash_ref(account.code_hash)?,
+            };
+            Ok(code.original_bytes())
+        })
+        .await
+    }
+
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn call(&self, request: TransactionRequest, block_number: Option<BlockId>) -> RpcResult<Bytes> {
+        trace!(?request, ?block_number, "new request");
+
+        self.blocking(move |this| this.call_at(request, block_number)).await
+    }
+}
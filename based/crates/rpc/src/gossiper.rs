@@ -1,35 +1,71 @@
-use bop_common::{actor::Actor, communication::SpineConnections, p2p, signing::ECDSASigner};
+use bop_common::{
+    actor::Actor,
+    communication::SpineConnections,
+    metrics::GOSSIP_MESSAGES,
+    p2p::{self, WireEncoding},
+    signing::ECDSASigner,
+};
 use jsonrpsee::client_transport::ws::Url;
-use reqwest::blocking::{Client, ClientBuilder};
+use metrics::counter;
+use reqwest::{
+    blocking::{Client, ClientBuilder},
+    header::CONTENT_TYPE,
+};
 use tracing::{error, info};
 
+use crate::archive::FragArchive;
+
 pub struct Gossiper {
     target_rpc: Option<Url>,
     client: Client,
     signer: ECDSASigner,
+    archive: FragArchive,
+    /// Sends JSON-RPC requests if not set.
+    encoding: Option<WireEncoding>,
 }
 
 impl Gossiper {
-    pub fn new(target_rpc: Option<Url>) -> Self {
+    pub fn new(
+        target_rpc: Option<Url>,
+        signer: ECDSASigner,
+        archive: FragArchive,
+        encoding: Option<WireEncoding>,
+    ) -> Self {
         let client = ClientBuilder::new()
             .timeout(std::time::Duration::from_secs(10))
             .build()
             .expect("couldn't build http client");
 
-        let signer = ECDSASigner::random();
+        info!(address = %signer.address, "signing gossip");
 
-        Self { target_rpc, client, signer }
+        Self { target_rpc, client, signer, archive, encoding }
     }
 
+    /// Signs and archives the message, then sends it to the root peer.
     fn gossip(&self, msg: p2p::VersionedMessage) {
+        let kind = msg.as_ref().to_string();
+        let signed = msg.sign(&self.signer);
+        self.archive.insert(signed.clone());
+
         let Some(url) = self.target_rpc.as_ref().cloned() else {
             return;
         };
 
-        let payload = msg.to_json(&self.signer);
+        let request = match self.encoding {
+            None => self.client.post(url).json(&signed.to_json()),
+            Some(encoding) => match signed.to_wire(encoding) {
+                Ok(body) => self.client.post(url).header(CONTENT_TYPE, encoding.content_type()).body(body),
+                Err(err) => {
+                    error!(%err, ?signed, "couldn't encode");
+                    counter!(GOSSIP_MESSAGES, "kind" => kind, "result" => "error").increment(1);
+                    return;
+                }
+            },
+        };
 
-        let Ok(res) = self.client.post(url).json(&payload).send() else {
-            tracing::error!("couldn't send {}", payload);
+        let Ok(res) = request.send() else {
+            tracing::error!(?signed, "couldn't send");
+            counter!(GOSSIP_MESSAGES, "kind" => kind, "result" => "error").increment(1);
             return;
         };
 
@@ -37,9 +73,11 @@ impl Gossiper {
         let body = res.text().expect("couldn't read response");
 
         if code.is_success() {
-            info!("successfully sent {}", msg.as_ref());
+            info!("successfully sent {}", kind);
+            counter!(GOSSIP_MESSAGES, "kind" => kind, "result" => "success").increment(1);
         } else {
-            error!(body, %payload, code = code.as_u16(), "failed to send");
+            error!(body, ?signed, code = code.as_u16(), "failed to send");
+            counter!(GOSSIP_MESSAGES, "kind" => kind, "result" => "error").increment(1);
         }
     }
 }
@@ -50,4 +88,9 @@ impl<Db> Actor<Db> for Gossiper {
             self.gossip(msg);
         });
     }
+
+    /// Sends what the sequencer queued before stopping, e.g. the last frag of an aborted block.
+    fn on_exit(self, connections: &mut SpineConnections<Db>) {
+        while connections.receive(|msg, _| self.gossip(msg)) {}
+    }
 }
@@ -2,75 +2,278 @@ use std::{net::SocketAddr, sync::Arc};
 
 use alloy_primitives::{Bytes, B256};
 use bop_common::{
-    api::{EngineApiServer, MinimalEthApiServer},
+    api::{EngineApiServer, EthStateApiServer, FragArchiveApiServer, MinimalEthApiServer, PreconfApiServer},
     communication::{
-        messages::{EngineApi, RpcResult},
+        messages::{EngineApi, RpcError, RpcResult},
         Sender, Spine,
     },
     config::GatewayArgs,
-    db::DatabaseRead,
+    db::DatabaseHistory,
+    shared::SharedState,
+    shutdown::{Shutdown, Stage, StopToken},
+    signing::{ECDSASigner, SignedPreconfirmation},
     time::Duration,
     transaction::Transaction,
 };
-use jsonrpsee::{core::async_trait, server::ServerBuilder};
+use jsonrpsee::{
+    core::async_trait,
+    server::{ServerBuilder, ServerHandle},
+    RpcModule,
+};
+use reth_optimism_evm::OpEvmConfig;
+use reth_rpc_layer::{AuthLayer, JwtAuthValidator, JwtSecret};
 use tokio::runtime::Runtime;
 use tracing::{error, info, trace, Level};
 
+pub mod archive;
 mod engine;
+mod eth;
 pub mod gossiper;
 
-pub fn start_rpc<Db: DatabaseRead>(config: &GatewayArgs, spine: &Spine<Db>, rt: &Runtime) {
+use archive::FragArchive;
+pub use eth::StateRpc;
+
+/// Starts the public eth_ RPC and the JWT authenticated engine_ RPC. The engine listener also serves the eth_ methods,
+/// so the portal only needs a single authenticated connection to each gateway.
+///
+/// Transactions sent with `based_sendRawTransactionWithCommitment` are answered once they are in a sealed frag, with
+/// their inclusion signed by `signer`, the key frags are gossiped with. The messages of recent blocks are served from
+/// the `archive` the gossiper fills.
+///
+/// On shutdown the public RPC stops, and transactions are rejected, with [`Stage::Intake`]. The engine RPC stays up
+/// until [`Stage::Gossip`], so the block being built can still be fetched.
+#[allow(clippy::too_many_arguments)]
+pub fn start_rpc<Db: DatabaseHistory>(
+    config: &GatewayArgs,
+    engine_jwt: JwtSecret,
+    spine: &Spine<Db>,
+    db: Db,
+    shared_state: SharedState<Db>,
+    signer: ECDSASigner,
+    archive: FragArchive,
+    evm_config: OpEvmConfig,
+    shutdown: &Shutdown,
+    rt: &Runtime,
+) {
     let addr = SocketAddr::new(config.rpc_host.into(), config.rpc_port);
-    let server = RpcServer::new(spine);
-    rt.spawn(server.run(addr));
+    let engine_addr = SocketAddr::new(config.rpc_host.into(), config.engine_port);
+    let state = StateRpc::new(db, shared_state.as_ref().clone(), evm_config.clone(), config.rpc_max_history_blocks);
+    let server = RpcServer::new(
+        spine,
+        state,
+        shared_state,
+        signer,
+        Duration::from_millis(config.rpc_commitment_timeout_ms),
+        archive,
+        evm_config,
+        shutdown.clone(),
+    );
+    rt.spawn(server.clone().run(addr, shutdown.register(Stage::Intake)));
+    rt.spawn(server.run_engine(engine_addr, engine_jwt, shutdown.register(Stage::Gossip)));
+}
+
+/// Serves until the server stops by itself or `stop` is signalled.
+async fn serve_until(handle: ServerHandle, mut stop: StopToken) {
+    tokio::select! {
+        _ = handle.clone().stopped() => {
+            error!("server stopped");
+        }
+
+        _ = stop.stopped() => {
+            let _ = handle.stop();
+            handle.stopped().await;
+            info!("server stopped for shutdown");
+        }
+    }
 }
 
-// TODO: jwt auth
+/// Starts serving `module`, requests without a valid token for `jwt` are rejected before reaching it. Returns the
+/// address the server is bound to.
+async fn start_with_jwt_auth<T: Send + Sync + 'static>(
+    addr: SocketAddr,
+    jwt: JwtSecret,
+    module: RpcModule<T>,
+) -> (SocketAddr, ServerHandle) {
+    let auth = tower::ServiceBuilder::new().layer(AuthLayer::new(JwtAuthValidator::new(jwt)));
+    let server = ServerBuilder::default()
+        .set_http_middleware(auth)
+        .build(addr)
+        .await
+        .expect("failed to create engine RPC server");
+    let addr = server.local_addr().expect("engine RPC server isn't bound");
+    (addr, server.start(module))
+}
+
+/// How long transactions wait for room in a full channel to the sequencer before they are rejected.
+const ORDER_SEND_TIMEOUT_MS: u64 = 100;
+
 // TODO: timing
 #[derive(Debug, Clone)]
-struct RpcServer {
+struct RpcServer<Db> {
     new_order_tx: Sender<Arc<Transaction>>,
     engine_timeout: Duration,
     engine_rpc_tx: Sender<EngineApi>,
+    state: StateRpc<Db>,
+    shared_state: SharedState<Db>,
+    signer: ECDSASigner,
+    commitment_timeout: Duration,
+    archive: FragArchive,
+    evm_config: OpEvmConfig,
+    shutdown: Shutdown,
 }
 
-impl RpcServer {
-    pub fn new<Db>(spine: &Spine<Db>) -> Self {
-        Self { new_order_tx: spine.into(), engine_rpc_tx: spine.into(), engine_timeout: Duration::from_secs(1) }
+impl<Db: DatabaseHistory> RpcServer<Db> {
+    pub fn new(
+        spine: &Spine<Db>,
+        state: StateRpc<Db>,
+        shared_state: SharedState<Db>,
+        signer: ECDSASigner,
+        commitment_timeout: Duration,
+        archive: FragArchive,
+        evm_config: OpEvmConfig,
+        shutdown: Shutdown,
+    ) -> Self {
+        Self {
+            new_order_tx: spine.into(),
+            engine_rpc_tx: spine.into(),
+            engine_timeout: Duration::from_secs(1),
+            state,
+            shared_state,
+            signer,
+            commitment_timeout,
+            archive,
+            evm_config,
+            shutdown,
+        }
+    }
+
+    /// Waits for room in the channel to the sequencer for at most [`ORDER_SEND_TIMEOUT_MS`], without blocking the
+    /// runtime.
+    async fn send_order(&self, tx: Arc<Transaction>) -> RpcResult<()> {
+        let timeout = Duration::from_millis(ORDER_SEND_TIMEOUT_MS);
+        self.new_order_tx.send_timeout_async(tx.into(), timeout).await.map_err(|_| RpcError::Overloaded)
+    }
+
+    fn eth_module(&self) -> RpcModule<Self> {
+        let mut module = MinimalEthApiServer::into_rpc(self.clone());
+        module.merge(EthStateApiServer::into_rpc(self.state.clone())).expect("failed to merge modules");
+        module.merge(PreconfApiServer::into_rpc(self.clone())).expect("failed to merge modules");
+        module.merge(FragArchiveApiServer::into_rpc(self.archive.clone())).expect("failed to merge modules");
+        module
+    }
+
+    #[tracing::instrument(skip_all, name = "rpc_engine")]
+    pub async fn run_engine(self, addr: SocketAddr, jwt: JwtSecret, stop: StopToken) {
+        info!(%addr, "starting engine RPC server");
+
+        let mut module = self.eth_module();
+        module.merge(EngineApiServer::into_rpc(self)).expect("failed to merge modules");
+
+        let (_, handle) = start_with_jwt_auth(addr, jwt, module).await;
+        serve_until(handle, stop).await;
     }
 
     #[tracing::instrument(skip_all, name = "rpc")]
-    pub async fn run(self, addr: SocketAddr) {
+    pub async fn run(self, addr: SocketAddr, stop: StopToken) {
         info!(%addr, "starting RPC server");
 
         let server = ServerBuilder::default().build(addr).await.expect("failed to create eth RPC server");
-        let mut module = MinimalEthApiServer::into_rpc(self.clone());
-        module.merge(EngineApiServer::into_rpc(self)).expect("failed to merge modules");
+        let module = self.eth_module();
 
         let server_handle = server.start(module);
         //TODO: Handle other communcation from sequencer ?
         //      Idea: we have this part do rpc requests, using the rpc->sequencer channel,
         //      but we make it part of another sync actor that uses the connections and gathers
         //      state etc in a spinloop that the rpc runtime can use to serve requests with?
-        server_handle.stopped().await;
-
-        error!("server stopped");
+        serve_until(server_handle, stop).await;
     }
 }
 
 /// Note: this is a temporary RPC implementation that only serves the lastest state from the sequencer.
-/// It doesn't adhere to the specific block number or hash requests.
 /// This will ultimately be replaced by the RPC server in the EL when the full Frag handling is implemented.
 #[async_trait]
-impl MinimalEthApiServer for RpcServer {
+impl<Db: DatabaseHistory> MinimalEthApiServer for RpcServer<Db> {
     #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
     async fn send_raw_transaction(&self, bytes: Bytes) -> RpcResult<B256> {
         trace!(?bytes, "new request");
 
+        if self.shutdown.is_stopping(Stage::Intake) {
+            return Err(RpcError::ShuttingDown);
+        }
+
         let tx = Arc::new(Transaction::decode(bytes)?);
         let hash = tx.tx_hash();
-        let _ = self.new_order_tx.send(tx.into());
+        self.send_order(tx).await?;
 
         Ok(hash)
     }
 }
+
+#[async_trait]
+impl<Db: DatabaseHistory> PreconfApiServer for RpcServer<Db> {
+    #[tracing::instrument(skip_all, err, ret(level = Level::TRACE))]
+    async fn send_raw_transaction_with_commitment(&self, bytes: Bytes) -> RpcResult<SignedPreconfirmation> {
+        trace!(?bytes, "new request");
+
+        if self.shutdown.is_stopping(Stage::Intake) {
+            return Err(RpcError::ShuttingDown);
+        }
+
+        let tx = Arc::new(Transaction::decode(bytes)?);
+        let hash = tx.tx_hash();
+        // wait before sending, so the inclusion can't be missed
+        let inclusion = self.shared_state.wait_for_inclusion(hash);
+        if let Err(err) = self.send_order(tx).await {
+            self.shared_state.cancel_inclusion_waiters(&hash);
+            return Err(err);
+        }
+
+        let preconfirmation = match tokio::time::timeout(self.commitment_timeout.into(), inclusion).await {
+            Ok(inclusion) => inclusion?,
+            Err(elapsed) => {
+                self.shared_state.cancel_inclusion_waiters(&hash);
+                return Err(elapsed.into());
+            }
+        };
+
+        preconfirmation.sign(&self.signer).map_err(|err| {
+            error!(%err, %hash, "couldn't sign preconfirmation");
+            RpcError::Internal
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+    use reth_rpc_layer::secret_to_bearer_header;
+
+    use super::*;
+
+    async fn call(addr: SocketAddr, jwt: Option<&JwtSecret>) -> reqwest::Response {
+        let request = serde_json::json!({ "jsonrpc": "2.0", "id": 1, "method": "engine_ping", "params": [] });
+        let mut builder = reqwest::Client::new().post(format!("http://{addr}")).json(&request);
+        if let Some(jwt) = jwt {
+            builder = builder.header("Authorization", secret_to_bearer_header(jwt).to_str().unwrap());
+        }
+        builder.send().await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn engine_rpc_requires_jwt() {
+        let jwt = JwtSecret::random();
+        let mut module = RpcModule::new(());
+        module.register_method("engine_ping", |_, _, _| "pong").unwrap();
+        let (addr, handle) = start_with_jwt_auth(SocketAddr::from(([127, 0, 0, 1], 0)), jwt, module).await;
+
+        assert_eq!(call(addr, None).await.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(call(addr, Some(&JwtSecret::random())).await.status(), StatusCode::UNAUTHORIZED);
+
+        let response = call(addr, Some(&jwt)).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        let body: serde_json::Value = response.json().await.unwrap();
+        assert_eq!(body["result"], "pong");
+
+        handle.stop().unwrap();
+    }
+}
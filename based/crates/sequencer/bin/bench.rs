@@ -0,0 +1,326 @@
+use std::{path::PathBuf, sync::Arc};
+
+use alloy_rpc_types::engine::{ForkchoiceState, PayloadStatusEnum};
+use bop_common::{
+    actor::{Actor, ActorConfig},
+    communication::{
+        messages::{envelope_v3, EngineApi, EngineApiVersion},
+        Spine, SpineConnections,
+    },
+    p2p::VersionedMessage,
+    shared::SharedState,
+    shutdown::{Shutdown, Stage},
+    time::{Duration, Instant},
+    transaction::Transaction,
+    utils::initialize_test_tracing,
+};
+use bop_db::{BlockFixture, FixtureDB};
+use bop_sequencer::{Sequencer, SequencerConfig, Simulator};
+use clap::Parser;
+use reqwest::Url;
+use reth_cli::chainspec::ChainSpecParser;
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_cli::chainspec::OpChainSpecParser;
+use reth_optimism_evm::OpEvmConfig;
+use reth_optimism_primitives::OpBlock;
+use reth_primitives::BlockWithSenders;
+use serde::Serialize;
+use tokio::sync::oneshot;
+use tracing::level_filters::LevelFilter;
+
+/// Runs the sequencer on the blocks of a fixture made with `export-fixture`, without any network access.
+///
+/// For each block the sequencer gets the same engine API messages and transactions as when following a chain: a fork
+/// choice update with the deposits as attributes, the other transactions through the pool, and a `GetPayload` once all
+/// of them were sealed in frags or the block time passed. The original block is then committed with `NewPayload`, so
+/// every block starts from the same state as on chain.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the fixture
+    #[arg(short, long)]
+    fixture: PathBuf,
+
+    /// Chain the fixture was exported from
+    #[arg(
+        long,
+        value_name = "CHAIN_OR_PATH",
+        default_value = OpChainSpecParser::SUPPORTED_CHAINS[6],
+        value_parser = OpChainSpecParser::parser(),
+    )]
+    chain: Arc<OpChainSpec>,
+
+    /// Duration of a frag in ms. Blocks are only complete once a frag is sealed, so this bounds the MGas/s
+    #[arg(long, default_value_t = 200)]
+    frag_duration_ms: u64,
+
+    /// Max time to build a block in ms
+    #[arg(long, default_value_t = 2000)]
+    block_time_ms: u64,
+
+    /// Number of simulator threads
+    #[arg(long, default_value_t = 4)]
+    sim_threads: usize,
+
+    /// Pass all transactions as attributes, so they're included in the original order and the block hash should match
+    #[arg(long)]
+    txs_in_attributes: bool,
+
+    /// Fail if a sealed block hash doesn't match the original
+    #[arg(long)]
+    require_match: bool,
+
+    /// Print a json line per block instead of a summary
+    #[arg(long)]
+    json: bool,
+
+    /// RPC URL for the sequencer config, not called during the benchmark
+    #[arg(long, default_value = "http://localhost:8545")]
+    rpc_url: Url,
+}
+
+#[derive(Debug, Serialize)]
+struct BlockReport {
+    number: u64,
+    txs: usize,
+    included_txs: usize,
+    gas_used: u64,
+    mgas_per_sec: f64,
+    frags: usize,
+    /// Time from the fork choice update to each frag, in ms
+    frag_times_ms: Vec<f64>,
+    get_payload_ms: f64,
+    /// Time spent calculating the state and withdrawals roots while building the block, in ms
+    state_root_ms: f64,
+    hash_matches: bool,
+}
+
+fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    initialize_test_tracing(LevelFilter::WARN);
+
+    let fixture = BlockFixture::read(&args.fixture)?;
+    let blocks = fixture.blocks()?;
+    eyre::ensure!(blocks.len() > 1, "fixture needs at least two blocks, the first one is only synced");
+    let db = FixtureDB::new(&fixture)?;
+    let fixture_db = db.clone();
+
+    let evm_config = OpEvmConfig::new(args.chain.clone());
+    let config = SequencerConfig {
+        frag_duration: Duration::from_millis(args.frag_duration_ms),
+        n_per_loop: args.sim_threads,
+        rpc_url: args.rpc_url.clone(),
+        evm_config: evm_config.clone(),
+        simulate_tof_in_pools: false,
+        commit_sealed_frags_to_db: false,
+        gossip_frag_v1: false,
+        record_path: None,
+    };
+
+    let spine = Spine::default();
+    let shared_state = SharedState::new(db.clone().into());
+    let shutdown = Shutdown::new(Duration::from_secs(5));
+
+    let reports = std::thread::scope(|s| {
+        let stop = shutdown.register(Stage::Sequencer);
+        let connections = spine.to_connections("Sequencer");
+        let state = shared_state.clone();
+        s.spawn(move || Sequencer::new(db, state, config).run(connections, ActorConfig::default(), stop));
+
+        for id in 0..args.sim_threads {
+            let name = format!("Simulator-{id}");
+            let connections = spine.to_connections(name.as_str());
+            let db_frag = (&shared_state).into();
+            let stop = shutdown.register(Stage::Simulators);
+            let evm_config = &evm_config;
+            s.spawn(move || Simulator::new(db_frag, evm_config, id).run(connections, ActorConfig::default(), stop));
+        }
+
+        let mut connections = spine.to_connections("Bench");
+        let reports = run(&args, &blocks, &fixture_db, &mut connections);
+        shutdown.shutdown();
+        reports
+    })?;
+
+    if !args.json {
+        let gas: u64 = reports.iter().map(|report| report.gas_used).sum();
+        let secs: f64 = reports.iter().map(|report| report.gas_used as f64 / 1e6 / report.mgas_per_sec).sum();
+        let frags: usize = reports.iter().map(|report| report.frags).sum();
+        let state_root_ms: f64 = reports.iter().map(|report| report.state_root_ms).sum();
+        let matches = reports.iter().filter(|report| report.hash_matches).count();
+        println!(
+            "{} blocks, {gas} gas, {:.3} MGas/s, {frags} frags, {:.3}ms state root, {matches}/{} block hashes match",
+            reports.len(),
+            gas as f64 / 1e6 / secs,
+            state_root_ms,
+            reports.len()
+        );
+    }
+
+    let mismatches = reports.iter().filter(|report| !report.hash_matches).count();
+    eyre::ensure!(!args.require_match || mismatches == 0, "{mismatches} block hashes don't match");
+    Ok(())
+}
+
+/// Syncs the first block, and builds all the others.
+fn run(
+    args: &Args,
+    blocks: &[BlockWithSenders<OpBlock>],
+    db: &FixtureDB,
+    connections: &mut SpineConnections<FixtureDB>,
+) -> eyre::Result<Vec<BlockReport>> {
+    commit(&blocks[0], connections)?;
+
+    let mut reports = Vec::with_capacity(blocks.len() - 1);
+    for block in &blocks[1..] {
+        let report = build(args, block, db, connections)?;
+        if args.json {
+            println!("{}", serde_json::to_string(&report)?);
+        } else {
+            println!(
+                "block {}: {}/{} txs in {} frags, {} gas, {:.3} MGas/s, GetPayload {:.3}ms, root {:.3}ms, hash {}",
+                report.number,
+                report.included_txs,
+                report.txs,
+                report.frags,
+                report.gas_used,
+                report.mgas_per_sec,
+                report.get_payload_ms,
+                report.state_root_ms,
+                if report.hash_matches { "matches" } else { "MISMATCH" }
+            );
+        }
+        reports.push(report);
+        commit(block, connections)?;
+    }
+    Ok(reports)
+}
+
+/// Commits the original block and makes it the head.
+fn commit(block: &BlockWithSenders<OpBlock>, connections: &mut SpineConnections<FixtureDB>) -> eyre::Result<()> {
+    let (new_payload, fcu, _) = EngineApi::messages_from_block(block, false, None);
+
+    let (res, rx) = oneshot::channel();
+    let new_payload = match new_payload {
+        EngineApi::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, .. } => {
+            EngineApi::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, res: Some(res) }
+        }
+        EngineApi::NewPayloadV4 { payload, versioned_hashes, parent_beacon_block_root, execution_requests, .. } => {
+            EngineApi::NewPayloadV4 {
+                payload,
+                versioned_hashes,
+                parent_beacon_block_root,
+                execution_requests,
+                res: Some(res),
+            }
+        }
+        _ => unreachable!(),
+    };
+    connections.send(new_payload);
+    let status = rx.blocking_recv()?;
+    eyre::ensure!(
+        status.status == PayloadStatusEnum::Valid,
+        "block {} wasn't committed: {:?}",
+        block.header.number,
+        status.status
+    );
+
+    let EngineApi::ForkChoiceUpdatedV3 { fork_choice_state, .. } = fcu else { unreachable!() };
+    let (res, rx) = oneshot::channel();
+    connections.send(EngineApi::ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes: None, res: Some(res) });
+    rx.blocking_recv()?;
+    Ok(())
+}
+
+/// Builds a block with the transactions of `block`.
+fn build(
+    args: &Args,
+    block: &BlockWithSenders<OpBlock>,
+    db: &FixtureDB,
+    connections: &mut SpineConnections<FixtureDB>,
+) -> eyre::Result<BlockReport> {
+    let (_, _, fcu) = EngineApi::messages_from_block(block, true, None);
+    let EngineApi::ForkChoiceUpdatedV3 { payload_attributes: Some(mut attributes), .. } = fcu else { unreachable!() };
+
+    let mut txs = attributes.transactions.take().unwrap_or_default();
+    let n_txs = txs.len();
+    let pool_txs = if args.txs_in_attributes {
+        vec![]
+    } else {
+        let n_deposits = block.body.transactions.iter().take_while(|tx| tx.is_deposit()).count();
+        txs.split_off(n_deposits)
+    };
+    attributes.transactions = Some(txs);
+
+    let fork_choice_state = ForkchoiceState { head_block_hash: block.header.parent_hash, ..Default::default() };
+    let (res, rx) = oneshot::channel();
+    // Drop the time of the roots calculated to commit the previous block.
+    db.take_root_time();
+    let start = Instant::now();
+    connections.send(EngineApi::ForkChoiceUpdatedV3 {
+        fork_choice_state,
+        payload_attributes: Some(attributes),
+        res: Some(res),
+    });
+    for tx in pool_txs {
+        connections.send(Arc::new(Transaction::decode(tx)?));
+    }
+    let payload_id = rx.blocking_recv()?.payload_id.ok_or_else(|| eyre::eyre!("no payload id"))?;
+
+    // Wait until all transactions are sealed in frags, or the block time is up
+    let mut frag_times = vec![];
+    let mut included_txs = 0;
+    let block_time = Duration::from_millis(args.block_time_ms);
+    while included_txs < n_txs && start.elapsed() < block_time {
+        connections.receive(|msg: VersionedMessage, _| {
+            if let VersionedMessage::FragV0(frag) = msg {
+                frag_times.push(start.elapsed());
+                included_txs += frag.txs.len();
+            }
+        });
+    }
+    let built = start.elapsed();
+
+    let get_payload = Instant::now();
+    let version = EngineApiVersion::for_timestamp(&args.chain, block.header.timestamp);
+    let payload = if version == EngineApiVersion::V4 {
+        let (res, rx) = oneshot::channel();
+        connections.send(EngineApi::GetPayloadV4 { payload_id, res });
+        envelope_v3(rx.blocking_recv()??)
+    } else {
+        let (res, rx) = oneshot::channel();
+        connections.send(EngineApi::GetPayloadV3 { payload_id, res });
+        rx.blocking_recv()??
+    };
+    let get_payload = get_payload.elapsed();
+
+    // The last frag is sealed by GetPayload
+    let mut sealed = false;
+    let deadline = Instant::now() + Duration::from_secs(1);
+    while !sealed && Instant::now() < deadline {
+        connections.receive(|msg: VersionedMessage, _| match msg {
+            VersionedMessage::FragV0(frag) => {
+                frag_times.push(start.elapsed());
+                included_txs += frag.txs.len();
+            }
+            VersionedMessage::SealV0(_) => sealed = true,
+            _ => {}
+        });
+    }
+
+    let state_root = db.take_root_time();
+
+    let payload = payload.execution_payload.payload_inner.payload_inner;
+    Ok(BlockReport {
+        number: block.header.number,
+        txs: n_txs,
+        included_txs,
+        gas_used: payload.gas_used,
+        mgas_per_sec: payload.gas_used as f64 / 1e6 / built.as_secs(),
+        frags: frag_times.len(),
+        frag_times_ms: frag_times.iter().map(Duration::as_millis).collect(),
+        get_payload_ms: get_payload.as_millis(),
+        state_root_ms: state_root.as_millis(),
+        hash_matches: payload.block_hash == block.hash_slow(),
+    })
+}
@@ -1,7 +1,7 @@
 use std::path::PathBuf;
 
 use alloy_provider::ProviderBuilder;
-use bop_db::init_database;
+use bop_db::{init_database, LAST_COMMITTED_BLOCK};
 use bop_sequencer::block_sync::fetch_blocks::fetch_block;
 use clap::Parser;
 use reqwest::Url;
@@ -10,6 +10,8 @@ use reth_db::{
     transaction::{DbTx, DbTxMut},
 };
 use reth_optimism_chainspec::BASE_SEPOLIA;
+use reth_provider::{StageCheckpointReader, StageCheckpointWriter};
+use reth_stages_types::StageCheckpoint;
 
 #[derive(Parser)]
 #[command(author, version, about, long_about = None)]
@@ -73,6 +75,13 @@ async fn main() -> eyre::Result<()> {
         }
     }
 
+    // Headers above the last committed block are treated as left over from an interrupted commit and removed on the
+    // next start, so advance the marker when inserting headers for a state that was populated externally.
+    let last_committed = tx.get_stage_checkpoint(LAST_COMMITTED_BLOCK)?.map_or(0, |checkpoint| checkpoint.block_number);
+    if args.end_block > last_committed {
+        tx.save_stage_checkpoint(LAST_COMMITTED_BLOCK, StageCheckpoint::new(args.end_block))?;
+    }
+
     tx.commit().map_err(|e| eyre::eyre!("Failed to commit transaction: {e}"))?;
 
     println!("Completed block insertion from {} to {}", args.start_block, args.end_block);
@@ -0,0 +1,177 @@
+use std::{
+    collections::{btree_map::Entry, BTreeMap},
+    future::IntoFuture,
+    path::PathBuf,
+};
+
+use alloy_eips::{eip2935::HISTORY_STORAGE_ADDRESS, eip4788::BEACON_ROOTS_ADDRESS, BlockId, BlockNumberOrTag};
+use alloy_primitives::{address, Address, Bytes, B256, U256};
+use alloy_provider::{
+    network::{primitives::HeaderResponse, BlockResponse},
+    Provider, ProviderBuilder,
+};
+use bop_db::{AccountDump, AccountProof, BlockFixture, FixtureBlock};
+use bop_sequencer::{
+    block_sync::{fetch_blocks::fetch_block, AlloyProvider},
+    header::L2_TO_L1_MESSAGE_PASSER,
+};
+use clap::Parser;
+use futures::future::{join_all, try_join_all};
+use reqwest::Url;
+use serde::Deserialize;
+
+/// Accounts that are changed outside of transactions, so the prestate tracer doesn't return them.
+const SYSTEM_ACCOUNTS: [Address; 8] = [
+    BEACON_ROOTS_ADDRESS,
+    HISTORY_STORAGE_ADDRESS,
+    // create2 deployer, set at Canyon
+    address!("13b0D85CcB8bf860b6b79AF3029fCA081AE9beF2"),
+    // sequencer, base fee, L1 fee and operator fee vaults
+    address!("4200000000000000000000000000000000000011"),
+    address!("4200000000000000000000000000000000000019"),
+    address!("420000000000000000000000000000000000001a"),
+    address!("420000000000000000000000000000000000001b"),
+    L2_TO_L1_MESSAGE_PASSER,
+];
+
+/// Number of blocks before the range whose hashes are exported, the ones `BLOCKHASH` can access.
+const BLOCK_HASHES: u64 = 256;
+
+/// Exports a block range and the state it accesses from an RPC, for the `bench` binary. The RPC needs to support
+/// `debug_traceBlockByNumber` with the `prestateTracer`, and `eth_getProof` at the block before the range.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// RPC URL
+    #[arg(short, long)]
+    rpc_url: Url,
+
+    /// First block, it's synced before the benchmark starts
+    #[arg(short, long)]
+    start_block: u64,
+
+    /// Last block
+    #[arg(short, long)]
+    end_block: u64,
+
+    /// Path to write the fixture to
+    #[arg(short, long)]
+    output: PathBuf,
+}
+
+/// Account in the result of the `prestateTracer`, fields that are zero or empty are left out.
+#[derive(Deserialize)]
+struct PrestateAccount {
+    #[serde(default)]
+    balance: U256,
+    #[serde(default)]
+    nonce: u64,
+    #[serde(default)]
+    code: Option<Bytes>,
+    #[serde(default)]
+    storage: BTreeMap<B256, U256>,
+}
+
+#[derive(Deserialize)]
+struct TxPrestate {
+    result: BTreeMap<Address, PrestateAccount>,
+}
+
+async fn fetch_account(provider: &AlloyProvider, address: Address, block: BlockId) -> eyre::Result<AccountDump> {
+    let (nonce, balance, code) = tokio::try_join!(
+        provider.get_transaction_count(address).block_id(block).into_future(),
+        provider.get_balance(address).block_id(block).into_future(),
+        provider.get_code_at(address).block_id(block).into_future()
+    )?;
+    Ok(AccountDump { address, nonce, balance, code_hash: None, code: Some(code), storage: Default::default() })
+}
+
+/// Proofs of the account and the slots in its dump, for the bench to compute state roots from.
+async fn fetch_proof(provider: &AlloyProvider, account: &AccountDump, block: BlockId) -> eyre::Result<AccountProof> {
+    let slots = account.storage.keys().copied().collect();
+    let proof = provider.get_proof(account.address, slots).block_id(block).into_future().await?;
+    Ok(AccountProof {
+        address: account.address,
+        proof: proof.account_proof,
+        storage_proofs: proof.storage_proof.into_iter().map(|slot| (slot.key.as_b256(), slot.proof)).collect(),
+    })
+}
+
+#[tokio::main]
+async fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+    eyre::ensure!(args.start_block > 0 && args.start_block <= args.end_block, "invalid block range");
+
+    let provider: AlloyProvider = ProviderBuilder::new().network().on_http(args.rpc_url);
+    let parent = BlockId::number(args.start_block - 1);
+
+    let blocks = join_all((args.start_block..=args.end_block).map(|number| fetch_block(number, &provider))).await;
+
+    // The state before the first block. An account or slot that is accessed for the first time in a later block wasn't
+    // changed before, so the first value the tracer returns is the one at the start of the range.
+    let mut accounts = BTreeMap::new();
+    for account in try_join_all(SYSTEM_ACCOUNTS.map(|address| fetch_account(&provider, address, parent))).await? {
+        accounts.insert(account.address, account);
+    }
+    for number in args.start_block..=args.end_block {
+        let traces: Vec<TxPrestate> = provider
+            .raw_request(
+                "debug_traceBlockByNumber".into(),
+                (BlockNumberOrTag::Number(number), serde_json::json!({ "tracer": "prestateTracer" })),
+            )
+            .await?;
+        for (address, account) in traces.into_iter().flat_map(|trace| trace.result) {
+            match accounts.entry(address) {
+                Entry::Vacant(entry) => {
+                    entry.insert(AccountDump {
+                        address,
+                        nonce: account.nonce,
+                        balance: account.balance,
+                        code_hash: None,
+                        code: account.code,
+                        storage: account.storage,
+                    });
+                }
+                Entry::Occupied(mut entry) => {
+                    for (slot, value) in account.storage {
+                        entry.get_mut().storage.entry(slot).or_insert(value);
+                    }
+                }
+            }
+        }
+    }
+
+    let hashes_from = args.start_block.saturating_sub(BLOCK_HASHES);
+    let hashes = try_join_all(
+        (hashes_from..args.start_block).map(|number| provider.get_block_by_number(number.into(), false.into())),
+    )
+    .await?;
+    let block_hashes = (hashes_from..args.start_block)
+        .zip(hashes)
+        .map(|(number, block)| {
+            let block = block.ok_or_else(|| eyre::eyre!("block {number} not found"))?;
+            Ok((number, block.header().hash()))
+        })
+        .collect::<eyre::Result<_>>()?;
+
+    let proofs = try_join_all(accounts.values().map(|account| fetch_proof(&provider, account, parent))).await?;
+
+    let fixture = BlockFixture {
+        blocks: blocks.iter().map(FixtureBlock::new).collect(),
+        accounts: accounts.into_values().collect(),
+        block_hashes,
+        proofs,
+    };
+    fixture.write(&args.output)?;
+
+    let slots: usize = fixture.accounts.iter().map(|account| account.storage.len()).sum();
+    println!(
+        "Exported blocks {} to {}, {} accounts and {} storage slots to {}",
+        args.start_block,
+        args.end_block,
+        fixture.accounts.len(),
+        slots,
+        args.output.display()
+    );
+    Ok(())
+}
@@ -0,0 +1,71 @@
+use std::{path::PathBuf, sync::Arc};
+
+use bop_common::p2p::VersionedMessage;
+use bop_db::init_database;
+use bop_sequencer::replay::{read_recording, Replay};
+use clap::Parser;
+use reqwest::Url;
+use reth_cli::chainspec::ChainSpecParser;
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_cli::chainspec::OpChainSpecParser;
+use reth_optimism_evm::OpEvmConfig;
+use tree_hash::TreeHash;
+
+/// Replays a recording made with `--sequencer.record_path`, and prints the frags and blocks it produced.
+#[derive(Parser)]
+#[command(author, version, about, long_about = None)]
+struct Args {
+    /// Path to the recording
+    #[arg(short, long)]
+    recording: PathBuf,
+
+    /// Path to a copy of the database of the recorded gateway, at the block the recording started on. Blocks that are
+    /// replayed are committed to it
+    #[arg(short, long)]
+    db_path: PathBuf,
+
+    /// Chain the recording was made on
+    #[arg(
+        long,
+        value_name = "CHAIN_OR_PATH",
+        default_value = OpChainSpecParser::SUPPORTED_CHAINS[6],
+        value_parser = OpChainSpecParser::parser(),
+    )]
+    chain: Arc<OpChainSpec>,
+
+    /// RPC URL for the sequencer config, not called during the replay
+    #[arg(long, default_value = "http://localhost:8545")]
+    rpc_url: Url,
+}
+
+fn main() -> eyre::Result<()> {
+    let args = Args::parse();
+
+    let db = init_database(&args.db_path, 0, 0, args.chain.clone())?;
+    let (header, records) = read_recording(&args.recording)?;
+    println!("Replaying from block {} {}", header.head_block_number, header.head_block_hash);
+
+    let config = header.config(OpEvmConfig::new(args.chain), args.rpc_url);
+    let output = Replay::new(db, &header, config)?.run(records)?;
+
+    for msg in &output.messages {
+        match msg {
+            VersionedMessage::FragV0(frag) => {
+                println!("Frag {}:{}, {} txs, {}", frag.block_number, frag.seq, frag.txs.len(), msg.tree_hash_root())
+            }
+            VersionedMessage::FragV1(frag) => {
+                println!("Frag {}:{}, {} txs, {}", frag.block_number, frag.seq, frag.txs.len(), msg.tree_hash_root())
+            }
+            VersionedMessage::SealV0(seal) => {
+                println!("Seal {}, {} frags, {}", seal.block_number, seal.total_frags, seal.block_hash)
+            }
+            VersionedMessage::EnvV0(env) => println!("Env {}", env.number),
+        }
+    }
+    for payload in &output.payloads {
+        let block = &payload.execution_payload.payload_inner.payload_inner;
+        println!("Payload {} {}", block.block_number, block.block_hash);
+    }
+
+    Ok(())
+}
@@ -1,13 +1,25 @@
-use alloy_provider::{Provider, ProviderBuilder};
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+use alloy_primitives::B256;
 use bop_common::{
     actor::Actor,
-    communication::{messages::BlockFetch, SpineConnections},
+    communication::{messages::BlockFetch, SpineConnections, TrackedSenders},
     db::DatabaseRead,
+    metrics::UPSTREAM_HEAD_BLOCK,
+    time::{Duration, Repeater},
 };
+use metrics::gauge;
 use reqwest::Url;
 use tokio::runtime::Runtime;
+use tracing::{error, info, warn};
 
-use super::{fetch_blocks::async_fetch_blocks_and_send_sequentially, AlloyProvider};
+use super::{
+    fetch_blocks::FetchError,
+    providers::{follow_new_heads, BlockProviders},
+};
 
 #[derive(Debug)]
 pub struct BlockFetcher {
@@ -15,19 +27,44 @@ pub struct BlockFetcher {
     next_block: u64,
     sync_until: u64,
     batch_size: u64,
-    provider: AlloyProvider,
+    providers: BlockProviders,
+    /// Number and hash of the last block sent, the next fetched range has to link to it.
+    last_sent: Option<(u64, B256)>,
+    /// Highest block number reported by the `newHeads` subscription or by polling the providers.
+    remote_head: Arc<AtomicU64>,
+    /// Polls the providers for the head block, only used when no websocket url is configured.
+    head_poll: Option<Repeater>,
 }
 impl BlockFetcher {
-    pub fn new(rpc_url: Url, db_block: u64) -> Self {
-        let executor = tokio::runtime::Builder::new_current_thread()
+    /// Starts fetching after `db_head`, the number and hash of the head block of the database. The first range is
+    /// checked to link to it, unless the hash is unknown (zero).
+    pub fn new(providers: BlockProviders, ws_url: Option<Url>, db_head: (u64, B256)) -> Self {
+        // Multi threaded so the head subscription keeps running in between fetches.
+        let executor = tokio::runtime::Builder::new_multi_thread()
             .worker_threads(1)
             .enable_all()
             .build()
             .expect("couldn't build local tokio runtime");
 
-        let provider = ProviderBuilder::new().network().on_http(rpc_url);
+        let remote_head = Arc::new(AtomicU64::new(0));
+        let head_poll = match ws_url {
+            Some(ws_url) => {
+                executor.spawn(follow_new_heads(ws_url, remote_head.clone()));
+                None
+            }
+            None => Some(Repeater::every(Duration::from_secs(1))),
+        };
 
-        Self { executor, next_block: db_block + 1, sync_until: db_block + 1, batch_size: 20, provider }
+        Self {
+            executor,
+            next_block: db_head.0 + 1,
+            sync_until: db_head.0,
+            batch_size: 20,
+            providers,
+            last_sent: (db_head.1 != B256::ZERO).then_some(db_head),
+            remote_head,
+            head_poll,
+        }
     }
 
     pub fn handle_fetch(&mut self, msg: BlockFetch) {
@@ -38,27 +75,63 @@ impl BlockFetcher {
             }
         }
     }
+
+    /// Hash of the block before [`Self::next_block`], if it is the last one sent.
+    fn parent_hash(&self) -> Option<B256> {
+        self.last_sent.filter(|(number, _)| number + 1 == self.next_block).map(|(_, hash)| hash)
+    }
+
+    fn on_fetch_error(&mut self, err: FetchError, stop: u64) {
+        match err {
+            // The last block sent was reorged out, fetch it again. The block sync rolls back to where the new block
+            // links.
+            FetchError::BrokenLink { number, .. } if number == self.next_block => {
+                warn!(%err, "fetched range doesn't link to the last block sent, refetching it");
+                self.next_block = self.next_block.saturating_sub(1).max(1);
+                self.last_sent = None;
+            }
+            // The range is retried on the next loop, retries within the range are already backed off.
+            err => warn!(%err, start = self.next_block, last = stop, "failed to fetch blocks"),
+        }
+    }
+
+    fn poll_head(&mut self) {
+        match self.executor.block_on(self.providers.block_number()) {
+            Ok(head) => {
+                self.remote_head.fetch_max(head, Ordering::Relaxed);
+            }
+            Err(err) => error!(%err, "failed to fetch head block number"),
+        }
+    }
 }
 
 impl<Db: DatabaseRead> Actor<Db> for BlockFetcher {
     fn on_init(&mut self, _connections: &mut SpineConnections<Db>) {
-        let head_block_number = self.executor.block_on(async {
-            self.provider.get_block_number().await.expect("failed to fetch last block, is the RPC url correct?")
-        });
-
-        self.sync_until = head_block_number;
+        self.poll_head();
+        info!(head = self.remote_head.load(Ordering::Relaxed), next_block = self.next_block, "starting block fetch");
     }
 
     fn loop_body(&mut self, connections: &mut SpineConnections<Db>) {
-        if self.next_block < self.sync_until {
+        if self.head_poll.as_mut().is_some_and(|poll| poll.fired()) {
+            self.poll_head();
+        }
+        let remote_head = self.remote_head.load(Ordering::Relaxed);
+        gauge!(UPSTREAM_HEAD_BLOCK).set(remote_head as f64);
+        self.sync_until = self.sync_until.max(remote_head);
+
+        if self.next_block <= self.sync_until {
             let stop = (self.next_block + self.batch_size).min(self.sync_until);
-            self.executor.block_on(async_fetch_blocks_and_send_sequentially(
-                self.next_block,
-                stop,
-                connections.senders(),
-                &self.provider,
-            ));
-            self.next_block = stop + 1;
+            match self.executor.block_on(self.providers.blocks(self.next_block, stop, self.parent_hash())) {
+                Ok(blocks) => {
+                    self.last_sent = blocks.last().map(|block| (block.header.number, block.header.hash_slow()));
+                    for block in blocks {
+                        connections.senders().send_forever(block);
+                    }
+                    info!(start = self.next_block, last = stop, "fetched blocks");
+                    self.next_block = stop + 1;
+                }
+                Err(err) => self.on_fetch_error(err, stop),
+            }
         }
 
         connections.receive(|msg, _| {
@@ -66,3 +139,28 @@ impl<Db: DatabaseRead> Actor<Db> for BlockFetcher {
         });
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn refetches_on_broken_link_between_ranges() {
+        let providers = BlockProviders::new(["http://127.0.0.1:1".parse().unwrap()], 1);
+        let mut fetcher = BlockFetcher::new(providers, None, (10, B256::with_last_byte(10)));
+        assert_eq!(fetcher.parent_hash(), Some(B256::with_last_byte(10)));
+
+        // The first block of the range doesn't link to the last block sent
+        let err = FetchError::BrokenLink { number: 11, parent_hash: B256::with_last_byte(1), expected: B256::ZERO };
+        fetcher.on_fetch_error(err, 20);
+        assert_eq!(fetcher.next_block, 10);
+        assert_eq!(fetcher.parent_hash(), None);
+
+        // A reorg within the range is retried as is
+        fetcher.last_sent = Some((9, B256::with_last_byte(9)));
+        let err = FetchError::BrokenLink { number: 15, parent_hash: B256::with_last_byte(1), expected: B256::ZERO };
+        fetcher.on_fetch_error(err, 20);
+        assert_eq!(fetcher.next_block, 10);
+        assert_eq!(fetcher.parent_hash(), Some(B256::with_last_byte(9)));
+    }
+}
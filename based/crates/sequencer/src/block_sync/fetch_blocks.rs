@@ -1,14 +1,17 @@
 use std::time::Duration;
 
 use alloy_consensus::Block;
+use alloy_primitives::B256;
 use alloy_provider::Provider;
 use alloy_rpc_types::Block as RpcBlock;
+use alloy_transport::TransportError;
 use bop_common::communication::{messages::BlockSyncMessage, SendersSpine, TrackedSenders};
 use bop_db::DatabaseRead;
 use futures::future::join_all;
 use reth_optimism_primitives::{OpBlock, OpTransactionSigned};
 use reth_primitives::BlockWithSenders;
 use reth_primitives_traits::SignedTransaction;
+use thiserror::Error;
 use tracing::{info, warn};
 
 use super::AlloyProvider;
@@ -38,23 +41,34 @@ pub async fn async_fetch_blocks_and_send_sequentially<Db: DatabaseRead>(
     info!(start = curr_block, last = end_block, "fetched blocks");
 }
 
-pub async fn fetch_block(block_number: u64, client: &AlloyProvider) -> BlockSyncMessage {
-    const BACKOFF_MAX: Duration = Duration::from_secs(1);
-    const BACKOFF_STEP: Duration = Duration::from_millis(10);
+pub const BACKOFF_MAX: Duration = Duration::from_secs(1);
+pub const BACKOFF_STEP: Duration = Duration::from_millis(10);
+
+#[derive(Debug, Error)]
+pub enum FetchError {
+    #[error("RPC error: {0}")]
+    Rpc(#[from] TransportError),
+    #[error("block {0} not found")]
+    NotFound(u64),
+    #[error("requested block {requested}, got block {got}")]
+    WrongNumber { requested: u64, got: u64 },
+    #[error("header hash mismatch for block {number}. Reported: {reported}, computed: {computed}")]
+    HashMismatch { number: u64, reported: B256, computed: B256 },
+    #[error("block {number} doesn't link to its parent. Parent hash: {parent_hash}, previous block hash: {expected}")]
+    BrokenLink { number: u64, parent_hash: B256, expected: B256 },
+    #[error("no provider returned {what} after {attempts} attempts: {last}")]
+    Exhausted { what: String, attempts: usize, last: Box<FetchError> },
+}
 
+/// Fetches a block, retrying until a valid block is returned. Used by tooling and tests, the gateway fetches
+/// through [`BlockProviders`](super::providers::BlockProviders) which bounds the retries.
+pub async fn fetch_block(block_number: u64, client: &AlloyProvider) -> BlockSyncMessage {
     let mut backoff = BACKOFF_STEP;
 
     loop {
-        match client.get_block_by_number(block_number.into(), true.into()).await {
-            Ok(Some(block)) => return convert_block(block),
-
-            Ok(None) => {
-                warn!(?backoff, block_number, "block not found");
-            }
-
-            Err(err) => {
-                warn!(?err, ?backoff, block_number, "failed fetching");
-            }
+        match try_fetch_block(block_number, client).await {
+            Ok(block) => return block,
+            Err(err) => warn!(%err, ?backoff, block_number, "failed fetching"),
         }
 
         tokio::time::sleep(backoff).await;
@@ -62,6 +76,48 @@ pub async fn fetch_block(block_number: u64, client: &AlloyProvider) -> BlockSync
     }
 }
 
+/// Fetches a block once, checking that it is the requested block and that the reported hash matches the header.
+pub async fn try_fetch_block(block_number: u64, client: &AlloyProvider) -> Result<BlockSyncMessage, FetchError> {
+    let block = client
+        .get_block_by_number(block_number.into(), true.into())
+        .await?
+        .ok_or(FetchError::NotFound(block_number))?;
+    validate_header(block_number, &block)?;
+    Ok(convert_block(block))
+}
+
+/// Checks that an RPC block is the requested one and that its reported hash is the hash of its header, so a faulty
+/// or malicious RPC can't make us commit a header under a different hash.
+pub fn validate_header<T>(block_number: u64, block: &RpcBlock<T>) -> Result<(), FetchError> {
+    if block.header.inner.number != block_number {
+        return Err(FetchError::WrongNumber { requested: block_number, got: block.header.inner.number });
+    }
+
+    let computed = block.header.inner.hash_slow();
+    if computed != block.header.hash {
+        return Err(FetchError::HashMismatch { number: block_number, reported: block.header.hash, computed });
+    }
+
+    Ok(())
+}
+
+/// Checks that consecutive blocks link through their parent hashes, and that the first block links to `parent_hash`,
+/// the hash of the block before the range if it is known.
+pub fn validate_linkage(parent_hash: Option<B256>, blocks: &[BlockSyncMessage]) -> Result<(), FetchError> {
+    let mut expected = parent_hash;
+    for block in blocks {
+        if let Some(expected) = expected.filter(|expected| *expected != block.header.parent_hash) {
+            return Err(FetchError::BrokenLink {
+                number: block.header.number,
+                parent_hash: block.header.parent_hash,
+                expected,
+            });
+        }
+        expected = Some(block.header.hash_slow());
+    }
+    Ok(())
+}
+
 /// Converts an RPC block with OpTxEnvelope transactions to a consensus block with OpTransactionSigned
 pub fn convert_block(block: RpcBlock<op_alloy_rpc_types::Transaction>) -> BlockWithSenders<OpBlock> {
     // First convert the block to consensus format
@@ -103,13 +159,50 @@ pub const TEST_BASE_SEPOLIA_RPC_URL: &str = "https://base-sepolia-rpc.publicnode
 
 #[cfg(test)]
 mod tests {
+    use alloy_consensus::Header;
     use alloy_primitives::b256;
     use alloy_provider::ProviderBuilder;
+    use alloy_rpc_types::BlockTransactions;
     use bop_common::communication::Spine;
     use bop_db::AlloyDB;
 
     use super::*;
 
+    fn test_block(number: u64, parent_hash: B256) -> BlockSyncMessage {
+        let header = Header { number, parent_hash, ..Default::default() };
+        BlockWithSenders::new_unchecked(Block { header, body: Default::default() }, vec![])
+    }
+
+    #[test]
+    fn validates_header_hash() {
+        let inner = Header { number: 7, ..Default::default() };
+        let header = alloy_rpc_types::Header { hash: inner.hash_slow(), inner, total_difficulty: None, size: None };
+        let mut block: RpcBlock<()> =
+            RpcBlock { header, uncles: vec![], transactions: BlockTransactions::Hashes(vec![]), withdrawals: None };
+        assert!(validate_header(7, &block).is_ok());
+        assert!(matches!(validate_header(8, &block), Err(FetchError::WrongNumber { requested: 8, got: 7 })));
+
+        block.header.hash = B256::with_last_byte(1);
+        assert!(matches!(validate_header(7, &block), Err(FetchError::HashMismatch { number: 7, .. })));
+    }
+
+    #[test]
+    fn validates_linkage() {
+        let first = test_block(1, B256::ZERO);
+        let second = test_block(2, first.header.hash_slow());
+        let third = test_block(3, second.header.hash_slow());
+        assert!(validate_linkage(None, &[first.clone(), second.clone(), third.clone()]).is_ok());
+
+        let orphan = test_block(3, B256::with_last_byte(1));
+        let err = validate_linkage(None, &[first.clone(), second.clone(), orphan.clone()]).unwrap_err();
+        assert!(matches!(err, FetchError::BrokenLink { number: 3, .. }));
+
+        // Batches link to the last block of the previous one
+        assert!(validate_linkage(Some(second.header.hash_slow()), &[third]).is_ok());
+        let err = validate_linkage(Some(second.header.hash_slow()), &[orphan]).unwrap_err();
+        assert!(matches!(err, FetchError::BrokenLink { number: 3, .. }));
+    }
+
     #[ignore = "Requires RPC call"]
     #[tokio::test]
     async fn test_single_block_fetch() {
@@ -3,7 +3,7 @@ use std::sync::Arc;
 use alloy_consensus::{BlockHeader, TxEip1559};
 use alloy_eips::eip2718::Encodable2718;
 use alloy_provider::{Provider, ProviderBuilder};
-use alloy_rpc_types::engine::{ForkchoiceState, PayloadId};
+use alloy_rpc_types::engine::ForkchoiceState;
 use bop_common::{
     actor::Actor,
     communication::{
@@ -129,6 +129,7 @@ impl<Db> MockFetcher<Db> {
                 .as_mut()
                 .map(|t| t.split_off(0).into_iter().map(|tx| Arc::new(Transaction::decode(tx).unwrap())).collect())
                 .unwrap_or_default();
+            let payload_id = fcu.payload_id().expect("fcu has attributes");
             connections.send(fcu);
             for t in txs_for_pool {
                 connections.send(t);
@@ -137,12 +138,17 @@ impl<Db> MockFetcher<Db> {
 
             Duration::from_millis(2000).sleep();
             let (block_tx, mut block_rx) = oneshot::channel();
-            connections.send(EngineApi::GetPayloadV3 { payload_id: PayloadId::new([0; 8]), res: block_tx });
+            connections.send(EngineApi::GetPayloadV3 { payload_id, res: block_tx });
             Duration::from_millis(100).sleep();
             let curt = Instant::now();
             let mut sealed_block = loop {
-                if let Ok(sealed_block) = block_rx.try_recv() {
-                    break sealed_block;
+                match block_rx.try_recv() {
+                    Ok(Ok(sealed_block)) => break sealed_block,
+                    Ok(Err(err)) => {
+                        tracing::warn!(%err, "coun't get block");
+                        return;
+                    }
+                    Err(_) => {}
                 }
                 if curt.elapsed() > Duration::from_secs(2) {
                     tracing::warn!("coun't get block");
@@ -205,8 +211,12 @@ impl<Db> MockFetcher<Db> {
         };
 
         info!("gas limit is {}", attributes.gas_limit.unwrap());
-        let fcu =
-            EngineApi::ForkChoiceUpdatedV3 { fork_choice_state: *fcu, payload_attributes: Some(attributes.clone()) };
+        let fcu = EngineApi::ForkChoiceUpdatedV3 {
+            fork_choice_state: *fcu,
+            payload_attributes: Some(attributes.clone()),
+            res: None,
+        };
+        let payload_id = fcu.payload_id().expect("fcu has attributes");
 
         info!("sending {} txs", txs.len());
         // first we send enough for the first frag
@@ -244,9 +254,9 @@ impl<Db> MockFetcher<Db> {
 
         while curt.elapsed() < *get_payload_delay {}
         let (block_tx, block_rx) = oneshot::channel();
-        connections.send(EngineApi::GetPayloadV3 { payload_id: PayloadId::new([0; 8]), res: block_tx });
+        connections.send(EngineApi::GetPayloadV3 { payload_id, res: block_tx });
 
-        let Ok(sealed_block) = block_rx.blocking_recv() else {
+        let Ok(Ok(sealed_block)) = block_rx.blocking_recv() else {
             warn!("issue getting block");
             return;
         };
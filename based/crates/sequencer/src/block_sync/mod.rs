@@ -1,10 +1,13 @@
 use std::{fmt::Display, sync::Arc};
 
+use alloy_primitives::B256;
 use bop_common::{
     communication::messages::BlockSyncError,
     db::{DatabaseRead, DatabaseWrite},
+    metrics::STATE_ROOT_DURATION,
     time::BlockSyncTimers,
 };
+use metrics::histogram;
 use reth_consensus::ConsensusError;
 use reth_evm::execute::{
     BlockExecutionError, BlockExecutionOutput, BlockExecutionStrategy, BlockExecutionStrategyFactory, ExecuteOutput,
@@ -12,15 +15,19 @@ use reth_evm::execute::{
 };
 use reth_optimism_chainspec::OpChainSpec;
 use reth_optimism_evm::OpExecutionStrategyFactory;
+use reth_optimism_forks::OpHardforks;
 use reth_optimism_primitives::{OpBlock, OpReceipt};
 use reth_primitives::{BlockWithSenders, GotExpected};
 use reth_trie_common::updates::TrieUpdates;
 use revm::Database;
 use tracing::{info, warn};
 
+use crate::header::L2_TO_L1_MESSAGE_PASSER;
+
 pub mod block_fetcher;
 pub mod fetch_blocks;
 pub mod mock_fetcher;
+pub mod providers;
 
 pub type AlloyProvider =
     alloy_provider::RootProvider<alloy_transport_http::Http<reqwest::Client>, op_alloy_network::Optimism>;
@@ -32,6 +39,9 @@ pub struct BlockSync {
     /// Blocks that we have received from the provider but require a prior block to be applied before this can be.
     /// Sorted list in reverse order by block number.
     pending_blocks: Vec<BlockWithSenders<OpBlock>>,
+    /// Execution of the last block completed by [`Self::complete_payload_header`], keyed by the completed hash, reused
+    /// when that block is committed next.
+    executed: Option<(B256, BlockExecutionOutput<OpReceipt>, TrieUpdates)>,
     timers: BlockSyncTimers,
 }
 
@@ -39,7 +49,7 @@ impl BlockSync {
     /// Creates a new BlockSync instance with the given chain specification and RPC endpoint
     pub fn new(chain_spec: Arc<OpChainSpec>) -> Self {
         let execution_factory = OpExecutionStrategyFactory::optimism(chain_spec.clone());
-        Self { chain_spec, execution_factory, pending_blocks: vec![], timers: Default::default() }
+        Self { chain_spec, execution_factory, pending_blocks: vec![], executed: None, timers: Default::default() }
     }
 
     /// Returns block numbers to fetch, start to end. This will be used in the case of a reorg.
@@ -153,7 +163,10 @@ impl BlockSync {
         DB: DatabaseWrite + DatabaseRead + Database<Error: Into<ProviderError> + Display>,
     {
         self.timers.execution.start();
-        let (execution_output, trie_updates) = self.execute(block, db)?;
+        let (execution_output, trie_updates) = match self.executed.take() {
+            Some((hash, output, trie_updates)) if hash == block.header.hash_slow() => (output, trie_updates),
+            _ => self.execute(block, db)?,
+        };
         self.timers.execution.stop();
         if commit {
             self.timers.db_commit.start();
@@ -163,13 +176,58 @@ impl BlockSync {
         Ok(())
     }
 
-    /// Executes a block and validates its state root and receipts.
+    /// Completes the header of a block built from an engine API payload, which has to be on top of the head. From
+    /// Isthmus on the withdrawals root is the storage root of the [`L2_TO_L1_MESSAGE_PASSER`] after the block, which
+    /// payloads don't carry, so the block is executed to compute it. The execution is reused if the block is committed
+    /// next.
+    pub fn complete_payload_header<DB>(
+        &mut self,
+        block: &mut BlockWithSenders<OpBlock>,
+        db: &DB,
+    ) -> Result<(), BlockExecutionError>
+    where
+        DB: DatabaseRead + Database<Error: Into<ProviderError> + Display>,
+    {
+        self.executed = None;
+        let (output, trie_updates, withdrawals_root) = self.execute_unchecked_withdrawals(block, db)?;
+        if let Some(withdrawals_root) = withdrawals_root {
+            block.block.header.withdrawals_root = Some(withdrawals_root);
+            self.executed = Some((block.header.hash_slow(), output, trie_updates));
+        }
+        Ok(())
+    }
+
+    /// Executes a block and validates its state root, receipts and, from Isthmus on, its withdrawals root.
     /// Returns the execution output containing state changes, receipts, and gas usage.
     pub fn execute<DB>(
         &mut self,
         block: &BlockWithSenders<OpBlock>,
         db: &DB,
     ) -> Result<(BlockExecutionOutput<OpReceipt>, TrieUpdates), BlockExecutionError>
+    where
+        DB: DatabaseRead + Database<Error: Into<ProviderError> + Display>,
+    {
+        let (output, trie_updates, withdrawals_root) = self.execute_unchecked_withdrawals(block, db)?;
+
+        if let Some(withdrawals_root) = withdrawals_root {
+            let expected = block.header.withdrawals_root.ok_or(ConsensusError::WithdrawalsRootMissing)?;
+            if withdrawals_root != expected {
+                return Err(BlockExecutionError::Consensus(ConsensusError::BodyWithdrawalsRootDiff(
+                    GotExpected::new(withdrawals_root, expected).into(),
+                )));
+            }
+        }
+
+        Ok((output, trie_updates))
+    }
+
+    /// Executes a block and validates its state root and receipts. From Isthmus on also returns the withdrawals root
+    /// the header has to commit to.
+    fn execute_unchecked_withdrawals<DB>(
+        &mut self,
+        block: &BlockWithSenders<OpBlock>,
+        db: &DB,
+    ) -> Result<(BlockExecutionOutput<OpReceipt>, TrieUpdates, Option<B256>), BlockExecutionError>
     where
         DB: DatabaseRead + Database<Error: Into<ProviderError> + Display>,
     {
@@ -207,8 +265,19 @@ impl BlockSync {
             Ok(trie_updates)
         })?;
         self.timers.state_root.stop();
+        histogram!(STATE_ROOT_DURATION, "stage" => "sync").record(self.timers.state_root.elapsed().as_secs());
+
+        // From Isthmus the withdrawals root commits to the message passer storage
+        let withdrawals_root = if self.chain_spec.is_isthmus_active_at_timestamp(block.header.timestamp) {
+            let root = db
+                .calculate_storage_root(L2_TO_L1_MESSAGE_PASSER, &state)
+                .map_err(|e| BlockExecutionError::Internal(InternalBlockExecutionError::Other(e.into())))?;
+            Some(root)
+        } else {
+            None
+        };
 
-        Ok((BlockExecutionOutput { state, receipts, requests, gas_used }, trie_updates))
+        Ok((BlockExecutionOutput { state, receipts, requests, gas_used }, trie_updates, withdrawals_root))
     }
 
     fn insert_pending_block(&mut self, block: &BlockWithSenders<OpBlock>) {
@@ -0,0 +1,187 @@
+use std::{
+    future::Future,
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use alloy_primitives::B256;
+use alloy_provider::{Provider, ProviderBuilder, WsConnect};
+use bop_common::communication::messages::BlockSyncMessage;
+use futures::future::join_all;
+use op_alloy_network::Optimism;
+use reqwest::Url;
+use tracing::{error, info, warn};
+
+use super::{
+    fetch_blocks::{try_fetch_block, validate_linkage, FetchError, BACKOFF_MAX, BACKOFF_STEP},
+    AlloyProvider,
+};
+
+/// Consecutive failures after which a provider is skipped while healthier ones are available.
+const UNHEALTHY_AFTER_FAILURES: u64 = 3;
+
+#[derive(Debug)]
+struct Upstream {
+    url: Url,
+    provider: AlloyProvider,
+    consecutive_failures: AtomicU64,
+}
+
+impl Upstream {
+    fn is_healthy(&self) -> bool {
+        self.consecutive_failures.load(Ordering::Relaxed) < UNHEALTHY_AFTER_FAILURES
+    }
+}
+
+/// Set of full nodes that blocks are fetched from.
+///
+/// Requests go round robin over the providers, skipping those that failed repeatedly unless all of them did. Failed
+/// requests are retried on the next provider with exponential backoff, up to `max_attempts` in total.
+#[derive(Debug)]
+pub struct BlockProviders {
+    upstreams: Vec<Upstream>,
+    next: AtomicUsize,
+    max_attempts: usize,
+}
+
+impl BlockProviders {
+    pub fn new(urls: impl IntoIterator<Item = Url>, max_attempts: usize) -> Self {
+        let upstreams: Vec<_> = urls
+            .into_iter()
+            .map(|url| Upstream {
+                provider: ProviderBuilder::new().network().on_http(url.clone()),
+                url,
+                consecutive_failures: AtomicU64::new(0),
+            })
+            .collect();
+        assert!(!upstreams.is_empty(), "at least one block provider is required");
+
+        Self { upstreams, next: AtomicUsize::new(0), max_attempts: max_attempts.max(1) }
+    }
+
+    /// Returns the latest block number.
+    pub async fn block_number(&self) -> Result<u64, FetchError> {
+        self.with_failover("head block number", |provider| async move { Ok(provider.get_block_number().await?) }).await
+    }
+
+    /// Fetches a single validated block.
+    pub async fn block(&self, block_number: u64) -> Result<BlockSyncMessage, FetchError> {
+        self.with_failover(&format!("block {block_number}"), |provider| try_fetch_block(block_number, provider)).await
+    }
+
+    /// Fetches blocks `start..=end` concurrently. Blocks are returned in order and are checked to form a chain on top of
+    /// `parent_hash` if given, so a reorg in the middle of the range or since the previous range, or providers on
+    /// different forks, fail the whole range.
+    pub async fn blocks(
+        &self,
+        start: u64,
+        end: u64,
+        parent_hash: Option<B256>,
+    ) -> Result<Vec<BlockSyncMessage>, FetchError> {
+        let blocks = join_all((start..=end).map(|number| self.block(number))).await;
+        let blocks = blocks.into_iter().collect::<Result<Vec<_>, _>>()?;
+        validate_linkage(parent_hash, &blocks)?;
+        Ok(blocks)
+    }
+
+    fn next_upstream(&self) -> &Upstream {
+        let start = self.next.fetch_add(1, Ordering::Relaxed);
+        let n = self.upstreams.len();
+        (0..n)
+            .map(|i| &self.upstreams[(start + i) % n])
+            .find(|upstream| upstream.is_healthy())
+            .unwrap_or(&self.upstreams[start % n])
+    }
+
+    async fn with_failover<'a, T, F, Fut>(&'a self, what: &str, f: F) -> Result<T, FetchError>
+    where
+        F: Fn(&'a AlloyProvider) -> Fut,
+        Fut: Future<Output = Result<T, FetchError>> + 'a,
+    {
+        let mut backoff = BACKOFF_STEP;
+        let mut attempt = 0;
+
+        loop {
+            let upstream = self.next_upstream();
+            let err = match f(&upstream.provider).await {
+                Ok(value) => {
+                    upstream.consecutive_failures.store(0, Ordering::Relaxed);
+                    return Ok(value);
+                }
+                Err(err) => err,
+            };
+
+            let failures = upstream.consecutive_failures.fetch_add(1, Ordering::Relaxed) + 1;
+            if failures == UNHEALTHY_AFTER_FAILURES {
+                error!(url = %upstream.url, %err, "block provider marked unhealthy");
+            }
+
+            attempt += 1;
+            if attempt >= self.max_attempts {
+                error!(%err, attempts = attempt, what, "all block provider attempts failed");
+                return Err(FetchError::Exhausted { what: what.to_string(), attempts: attempt, last: Box::new(err) });
+            }
+
+            warn!(url = %upstream.url, %err, ?backoff, what, "fetch failed, retrying");
+            tokio::time::sleep(backoff).await;
+            backoff = std::cmp::min(backoff * 2, BACKOFF_MAX);
+        }
+    }
+}
+
+/// Follows `newHeads` on a websocket endpoint and stores the highest block number seen in `head`. Reconnects with
+/// backoff when the connection or subscription drops.
+pub async fn follow_new_heads(ws_url: Url, head: Arc<AtomicU64>) {
+    const RECONNECT_MAX: Duration = Duration::from_secs(30);
+
+    let mut backoff = Duration::from_millis(100);
+
+    loop {
+        match ProviderBuilder::new().network::<Optimism>().on_ws(WsConnect::new(ws_url.clone())).await {
+            Ok(provider) => match provider.subscribe_blocks().await {
+                Ok(mut subscription) => {
+                    info!(%ws_url, "subscribed to new heads");
+                    backoff = Duration::from_millis(100);
+
+                    while let Ok(header) = subscription.recv().await {
+                        head.fetch_max(header.inner.number, Ordering::Relaxed);
+                    }
+                    warn!(%ws_url, "new heads subscription closed");
+                }
+                Err(err) => warn!(%ws_url, %err, "failed to subscribe to new heads"),
+            },
+            Err(err) => warn!(%ws_url, %err, "failed to connect to websocket"),
+        }
+
+        tokio::time::sleep(backoff).await;
+        backoff = std::cmp::min(backoff * 2, RECONNECT_MAX);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn skips_unhealthy_providers() {
+        let urls = ["http://127.0.0.1:1", "http://127.0.0.1:2", "http://127.0.0.1:3"].map(|url| url.parse().unwrap());
+        let providers = BlockProviders::new(urls, 3);
+
+        let picked: Vec<_> = (0..3).map(|_| providers.next_upstream().url.port()).collect();
+        assert_eq!(picked, vec![Some(1), Some(2), Some(3)]);
+
+        providers.upstreams[1].consecutive_failures.store(UNHEALTHY_AFTER_FAILURES, Ordering::Relaxed);
+        let picked: Vec<_> = (0..3).map(|_| providers.next_upstream().url.port()).collect();
+        assert_eq!(picked, vec![Some(1), Some(3), Some(3)]);
+
+        // With every provider unhealthy we keep rotating rather than stalling.
+        for upstream in &providers.upstreams {
+            upstream.consecutive_failures.store(UNHEALTHY_AFTER_FAILURES, Ordering::Relaxed);
+        }
+        let picked: Vec<_> = (0..3).map(|_| providers.next_upstream().url.port()).collect();
+        assert_eq!(picked, vec![Some(1), Some(2), Some(3)]);
+    }
+}
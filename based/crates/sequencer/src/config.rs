@@ -1,3 +1,5 @@
+use std::path::PathBuf;
+
 use bop_common::{config::GatewayArgs, time::Duration};
 use reqwest::Url;
 use reth_optimism_evm::OpEvmConfig;
@@ -12,6 +14,10 @@ pub struct SequencerConfig {
     pub simulate_tof_in_pools: bool,
     /// If true will commit locally sequenced blocks to the db before getting payload from the engine api.
     pub commit_sealed_frags_to_db: bool,
+    /// If true, frags are gossiped as `FragV1`, with commitments to the state after each frag.
+    pub gossip_frag_v1: bool,
+    /// If set, all inputs of the sequencer are recorded to this file, see [`crate::replay`].
+    pub record_path: Option<PathBuf>,
 }
 
 impl From<&GatewayArgs> for SequencerConfig {
@@ -23,6 +29,8 @@ impl From<&GatewayArgs> for SequencerConfig {
             simulate_tof_in_pools: false,
             evm_config: OpEvmConfig::new(args.chain.clone()),
             commit_sealed_frags_to_db: args.commit_sealed_frags_to_db,
+            gossip_frag_v1: args.gossip_frag_v1,
+            record_path: args.sequencer_record_path.clone(),
         }
     }
 }
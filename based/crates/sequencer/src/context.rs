@@ -3,20 +3,23 @@ use std::{collections::VecDeque, fmt::Display, sync::Arc};
 use alloy_consensus::{Header, EMPTY_OMMER_ROOT_HASH};
 use alloy_eips::merge::BEACON_NONCE;
 use alloy_rpc_types::engine::{
-    BlobsBundleV1, ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3, ForkchoiceState,
+    BlobsBundleV1, ExecutionPayloadV1, ExecutionPayloadV2, ExecutionPayloadV3, ForkchoiceState, PayloadId,
 };
 use bop_common::{
     communication::{
-        messages::{BlockSyncMessage, EvmBlockParams},
+        messages::{BlockSyncError, BlockSyncMessage, EvmBlockParams, SequencerToSimulator},
         SendersSpine, TrackedSenders,
     },
-    p2p::{FragV0, SealV0},
+    metrics::{DB_HEAD_BLOCK, FRAGS_PER_BLOCK, STATE_ROOT_DURATION},
+    p2p::{FragV0, FragV1, SealV0, VersionedMessage},
     shared::SharedState,
-    time::Timer,
+    time::{Instant, Timer},
     transaction::Transaction,
 };
 use bop_db::{DatabaseRead, DatabaseWrite};
 use bop_pool::transaction::pool::TxPool;
+use metrics::{gauge, histogram};
+use op_alloy_consensus::OpTxEnvelope;
 use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpPayloadAttributes};
 use reth_evm::{
     env::EvmEnv, execute::ProviderError, system_calls::SystemCaller, ConfigureEvmEnv, NextBlockEnvAttributes,
@@ -25,10 +28,19 @@ use reth_optimism_chainspec::OpChainSpec;
 use reth_optimism_evm::OpEvmConfig;
 use reth_optimism_forks::{OpHardfork, OpHardforks};
 use revm::{Database, DatabaseRef};
-use revm_primitives::{b256, BlockEnv, Bytes, EnvWithHandlerCfg, B256, U256};
-use tracing::info;
+use revm_primitives::{BlockEnv, Bytes, EnvWithHandlerCfg, B256, U256};
+use tracing::{info, warn};
+
+use crate::{
+    block_sync::BlockSync,
+    header::{ForkFields, L2_TO_L1_MESSAGE_PASSER},
+    replay::{Recorder, RecordingHeader, SequencerInput},
+    sorting::SortingData,
+    FragSequence, SequencerConfig,
+};
 
-use crate::{block_sync::BlockSync, sorting::SortingData, FragSequence, SequencerConfig};
+/// Max number of accounts sent to the simulators to warm after a block was committed.
+const MAX_WARM_ACCOUNTS: usize = 4096;
 
 /// These are used to time different parts of the sequencer loop
 pub struct SequencerTimers {
@@ -71,14 +83,23 @@ pub struct SequencerContext<Db> {
     pub parent_header: Header,
     pub fork_choice_state: ForkchoiceState,
     pub payload_attributes: Box<OpPayloadAttributes>,
+    /// Id of the payload currently being built, returned by the fork choice update that started it.
+    pub payload_id: Option<PayloadId>,
     pub system_caller: SystemCaller<OpEvmConfig, OpChainSpec>,
     pub timers: SequencerTimers,
+    /// Records the inputs of the sequencer if [`SequencerConfig::record_path`] is set.
+    pub recorder: Option<Recorder>,
 }
 
 impl<Db: DatabaseRead> SequencerContext<Db> {
     pub fn new(db: Db, shared_state: SharedState<Db>, config: SequencerConfig) -> Self {
         let block_executor = BlockSync::new(config.evm_config.chain_spec().clone());
         let system_caller = SystemCaller::new(config.evm_config.clone(), config.evm_config.chain_spec().clone());
+        let recorder = config.record_path.as_ref().map(|path| {
+            let header = RecordingHeader::new(&db, &shared_state, &config).expect("couldn't read db head");
+            info!(path = %path.display(), "recording sequencer inputs");
+            Recorder::create(path, &header).expect("couldn't create recording")
+        });
         Self {
             db,
             shared_state,
@@ -89,11 +110,13 @@ impl<Db: DatabaseRead> SequencerContext<Db> {
             deposits: Default::default(),
             fork_choice_state: Default::default(),
             payload_attributes: Default::default(),
+            payload_id: Default::default(),
             parent_hash: Default::default(),
             parent_header: Default::default(),
             block_env: Default::default(),
             base_fee: Default::default(),
             timers: Default::default(),
+            recorder,
         }
     }
 }
@@ -102,6 +125,13 @@ impl<Db> SequencerContext<Db> {
         self.config.evm_config.chain_spec()
     }
 
+    /// Records an input received now, if recording.
+    pub fn record(&mut self, input: impl FnOnce() -> SequencerInput) {
+        if let Some(recorder) = &mut self.recorder {
+            recorder.record(Instant::now(), input);
+        }
+    }
+
     pub fn extra_data(&self) -> Bytes {
         let timestamp = self.payload_attributes.payload_attributes.timestamp;
         if self.chain_spec().is_holocene_active_at_timestamp(timestamp) {
@@ -143,7 +173,7 @@ impl<Db: DatabaseRef + Clone> SequencerContext<Db> {
         &mut self,
         mut sorting_data: SortingData<Db>,
         frag_seq: &mut FragSequence,
-    ) -> (FragV0, SortingData<Db>) {
+    ) -> (FragV1, SortingData<Db>) {
         info!(
             frag_id = frag_seq.next_seq,
             txs = sorting_data.txs.len(),
@@ -154,6 +184,16 @@ impl<Db: DatabaseRef + Clone> SequencerContext<Db> {
         self.tx_pool.remove_mined_txs(sorting_data.txs.iter());
         (frag_seq.apply_sorted_frag(sorting_data, self), SortingData::new(frag_seq, self))
     }
+
+    /// Message a sealed frag is gossiped as. Followers that don't verify the per frag commitments yet get a
+    /// [`FragV0`].
+    pub fn frag_message(&self, frag: FragV1) -> VersionedMessage {
+        if self.config.gossip_frag_v1 {
+            frag.into()
+        } else {
+            FragV0::from(frag).into()
+        }
+    }
 }
 
 impl<Db: DatabaseRead + Database<Error: Into<ProviderError> + Display>> SequencerContext<Db> {
@@ -162,6 +202,13 @@ impl<Db: DatabaseRead + Database<Error: Into<ProviderError> + Display>> Sequence
             self.deposits.push_back(tx);
             return;
         }
+        // Set code transactions are only valid from Isthmus (Prague) on
+        if matches!(tx.tx, OpTxEnvelope::Eip7702(_)) &&
+            !self.chain_spec().is_isthmus_active_at_timestamp(self.timestamp())
+        {
+            warn!(hash = %tx.tx_hash(), "dropping EIP-7702 transaction before Isthmus");
+            return;
+        }
         self.tx_pool.handle_new_tx(
             tx.clone(),
             self.shared_state.as_ref(),
@@ -220,35 +267,51 @@ impl<Db: DatabaseRead + Database<Error: Into<ProviderError> + Display>> Sequence
         (simulator_evm_block_params, env_with_handler_cfg)
     }
 
-    pub fn seal_last_frag(&mut self, frag_seq: &mut FragSequence, last_frag: SortingData<Db>) -> FragV0 {
+    pub fn seal_last_frag(&mut self, frag_seq: &mut FragSequence, last_frag: SortingData<Db>) -> FragV1 {
         let (mut frag_msg, _) = self.seal_frag(last_frag, frag_seq);
         frag_msg.is_last = true;
         frag_msg
     }
 
-    /// Finalize the block after the last frag has been sealed
-    pub fn seal_block(&mut self, frag_seq: FragSequence) -> (SealV0, OpExecutionPayloadEnvelopeV3) {
+    /// Finalize the block after the last frag has been sealed.
+    /// Also returns the sealed header, as it carries fork fields that the payload can't represent (e.g. the Isthmus
+    /// withdrawals root).
+    pub fn seal_block(
+        &mut self,
+        frag_seq: FragSequence,
+    ) -> Result<(SealV0, OpExecutionPayloadEnvelopeV3, Header), bop_common::db::Error> {
         frag_seq.sorting_telemetry.report();
+        histogram!(FRAGS_PER_BLOCK).record(frag_seq.next_seq as f64);
         let gas_used = frag_seq.gas_used;
         let canyon_active = self.chain_spec().fork(OpHardfork::Canyon).active_at_timestamp(self.timestamp());
         let (transactions, transactions_root, receipts_root, logs_bloom) =
             frag_seq.encoded_txs_roots_bloom(canyon_active);
 
         let state_changes = self.shared_state.as_mut().take_state_changes();
-        let state_root = self.db.calculate_state_root(&state_changes).unwrap().0;
+        let state_root_start = Instant::now();
+        let state_root = self.db.calculate_state_root(&state_changes)?.0;
+        histogram!(STATE_ROOT_DURATION, "stage" => "seal").record(state_root_start.elapsed().as_secs());
 
         let extra_data = self.extra_data();
 
         let parent_beacon_block_root = self.parent_beacon_block_root();
+        let withdrawals_root = if self.chain_spec().is_isthmus_active_at_timestamp(self.timestamp()) {
+            Some(self.db.calculate_storage_root(L2_TO_L1_MESSAGE_PASSER, &state_changes)?)
+        } else {
+            None
+        };
+        let fork_fields = ForkFields::new(self.chain_spec(), self.timestamp(), parent_beacon_block_root, || {
+            withdrawals_root.expect("computed for Isthmus")
+        });
 
-        let header = Header {
+        let mut header = Header {
             parent_hash: self.parent_hash,
             ommers_hash: EMPTY_OMMER_ROOT_HASH,
             beneficiary: self.block_env.coinbase,
             state_root,
             transactions_root,
             receipts_root,
-            withdrawals_root: Some(b256!("0x56e81f171bcc55a6ff8345e692c0f86e5b48e01b996cadc001622fb5e363b421")),
+            withdrawals_root: None,
             logs_bloom,
             timestamp: self.block_env.timestamp.to(),
             mix_hash: self.block_env.prevrandao.unwrap_or_default(),
@@ -259,11 +322,12 @@ impl<Db: DatabaseRead + Database<Error: Into<ProviderError> + Display>> Sequence
             difficulty: U256::ZERO,
             gas_used,
             extra_data: extra_data.clone(),
-            parent_beacon_block_root,
-            blob_gas_used: Some(0),
-            excess_blob_gas: Some(0),
+            parent_beacon_block_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
             requests_hash: None,
         };
+        fork_fields.apply(&mut header);
 
         let v1 = ExecutionPayloadV1 {
             parent_hash: self.parent_hash,
@@ -299,7 +363,7 @@ impl<Db: DatabaseRead + Database<Error: Into<ProviderError> + Display>> Sequence
             frag_seq.txs.len(),
             mgas / frag_seq.start_t.elapsed().as_secs()
         );
-        (seal, OpExecutionPayloadEnvelopeV3 {
+        let envelope = OpExecutionPayloadEnvelopeV3 {
             execution_payload: ExecutionPayloadV3 {
                 payload_inner: ExecutionPayloadV2 { payload_inner: v1, withdrawals: vec![] },
                 blob_gas_used: 0,
@@ -309,7 +373,8 @@ impl<Db: DatabaseRead + Database<Error: Into<ProviderError> + Display>> Sequence
             blobs_bundle: BlobsBundleV1::new(vec![]),
             should_override_builder: false,
             parent_beacon_block_root: parent_beacon_block_root.expect("should always be set"),
-        })
+        };
+        Ok((seal, envelope, header))
     }
 }
 impl<Db: DatabaseWrite + DatabaseRead> SequencerContext<Db> {
@@ -317,11 +382,21 @@ impl<Db: DatabaseWrite + DatabaseRead> SequencerContext<Db> {
     /// If it was based on a new payload message rather than blocksync, we pass the base_fee,
     /// and clear the existing pool based on that
     /// Returns a list of block numbers to fetch. This will be used in the case of a reorg.
-    pub fn commit_block(&mut self, block: &BlockSyncMessage) -> Option<(u64, u64)> {
-        let blocks_to_fetch = self.block_executor.commit_block(block, &self.db, true).expect("couldn't commit block");
+    pub fn commit_block(&mut self, block: &BlockSyncMessage, senders: &SendersSpine<Db>) -> Option<(u64, u64)> {
+        self.try_commit_block(block, senders).expect("couldn't commit block")
+    }
+
+    /// Same as [`Self::commit_block`], but returns the error instead of panicking, for blocks that may be invalid.
+    pub fn try_commit_block(
+        &mut self,
+        block: &BlockSyncMessage,
+        senders: &SendersSpine<Db>,
+    ) -> Result<Option<(u64, u64)>, BlockSyncError> {
+        let blocks_to_fetch = self.block_executor.commit_block(block, &self.db, true)?;
 
         self.parent_header = block.header.clone();
         self.parent_hash = block.hash_slow();
+        gauge!(DB_HEAD_BLOCK).set(block.number as f64);
 
         if let Some(base_fee) = block.base_fee_per_gas {
             self.base_fee = base_fee;
@@ -335,7 +410,16 @@ impl<Db: DatabaseWrite + DatabaseRead> SequencerContext<Db> {
             );
         }
 
-        blocks_to_fetch
+        // Pending txs are likely to be included soon, have a simulator read their accounts so they're not read cold.
+        let addresses = self.tx_pool.touched_addresses(MAX_WARM_ACCOUNTS);
+        if !addresses.is_empty() {
+            let warm = SequencerToSimulator::WarmAccounts(addresses, self.shared_state.as_ref().clone());
+            if senders.send(warm).is_err() {
+                warn!("couldn't send accounts to warm to the simulators");
+            }
+        }
+
+        Ok(blocks_to_fetch)
     }
 }
 
@@ -0,0 +1,568 @@
+//! Follows the frags gossiped by a gateway.
+//!
+//! Frags are re-executed on top of the local database as they arrive, so the preconfirmed state can be served before
+//! the block is sealed. On seal the whole block is executed again with [`BlockSync`], which checks the state root,
+//! receipts and gas used against the ones of the seal, and committed. A `FragV1` is also checked against its gas used,
+//! receipts root and state commitment as soon as it's executed.
+
+use std::{iter, sync::Arc};
+
+use alloy_consensus::{Header, EMPTY_OMMER_ROOT_HASH, EMPTY_ROOT_HASH};
+use alloy_eips::{eip2718::Decodable2718, merge::BEACON_NONCE};
+use alloy_primitives::{Address, Bytes, B256};
+use bop_common::{
+    communication::messages::{BlockSyncError, BlockSyncMessage, SimulationError},
+    db::{state::ensure_create2_deployer, DBFrag, DatabaseRead, DatabaseWrite},
+    metrics::{FOLLOWER_HEAD_BLOCK, FOLLOWER_MESSAGES},
+    p2p::{EnvV0, FragV0, FragV1, SealV0, SignedMessage, VersionedMessage},
+    schedule::SequencerSchedule,
+    signing::SignerError,
+    transaction::Transaction,
+};
+use metrics::{counter, gauge};
+use reth_chainspec::EthereumHardforks;
+use reth_evm::{env::EvmEnv, execute::BlockExecutionError, system_calls::SystemCaller, ConfigureEvm, ConfigureEvmEnv};
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_evm::{OpBlockExecutionError, OpEvmConfig};
+use reth_optimism_forks::OpHardforks;
+use reth_optimism_primitives::{OpBlock, OpTransactionSigned};
+use reth_primitives::{BlockBody, BlockWithSenders};
+use revm_primitives::EnvWithHandlerCfg;
+use strum_macros::AsRefStr;
+use tracing::{debug, info, warn};
+use tree_hash::TreeHash;
+
+use crate::{
+    block_sync::BlockSync,
+    header::{ForkFields, L2_TO_L1_MESSAGE_PASSER},
+    simulator::simulate_tx_inner,
+    FragSequence,
+};
+
+#[derive(Debug, thiserror::Error, AsRefStr)]
+pub enum FollowerError {
+    #[error("invalid signature: {0}")]
+    Signature(#[from] SignerError),
+    #[error("message for block {number} signed by {got}, expected {expected}")]
+    UnexpectedSigner { number: u64, got: Address, expected: Address },
+    #[error("no sequencer scheduled for block {0}")]
+    Unscheduled(u64),
+    #[error("unsupported message {0}")]
+    Unsupported(String),
+    /// The blocks between the local head and the new block have to be synced first.
+    #[error("local head {head} is behind block {number}")]
+    Behind { head: u64, number: u64 },
+    /// The parent of the new block has to be synced first.
+    #[error("parent {parent_hash} of block {number} is not the local head")]
+    ParentMismatch { number: u64, parent_hash: B256 },
+    #[error("block {number} is not after the local head {head}")]
+    Stale { head: u64, number: u64 },
+    #[error("no open block")]
+    NoOpenBlock,
+    /// Frags `from..to` of the open block were missed, the block stays open so they can be backfilled.
+    #[error("frags {from}..{to} of block {number} are missing")]
+    MissingFrags { number: u64, from: u64, to: u64 },
+    #[error("unexpected frag {seq} of block {number}, expected frag {expected_seq} of block {expected_number}")]
+    UnexpectedFrag { number: u64, seq: u64, expected_number: u64, expected_seq: u64 },
+    #[error("env of block {0} conflicts with the one of the open block")]
+    ConflictingEnv(u64),
+    #[error("frag {seq} of block {number} conflicts with the one already applied")]
+    ConflictingFrag { number: u64, seq: u64 },
+    #[error("invalid transaction: {0}")]
+    InvalidTransaction(#[from] alloy_rlp::Error),
+    #[error("transaction {hash} failed: {error}")]
+    Transaction { hash: B256, error: SimulationError },
+    #[error("frag {seq} doesn't match block {number}: {field} is {got}, expected {expected}")]
+    FragMismatch { number: u64, seq: u64, field: &'static str, got: String, expected: String },
+    #[error("seal doesn't match block {number}: {field} is {got}, expected {expected}")]
+    SealMismatch { number: u64, field: &'static str, got: String, expected: String },
+    #[error("block execution failed: {0}")]
+    Execution(#[from] BlockExecutionError),
+    #[error("block sync failed: {0}")]
+    BlockSync(#[from] BlockSyncError),
+    #[error("db error: {0}")]
+    Db(#[from] bop_common::db::Error),
+}
+
+impl From<OpBlockExecutionError> for FollowerError {
+    fn from(value: OpBlockExecutionError) -> Self {
+        Self::Execution(value.into())
+    }
+}
+
+/// Who frags are expected to be signed by.
+#[derive(Debug, Clone)]
+pub enum ExpectedSigner {
+    /// A single gateway, sequencing all blocks.
+    Address(Address),
+    /// The gateway scheduled for each block.
+    Schedule(SequencerSchedule),
+}
+
+impl ExpectedSigner {
+    pub fn for_block(&self, number: u64) -> Option<Address> {
+        match self {
+            ExpectedSigner::Address(address) => Some(*address),
+            ExpectedSigner::Schedule(schedule) => schedule.assignment(number).map(|slot| slot.address),
+        }
+    }
+}
+
+/// Block that frags are being applied to, until it is sealed.
+struct OpenBlock {
+    env: EnvV0,
+    /// Header with the fields known from the env, completed on seal.
+    header: Header,
+    evm_env: EnvWithHandlerCfg,
+    seq: FragSequence,
+    transactions: Vec<OpTransactionSigned>,
+    /// Tree hash root of each applied frag as it was received, to tell a redelivered frag from a conflicting one.
+    frag_hashes: Vec<B256>,
+}
+
+pub struct Follower<Db> {
+    db: Db,
+    /// Preconfirmed state of the open block.
+    frag_db: DBFrag<Db>,
+    evm_config: OpEvmConfig,
+    system_caller: SystemCaller<OpEvmConfig, OpChainSpec>,
+    block_sync: BlockSync,
+    signer: ExpectedSigner,
+    open: Option<OpenBlock>,
+}
+
+impl<Db: DatabaseWrite + DatabaseRead> Follower<Db> {
+    pub fn new(db: Db, evm_config: OpEvmConfig, signer: ExpectedSigner) -> Self {
+        let chain_spec = evm_config.chain_spec().clone();
+        Self {
+            frag_db: db.clone().into(),
+            db,
+            system_caller: SystemCaller::new(evm_config.clone(), chain_spec.clone()),
+            block_sync: BlockSync::new(chain_spec),
+            evm_config,
+            signer,
+            open: None,
+        }
+    }
+
+    /// Preconfirmed state, shared with the RPC.
+    pub fn frag_db(&self) -> DBFrag<Db> {
+        self.frag_db.clone()
+    }
+
+    pub fn head_block_number(&self) -> Result<u64, FollowerError> {
+        Ok(self.db.head_block_number()?)
+    }
+
+    /// Block number and seq of the next frag expected for the open block.
+    pub fn next_frag(&self) -> Option<(u64, u64)> {
+        self.open.as_ref().map(|open| (open.header.number, open.seq.next_seq))
+    }
+
+    fn chain_spec(&self) -> &Arc<OpChainSpec> {
+        self.evm_config.chain_spec()
+    }
+
+    /// Drops the open block and its preconfirmed state.
+    fn discard_open_block(&mut self) {
+        if let Some(open) = self.open.take() {
+            warn!(number = open.header.number, frags = open.seq.next_seq, "discarding unsealed block");
+        }
+        self.frag_db.reset();
+    }
+
+    /// Verifies the signer of a gossiped message and applies it. A block whose frags or seal fail to apply is
+    /// discarded, the follower waits for the env of the next block.
+    pub fn handle(&mut self, signed: SignedMessage) -> Result<(), FollowerError> {
+        let kind = signed.message.as_ref().to_string();
+        let res = self.apply(signed);
+
+        match &res {
+            Ok(()) => counter!(FOLLOWER_MESSAGES, "kind" => kind, "result" => "success").increment(1),
+            Err(err) => counter!(FOLLOWER_MESSAGES, "kind" => kind, "result" => err.as_ref().to_string()).increment(1),
+        }
+        res
+    }
+
+    fn apply(&mut self, signed: SignedMessage) -> Result<(), FollowerError> {
+        // Messages not signed by the sequencer don't affect the open block
+        self.verify_signer(&signed)?;
+
+        let res = match signed.message {
+            VersionedMessage::EnvV0(env) => self.on_env(env),
+            VersionedMessage::FragV0(frag) => self.on_frag(frag),
+            VersionedMessage::SealV0(seal) => self.on_seal(seal),
+            VersionedMessage::FragV1(frag) => self.on_frag_v1(frag),
+            message => Err(FollowerError::Unsupported(message.as_ref().to_string())),
+        };
+        if res.as_ref().is_err_and(|err| !matches!(err, FollowerError::MissingFrags { .. })) {
+            self.discard_open_block();
+        }
+        res
+    }
+
+    /// Commits a canonical block, e.g. fetched from a full node to catch up. Any open block is discarded.
+    ///
+    /// Returns block numbers to fetch in the case of a reorg, see [`BlockSync::commit_block`].
+    pub fn sync_block(&mut self, block: &BlockSyncMessage) -> Result<Option<(u64, u64)>, FollowerError> {
+        self.discard_open_block();
+        let to_fetch = self.block_sync.commit_block(block, &self.db, true)?;
+        gauge!(FOLLOWER_HEAD_BLOCK).set(self.db.head_block_number()? as f64);
+        Ok(to_fetch)
+    }
+
+    fn verify_signer(&self, signed: &SignedMessage) -> Result<(), FollowerError> {
+        let number = signed.message.block_number();
+        let expected = self.signer.for_block(number).ok_or(FollowerError::Unscheduled(number))?;
+        let got = signed.recover_signer()?;
+        if got != expected {
+            return Err(FollowerError::UnexpectedSigner { number, got, expected });
+        }
+        Ok(())
+    }
+
+    /// Opens a new block on top of the local head and applies the pre-execution changes to the preconfirmed state.
+    /// The env of the open block or of an earlier one is ignored, a different env for the open block conflicts with it.
+    fn on_env(&mut self, env: EnvV0) -> Result<(), FollowerError> {
+        if let Some(open) = &self.open {
+            if env.number < open.header.number {
+                debug!(number = env.number, "ignoring env of an earlier block");
+                return Ok(());
+            }
+            if env.number == open.header.number {
+                if env.tree_hash_root() != open.env.tree_hash_root() {
+                    return Err(FollowerError::ConflictingEnv(env.number));
+                }
+                debug!(number = env.number, "ignoring env that was already applied");
+                return Ok(());
+            }
+        }
+        self.discard_open_block();
+
+        let head = self.db.head_block_number()?;
+        if env.number > head + 1 {
+            return Err(FollowerError::Behind { head, number: env.number });
+        }
+        if env.number <= head {
+            return Err(FollowerError::Stale { head, number: env.number });
+        }
+        if env.parent_hash != self.db.head_block_hash()? {
+            return Err(FollowerError::ParentMismatch { number: env.number, parent_hash: env.parent_hash });
+        }
+
+        let mut header = Header {
+            parent_hash: env.parent_hash,
+            ommers_hash: EMPTY_OMMER_ROOT_HASH,
+            beneficiary: env.beneficiary,
+            timestamp: env.timestamp,
+            mix_hash: env.prevrandao,
+            nonce: BEACON_NONCE.into(),
+            base_fee_per_gas: Some(env.basefee),
+            number: env.number,
+            gas_limit: env.gas_limit,
+            difficulty: env.difficulty,
+            extra_data: Bytes::from(env.extra_data.to_vec()),
+            ..Default::default()
+        };
+        // The Isthmus withdrawals root is only known once the block is executed, it's set again on seal
+        ForkFields::new(self.chain_spec(), env.timestamp, Some(env.parent_beacon_block_root), || EMPTY_ROOT_HASH)
+            .apply(&mut header);
+
+        let EvmEnv { cfg_env_with_handler_cfg, block_env } = self.evm_config.cfg_and_block_env(&header);
+        let evm_env = EnvWithHandlerCfg::new_with_cfg_env(cfg_env_with_handler_cfg, block_env, Default::default());
+
+        let chain_spec = self.chain_spec().clone();
+        let mut evm = self.evm_config.evm_with_env(&mut self.frag_db, evm_env.clone());
+        evm.db_mut().db.write().set_state_clear_flag(chain_spec.is_spurious_dragon_active_at_block(env.number));
+        self.system_caller.apply_beacon_root_contract_call(
+            env.timestamp,
+            env.number,
+            Some(env.parent_beacon_block_root),
+            &mut evm,
+        )?;
+        ensure_create2_deployer(chain_spec, env.timestamp, &mut evm.db_mut().db.write())
+            .map_err(|_| OpBlockExecutionError::ForceCreate2DeployerFail)?;
+        drop(evm);
+
+        info!(number = env.number, "opened block");
+        let seq = FragSequence::new(env.gas_limit, env.number, env.timestamp);
+        self.open = Some(OpenBlock { env, header, evm_env, seq, transactions: vec![], frag_hashes: vec![] });
+        Ok(())
+    }
+
+    /// Whether a frag was already applied, e.g. delivered again by the gossip and a backfill, or belongs to an earlier
+    /// block. Such frags are ignored. A different frag with the seq of an applied one conflicts with the open block.
+    fn is_redelivered(&self, number: u64, seq: u64, hash: B256) -> Result<bool, FollowerError> {
+        let open = self.open.as_ref().ok_or(FollowerError::NoOpenBlock)?;
+        if number < open.header.number {
+            return Ok(true);
+        }
+        if number > open.header.number || seq >= open.seq.next_seq {
+            return Ok(false);
+        }
+        if open.frag_hashes[seq as usize] != hash {
+            return Err(FollowerError::ConflictingFrag { number, seq });
+        }
+        Ok(true)
+    }
+
+    /// Executes the transactions of the next frag on top of the preconfirmed state.
+    fn on_frag(&mut self, frag: FragV0) -> Result<(), FollowerError> {
+        let hash = frag.tree_hash_root();
+        if self.is_redelivered(frag.block_number, frag.seq, hash)? {
+            debug!(number = frag.block_number, seq = frag.seq, "ignoring frag that was already applied");
+            return Ok(());
+        }
+        self.apply_frag(frag, hash)
+    }
+
+    fn apply_frag(&mut self, frag: FragV0, hash: B256) -> Result<(), FollowerError> {
+        let open = self.open.as_mut().ok_or(FollowerError::NoOpenBlock)?;
+        if frag.block_number == open.header.number && frag.seq > open.seq.next_seq {
+            return Err(FollowerError::MissingFrags {
+                number: frag.block_number,
+                from: open.seq.next_seq,
+                to: frag.seq,
+            });
+        }
+        if frag.block_number != open.header.number || frag.seq != open.seq.next_seq {
+            return Err(FollowerError::UnexpectedFrag {
+                number: frag.block_number,
+                seq: frag.seq,
+                expected_number: open.header.number,
+                expected_seq: open.seq.next_seq,
+            });
+        }
+
+        let regolith_active = self.evm_config.chain_spec().is_regolith_active_at_timestamp(open.header.timestamp);
+        let mut evm = self.evm_config.evm_with_env(&mut self.frag_db, open.evm_env.clone());
+        for bytes in frag.txs.iter() {
+            let tx = Arc::new(Transaction::decode(Bytes::copy_from_slice(bytes))?);
+            let signed = OpTransactionSigned::decode_2718(&mut bytes.as_ref()).map_err(alloy_rlp::Error::from)?;
+
+            let mut simulated = simulate_tx_inner(tx.clone(), &mut evm, regolith_active, true, true)
+                .map_err(|error| FollowerError::Transaction { hash: tx.tx_hash(), error })?;
+            evm.db_mut().commit_txs(iter::once(&mut simulated));
+
+            open.seq.gas_used += simulated.gas_used();
+            open.seq.txs.push(simulated);
+            open.transactions.push(signed);
+        }
+        open.seq.next_seq += 1;
+        open.frag_hashes.push(hash);
+
+        Ok(())
+    }
+
+    /// Executes the transactions of the next frag like [`Self::on_frag`], then checks its commitments against the
+    /// preconfirmed state, so a diverging sequencer is caught before the seal.
+    fn on_frag_v1(&mut self, frag: FragV1) -> Result<(), FollowerError> {
+        let hash = frag.tree_hash_root();
+        if self.is_redelivered(frag.block_number, frag.seq, hash)? {
+            debug!(number = frag.block_number, seq = frag.seq, "ignoring frag that was already applied");
+            return Ok(());
+        }
+        let FragV1 {
+            block_number: number,
+            seq,
+            cumulative_gas_used,
+            state_commitment,
+            receipts_root,
+            prev_frag_hash,
+            ..
+        } = frag;
+        self.apply_frag(frag.into(), hash)?;
+
+        let open = self.open.as_mut().expect("frag was applied to the open block");
+        let check = |field: &'static str, got: String, expected: String| {
+            if got == expected {
+                Ok(())
+            } else {
+                Err(FollowerError::FragMismatch { number, seq, field, got, expected })
+            }
+        };
+        check("prev_frag_hash", open.seq.prev_frag_hash.to_string(), prev_frag_hash.to_string())?;
+        check("cumulative_gas_used", open.seq.gas_used.to_string(), cumulative_gas_used.to_string())?;
+
+        let canyon_active = self.evm_config.chain_spec().is_canyon_active_at_timestamp(open.header.timestamp);
+        check("receipts_root", open.seq.receipts_root(canyon_active).to_string(), receipts_root.to_string())?;
+        check("state_commitment", self.frag_db.state_commitment().to_string(), state_commitment.to_string())?;
+
+        open.seq.prev_frag_hash = hash;
+        Ok(())
+    }
+
+    /// Checks the seal against the executed frags, then executes and commits the block.
+    fn on_seal(&mut self, seal: SealV0) -> Result<(), FollowerError> {
+        let OpenBlock { env, mut header, seq, transactions, .. } =
+            self.open.take().ok_or(FollowerError::NoOpenBlock)?;
+        let number = header.number;
+
+        let check = |field: &'static str, got: String, expected: String| {
+            if got == expected {
+                Ok(())
+            } else {
+                Err(FollowerError::SealMismatch { number, field, got, expected })
+            }
+        };
+        check("block_number", number.to_string(), seal.block_number.to_string())?;
+        check("parent_hash", header.parent_hash.to_string(), seal.parent_hash.to_string())?;
+        check("gas_limit", header.gas_limit.to_string(), seal.gas_limit.to_string())?;
+        check("total_frags", seq.next_seq.to_string(), seal.total_frags.to_string())?;
+        check("gas_used", seq.gas_used.to_string(), seal.gas_used.to_string())?;
+
+        let canyon_active = self.chain_spec().is_canyon_active_at_timestamp(header.timestamp);
+        let (_, transactions_root, receipts_root, logs_bloom) = seq.encoded_txs_roots_bloom(canyon_active);
+        check("transactions_root", transactions_root.to_string(), seal.transactions_root.to_string())?;
+        check("receipts_root", receipts_root.to_string(), seal.receipts_root.to_string())?;
+
+        let withdrawals_root = if self.chain_spec().is_isthmus_active_at_timestamp(header.timestamp) {
+            let state_changes = self.frag_db.take_state_changes();
+            Some(self.db.calculate_storage_root(L2_TO_L1_MESSAGE_PASSER, &state_changes)?)
+        } else {
+            None
+        };
+        ForkFields::new(self.chain_spec(), header.timestamp, Some(env.parent_beacon_block_root), || {
+            withdrawals_root.expect("computed for Isthmus")
+        })
+        .apply(&mut header);
+        header.transactions_root = transactions_root;
+        header.receipts_root = receipts_root;
+        header.logs_bloom = logs_bloom;
+        header.gas_used = seq.gas_used;
+        // Checked by executing the block below
+        header.state_root = seal.state_root;
+        check("block_hash", header.hash_slow().to_string(), seal.block_hash.to_string())?;
+
+        let withdrawals = self.chain_spec().is_shanghai_active_at_timestamp(header.timestamp).then(Default::default);
+        let senders = seq.txs.iter().map(|tx| tx.tx.sender()).collect();
+        let block = BlockWithSenders {
+            block: OpBlock { header, body: BlockBody { transactions, ommers: vec![], withdrawals } },
+            senders,
+        };
+
+        self.frag_db.reset();
+        self.block_sync.execute_and_maybe_commit(&block, &self.db, true)?;
+        gauge!(FOLLOWER_HEAD_BLOCK).set(number as f64);
+        info!(number, hash = %seal.block_hash, frags = seal.total_frags, txs = seq.txs.len(), "verified block");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_primitives::U256;
+    use bop_common::{
+        p2p::{ExtraData, Transactions},
+        signing::ECDSASigner,
+    };
+    use bop_db::{test_utils::TestDatadir, SequencerDB};
+    use reth_optimism_chainspec::BASE_SEPOLIA;
+
+    use super::*;
+
+    /// Follower on an empty Base Sepolia datadir, following messages signed by the returned signer.
+    fn follower(datadir: &TestDatadir) -> (Follower<SequencerDB>, ECDSASigner) {
+        let db = datadir.init(BASE_SEPOLIA.clone());
+        let signer = ECDSASigner::random();
+        let follower =
+            Follower::new(db, OpEvmConfig::new(BASE_SEPOLIA.clone()), ExpectedSigner::Address(signer.address));
+        (follower, signer)
+    }
+
+    /// Env of block 1, on top of genesis.
+    fn env(follower: &Follower<SequencerDB>) -> EnvV0 {
+        let genesis = BASE_SEPOLIA.genesis_header();
+        EnvV0 {
+            number: 1,
+            parent_hash: follower.db.head_block_hash().unwrap(),
+            beneficiary: Address::ZERO,
+            timestamp: genesis.timestamp + 2,
+            gas_limit: genesis.gas_limit,
+            basefee: genesis.base_fee_per_gas.unwrap_or_default(),
+            difficulty: U256::ZERO,
+            prevrandao: B256::ZERO,
+            extra_data: ExtraData::from(vec![]),
+            parent_beacon_block_root: B256::ZERO,
+        }
+    }
+
+    /// Frag of block 1 without transactions, committing to the preconfirmed state of `follower`.
+    fn empty_frag(follower: &Follower<SequencerDB>, seq: u64, prev_frag_hash: B256) -> FragV1 {
+        FragV1 {
+            block_number: 1,
+            seq,
+            is_last: false,
+            txs: Transactions::from(vec![]),
+            cumulative_gas_used: 0,
+            state_commitment: follower.frag_db.state_commitment(),
+            receipts_root: EMPTY_ROOT_HASH,
+            prev_frag_hash,
+        }
+    }
+
+    #[test]
+    fn rejects_frag_v1_with_mismatching_commitments() {
+        let datadir = TestDatadir::new();
+        let (mut follower, signer) = follower(&datadir);
+
+        let tampers: [(&str, fn(&mut FragV1)); 4] = [
+            ("prev_frag_hash", |frag| frag.prev_frag_hash = B256::repeat_byte(1)),
+            ("cumulative_gas_used", |frag| frag.cumulative_gas_used = 21_000),
+            ("receipts_root", |frag| frag.receipts_root = B256::repeat_byte(1)),
+            ("state_commitment", |frag| frag.state_commitment = B256::repeat_byte(1)),
+        ];
+        for (field, tamper) in tampers {
+            follower.handle(VersionedMessage::from(env(&follower)).sign(&signer)).unwrap();
+            let first = empty_frag(&follower, 0, B256::ZERO);
+            follower.handle(VersionedMessage::from(first.clone()).sign(&signer)).unwrap();
+            assert_eq!(follower.next_frag(), Some((1, 1)));
+
+            let mut second = empty_frag(&follower, 1, first.tree_hash_root());
+            tamper(&mut second);
+            let err = follower.handle(VersionedMessage::from(second).sign(&signer)).unwrap_err();
+            assert!(
+                matches!(err, FollowerError::FragMismatch { number: 1, seq: 1, field: got, .. } if got == field),
+                "{field}: {err}"
+            );
+            // The diverging block is discarded
+            assert_eq!(follower.next_frag(), None);
+        }
+    }
+
+    #[test]
+    fn ignores_redelivered_frags() {
+        let datadir = TestDatadir::new();
+        let (mut follower, signer) = follower(&datadir);
+
+        let env = VersionedMessage::from(env(&follower)).sign(&signer);
+        follower.handle(env.clone()).unwrap();
+        let first = empty_frag(&follower, 0, B256::ZERO);
+        let second = empty_frag(&follower, 1, first.tree_hash_root());
+        for frag in [&first, &first, &second, &first, &second] {
+            follower.handle(VersionedMessage::from(frag.clone()).sign(&signer)).unwrap();
+        }
+        assert_eq!(follower.next_frag(), Some((1, 2)));
+
+        // Redelivering the env keeps the applied frags
+        follower.handle(env.clone()).unwrap();
+        assert_eq!(follower.next_frag(), Some((1, 2)));
+
+        // Redelivered as FragV0 the frag doesn't match the one applied
+        let err = follower.handle(VersionedMessage::from(FragV0::from(first.clone())).sign(&signer)).unwrap_err();
+        assert!(matches!(err, FollowerError::ConflictingFrag { number: 1, seq: 0 }), "{err}");
+        assert_eq!(follower.next_frag(), None);
+
+        // Frags of a block before the open one are stale
+        follower.handle(env).unwrap();
+        follower.handle(VersionedMessage::from(FragV1 { block_number: 0, ..first }).sign(&signer)).unwrap();
+        assert_eq!(follower.next_frag(), Some((1, 0)));
+
+        // A different env for the open block conflicts with it
+        let conflicting = EnvV0 { extra_data: ExtraData::from(vec![1]), ..self::env(&follower) };
+        let err = follower.handle(VersionedMessage::from(conflicting).sign(&signer)).unwrap_err();
+        assert!(matches!(err, FollowerError::ConflictingEnv(1)), "{err}");
+        assert_eq!(follower.next_frag(), None);
+    }
+}
@@ -0,0 +1,275 @@
+use alloy_consensus::{Header, EMPTY_ROOT_HASH};
+use alloy_eips::eip7685::EMPTY_REQUESTS_HASH;
+use alloy_primitives::{address, Address, B256};
+use reth_chainspec::EthereumHardforks;
+use reth_optimism_chainspec::OpChainSpec;
+use reth_optimism_forks::OpHardforks;
+
+/// Predeploy whose storage root is committed to as the withdrawals root from Isthmus on.
+pub const L2_TO_L1_MESSAGE_PASSER: Address = address!("4200000000000000000000000000000000000016");
+
+/// Header fields whose presence and value depend on the hardforks active at a block's timestamp.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ForkFields {
+    pub withdrawals_root: Option<B256>,
+    pub parent_beacon_block_root: Option<B256>,
+    pub blob_gas_used: Option<u64>,
+    pub excess_blob_gas: Option<u64>,
+    pub requests_hash: Option<B256>,
+}
+
+impl ForkFields {
+    /// - Canyon (Shanghai): empty withdrawals root, withdrawals are not supported on L2
+    /// - Ecotone (Cancun): parent beacon block root and zeroed blob gas fields
+    /// - Isthmus (Prague): the storage root of the [`L2_TO_L1_MESSAGE_PASSER`] as withdrawals root and the empty
+    ///   requests hash
+    ///
+    /// `l2_withdrawals_root` is only called when Isthmus is active, as it requires a storage root calculation.
+    pub fn new(
+        chain_spec: &OpChainSpec,
+        timestamp: u64,
+        parent_beacon_block_root: Option<B256>,
+        l2_withdrawals_root: impl FnOnce() -> B256,
+    ) -> Self {
+        let mut fields = Self::default();
+
+        if chain_spec.is_isthmus_active_at_timestamp(timestamp) {
+            fields.withdrawals_root = Some(l2_withdrawals_root());
+            fields.requests_hash = Some(EMPTY_REQUESTS_HASH);
+        } else if chain_spec.is_shanghai_active_at_timestamp(timestamp) {
+            fields.withdrawals_root = Some(EMPTY_ROOT_HASH);
+        }
+
+        if chain_spec.is_cancun_active_at_timestamp(timestamp) {
+            fields.parent_beacon_block_root = parent_beacon_block_root;
+            fields.blob_gas_used = Some(0);
+            fields.excess_blob_gas = Some(0);
+        }
+
+        fields
+    }
+
+    pub fn apply(self, header: &mut Header) {
+        header.withdrawals_root = self.withdrawals_root;
+        header.parent_beacon_block_root = self.parent_beacon_block_root;
+        header.blob_gas_used = self.blob_gas_used;
+        header.excess_blob_gas = self.excess_blob_gas;
+        header.requests_hash = self.requests_hash;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use alloy_consensus::EMPTY_OMMER_ROOT_HASH;
+    use alloy_primitives::b256;
+    use alloy_provider::ProviderBuilder;
+    use bop_db::{test_utils::TestDatadir, DatabaseRead};
+    use reqwest::Url;
+    use reth_chainspec::{EthChainSpec, ForkCondition};
+    use reth_optimism_chainspec::{OpChainSpecBuilder, BASE_SEPOLIA, OP_DEV};
+    use reth_optimism_forks::OpHardfork;
+    use reth_optimism_primitives::OpBlock;
+    use reth_primitives::BlockWithSenders;
+    use revm::db::BundleState;
+    use serde_json::json;
+
+    use super::*;
+    use crate::block_sync::{
+        fetch_blocks::{fetch_block, TEST_BASE_SEPOLIA_RPC_URL},
+        BlockSync,
+    };
+
+    const BEACON_ROOT: B256 = b256!("0x1111111111111111111111111111111111111111111111111111111111111111");
+    const L2_ROOT: B256 = b256!("0x2222222222222222222222222222222222222222222222222222222222222222");
+
+    fn fields(chain_spec: OpChainSpec) -> ForkFields {
+        ForkFields::new(&chain_spec, 1, Some(BEACON_ROOT), || L2_ROOT)
+    }
+
+    #[test]
+    fn fork_fields_per_hardfork() {
+        let base = OpChainSpecBuilder::base_mainnet;
+        let regolith = ForkFields::default();
+        let canyon = ForkFields { withdrawals_root: Some(EMPTY_ROOT_HASH), ..regolith };
+        let ecotone = ForkFields {
+            parent_beacon_block_root: Some(BEACON_ROOT),
+            blob_gas_used: Some(0),
+            excess_blob_gas: Some(0),
+            ..canyon
+        };
+        let isthmus =
+            ForkFields { withdrawals_root: Some(L2_ROOT), requests_hash: Some(EMPTY_REQUESTS_HASH), ..ecotone };
+
+        assert_eq!(fields(base().regolith_activated().build()), regolith);
+        assert_eq!(fields(base().canyon_activated().build()), canyon);
+        assert_eq!(fields(base().ecotone_activated().build()), ecotone);
+        assert_eq!(fields(base().fjord_activated().build()), ecotone);
+        assert_eq!(fields(base().granite_activated().build()), ecotone);
+        assert_eq!(fields(base().holocene_activated().build()), ecotone);
+        assert_eq!(fields(base().isthmus_activated().build()), isthmus);
+    }
+
+    #[test]
+    fn l2_withdrawals_root_only_computed_for_isthmus() {
+        let chain_spec = OpChainSpecBuilder::base_mainnet().holocene_activated().build();
+        ForkFields::new(&chain_spec, 1, Some(BEACON_ROOT), || panic!("computed withdrawals root before Isthmus"));
+    }
+
+    /// Slot of `sentMessages[0x11..11]` in the [`L2_TO_L1_MESSAGE_PASSER`], i.e. `keccak256(0x11..11 ++ 0)`.
+    const SENT_MESSAGE_SLOT: B256 = b256!("0x5c75bb376affa44a4f06c8a768453c2f7945122a65eb322a0dd3cc2edcbd6f0a");
+    /// Storage root of the [`L2_TO_L1_MESSAGE_PASSER`] with one sent message and `msgNonce` 1, computed with an
+    /// independent keccak and trie implementation.
+    const MESSAGE_PASSER_STORAGE_ROOT: B256 =
+        b256!("0x9b9523067f5e21f44cf687fe61771b826bfd1bdcf887fce64895dc473f201478");
+
+    /// OP dev chain with `activate` applied at genesis and a message passer that has sent one message.
+    fn local_chain_spec(activate: fn(OpChainSpecBuilder) -> OpChainSpecBuilder) -> Arc<OpChainSpec> {
+        let mut genesis = OP_DEV.genesis.clone();
+        let message_passer = json!({
+            "balance": "0x0",
+            "storage": {
+                SENT_MESSAGE_SLOT.to_string(): B256::with_last_byte(1),
+                B256::with_last_byte(1).to_string(): B256::with_last_byte(1),
+            }
+        });
+        genesis.alloc.insert(L2_TO_L1_MESSAGE_PASSER, serde_json::from_value(message_passer).unwrap());
+        Arc::new(activate(OpChainSpecBuilder::default().chain(OP_DEV.chain).genesis(genesis)).build())
+    }
+
+    /// Seals an empty block 1 the way the sequencer does, then executes and commits it. Execution validates the
+    /// state root, receipts and, from Isthmus on, the withdrawals root.
+    fn seal_and_execute(chain_spec: Arc<OpChainSpec>) -> Header {
+        let datadir = TestDatadir::new();
+        let db = datadir.init(chain_spec.clone());
+
+        let genesis = chain_spec.genesis_header();
+        let timestamp = genesis.timestamp + 2;
+        let mut header = Header {
+            parent_hash: chain_spec.genesis_hash(),
+            ommers_hash: EMPTY_OMMER_ROOT_HASH,
+            state_root: genesis.state_root,
+            transactions_root: EMPTY_ROOT_HASH,
+            receipts_root: EMPTY_ROOT_HASH,
+            number: 1,
+            gas_limit: genesis.gas_limit,
+            timestamp,
+            base_fee_per_gas: genesis.base_fee_per_gas,
+            ..Default::default()
+        };
+        ForkFields::new(&chain_spec, timestamp, Some(BEACON_ROOT), || {
+            db.calculate_storage_root(L2_TO_L1_MESSAGE_PASSER, &BundleState::default()).unwrap()
+        })
+        .apply(&mut header);
+
+        let block = BlockWithSenders::new_unchecked(OpBlock { header, body: Default::default() }, vec![]);
+        BlockSync::new(chain_spec).execute_and_maybe_commit(&block, &db, true).unwrap();
+        assert_eq!(db.head_block_hash().unwrap(), block.header.hash_slow());
+
+        block.block.header
+    }
+
+    #[test]
+    fn seals_and_executes_per_hardfork() {
+        let forks: [(OpHardfork, fn(OpChainSpecBuilder) -> OpChainSpecBuilder, Option<B256>); 7] = [
+            (OpHardfork::Regolith, OpChainSpecBuilder::regolith_activated, None),
+            (OpHardfork::Canyon, OpChainSpecBuilder::canyon_activated, Some(EMPTY_ROOT_HASH)),
+            (OpHardfork::Ecotone, OpChainSpecBuilder::ecotone_activated, Some(EMPTY_ROOT_HASH)),
+            (OpHardfork::Fjord, OpChainSpecBuilder::fjord_activated, Some(EMPTY_ROOT_HASH)),
+            (OpHardfork::Granite, OpChainSpecBuilder::granite_activated, Some(EMPTY_ROOT_HASH)),
+            (OpHardfork::Holocene, OpChainSpecBuilder::holocene_activated, Some(EMPTY_ROOT_HASH)),
+            (OpHardfork::Isthmus, OpChainSpecBuilder::isthmus_activated, Some(MESSAGE_PASSER_STORAGE_ROOT)),
+        ];
+        for (fork, activate, withdrawals_root) in forks {
+            let chain_spec = local_chain_spec(activate);
+            let header = seal_and_execute(chain_spec.clone());
+
+            assert_eq!(header.withdrawals_root, withdrawals_root, "{fork}");
+            let ecotone = chain_spec.is_ecotone_active_at_timestamp(header.timestamp);
+            assert_eq!(header.parent_beacon_block_root, ecotone.then_some(BEACON_ROOT), "{fork}");
+            assert_eq!(header.blob_gas_used, ecotone.then_some(0), "{fork}");
+            let isthmus = fork == OpHardfork::Isthmus;
+            assert_eq!(header.requests_hash, isthmus.then_some(EMPTY_REQUESTS_HASH), "{fork}");
+        }
+    }
+
+    /// Isthmus activation on Base Sepolia, which the bundled chain spec doesn't have yet.
+    const BASE_SEPOLIA_ISTHMUS_TIMESTAMP: u64 = 1_744_905_600;
+
+    /// Rebuilds the header of a reference block with the fork fields derived from the chain spec, the sealed hash
+    /// has to match the one reported by the node. The Isthmus withdrawals root is taken from the reference, as it
+    /// needs the block's state, see [`seals_and_executes_per_hardfork`] for the computed one.
+    fn assert_sealed_hash_matches(block_number: u64, fork: OpHardfork) {
+        assert_sealed_hash_matches_with(&BASE_SEPOLIA, block_number, fork);
+    }
+
+    fn assert_sealed_hash_matches_with(chain_spec: &OpChainSpec, block_number: u64, fork: OpHardfork) {
+        let rt = tokio::runtime::Runtime::new().unwrap();
+        let provider = ProviderBuilder::new().network().on_http(Url::parse(TEST_BASE_SEPOLIA_RPC_URL).unwrap());
+        let block = rt.block_on(fetch_block(block_number, &provider));
+
+        assert!(chain_spec.fork(fork).active_at_timestamp(block.timestamp), "{fork} not active at {block_number}");
+
+        let reference = block.header.clone();
+        let mut header = Header {
+            withdrawals_root: None,
+            parent_beacon_block_root: None,
+            blob_gas_used: None,
+            excess_blob_gas: None,
+            requests_hash: None,
+            ..reference.clone()
+        };
+        ForkFields::new(chain_spec, header.timestamp, reference.parent_beacon_block_root, || {
+            reference.withdrawals_root.expect("Isthmus block without withdrawals root")
+        })
+        .apply(&mut header);
+
+        assert_eq!(header.hash_slow(), reference.hash_slow());
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_regolith() {
+        assert_sealed_hash_matches(1_000_000, OpHardfork::Regolith);
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_canyon() {
+        assert_sealed_hash_matches(2_106_456, OpHardfork::Canyon);
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_ecotone() {
+        assert_sealed_hash_matches(6_383_256, OpHardfork::Ecotone);
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_fjord() {
+        assert_sealed_hash_matches(10_615_056, OpHardfork::Fjord);
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_granite() {
+        assert_sealed_hash_matches(14_000_000, OpHardfork::Granite);
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_holocene() {
+        assert_sealed_hash_matches(19_000_000, OpHardfork::Holocene);
+    }
+
+    #[ignore = "Requires RPC access"]
+    #[test]
+    fn sealed_hash_isthmus() {
+        let chain_spec = OpChainSpecBuilder::base_sepolia()
+            .with_fork(OpHardfork::Isthmus, ForkCondition::Timestamp(BASE_SEPOLIA_ISTHMUS_TIMESTAMP))
+            .build();
+        assert_sealed_hash_matches_with(&chain_spec, 25_000_000, OpHardfork::Isthmus);
+    }
+}
@@ -1,45 +1,59 @@
 use std::sync::Arc;
 
+use alloy_eips::eip7685::RequestsOrHash;
 use alloy_primitives::B256;
 use alloy_rpc_types::engine::{
     CancunPayloadFields, ExecutionPayload, ExecutionPayloadSidecar, ExecutionPayloadV3, ForkchoiceState,
+    ForkchoiceUpdated, PayloadError, PayloadId, PayloadStatus, PayloadStatusEnum, PraguePayloadFields,
 };
 use bop_common::{
     actor::Actor,
     communication::{
         messages::{
-            self, BlockFetch, BlockSyncError, BlockSyncMessage, EngineApi, SimulatorToSequencer,
-            SimulatorToSequencerMsg,
+            self, envelope_v4, payload_id_v3, BlockFetch, BlockSyncError, BlockSyncMessage, EngineApi,
+            EngineApiVersion, RpcError, RpcResult, SimulatorToSequencer, SimulatorToSequencerMsg,
         },
         Connections, ReceiversSpine, SendersSpine, SpineConnections, TrackedSenders,
     },
     db::DatabaseWrite,
+    metrics::{SEAL_BLOCK_DURATION, SEAL_FRAG_DURATION, SEQUENCER_STATE, TX_POOL_ACTIVE_TXS, TX_POOL_SENDERS},
     p2p::{EnvV0, VersionedMessage},
     shared::SharedState,
-    time::{Duration, Repeater},
+    shutdown::StopToken,
+    time::{Duration, Instant, Repeater},
     transaction::Transaction,
 };
 use bop_db::DatabaseRead;
+use metrics::{gauge, histogram};
 use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpPayloadAttributes};
+use reth_optimism_forks::OpHardforks;
 use reth_optimism_primitives::OpTransactionSigned;
 use reth_primitives::BlockWithSenders;
 use reth_primitives_traits::SignedTransaction;
 use revm::DatabaseRef;
 use sorting::FragSequence;
-use strum_macros::AsRefStr;
-use tokio::sync::oneshot;
+use strum_macros::{AsRefStr, IntoStaticStr};
 
 pub mod block_sync;
 pub mod config;
 mod context;
+pub mod follower;
+pub mod header;
+pub mod replay;
 pub mod simulator;
 pub(crate) mod sorting;
+pub mod watcher;
 
 pub use config::SequencerConfig;
 use context::SequencerContext;
+use replay::SequencerInput;
 pub use simulator::Simulator;
 use sorting::SortingData;
-use tracing::{info, warn};
+use tracing::{error, info, warn};
+
+fn invalid_payload_status(err: BlockSyncError, latest_valid_hash: Option<B256>) -> PayloadStatus {
+    PayloadStatus::new(PayloadStatusEnum::Invalid { validation_error: err.to_string() }, latest_valid_hash)
+}
 
 pub fn payload_to_block(
     payload: ExecutionPayload,
@@ -60,6 +74,9 @@ pub struct Sequencer<Db> {
     state: SequencerState<Db>,
     data: SequencerContext<Db>,
     heartbeat: Repeater,
+    cache_report: Repeater,
+    /// State last exported to metrics.
+    reported_state: Option<&'static str>,
 }
 
 impl<Db: DatabaseRead> Sequencer<Db> {
@@ -68,10 +85,23 @@ impl<Db: DatabaseRead> Sequencer<Db> {
             state: SequencerState::default(),
             data: SequencerContext::new(db, shared_state, config),
             heartbeat: Repeater::every(Duration::from_secs(2)),
+            cache_report: Repeater::every(Duration::from_secs(60)),
+            reported_state: None,
         }
     }
 }
 
+impl<Db> Sequencer<Db> {
+    /// What a tick can change, ticks that don't change it aren't recorded.
+    fn progress(&self) -> (Option<u64>, Option<(usize, usize, usize, bool)>, usize) {
+        let (seq, sorting) = match &self.state {
+            SequencerState::Sorting(seq, sorting_data) => (Some(seq.next_seq), Some(sorting_data.progress())),
+            _ => (None, None),
+        };
+        (seq, sorting, self.data.deposits.len())
+    }
+}
+
 impl<Db> Actor<Db> for Sequencer<Db>
 where
     Db: DatabaseWrite + DatabaseRead,
@@ -85,40 +115,92 @@ where
         };
         // handle block sync
         connections.receive_for(block_sync_receive_duration, |msg, senders| {
+            self.data.record(|| SequencerInput::block_sync(&msg));
             let state = std::mem::take(&mut self.state);
             self.state = state.handle_block_sync(msg, &mut self.data, senders);
         });
 
         // handle new transaction
         connections.receive_for(Duration::from_millis(10), |msg, senders| {
+            self.data.record(|| SequencerInput::tx(&msg));
             self.state.handle_new_tx(msg, &mut self.data, senders);
         });
 
         // handle sim results
         connections.receive_for(Duration::from_millis(10), |msg, _| {
+            self.data.record(|| SequencerInput::SimResult((&msg).into()));
             let state = std::mem::take(&mut self.state);
             self.state = state.handle_sim_result(msg, &mut self.data);
         });
 
         // handle engine API messages from rpc
         connections.receive_for(Duration::from_millis(10), |msg: messages::EngineApi, senders| {
+            self.data.record(|| SequencerInput::EngineApi((&msg).into()));
             let state = std::mem::take(&mut self.state);
             self.state = state.handle_engine_api(msg, &mut self.data, senders);
         });
 
         // Check for passive state changes. e.g., sealing frags, sending sims, etc.
+        let progress = self.data.recorder.is_some().then(|| (Instant::now(), self.progress()));
         let state = std::mem::take(&mut self.state);
         self.state = state.tick(&mut self.data, connections);
+        if let Some((at, before)) = progress {
+            let after = self.progress();
+            if let Some(recorder) = self.data.recorder.as_mut().filter(|_| after != before) {
+                let sealed_frag = after.0 != before.0;
+                recorder.record(at, || SequencerInput::Tick { sealed_frag });
+            }
+        }
+
+        let state: &'static str = (&self.state).into();
+        if self.reported_state != Some(state) {
+            if let Some(previous) = self.reported_state {
+                gauge!(SEQUENCER_STATE, "state" => previous).set(0.0);
+            }
+            gauge!(SEQUENCER_STATE, "state" => state).set(1.0);
+            self.reported_state = Some(state);
+        }
 
         if self.heartbeat.fired() {
-            info!("in state {}", self.state.as_ref())
+            info!("in state {}", self.state.as_ref());
+            gauge!(TX_POOL_ACTIVE_TXS).set(self.data.tx_pool.num_active_txs() as f64);
+            gauge!(TX_POOL_SENDERS).set(self.data.tx_pool.num_senders() as f64);
+            if let Some(stats) = self.data.db.cache_stats() {
+                stats.record_metrics();
+            }
+        }
+
+        if self.cache_report.fired() {
+            if let Some(stats) = self.data.db.cache_stats() {
+                info!(%stats, "db read caches");
+            }
         }
     }
+
+    /// Keeps sorting until the block is fetched with GetPayload, unless the shutdown deadline gets too close.
+    fn ready_to_stop(&mut self, _connections: &mut SpineConnections<Db>, stop: &StopToken) -> bool {
+        !matches!(self.state, SequencerState::Sorting(..)) ||
+            stop.remaining().is_none_or(|remaining| remaining < Duration::from_millis(ABORT_BLOCK_BEFORE_DEADLINE_MS))
+    }
+
+    fn on_exit(mut self, connections: &mut SpineConnections<Db>) {
+        let SequencerState::Sorting(mut seq, sorting_data) = std::mem::take(&mut self.state) else {
+            return;
+        };
+
+        // Seal what was sorted so the frags gossiped so far are closed off, the block itself is never sealed
+        warn!(payload_id = ?self.data.payload_id, "aborting block for shutdown");
+        let last_frag = self.data.seal_last_frag(&mut seq, sorting_data);
+        connections.send(self.data.frag_message(last_frag));
+    }
 }
 
+/// Time before the shutdown deadline at which a block that is still being sorted gets aborted.
+const ABORT_BLOCK_BEFORE_DEADLINE_MS: u64 = 1000;
+
 /// Contains different states of the Sequencer state machine.
 /// The state is stored as a reference in the Sequencer struct.
-#[derive(Clone, Debug, Default, AsRefStr)]
+#[derive(Clone, Debug, Default, AsRefStr, IntoStaticStr)]
 pub enum SequencerState<Db> {
     /// Waiting for block sync
     Syncing {
@@ -170,13 +252,44 @@ where
         use EngineApi::*;
 
         match msg {
-            NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, .. } => {
-                self.handle_new_payload_engine_api(ctx, senders, payload, versioned_hashes, parent_beacon_block_root)
+            NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, res } => {
+                let sidecar =
+                    ExecutionPayloadSidecar::v3(CancunPayloadFields::new(parent_beacon_block_root, versioned_hashes));
+                let (state, status) = self.handle_new_payload_engine_api(ctx, senders, payload, sidecar);
+                if let Some(res) = res {
+                    let _ = res.send(status);
+                }
+                state
+            }
+            NewPayloadV4 { payload, versioned_hashes, parent_beacon_block_root, execution_requests, res } => {
+                let sidecar = ExecutionPayloadSidecar::v4(
+                    CancunPayloadFields::new(parent_beacon_block_root, versioned_hashes),
+                    PraguePayloadFields { requests: RequestsOrHash::Requests(execution_requests) },
+                );
+                let (state, status) = self.handle_new_payload_engine_api(ctx, senders, payload, sidecar);
+                if let Some(res) = res {
+                    let _ = res.send(status);
+                }
+                state
+            }
+            ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes, res } => {
+                let (state, updated) =
+                    self.handle_fork_choice_updated_engine_api(fork_choice_state, payload_attributes, ctx, senders);
+                if let Some(res) = res {
+                    let _ = res.send(updated);
+                }
+                state
+            }
+            GetPayloadV3 { payload_id, res } => {
+                self.handle_get_payload_engine_api(payload_id, EngineApiVersion::V3, ctx, senders, |envelope| {
+                    let _ = res.send(envelope);
+                })
             }
-            ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes, .. } => {
-                self.handle_fork_choice_updated_engine_api(fork_choice_state, payload_attributes, ctx, senders)
+            GetPayloadV4 { payload_id, res } => {
+                self.handle_get_payload_engine_api(payload_id, EngineApiVersion::V4, ctx, senders, |envelope| {
+                    let _ = res.send(envelope.map(envelope_v4));
+                })
             }
-            GetPayloadV3 { res, .. } => self.handle_get_payload_engine_api(res, ctx, senders),
         }
     }
 
@@ -186,31 +299,37 @@ where
     /// - Buffers it while waiting for fork choice confirmation
     /// - Triggers sync if we've fallen behind
     /// - Ignores if duplicate/old payload
+    ///
+    /// Returns the new state and the status to reply with:
+    /// - `VALID` if the payload was committed, or already was
+    /// - `INVALID` if the payload couldn't be decoded or failed execution
+    /// - `SYNCING` if we're missing blocks before the payload
+    /// - `ACCEPTED` if the payload was ignored while sorting
     fn handle_new_payload_engine_api(
         self,
         ctx: &mut SequencerContext<Db>,
         senders: &SendersSpine<Db>,
         payload: ExecutionPayloadV3,
-        versioned_hashes: Vec<B256>,
-        parent_beacon_block_root: B256,
-    ) -> SequencerState<Db> {
+        sidecar: ExecutionPayloadSidecar,
+    ) -> (SequencerState<Db>, PayloadStatus) {
         use SequencerState::*;
         if matches!(self, Sorting(_, _)) {
             warn!("Received NewPayload when state is Sorting. This is normally not a problem, but rare nonetheless.");
-            return self;
+            return (self, PayloadStatus::from_status(PayloadStatusEnum::Accepted));
         }
         let head_bn = ctx.db.head_block_number().expect("couldn't get db");
         let bn = payload.payload_inner.payload_inner.block_number;
         if bn > head_bn + 1 {
-            return Self::sync_until(head_bn + 1, bn, senders);
+            return (
+                Self::sync_until(head_bn + 1, bn, senders),
+                PayloadStatus::from_status(PayloadStatusEnum::Syncing),
+            );
         };
 
         match self {
             // Default path once synced. Apply and commit the payload.
             WaitingForNewPayload | WaitingForForkChoiceWithAttributes => {
                 let payload = ExecutionPayload::V3(payload);
-                let sidecar =
-                    ExecutionPayloadSidecar::v3(CancunPayloadFields::new(parent_beacon_block_root, versioned_hashes));
 
                 // Clear shared state for each NewPayload event
                 ctx.shared_state.reset();
@@ -219,23 +338,63 @@ where
                 let payload_hash = payload.block_hash();
                 // Check if we have already committed this payload.
                 if payload_hash == ctx.db.head_block_hash().expect("couldn't get db head block hash") {
-                    return WaitingForForkChoiceWithAttributes;
+                    return (
+                        WaitingForForkChoiceWithAttributes,
+                        PayloadStatus::new(PayloadStatusEnum::Valid, Some(payload_hash)),
+                    );
                 }
 
-                let block = payload_to_block(payload, sidecar).expect("couldn't get block from payload");
+                let parent_hash = payload.parent_hash();
+                let mut block = match payload_to_block(payload, sidecar) {
+                    Ok(block) => block,
+                    Err(err) => {
+                        warn!(%payload_hash, %err, "received invalid payload");
+                        return (self, invalid_payload_status(err, None));
+                    }
+                };
 
-                // Update sorting context
-                ctx.parent_header = block.header.clone();
-                ctx.parent_hash = payload_hash;
+                // From Isthmus the header commits to the message passer storage root, which the payload doesn't
+                // carry. Execute on top of the head to complete the header and check it against the payload hash.
+                if ctx.chain_spec().is_isthmus_active_at_timestamp(block.header.timestamp) {
+                    if parent_hash != ctx.db.head_block_hash().expect("couldn't get db head block hash") {
+                        return (
+                            Self::sync_until(bn.min(head_bn), bn, senders),
+                            PayloadStatus::from_status(PayloadStatusEnum::Syncing),
+                        );
+                    }
+                    if let Err(err) = ctx.block_executor.complete_payload_header(&mut block, &ctx.db) {
+                        warn!(%payload_hash, %err, "payload failed execution");
+                        return (self, invalid_payload_status(err.into(), Some(parent_hash)));
+                    }
+                    let execution = block.header.hash_slow();
+                    if execution != payload_hash {
+                        let err = PayloadError::BlockHash { execution, consensus: payload_hash };
+                        warn!(%payload_hash, %err, "received invalid payload");
+                        return (self, invalid_payload_status(err.into(), Some(parent_hash)));
+                    }
+                }
 
-                // Commit the block
-                if let Some((start, stop)) = ctx.commit_block(&block) {
-                    Self::sync_until(start, stop, senders)
-                } else {
-                    WaitingForForkChoiceWithAttributes
+                // Commit the block, this also updates the sorting context
+                match ctx.try_commit_block(&block, senders) {
+                    Ok(Some((start, stop))) => {
+                        (Self::sync_until(start, stop, senders), PayloadStatus::from_status(PayloadStatusEnum::Syncing))
+                    }
+                    Ok(None) => (
+                        WaitingForForkChoiceWithAttributes,
+                        PayloadStatus::new(PayloadStatusEnum::Valid, Some(payload_hash)),
+                    ),
+                    Err(err @ (BlockSyncError::Execution(_) | BlockSyncError::SignerRecovery)) => {
+                        warn!(%payload_hash, %err, "payload failed execution");
+                        (self, invalid_payload_status(err, Some(parent_hash)))
+                    }
+                    // Not a problem with the payload, e.g. a db error. The payload is sent again until we commit it
+                    Err(err) => {
+                        error!(%payload_hash, %err, "couldn't commit payload");
+                        (self, PayloadStatus::from_status(PayloadStatusEnum::Syncing))
+                    }
                 }
             }
-            _ => self,
+            _ => (self, PayloadStatus::from_status(PayloadStatusEnum::Syncing)),
         }
     }
 
@@ -243,16 +402,21 @@ where
     ///
     /// Two types of updates:
     /// 1. Without attributes - Confirms previous payload and triggers state update
-    /// 2. With attributes - Initiates new block building with provided parameters
+    /// 2. With attributes - Initiates new block building with provided parameters, replying with the id of the new
+    ///    payload
     fn handle_fork_choice_updated_engine_api(
         self,
         fork_choice_state: ForkchoiceState,
         payload_attributes: Option<Box<OpPayloadAttributes>>,
         ctx: &mut SequencerContext<Db>,
         senders: &SendersSpine<Db>,
-    ) -> SequencerState<Db> {
+    ) -> (SequencerState<Db>, ForkchoiceUpdated) {
         use SequencerState::*;
 
+        let head_block_hash = fork_choice_state.head_block_hash;
+        let valid = ForkchoiceUpdated::new(PayloadStatus::new(PayloadStatusEnum::Valid, Some(head_block_hash)));
+        let syncing = ForkchoiceUpdated::from_status(PayloadStatusEnum::Syncing);
+
         match self {
             // Waiting for new payload should not happen, but while testing
             // we can basically keep sequencing based on the same db state
@@ -269,9 +433,12 @@ where
                     Some(attributes) => {
                         // Don't start sequencing until we have a parent hash.
                         if ctx.parent_header.parent_hash == B256::ZERO {
-                            return self;
+                            return (self, syncing);
                         }
 
+                        let payload_id = payload_id_v3(head_block_hash, &attributes);
+                        ctx.payload_id = Some(payload_id);
+
                         ctx.timers.start_sequencing.start();
                         let (seq, first_frag) = ctx.start_sequencing(attributes, senders);
                         ctx.timers.start_sequencing.stop();
@@ -284,39 +451,45 @@ where
                         );
                         let _ = senders.send(VersionedMessage::from(env_msg));
 
-                        info!("start sorting with {} orders", first_frag.tof_snapshot.len());
-                        SequencerState::Sorting(seq, first_frag)
+                        info!(%payload_id, "start sorting with {} orders", first_frag.tof_snapshot.len());
+                        (SequencerState::Sorting(seq, first_frag), valid.with_payload_id(payload_id))
                     }
                     None => {
                         // Check that we are at this head.
-                        let head_block_hash = ctx.db.head_block_hash().expect("couldn't get db head block hash");
-                        if fork_choice_state.head_block_hash != head_block_hash {
+                        let db_head_hash = ctx.db.head_block_hash().expect("couldn't get db head block hash");
+                        if head_block_hash != db_head_hash {
                             // We are on the wrong head. Switch to syncing and request the head block.
                             let head_block_number =
                                 ctx.db.head_block_number().expect("couldn't get db head block number");
                             ctx.shared_state.reset();
-                            Self::sync_until(head_block_number, head_block_number, senders)
+                            (Self::sync_until(head_block_number, head_block_number, senders), syncing)
                         } else {
-                            WaitingForForkChoiceWithAttributes
+                            (WaitingForForkChoiceWithAttributes, valid)
                         }
                     }
                 }
             }
 
             Sorting(frag_seq, data) => {
-                let head_block_hash = ctx.db.head_block_hash().expect("couldn't get db head block hash");
-                if fork_choice_state.head_block_hash == head_block_hash {
-                    return Sorting(frag_seq, data);
+                let db_head_hash = ctx.db.head_block_hash().expect("couldn't get db head block hash");
+                if head_block_hash == db_head_hash {
+                    // A repeated update while building, point it to the payload being built
+                    let updated = match (payload_attributes, ctx.payload_id) {
+                        (Some(_), Some(payload_id)) => valid.with_payload_id(payload_id),
+                        _ => valid,
+                    };
+                    return (Sorting(frag_seq, data), updated);
                 }
                 warn!("received FCU when Sorting. Sending already Fragged txs back to the pools and syncing to the new head.");
                 for tx in frag_seq.txs.into_iter() {
                     ctx.handle_tx(tx.tx, senders);
                 }
+                ctx.payload_id = None;
                 let start = ctx.db.head_block_number().expect("couldn't get db head block number");
                 let stop = start + 1;
-                Self::sync_until(start, stop, senders)
+                (Self::sync_until(start, stop, senders), syncing)
             }
-            _ => self,
+            _ => (self, syncing),
         }
     }
 
@@ -327,47 +500,75 @@ where
     /// 2. Seals the block
     /// 3. Broadcasts block data to p2p network
     /// 4. Returns payload to consensus layer
+    ///
+    /// Requests for any payload other than the one being built are answered with an unknown payload error, and
+    /// requests with an endpoint `version` that doesn't match the fork of the payload with an unsupported fork error.
     fn handle_get_payload_engine_api(
         self,
-        res: oneshot::Sender<OpExecutionPayloadEnvelopeV3>,
+        payload_id: PayloadId,
+        version: EngineApiVersion,
         ctx: &mut SequencerContext<Db>,
         senders: &SendersSpine<Db>,
+        res: impl FnOnce(RpcResult<OpExecutionPayloadEnvelopeV3>),
     ) -> SequencerState<Db> {
         use SequencerState::*;
 
         match self {
-            Sorting(mut seq, sorting_data) => {
+            Sorting(..) if ctx.payload_id == Some(payload_id) &&
+                EngineApiVersion::for_timestamp(ctx.chain_spec(), ctx.timestamp()) != version =>
+            {
+                warn!(%payload_id, ?version, "received GetPayload with the wrong version for the fork");
+                res(Err(RpcError::UnsupportedFork));
+                self
+            }
+            Sorting(mut seq, sorting_data) if ctx.payload_id == Some(payload_id) => {
+                ctx.payload_id = None;
                 ctx.timers.waiting_for_sims.stop();
                 ctx.timers.seal_block.start();
 
                 // Gossip last frag before sealing
                 let last_frag = ctx.seal_last_frag(&mut seq, sorting_data);
-                let s = senders.send_timeout(VersionedMessage::from(last_frag), Duration::from_millis(10));
+                let s = senders.send_timeout(ctx.frag_message(last_frag), Duration::from_millis(10));
                 debug_assert!(s.is_ok(), "couldn't send last frag for 10 millis");
 
-                let (seal, block) = ctx.seal_block(seq);
+                let (seal, block, header) = match ctx.seal_block(seq) {
+                    Ok(sealed) => sealed,
+                    Err(err) => {
+                        error!(%payload_id, %err, "couldn't seal block");
+                        res(Err(err.into()));
+                        ctx.timers.seal_block.stop();
+                        ctx.shared_state.reset();
+                        return WaitingForNewPayload;
+                    }
+                };
 
                 // Gossip seal to p2p and return payload to rpc
                 let s = senders.send_timeout(VersionedMessage::from(seal), Duration::from_millis(10));
                 debug_assert!(s.is_ok(), "couldn't send seal for 10 millis");
-                let s = res.send(block.clone());
-                debug_assert!(s.is_ok(), "couldn't send block envelope to rpc");
+                res(Ok(block.clone()));
                 ctx.timers.seal_block.stop();
+                histogram!(SEAL_BLOCK_DURATION).record(ctx.timers.seal_block.elapsed().as_secs());
 
                 // Commit the block to the db
                 if ctx.config.commit_sealed_frags_to_db {
                     let sidecar =
                         ExecutionPayloadSidecar::v3(CancunPayloadFields::new(block.parent_beacon_block_root, vec![]));
-                    let block = payload_to_block(ExecutionPayload::V3(block.execution_payload), sidecar)
+                    let mut block = payload_to_block(ExecutionPayload::V3(block.execution_payload), sidecar)
                         .expect("couldn't get block from payload");
-                    ctx.commit_block(&block);
+                    // The payload doesn't carry all fork fields, commit with the header that was sealed
+                    block.block.header = header;
+                    ctx.commit_block(&block, senders);
                     ctx.shared_state.reset();
                     info!("committing to db");
                 }
 
                 WaitingForNewPayload
             }
-            s => s,
+            s => {
+                warn!(%payload_id, current = ?ctx.payload_id, "received GetPayload for unknown payload");
+                res(Err(RpcError::UnknownPayload));
+                s
+            }
         }
     }
 
@@ -388,7 +589,7 @@ where
 
         match self {
             Syncing { last_block_number } => {
-                if let Some((start, stop)) = ctx.commit_block(&block) {
+                if let Some((start, stop)) = ctx.commit_block(&block, senders) {
                     Self::sync_until(start, last_block_number.max(stop), senders)
                 } else if block.number != last_block_number {
                     Syncing { last_block_number }
@@ -399,7 +600,7 @@ where
             }
 
             WaitingForNewPayload | WaitingForForkChoiceWithAttributes => {
-                ctx.commit_block(&block);
+                ctx.commit_block(&block, senders);
                 WaitingForNewPayload
             }
             _ => {
@@ -475,9 +676,10 @@ impl<Db: Clone + DatabaseRef> SequencerState<Db> {
                 // Reset the tx pool.
                 data.tx_pool.remove_mined_txs(sorting_data.txs.iter());
                 let (msg, new_sort_dat) = data.seal_frag(sorting_data, &mut seq);
-                connections.send(VersionedMessage::from(msg));
+                connections.send(data.frag_message(msg));
 
                 data.timers.seal_frag.stop();
+                histogram!(SEAL_FRAG_DURATION).record(data.timers.seal_frag.elapsed().as_secs());
                 info!("start sorting with {} orders", new_sort_dat.tof_snapshot.len());
                 Sorting(seq, new_sort_dat)
             }
@@ -486,7 +688,7 @@ impl<Db: Clone + DatabaseRef> SequencerState<Db> {
                 sorting_data.maybe_apply(base_fee);
 
                 data.timers.handle_deposits.start();
-                sorting_data.handle_deposits(&mut data.deposits, connections);
+                sorting_data.handle_deposits(&mut data.deposits, connections, &mut data.recorder);
                 data.timers.handle_deposits.stop();
 
                 data.timers.send_next.start();
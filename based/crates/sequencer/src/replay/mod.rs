@@ -0,0 +1,496 @@
+//! Records the inputs of the [`Sequencer`](crate::Sequencer) and replays them with the timing they were received
+//! with, to reproduce the frags and blocks of a run offline.
+//!
+//! A recording is a snappy framed file of JSON lines, a [`RecordingHeader`] followed by one [`Record`] per input.
+//! Simulation results are recorded with their state changes, so a replay doesn't run any simulations and only needs a
+//! copy of the database at the head the recording started on. Ticks of the state machine are recorded when they
+//! changed the state, e.g. sealed a frag, and run at the same virtual time in the replay.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Write},
+    path::Path,
+};
+
+use alloy_eips::eip7685::Requests;
+use alloy_primitives::{Address, Bytes, B256, U256};
+use alloy_rpc_types::engine::{ExecutionPayloadV3, ForkchoiceState, PayloadId};
+use bop_common::{
+    communication::messages::{
+        BlockSyncMessage, EngineApi, SimulationError, SimulatorToSequencer, SimulatorToSequencerMsg,
+    },
+    db::{peek_state_id, DatabaseRead, Error as DbError},
+    shared::SharedState,
+    time::{Duration, Instant, Nanos},
+    transaction::Transaction,
+};
+use eyre::eyre;
+use op_alloy_rpc_types_engine::OpPayloadAttributes;
+use reqwest::Url;
+use reth_optimism_evm::OpEvmConfig;
+use revm_primitives::ResultAndState;
+use serde::{Deserialize, Serialize};
+use snap::{read::FrameDecoder, write::FrameEncoder};
+use tracing::warn;
+
+use crate::SequencerConfig;
+
+#[cfg(any(test, feature = "replay"))]
+mod runner;
+#[cfg(any(test, feature = "replay"))]
+pub use runner::{Replay, ReplayOutput};
+
+/// Where the recording starts, and the sequencer settings that change how inputs are handled.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RecordingHeader {
+    pub head_block_number: u64,
+    pub head_block_hash: B256,
+    /// State id of the shared frag db when the sequencer started
+    pub shared_state_id: u64,
+    /// Next state id handed out on the sequencer thread, sims are matched to states by id
+    pub next_state_id: u64,
+    pub frag_duration_ns: u64,
+    pub n_per_loop: usize,
+    pub simulate_tof_in_pools: bool,
+    pub commit_sealed_frags_to_db: bool,
+    pub gossip_frag_v1: bool,
+}
+
+impl RecordingHeader {
+    pub fn new<Db: DatabaseRead>(
+        db: &Db,
+        shared_state: &SharedState<Db>,
+        config: &SequencerConfig,
+    ) -> Result<Self, DbError> {
+        Ok(Self {
+            head_block_number: db.head_block_number()?,
+            head_block_hash: db.head_block_hash()?,
+            shared_state_id: shared_state.as_ref().state_id(),
+            next_state_id: peek_state_id(),
+            frag_duration_ns: Nanos::from(config.frag_duration).0,
+            n_per_loop: config.n_per_loop,
+            simulate_tof_in_pools: config.simulate_tof_in_pools,
+            commit_sealed_frags_to_db: config.commit_sealed_frags_to_db,
+            gossip_frag_v1: config.gossip_frag_v1,
+        })
+    }
+
+    /// Config of the recorded sequencer. `rpc_url` isn't used by the sequencer itself.
+    pub fn config(&self, evm_config: OpEvmConfig, rpc_url: Url) -> SequencerConfig {
+        SequencerConfig {
+            frag_duration: Duration::from(Nanos(self.frag_duration_ns)),
+            n_per_loop: self.n_per_loop,
+            rpc_url,
+            evm_config,
+            simulate_tof_in_pools: self.simulate_tof_in_pools,
+            commit_sealed_frags_to_db: self.commit_sealed_frags_to_db,
+            gossip_frag_v1: self.gossip_frag_v1,
+            record_path: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    /// Nanos since the recording started
+    pub at: u64,
+    pub input: SequencerInput,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum SequencerInput {
+    EngineApi(RecordedEngineApi),
+    /// EIP-2718 encoded transaction
+    Tx(Bytes),
+    /// RLP encoded block
+    BlockSync {
+        block: Bytes,
+        senders: Vec<Address>,
+    },
+    SimResult(RecordedSim),
+    /// Sim result received by a tick that waited for deposits to be simulated, it's queued for the next tick.
+    DepositSimResult(RecordedSim),
+    Tick {
+        sealed_frag: bool,
+    },
+}
+
+impl SequencerInput {
+    pub fn tx(tx: &Transaction) -> Self {
+        Self::Tx(tx.encode())
+    }
+
+    pub fn block_sync(block: &BlockSyncMessage) -> Self {
+        Self::BlockSync { block: alloy_rlp::encode(&block.block).into(), senders: block.senders.clone() }
+    }
+}
+
+/// An [`EngineApi`] message without the channel the response is sent on.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum RecordedEngineApi {
+    ForkChoiceUpdatedV3 {
+        fork_choice_state: ForkchoiceState,
+        payload_attributes: Option<Box<OpPayloadAttributes>>,
+    },
+    NewPayloadV3 {
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+    },
+    NewPayloadV4 {
+        payload: ExecutionPayloadV3,
+        versioned_hashes: Vec<B256>,
+        parent_beacon_block_root: B256,
+        execution_requests: Requests,
+    },
+    GetPayloadV3 {
+        payload_id: PayloadId,
+    },
+    GetPayloadV4 {
+        payload_id: PayloadId,
+    },
+}
+
+impl From<&EngineApi> for RecordedEngineApi {
+    fn from(msg: &EngineApi) -> Self {
+        match msg {
+            EngineApi::ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes, .. } => Self::ForkChoiceUpdatedV3 {
+                fork_choice_state: *fork_choice_state,
+                payload_attributes: payload_attributes.clone(),
+            },
+            EngineApi::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, .. } => Self::NewPayloadV3 {
+                payload: payload.clone(),
+                versioned_hashes: versioned_hashes.clone(),
+                parent_beacon_block_root: *parent_beacon_block_root,
+            },
+            EngineApi::NewPayloadV4 {
+                payload, versioned_hashes, parent_beacon_block_root, execution_requests, ..
+            } => Self::NewPayloadV4 {
+                payload: payload.clone(),
+                versioned_hashes: versioned_hashes.clone(),
+                parent_beacon_block_root: *parent_beacon_block_root,
+                execution_requests: execution_requests.clone(),
+            },
+            EngineApi::GetPayloadV3 { payload_id, .. } => Self::GetPayloadV3 { payload_id: *payload_id },
+            EngineApi::GetPayloadV4 { payload_id, .. } => Self::GetPayloadV4 { payload_id: *payload_id },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedSim {
+    pub sender: Address,
+    pub nonce: u64,
+    pub state_id: u64,
+    pub simtime_ns: u64,
+    pub top_of_frag: bool,
+    pub result: Result<RecordedSimulatedTx, SimulationError>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RecordedSimulatedTx {
+    /// EIP-2718 encoded transaction
+    pub tx: Bytes,
+    pub result_and_state: ResultAndState,
+    pub payment: U256,
+    pub deposit_nonce: Option<u64>,
+}
+
+impl From<&SimulatorToSequencer> for RecordedSim {
+    fn from(msg: &SimulatorToSequencer) -> Self {
+        let (top_of_frag, result) = match &msg.msg {
+            SimulatorToSequencerMsg::Tx(result) => (false, result),
+            SimulatorToSequencerMsg::TxPoolTopOfFrag(result) => (true, result),
+        };
+        let result = result.as_ref().map_err(Clone::clone).map(|simulated| RecordedSimulatedTx {
+            tx: simulated.tx.encode(),
+            result_and_state: simulated.result_and_state.clone(),
+            payment: simulated.payment,
+            deposit_nonce: simulated.deposit_nonce,
+        });
+
+        Self {
+            sender: msg.sender_info.0,
+            nonce: msg.sender_info.1,
+            state_id: msg.state_id,
+            simtime_ns: Nanos::from(msg.simtime).0,
+            top_of_frag,
+            result,
+        }
+    }
+}
+
+/// Writes the inputs of the sequencer to a recording. Recording stops on the first write error, so a full disk
+/// doesn't take down the sequencer.
+pub struct Recorder {
+    writer: FrameEncoder<File>,
+    start: Instant,
+    failed: bool,
+}
+
+impl Recorder {
+    pub fn create(path: &Path, header: &RecordingHeader) -> io::Result<Self> {
+        let mut recorder =
+            Self { writer: FrameEncoder::new(File::create(path)?), start: Instant::now(), failed: false };
+        recorder.write_line(header)?;
+        Ok(recorder)
+    }
+
+    /// Records an input received at `at`, the input is only built while recording.
+    pub fn record(&mut self, at: Instant, input: impl FnOnce() -> SequencerInput) {
+        if self.failed {
+            return;
+        }
+
+        let record = Record { at: Nanos::from(at.saturating_sub(&self.start)).0, input: input() };
+        // flush at block boundaries, so a recording of a crashed sequencer has all blocks up to the crash
+        let flush = matches!(record.input, SequencerInput::EngineApi(_));
+        let res = self.write_line(&record).and_then(|_| if flush { self.writer.flush() } else { Ok(()) });
+        if let Err(err) = res {
+            warn!(%err, "couldn't record sequencer input, stopping recording");
+            self.failed = true;
+        }
+    }
+
+    fn write_line(&mut self, value: &impl Serialize) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, value)?;
+        self.writer.write_all(b"\n")
+    }
+}
+
+impl Drop for Recorder {
+    fn drop(&mut self) {
+        let _ = self.writer.flush();
+    }
+}
+
+/// Reads the header of a recording, and returns an iterator over its records.
+pub fn read_recording(path: &Path) -> eyre::Result<(RecordingHeader, impl Iterator<Item = eyre::Result<Record>>)> {
+    let mut lines = BufReader::new(FrameDecoder::new(File::open(path)?)).lines();
+    let header = lines.next().ok_or_else(|| eyre!("empty recording"))??;
+    let header = serde_json::from_str(&header)?;
+    let records = lines.map(|line| Ok(serde_json::from_str(&line?)?));
+    Ok((header, records))
+}
+
+#[cfg(test)]
+mod tests {
+    use alloy_consensus::{Header, Sealable};
+    use alloy_eips::eip2718::Encodable2718;
+    use alloy_primitives::TxKind;
+    use alloy_rpc_types::engine::PayloadAttributes;
+    use bop_common::{
+        actor::{Actor, ActorConfig},
+        communication::Spine,
+        p2p::VersionedMessage,
+        shutdown::{Shutdown, Stage},
+    };
+    use bop_db::{test_utils::TestDatadir, SequencerDB};
+    use op_alloy_consensus::{OpTxEnvelope, TxDeposit};
+    use reth_optimism_chainspec::BASE_SEPOLIA;
+    use reth_optimism_primitives::OpBlock;
+    use reth_primitives::BlockWithSenders;
+    use tokio::sync::oneshot;
+
+    use super::*;
+    use crate::{Sequencer, Simulator};
+
+    /// Empty block 1 on top of the Base Sepolia genesis, it doesn't change the state.
+    fn block_1() -> BlockSyncMessage {
+        let genesis = BASE_SEPOLIA.genesis_header();
+        let header = Header {
+            parent_hash: BASE_SEPOLIA.genesis_hash(),
+            number: 1,
+            timestamp: genesis.timestamp + 2,
+            gas_limit: genesis.gas_limit,
+            base_fee_per_gas: genesis.base_fee_per_gas,
+            state_root: genesis.state_root,
+            ..Default::default()
+        };
+        BlockWithSenders { block: OpBlock { header, body: Default::default() }, senders: vec![] }
+    }
+
+    /// Attributes of block 2 with a deposit, so the block has a sim result to replay.
+    fn block_2_attributes(parent: &Header) -> Box<OpPayloadAttributes> {
+        let deposit = TxDeposit {
+            source_hash: B256::repeat_byte(1),
+            from: Address::repeat_byte(2),
+            to: TxKind::Call(Address::repeat_byte(3)),
+            mint: Some(1_000_000),
+            value: U256::from(1_000),
+            gas_limit: 100_000,
+            is_system_transaction: false,
+            input: Bytes::new(),
+        };
+        Box::new(OpPayloadAttributes {
+            payload_attributes: PayloadAttributes {
+                timestamp: parent.timestamp + 2,
+                prev_randao: B256::ZERO,
+                suggested_fee_recipient: Address::ZERO,
+                withdrawals: None,
+                parent_beacon_block_root: Some(B256::ZERO),
+            },
+            transactions: Some(vec![OpTxEnvelope::Deposit(deposit.seal_slow()).encoded_2718().into()]),
+            no_tx_pool: None,
+            gas_limit: Some(parent.gas_limit),
+            eip_1559_params: None,
+        })
+    }
+
+    /// Runs a recording sequencer with simulators: syncs block 1, then builds block 2 with a deposit.
+    fn record_block(db: SequencerDB, path: &Path) -> ReplayOutput {
+        let evm_config = OpEvmConfig::new(BASE_SEPOLIA.clone());
+        let config = SequencerConfig {
+            frag_duration: Duration::from_millis(50),
+            n_per_loop: 2,
+            rpc_url: Url::parse("http://localhost:8545").unwrap(),
+            evm_config: evm_config.clone(),
+            simulate_tof_in_pools: false,
+            commit_sealed_frags_to_db: false,
+            gossip_frag_v1: false,
+            record_path: Some(path.to_path_buf()),
+        };
+
+        let spine = Spine::default();
+        let shared_state = SharedState::new(db.clone().into());
+        let shutdown = Shutdown::new(Duration::from_secs(5));
+
+        std::thread::scope(|s| {
+            let stop = shutdown.register(Stage::Sequencer);
+            let connections = spine.to_connections("Sequencer");
+            let (sequencer_db, state) = (db.clone(), shared_state.clone());
+            s.spawn(move || Sequencer::new(sequencer_db, state, config).run(connections, ActorConfig::default(), stop));
+            for id in 0..2 {
+                let connections = spine.to_connections(format!("Simulator-{id}").as_str());
+                let (db_frag, stop, evm_config) =
+                    ((&shared_state).into(), shutdown.register(Stage::Simulators), &evm_config);
+                s.spawn(move || Simulator::new(db_frag, evm_config, id).run(connections, ActorConfig::default(), stop));
+            }
+
+            let mut connections = spine.to_connections("Test");
+            let mut output = ReplayOutput::default();
+            let block_1 = block_1();
+            let parent = block_1.block.header.clone();
+            connections.send(block_1);
+            let deadline = Instant::now() + Duration::from_secs(5);
+            while db.head_block_number().unwrap() != 1 {
+                assert!(Instant::now() < deadline, "block 1 wasn't synced");
+                std::thread::sleep(std::time::Duration::from_millis(1));
+            }
+
+            let (res, rx) = oneshot::channel();
+            connections.send(EngineApi::ForkChoiceUpdatedV3 {
+                fork_choice_state: ForkchoiceState { head_block_hash: parent.hash_slow(), ..Default::default() },
+                payload_attributes: Some(block_2_attributes(&parent)),
+                res: Some(res),
+            });
+            let payload_id = rx.blocking_recv().unwrap().payload_id.expect("no payload id");
+
+            // Wait until the deposit is sealed in a frag
+            let mut deposit_sealed = false;
+            while !deposit_sealed {
+                assert!(Instant::now() < deadline, "deposit wasn't sealed");
+                connections.receive(|msg: VersionedMessage, _| {
+                    deposit_sealed |= matches!(&msg, VersionedMessage::FragV0(frag) if !frag.txs.is_empty());
+                    output.messages.push(msg);
+                });
+            }
+
+            let (res, rx) = oneshot::channel();
+            connections.send(EngineApi::GetPayloadV3 { payload_id, res });
+            output.payloads.push(rx.blocking_recv().unwrap().unwrap());
+
+            let mut sealed = false;
+            while !sealed {
+                assert!(Instant::now() < deadline, "block wasn't sealed");
+                connections.receive(|msg: VersionedMessage, _| {
+                    sealed |= matches!(msg, VersionedMessage::SealV0(_));
+                    output.messages.push(msg);
+                });
+            }
+
+            shutdown.shutdown();
+            output
+        })
+    }
+
+    #[test]
+    fn replay_reproduces_recorded_block() {
+        let dir = TestDatadir::new();
+        let path = dir.path().join("recording.sz");
+        let recorded_dir = TestDatadir::new();
+        let recorded = record_block(recorded_dir.init(BASE_SEPOLIA.clone()), &path);
+
+        let replayed_dir = TestDatadir::new();
+        let db = replayed_dir.init(BASE_SEPOLIA.clone());
+        let (header, records) = read_recording(&path).unwrap();
+        let config =
+            header.config(OpEvmConfig::new(BASE_SEPOLIA.clone()), Url::parse("http://localhost:8545").unwrap());
+        let replayed = Replay::new(db, &header, config).unwrap().run(records).unwrap();
+
+        let frags = |output: &ReplayOutput| {
+            output
+                .messages
+                .iter()
+                .filter(|msg| matches!(msg, VersionedMessage::FragV0(_) | VersionedMessage::SealV0(_)))
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert!(frags(&recorded).len() > 2, "expected frags and a seal, got {:?}", recorded.messages);
+        assert_eq!(frags(&replayed), frags(&recorded));
+
+        let block_hash =
+            |output: &ReplayOutput| output.payloads[0].execution_payload.payload_inner.payload_inner.block_hash;
+        assert_eq!(replayed.payloads.len(), 1);
+        assert_eq!(block_hash(&replayed), block_hash(&recorded));
+    }
+
+    #[test]
+    fn reads_what_was_recorded() {
+        let dir = TestDatadir::new();
+        let path = dir.path().join("recording.sz");
+
+        let header = RecordingHeader {
+            head_block_number: 1,
+            head_block_hash: B256::repeat_byte(1),
+            shared_state_id: 2,
+            next_state_id: 3,
+            frag_duration_ns: 200_000_000,
+            n_per_loop: 5,
+            simulate_tof_in_pools: false,
+            commit_sealed_frags_to_db: false,
+            gossip_frag_v1: true,
+        };
+        let sim = SimulatorToSequencer::new(
+            (Address::repeat_byte(2), 7),
+            3,
+            Duration::from_micros(10),
+            SimulatorToSequencerMsg::Tx(Err(SimulationError::ZeroPayment)),
+        );
+
+        let mut recorder = Recorder::create(&path, &header).unwrap();
+        let start = recorder.start;
+        recorder.record(start + Duration::from_millis(1), || SequencerInput::SimResult((&sim).into()));
+        recorder.record(start + Duration::from_millis(2), || SequencerInput::Tick { sealed_frag: true });
+        drop(recorder);
+
+        let (read_header, records) = read_recording(&path).unwrap();
+        let records = records.collect::<eyre::Result<Vec<_>>>().unwrap();
+
+        assert_eq!(read_header, header);
+        assert_eq!(records.len(), 2);
+        assert!(records[0].at < records[1].at);
+        let SequencerInput::SimResult(recorded) = &records[0].input else {
+            panic!("expected a sim result, got {:?}", records[0].input);
+        };
+        let replayed = recorded.clone().into_message().unwrap();
+        assert_eq!(replayed.sender_info, sim.sender_info);
+        assert_eq!(replayed.state_id, 3);
+        assert!(matches!(replayed.msg, SimulatorToSequencerMsg::Tx(Err(SimulationError::ZeroPayment))));
+        assert!(matches!(records[1].input, SequencerInput::Tick { sealed_frag: true }));
+    }
+}
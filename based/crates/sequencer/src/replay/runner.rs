@@ -0,0 +1,205 @@
+//! Replays a recording on the sequencer state machine. Only compiled with the `replay` feature, which enables the
+//! virtual clock of [`Instant::now`].
+
+use std::sync::Arc;
+
+use alloy_rlp::Decodable;
+use bop_common::{
+    communication::{
+        messages::{
+            envelope_v3, BlockFetch, EngineApi, RpcResult, SequencerToSimulator, SimulatorToSequencer,
+            SimulatorToSequencerMsg,
+        },
+        Spine, SpineConnections,
+    },
+    db::{set_next_state_id, DBFrag, DatabaseRead, DatabaseWrite},
+    p2p::VersionedMessage,
+    shared::SharedState,
+    time::{set_virtual_now, Duration, Instant, Nanos},
+    transaction::{SimulatedTx, Transaction},
+};
+use eyre::ensure;
+use op_alloy_rpc_types_engine::{OpExecutionPayloadEnvelopeV3, OpExecutionPayloadEnvelopeV4};
+use reth_optimism_primitives::OpBlock;
+use reth_primitives::BlockWithSenders;
+use tokio::sync::oneshot;
+
+use super::{Record, RecordedEngineApi, RecordedSim, RecordingHeader, SequencerInput};
+use crate::{context::SequencerContext, SequencerConfig, SequencerState};
+
+enum PendingPayload {
+    V3(oneshot::Receiver<RpcResult<OpExecutionPayloadEnvelopeV3>>),
+    V4(oneshot::Receiver<RpcResult<OpExecutionPayloadEnvelopeV4>>),
+}
+
+impl PendingPayload {
+    fn try_take(self) -> Option<OpExecutionPayloadEnvelopeV3> {
+        match self {
+            PendingPayload::V3(mut rx) => rx.try_recv().ok()?.ok(),
+            PendingPayload::V4(mut rx) => rx.try_recv().ok()?.ok().map(envelope_v3),
+        }
+    }
+}
+
+impl RecordedEngineApi {
+    fn into_engine_api(self) -> (EngineApi, Option<PendingPayload>) {
+        match self {
+            Self::ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes } => {
+                (EngineApi::ForkChoiceUpdatedV3 { fork_choice_state, payload_attributes, res: None }, None)
+            }
+            Self::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root } => {
+                (EngineApi::NewPayloadV3 { payload, versioned_hashes, parent_beacon_block_root, res: None }, None)
+            }
+            Self::NewPayloadV4 { payload, versioned_hashes, parent_beacon_block_root, execution_requests } => (
+                EngineApi::NewPayloadV4 {
+                    payload,
+                    versioned_hashes,
+                    parent_beacon_block_root,
+                    execution_requests,
+                    res: None,
+                },
+                None,
+            ),
+            Self::GetPayloadV3 { payload_id } => {
+                let (res, rx) = oneshot::channel();
+                (EngineApi::GetPayloadV3 { payload_id, res }, Some(PendingPayload::V3(rx)))
+            }
+            Self::GetPayloadV4 { payload_id } => {
+                let (res, rx) = oneshot::channel();
+                (EngineApi::GetPayloadV4 { payload_id, res }, Some(PendingPayload::V4(rx)))
+            }
+        }
+    }
+}
+
+impl RecordedSim {
+    pub(super) fn into_message(self) -> Result<SimulatorToSequencer, alloy_rlp::Error> {
+        let result = match self.result {
+            Ok(simulated) => Ok(SimulatedTx::new(
+                Arc::new(Transaction::decode(simulated.tx)?),
+                simulated.result_and_state,
+                simulated.payment,
+                simulated.deposit_nonce,
+            )),
+            Err(err) => Err(err),
+        };
+        let msg = if self.top_of_frag {
+            SimulatorToSequencerMsg::TxPoolTopOfFrag(result)
+        } else {
+            SimulatorToSequencerMsg::Tx(result)
+        };
+        Ok(SimulatorToSequencer::new(
+            (self.sender, self.nonce),
+            self.state_id,
+            Duration::from(Nanos(self.simtime_ns)),
+            msg,
+        ))
+    }
+}
+
+/// What the sequencer sent out during a replay.
+#[derive(Debug, Default)]
+pub struct ReplayOutput {
+    /// Gossiped messages, in order
+    pub messages: Vec<VersionedMessage>,
+    /// Payloads returned to GetPayload, in order
+    pub payloads: Vec<OpExecutionPayloadEnvelopeV3>,
+}
+
+/// Feeds recorded inputs to the sequencer state machine on the current thread, with the clock set to the time each
+/// input was recorded at. Sims and block fetches the sequencer requests are dropped, their results are recorded.
+pub struct Replay<Db> {
+    state: SequencerState<Db>,
+    ctx: SequencerContext<Db>,
+    connections: SpineConnections<Db>,
+    outputs: SpineConnections<Db>,
+    start: Instant,
+}
+
+impl<Db: DatabaseWrite + DatabaseRead> Replay<Db> {
+    /// `db` must be at the head the recording started on, e.g. a copy of the recording gateway's database.
+    pub fn new(db: Db, header: &RecordingHeader, config: SequencerConfig) -> eyre::Result<Self> {
+        let head = (db.head_block_number()?, db.head_block_hash()?);
+        ensure!(
+            head == (header.head_block_number, header.head_block_hash),
+            "db is at block {} {}, the recording starts at {} {}",
+            head.0,
+            head.1,
+            header.head_block_number,
+            header.head_block_hash
+        );
+
+        set_next_state_id(header.shared_state_id);
+        let shared_state = SharedState::new(DBFrag::from(db.clone()));
+        set_next_state_id(header.next_state_id);
+
+        let spine = Spine::default();
+        Ok(Self {
+            state: SequencerState::default(),
+            ctx: SequencerContext::new(db, shared_state, config),
+            connections: spine.to_connections("Sequencer"),
+            outputs: spine.to_connections("Replay"),
+            start: Instant::now(),
+        })
+    }
+
+    pub fn run(mut self, records: impl IntoIterator<Item = eyre::Result<Record>>) -> eyre::Result<ReplayOutput> {
+        let mut output = ReplayOutput::default();
+        let res = records.into_iter().try_for_each(|record| self.apply(record?, &mut output));
+        set_virtual_now(None);
+        res.map(|_| output)
+    }
+
+    fn apply(&mut self, record: Record, output: &mut ReplayOutput) -> eyre::Result<()> {
+        let mut now = self.start + Duration::from(Nanos(record.at));
+
+        let state = std::mem::take(&mut self.state);
+        self.state = match record.input {
+            SequencerInput::EngineApi(msg) => {
+                set_virtual_now(Some(now));
+                let (msg, pending) = msg.into_engine_api();
+                let state = state.handle_engine_api(msg, &mut self.ctx, self.connections.senders());
+                output.payloads.extend(pending.and_then(PendingPayload::try_take));
+                state
+            }
+            SequencerInput::Tx(tx) => {
+                set_virtual_now(Some(now));
+                let mut state = state;
+                state.handle_new_tx(Arc::new(Transaction::decode(tx)?), &mut self.ctx, self.connections.senders());
+                state
+            }
+            SequencerInput::BlockSync { block, senders } => {
+                set_virtual_now(Some(now));
+                let block = OpBlock::decode(&mut block.as_ref())?;
+                let block = BlockWithSenders { block, senders };
+                state.handle_block_sync(block, &mut self.ctx, self.connections.senders())
+            }
+            SequencerInput::SimResult(sim) => {
+                set_virtual_now(Some(now));
+                state.handle_sim_result(sim.into_message()?, &mut self.ctx)
+            }
+            SequencerInput::DepositSimResult(sim) => {
+                self.outputs.send(sim.into_message()?);
+                state
+            }
+            SequencerInput::Tick { sealed_frag } => {
+                // The clock doesn't move while a tick runs, make sure the replay takes the same sealing decision as
+                // the recorded tick even though the frag deadline moved with it
+                if let SequencerState::Sorting(_, sorting_data) = &state {
+                    now = if sealed_frag {
+                        now.max(sorting_data.until + Duration(1))
+                    } else {
+                        now.min(sorting_data.until)
+                    };
+                }
+                set_virtual_now(Some(now));
+                state.tick(&mut self.ctx, &mut self.connections)
+            }
+        };
+
+        while self.outputs.receive(|msg: VersionedMessage, _| output.messages.push(msg)) {}
+        while self.outputs.receive(|_: SequencerToSimulator<Db>, _| {}) {}
+        while self.outputs.receive(|_: BlockFetch, _| {}) {}
+        Ok(())
+    }
+}
@@ -12,10 +12,12 @@ use bop_common::{
         SpineConnections, TrackedSenders,
     },
     db::{DBFrag, DBSorting, DatabaseRead, State},
+    metrics::SIM_DURATION,
     time::{Duration, Instant},
     transaction::{SimulatedTx, Transaction},
     utils::last_part_of_typename,
 };
+use metrics::histogram;
 use reth_evm::{execute::ProviderError, ConfigureEvm};
 use reth_optimism_evm::OpEvmConfig;
 use reth_optimism_forks::OpHardfork;
@@ -143,19 +145,43 @@ where
         });
 
         connections.receive(|msg: SequencerToSimulator<Db>, senders| {
-            let (sender, nonce, state_id) = msg.sim_info();
             let curt = Instant::now();
-            let msg =
-                match msg {
-                    SequencerToSimulator::SimulateTx(tx, db) => SimulatorToSequencerMsg::Tx(
-                        Self::simulate_transaction(tx, db, &mut self.evm_sorting, self.regolith_active, true, true),
-                    ),
-                    SequencerToSimulator::SimulateTxTof(tx, db) => SimulatorToSequencerMsg::TxPoolTopOfFrag(
-                        Self::simulate_transaction(tx, db, &mut self.evm_tof, self.regolith_active, true, true),
-                    ),
-                };
+            let (sender, nonce, state_id, msg) = match msg {
+                SequencerToSimulator::SimulateTx(tx, db) => (
+                    tx.sender(),
+                    tx.nonce(),
+                    db.state_id(),
+                    SimulatorToSequencerMsg::Tx(Self::simulate_transaction(
+                        tx,
+                        db,
+                        &mut self.evm_sorting,
+                        self.regolith_active,
+                        true,
+                        true,
+                    )),
+                ),
+                SequencerToSimulator::SimulateTxTof(tx, db) => (
+                    tx.sender(),
+                    tx.nonce(),
+                    db.state_id(),
+                    SimulatorToSequencerMsg::TxPoolTopOfFrag(Self::simulate_transaction(
+                        tx,
+                        db,
+                        &mut self.evm_tof,
+                        self.regolith_active,
+                        true,
+                        true,
+                    )),
+                ),
+                SequencerToSimulator::WarmAccounts(addresses, db) => {
+                    db.warm_accounts(&addresses);
+                    return;
+                }
+            };
+            let simtime = curt.elapsed();
+            histogram!(SIM_DURATION).record(simtime.as_secs());
             let _ = senders.send_timeout(
-                SimulatorToSequencer::new((sender, nonce), state_id, curt.elapsed(), msg),
+                SimulatorToSequencer::new((sender, nonce), state_id, simtime, msg),
                 Duration::from_millis(10),
             );
         });
@@ -1,8 +1,19 @@
 use alloy_consensus::proofs::ordered_trie_root_with_encoder;
 use alloy_eips::eip2718::Encodable2718;
-use alloy_primitives::{Bloom, U256};
-use bop_common::{p2p::FragV0, time::Instant, transaction::SimulatedTx};
+use alloy_primitives::{keccak256, Bloom, U256};
+use bop_common::{
+    metrics::TXS_PER_FRAG,
+    p2p::{FragV0, FragV1},
+    signing::Preconfirmation,
+    time::Instant,
+    transaction::SimulatedTx,
+};
+use metrics::histogram;
+use reth_optimism_forks::OpHardfork;
+use reth_trie_common::Nibbles;
+use reth_trie_sparse::RevealedSparseTrie;
 use revm_primitives::{Bytes, B256};
+use tree_hash::TreeHash;
 
 use super::{sorting_data::SortingTelemetry, SortingData};
 use crate::context::SequencerContext;
@@ -17,9 +28,16 @@ pub struct FragSequence {
     pub txs: Vec<SimulatedTx>,
     /// Next frag index
     pub next_seq: u64,
+    /// Tree hash root of the last frag, zero before the first one
+    pub prev_frag_hash: B256,
     /// Block number and timestamp shared by all frags of this sequence
     block_number: u64,
     block_timestamp: u64,
+    /// Receipts trie of the first `receipts_len` txs, extended by [`Self::receipts_root`] so only the paths of new
+    /// receipts are rehashed
+    receipts_trie: RevealedSparseTrie,
+    receipts_len: usize,
+    receipts_gas_used: u64,
 
     pub sorting_telemetry: SortingTelemetry,
 }
@@ -33,7 +51,11 @@ impl FragSequence {
             txs: vec![],
             block_number,
             block_timestamp,
+            receipts_trie: RevealedSparseTrie::default(),
+            receipts_len: 0,
+            receipts_gas_used: 0,
             next_seq: 0,
+            prev_frag_hash: B256::ZERO,
             sorting_telemetry: Default::default(),
         }
     }
@@ -42,30 +64,70 @@ impl FragSequence {
         self.gas_remaining = gas_limit;
     }
 
-    pub fn apply_sorted_frag<Db>(&mut self, in_sort: SortingData<Db>, ctx: &mut SequencerContext<Db>) -> FragV0 {
+    pub fn apply_sorted_frag<Db>(&mut self, in_sort: SortingData<Db>, ctx: &mut SequencerContext<Db>) -> FragV1 {
         let gas_used = in_sort.gas_used();
         self.gas_remaining -= gas_used;
         self.payment += in_sort.payment();
 
-        let msg = FragV0::new(self.block_number, self.next_seq, in_sort.txs.iter().map(|tx| tx.tx.as_ref()), false);
+        let FragV0 { block_number, seq, is_last, txs } =
+            FragV0::new(self.block_number, self.next_seq, in_sort.txs.iter().map(|tx| tx.tx.as_ref()), false);
+        histogram!(TXS_PER_FRAG).record(in_sort.txs.len() as f64);
+        in_sort.telemetry.record_metrics();
+        let canyon_active = ctx.chain_spec().fork(OpHardfork::Canyon).active_at_timestamp(self.block_timestamp);
         for tx in in_sort.txs {
             self.gas_used += tx.gas_used();
-            let receipt = tx.op_tx_receipt(
-                self.gas_used,
-                self.block_number,
-                self.block_timestamp,
-                ctx.base_fee(),
-                self.txs.len() as u64,
-            );
+            let index = self.txs.len() as u64;
+            let receipt =
+                tx.op_tx_receipt(self.gas_used, self.block_number, self.block_timestamp, ctx.base_fee(), index);
             ctx.shared_state.insert_confirmed_tx(tx.tx.tx.clone(), receipt);
+            ctx.shared_state.notify_inclusion(&tx.tx_hash(), || Preconfirmation {
+                tx_hash: tx.tx_hash(),
+                block_number,
+                seq,
+                index,
+                receipt_hash: keccak256(tx.receipt(self.gas_used, canyon_active).encoded_2718()),
+            });
             self.txs.push(tx);
         }
 
+        // The frag's txs were already committed to the shared state. The commitments are only gossiped with FragV1
+        let (state_commitment, receipts_root) = if ctx.config.gossip_frag_v1 {
+            (ctx.shared_state.as_ref().state_commitment(), self.receipts_root(canyon_active))
+        } else {
+            (B256::ZERO, B256::ZERO)
+        };
+        let msg = FragV1 {
+            block_number,
+            seq,
+            is_last,
+            txs,
+            cumulative_gas_used: self.gas_used,
+            state_commitment,
+            receipts_root,
+            prev_frag_hash: self.prev_frag_hash,
+        };
+
+        if ctx.config.gossip_frag_v1 {
+            self.prev_frag_hash = msg.tree_hash_root();
+        }
         self.next_seq += 1;
         self.sorting_telemetry += in_sort.telemetry;
         msg
     }
 
+    /// Receipts root of all txs applied so far. Only the receipts of the txs applied since the last call are inserted
+    /// into the receipts trie.
+    pub fn receipts_root(&mut self, canyon_active: bool) -> B256 {
+        for (index, t) in self.txs.iter().enumerate().skip(self.receipts_len) {
+            self.receipts_gas_used += t.gas_used();
+            let key = Nibbles::unpack(alloy_rlp::encode_fixed_size(&index));
+            let receipt = t.receipt(self.receipts_gas_used, canyon_active).encoded_2718();
+            self.receipts_trie.update_leaf(key, receipt).expect("receipts trie has no blinded nodes");
+        }
+        self.receipts_len = self.txs.len();
+        self.receipts_trie.root()
+    }
+
     /// Returns encoded_2718 txs, transactions root, receipts root, and receipts bloom
     pub fn encoded_txs_roots_bloom(&self, canyon_active: bool) -> (Vec<Bytes>, B256, B256, Bloom) {
         let mut receipts = Vec::with_capacity(self.txs.len());
@@ -153,6 +215,8 @@ mod tests {
             evm_config: evm_config.clone(),
             simulate_tof_in_pools: false,
             commit_sealed_frags_to_db: false,
+            gossip_frag_v1: false,
+            record_path: None,
         };
 
         // Create the alloydb.
@@ -241,7 +305,7 @@ mod tests {
         let (_frag, _sorting_db) = ctx.seal_frag(sorting_db, &mut seq);
 
         // Seal the block
-        let (_seal, payload) = ctx.seal_block(seq);
+        let (_seal, payload, _header) = ctx.seal_block(seq).unwrap();
         assert_eq!(block.block.header.hash_slow(), payload.execution_payload.payload_inner.payload_inner.block_hash);
     }
 }
@@ -10,10 +10,12 @@ use bop_common::{
         SpineConnections,
     },
     db::{state::ensure_create2_deployer, DBSorting},
+    metrics::SIMS,
     time::{Duration, Instant},
     transaction::{SimulatedTx, Transaction},
 };
 use bop_db::DatabaseRead;
+use metrics::counter;
 use reth_chainspec::EthereumHardforks;
 use reth_evm::{
     execute::{BlockExecutionError, ProviderError},
@@ -26,7 +28,12 @@ use revm_primitives::{Address, EnvWithHandlerCfg, U256};
 use tracing::trace;
 
 use super::FragSequence;
-use crate::{context::SequencerContext, simulator::simulate_tx_inner, sorting::ActiveOrders};
+use crate::{
+    context::SequencerContext,
+    replay::{Recorder, SequencerInput},
+    simulator::simulate_tx_inner,
+    sorting::ActiveOrders,
+};
 
 #[derive(Clone, Copy, Default)]
 pub struct SortingTelemetry {
@@ -49,6 +56,12 @@ impl SortingTelemetry {
             self.tot_sim_time
         );
     }
+
+    /// Adds the sims of a single frag to the exported counters.
+    pub fn record_metrics(&self) {
+        counter!(SIMS, "result" => "success").increment(self.n_sims_succesful as u64);
+        counter!(SIMS, "result" => "error").increment(self.n_sims_errored as u64);
+    }
 }
 
 impl AddAssign for SortingTelemetry {
@@ -189,6 +202,11 @@ impl<Db> SortingData<Db> {
     pub fn should_send_next_sims(&self) -> bool {
         self.in_flight_sims == 0
     }
+
+    /// Changes whenever sims are sent or applied, used to only record ticks that did something.
+    pub fn progress(&self) -> (usize, usize, usize, bool) {
+        (self.in_flight_sims, self.txs.len(), self.tof_snapshot.len(), self.next_to_be_applied.is_some())
+    }
 }
 
 impl<Db: Clone + DatabaseRef> SortingData<Db> {
@@ -196,6 +214,7 @@ impl<Db: Clone + DatabaseRef> SortingData<Db> {
         &mut self,
         deposits: &mut std::collections::VecDeque<Arc<Transaction>>,
         connections: &mut SpineConnections<Db>,
+        recorder: &mut Option<Recorder>,
     ) {
         //TODO: we should do this inline
         while let Some(deposit) = deposits.pop_front() {
@@ -204,6 +223,9 @@ impl<Db: Clone + DatabaseRef> SortingData<Db> {
             let mut found = false;
             while !found {
                 connections.receive(|msg: SimulatorToSequencer, _| {
+                    if let Some(recorder) = recorder.as_mut() {
+                        recorder.record(Instant::now(), || SequencerInput::DepositSimResult((&msg).into()));
+                    }
                     let state_id = msg.state_id;
                     self.telemetry.tot_sim_time += msg.simtime;
                     if !self.is_valid(state_id) {
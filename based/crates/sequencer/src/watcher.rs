@@ -0,0 +1,332 @@
+//! Watches the frag stream of the sequencer for broken promises.
+//!
+//! Every message signed by the expected sequencer is kept per block until the canonical block is known, e.g. fetched
+//! from a full node a few blocks later. Conflicting messages for the same block, preconfirmed transactions that are
+//! missing from or moved within the canonical block, and seals that don't match it are violations. They are written to
+//! an evidence file together with the signed messages and the canonical block, so they can be checked without trusting
+//! the watcher.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    fs::File,
+    io::BufWriter,
+    path::PathBuf,
+};
+
+use alloy_consensus::Header;
+use alloy_eips::eip2718::Encodable2718;
+use alloy_primitives::{keccak256, Address, Bytes, B256};
+use bop_common::{
+    communication::messages::BlockSyncMessage,
+    metrics::WATCHER_VIOLATIONS,
+    p2p::{SealV0, SignedMessage, Transactions, VersionedMessage},
+};
+use metrics::counter;
+use serde::{Deserialize, Serialize};
+use strum_macros::AsRefStr;
+use tracing::{debug, warn};
+use tree_hash::TreeHash;
+
+use crate::follower::ExpectedSigner;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, AsRefStr)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum Violation {
+    /// Two different envs for the same block.
+    ConflictingEnvs,
+    /// Two frags with the same seq but different transactions.
+    ConflictingFrags { seq: u64 },
+    /// Two different seals for the same block.
+    ConflictingSeals,
+    /// A preconfirmed transaction is not in the canonical block.
+    MissingTx { hash: B256, seq: u64 },
+    /// A transaction in the canonical block that wasn't preconfirmed, while transactions preconfirmed after it were.
+    ExtraTx { hash: B256, index: u64 },
+    /// A preconfirmed transaction is in the canonical block before one preconfirmed earlier.
+    Reordered { hash: B256, seq: u64, index: u64 },
+    /// A field of the seal doesn't match the canonical block.
+    SealMismatch { field: String, sealed: String, canonical: String },
+}
+
+/// Canonical block the messages were checked against. The header is RLP encoded, so its hash can be checked.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CanonicalBlock {
+    pub number: u64,
+    pub hash: B256,
+    pub header: Bytes,
+    /// EIP-2718 encoded transactions
+    pub transactions: Vec<Bytes>,
+}
+
+/// Self-contained proof that the sequencer broke its promises for a block.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Evidence {
+    pub block_number: u64,
+    /// Sequencer expected to sign the messages of the block
+    pub sequencer: Address,
+    pub violations: Vec<Violation>,
+    /// All distinct messages received for the block, in the order they were received
+    pub messages: Vec<SignedMessage>,
+    pub canonical: CanonicalBlock,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct FragTxs {
+    is_last: bool,
+    /// Hashes of the transactions, in order
+    txs: Vec<B256>,
+}
+
+impl FragTxs {
+    fn new(is_last: bool, txs: &Transactions) -> Self {
+        Self { is_last, txs: txs.iter().map(|tx| keccak256(&tx[..])).collect() }
+    }
+}
+
+/// Messages received for a block.
+#[derive(Debug, Default)]
+struct BlockMessages {
+    messages: Vec<SignedMessage>,
+    roots: HashSet<B256>,
+    env: bool,
+    /// First frag received for each seq
+    frags: BTreeMap<u64, FragTxs>,
+    seal: Option<SealV0>,
+    violations: Vec<Violation>,
+}
+
+impl BlockMessages {
+    /// Returns a violation if the message conflicts with one received before.
+    fn record(&mut self, signed: &SignedMessage) -> Option<Violation> {
+        if !self.roots.insert(signed.message.tree_hash_root()) {
+            return None;
+        }
+        self.messages.push(signed.clone());
+
+        match &signed.message {
+            VersionedMessage::EnvV0(_) if self.env => Some(Violation::ConflictingEnvs),
+            VersionedMessage::EnvV0(_) => {
+                self.env = true;
+                None
+            }
+            VersionedMessage::FragV0(frag) => self.record_frag(frag.seq, FragTxs::new(frag.is_last, &frag.txs)),
+            VersionedMessage::FragV1(frag) => self.record_frag(frag.seq, FragTxs::new(frag.is_last, &frag.txs)),
+            VersionedMessage::SealV0(_) if self.seal.is_some() => Some(Violation::ConflictingSeals),
+            VersionedMessage::SealV0(seal) => {
+                self.seal = Some(seal.clone());
+                None
+            }
+            _ => None,
+        }
+    }
+
+    /// The same frag may be gossiped both as V0 and V1, so frags are compared by their transactions.
+    fn record_frag(&mut self, seq: u64, frag: FragTxs) -> Option<Violation> {
+        match self.frags.get(&seq) {
+            Some(first) if first.txs != frag.txs => Some(Violation::ConflictingFrags { seq }),
+            Some(_) => None,
+            None => {
+                self.frags.insert(seq, frag);
+                None
+            }
+        }
+    }
+
+    /// Compares the preconfirmed transactions with the hashes of the canonical transactions.
+    fn compare_txs(&self, canonical: &[B256]) -> Vec<Violation> {
+        let index_of: HashMap<B256, u64> = canonical.iter().enumerate().map(|(i, hash)| (*hash, i as u64)).collect();
+        let mut violations = Vec::new();
+
+        let mut preconfirmed = HashSet::new();
+        let mut last_index = None;
+        for (seq, frag) in &self.frags {
+            for hash in &frag.txs {
+                preconfirmed.insert(*hash);
+                let Some(&index) = index_of.get(hash) else {
+                    violations.push(Violation::MissingTx { hash: *hash, seq: *seq });
+                    continue;
+                };
+                if last_index.is_some_and(|last| index < last) {
+                    violations.push(Violation::Reordered { hash: *hash, seq: *seq, index });
+                }
+                last_index = last_index.max(Some(index));
+            }
+        }
+
+        // Without gaps in the frags, nothing can be inserted before a preconfirmed transaction, and after the last frag
+        // nothing at all
+        let complete = self.frags.keys().copied().eq(0..self.frags.len() as u64);
+        if complete {
+            let sealed = self.frags.values().next_back().is_some_and(|frag| frag.is_last);
+            for (index, hash) in canonical.iter().enumerate() {
+                let index = index as u64;
+                if !preconfirmed.contains(hash) && (sealed || last_index.is_some_and(|last| index < last)) {
+                    violations.push(Violation::ExtraTx { hash: *hash, index });
+                }
+            }
+        }
+
+        violations
+    }
+}
+
+fn compare_seal(seal: &SealV0, header: &Header, hash: B256) -> Vec<Violation> {
+    let fields = [
+        ("blockHash", seal.block_hash.to_string(), hash.to_string()),
+        ("parentHash", seal.parent_hash.to_string(), header.parent_hash.to_string()),
+        ("stateRoot", seal.state_root.to_string(), header.state_root.to_string()),
+        ("transactionsRoot", seal.transactions_root.to_string(), header.transactions_root.to_string()),
+        ("receiptsRoot", seal.receipts_root.to_string(), header.receipts_root.to_string()),
+        ("gasUsed", seal.gas_used.to_string(), header.gas_used.to_string()),
+        ("gasLimit", seal.gas_limit.to_string(), header.gas_limit.to_string()),
+    ];
+    fields
+        .into_iter()
+        .filter(|(_, sealed, canonical)| sealed != canonical)
+        .map(|(field, sealed, canonical)| Violation::SealMismatch { field: field.to_string(), sealed, canonical })
+        .collect()
+}
+
+pub struct Watcher {
+    signer: ExpectedSigner,
+    evidence_dir: PathBuf,
+    blocks: BTreeMap<u64, BlockMessages>,
+    /// Highest block checked against the canonical chain, later messages for it are ignored
+    checked: u64,
+}
+
+impl Watcher {
+    pub fn new(signer: ExpectedSigner, evidence_dir: PathBuf) -> Self {
+        Self { signer, evidence_dir, blocks: BTreeMap::new(), checked: 0 }
+    }
+
+    /// Keeps a message of the expected sequencer until its block is checked. Messages signed by anyone else can't be
+    /// attributed to the sequencer, so they are ignored.
+    pub fn record(&mut self, signed: &SignedMessage) {
+        let number = signed.message.block_number();
+        if number <= self.checked {
+            return;
+        }
+        let Some(expected) = self.signer.for_block(number) else {
+            return;
+        };
+        if signed.recover_signer().ok() != Some(expected) {
+            return;
+        }
+
+        let block = self.blocks.entry(number).or_default();
+        if let Some(violation) = block.record(signed) {
+            warn!(number, kind = violation.as_ref(), "sequencer equivocated");
+            block.violations.push(violation);
+        }
+    }
+
+    /// Blocks up to and including `number` with messages that weren't checked yet.
+    pub fn pending_blocks(&self, number: u64) -> Vec<u64> {
+        self.blocks.range(..=number).map(|(number, _)| *number).collect()
+    }
+
+    /// Checks the messages of a block against its canonical version and writes an evidence file if the sequencer
+    /// broke any promise. Returns the violations.
+    pub fn check(&mut self, block: &BlockSyncMessage) -> eyre::Result<Vec<Violation>> {
+        let header = &block.block.header;
+        let number = header.number;
+        self.checked = self.checked.max(number);
+        let Some(messages) = self.blocks.remove(&number) else {
+            return Ok(vec![]);
+        };
+
+        let hash = header.hash_slow();
+        let transactions: Vec<Bytes> =
+            block.block.body.transactions.iter().map(|tx| tx.encoded_2718().into()).collect();
+        let hashes: Vec<B256> = transactions.iter().map(keccak256).collect();
+
+        let mut violations = messages.violations.clone();
+        violations.extend(messages.compare_txs(&hashes));
+        if let Some(seal) = messages.seal.as_ref() {
+            violations.extend(compare_seal(seal, header, hash));
+        }
+        if violations.is_empty() {
+            debug!(number, messages = messages.messages.len(), "messages match canonical block");
+            return Ok(violations);
+        }
+
+        for violation in &violations {
+            counter!(WATCHER_VIOLATIONS, "kind" => violation.as_ref().to_string()).increment(1);
+        }
+        let evidence = Evidence {
+            block_number: number,
+            sequencer: self.signer.for_block(number).unwrap_or_default(),
+            violations: violations.clone(),
+            messages: messages.messages,
+            canonical: CanonicalBlock { number, hash, header: alloy_rlp::encode(header).into(), transactions },
+        };
+
+        std::fs::create_dir_all(&self.evidence_dir)?;
+        let path = self.evidence_dir.join(format!("block-{number}-{hash}.json"));
+        serde_json::to_writer_pretty(BufWriter::new(File::create(&path)?), &evidence)?;
+        warn!(number, violations = violations.len(), path = %path.display(), "sequencer broke promises, wrote evidence");
+
+        Ok(violations)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bop_common::{
+        p2p::{FragV0, Transaction},
+        signing::ECDSASigner,
+    };
+
+    use super::*;
+
+    fn frag(seq: u64, is_last: bool, txs: &[u8]) -> VersionedMessage {
+        let txs = txs.iter().map(|tx| Transaction::from(vec![*tx])).collect::<Vec<_>>();
+        FragV0 { block_number: 1, seq, is_last, txs: Transactions::from(txs) }.into()
+    }
+
+    fn hash(tx: u8) -> B256 {
+        keccak256([tx])
+    }
+
+    #[test]
+    fn records_conflicts_of_the_sequencer() {
+        let sequencer = ECDSASigner::random();
+        let mut watcher = Watcher::new(ExpectedSigner::Address(sequencer.address), PathBuf::new());
+
+        watcher.record(&frag(0, false, &[1, 2]).sign(&sequencer));
+        watcher.record(&frag(0, false, &[1, 2]).sign(&sequencer));
+        watcher.record(&frag(0, false, &[2, 1]).sign(&ECDSASigner::random()));
+        assert!(watcher.blocks[&1].violations.is_empty());
+        assert_eq!(watcher.blocks[&1].messages.len(), 1);
+
+        watcher.record(&frag(0, false, &[2, 1]).sign(&sequencer));
+        assert_eq!(watcher.blocks[&1].violations, vec![Violation::ConflictingFrags { seq: 0 }]);
+        assert_eq!(watcher.pending_blocks(1), vec![1]);
+        assert!(watcher.pending_blocks(0).is_empty());
+    }
+
+    #[test]
+    fn compares_txs_with_canonical_block() {
+        let sequencer = ECDSASigner::random();
+        let mut messages = BlockMessages::default();
+        messages.record(&frag(0, false, &[1, 2]).sign(&sequencer));
+        messages.record(&frag(1, false, &[3, 4]).sign(&sequencer));
+
+        // preconfirmed txs in order, anything may follow the last frag until it's sealed
+        assert!(messages.compare_txs(&[hash(1), hash(2), hash(3), hash(4), hash(5)]).is_empty());
+
+        let violations = messages.compare_txs(&[hash(1), hash(3), hash(4)]);
+        assert_eq!(violations, vec![Violation::MissingTx { hash: hash(2), seq: 0 }]);
+        let violations = messages.compare_txs(&[hash(1), hash(3), hash(2), hash(4)]);
+        assert_eq!(violations, vec![Violation::Reordered { hash: hash(3), seq: 1, index: 1 }]);
+        let violations = messages.compare_txs(&[hash(1), hash(5), hash(2), hash(3), hash(4)]);
+        assert_eq!(violations, vec![Violation::ExtraTx { hash: hash(5), index: 1 }]);
+
+        messages.record(&frag(2, true, &[]).sign(&sequencer));
+        let violations = messages.compare_txs(&[hash(1), hash(2), hash(3), hash(4), hash(5)]);
+        assert_eq!(violations, vec![Violation::ExtraTx { hash: hash(5), index: 4 }]);
+    }
+}